@@ -2,36 +2,49 @@ use crate::{
     error::Error,
     protocol::{
         atom::{
-            Atom, Auto, Cut, CutToBlack, FadeToBlackAuto, FileTransferChunkParams, FileType,
-            FinishFileDownload, MediaPlayerSourceID, MediaPoolLock, Payload,
-            SetColourGeneratorParams, SetMediaPlayerSource, SetPreviewInput, SetProgramInput,
-            SetupFileDownload, SetupFileUpload, TimecodeRequest, TransferChunk, CAPTURE_STILL,
-            CLEAR_MEDIA_POOL, CLEAR_STARTUP_SETTINGS, RESTORE_STARTUP_SETTINGS,
-            RTMP_DURATION_REQUEST, SAVE_STARTUP_SETTINGS,
+            Atom, Auto, CameraCommand, CameraParameterID, CameraParameterValue, CancelFileDownload,
+            Colorimetry, ColorimetryMode, Cut, CutToBlack, DVETransitionParams, FadeToBlackAuto,
+            FairlightAudioInputSourceState, FileTransferChunkParams, FileType, FinishFileDownload,
+            HyperDeckTransportState, LensParam, MacroAction, MacroActionType, MacroStartRecord,
+            MediaPlayerSourceID, MediaPoolLock, MultiViewSafeArea, Payload, ProductName,
+            RecordToMedia, Sdi3gLevel, SetAudioMixerInputProperties,
+            SetAudioTalkbackMixerProperties, SetAuxSource, SetColourGeneratorParams,
+            SetDownstreamKeyerOnAir, SetFadeToBlackParams,
+            SetFairlightAudioMixerInputSourceProperties, SetHyperDeckClipPlayStatus,
+            SetInputProperties, SetKeyDVEProperties, SetMediaPlayerAudio, SetMediaPlayerSource,
+            SetMultiViewVuMeterEnabled, SetPreviewInput, SetProgramInput, SetSdi3gOutputLevel,
+            SetSuperSourceBoxProperties, SetTransitionDVEProperties, SetTransitionPreview,
+            SetTransitionSettings, SetTransitionWipeProperties, SetVideoMode, SetupFileDownload,
+            SetupFileUpload, StreamRtmp, SuperSourceBoxParams, SupportedVideoMode, TimecodeRequest,
+            TransferChunk, WipeTransitionParams, CAPTURE_STILL, CLEAR_MEDIA_POOL,
+            CLEAR_STARTUP_SETTINGS, NO_MACRO, RECORD_TO_MEDIA_SWITCH_DISK,
+            RESTORE_STARTUP_SETTINGS, RTMP_DURATION_REQUEST, SAVE_STARTUP_SETTINGS,
         },
-        rle::RLE_MARKER,
-        structs::VideoSource,
+        rle::{RleCompressor, RLE_MARKER},
+        structs::{TallyFlags, TransitionStyle, VideoMode, VideoSource},
         AtemControl, AtemPacket, AtemPacketFlags,
     },
-    rle::rle_md5_size,
-    state::{AtemState, StateUpdate},
+    rle::{rle_md5_size, RleMd5},
+    state::{AtemState, Capability, SourceBus, StateUpdate},
     udp::AtemUdpChannel,
 };
-use binrw::BinWrite;
+use binrw::{BinRead, BinWrite};
 use concread::cowcell::asynch::{CowCell, CowCellReadTxn};
-use futures::{pin_mut, StreamExt};
+use fixed::types::I5F11;
+use futures::{pin_mut, Stream, StreamExt};
 use rand::Rng;
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
     io::Cursor,
-    net::SocketAddrV4,
+    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
     sync::{
-        atomic::{AtomicBool, AtomicU16, Ordering},
-        Arc, Weak,
+        atomic::{AtomicBool, AtomicU16, AtomicUsize, Ordering},
+        Arc, Mutex, PoisonError, Weak,
     },
     time::{Duration, Instant},
 };
 use tokio::{
+    net::UdpSocket,
     select,
     sync::{
         broadcast::{self, Receiver},
@@ -41,7 +54,61 @@ use tokio::{
     task::JoinHandle,
     time::interval,
 };
-use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, IntervalStream};
+
+/// Store ID used for MultiView labels.
+///
+/// This is the same store ID used by `atem-connection`'s
+/// `DataTransferUploadMultiViewerLabel`.
+const MULTIVIEW_LABEL_STORE_ID: u16 = 0xffff;
+
+/// Returns `true` if `cmd` is only meaningful within the session it was sent
+/// in, and shouldn't be [replayed][ConnectionOptions::replay_on_reconnect].
+fn is_transient_atom(cmd: &Atom) -> bool {
+    matches!(
+        cmd.payload,
+        Payload::TimecodeRequest(_)
+            | Payload::MediaPoolLock(_)
+            | Payload::SetupFileUpload(_)
+            | Payload::SetupFileDownload(_)
+            | Payload::FinishFileDownload(_)
+            | Payload::CancelFileDownload(_)
+            | Payload::TransferChunk(_)
+            | Payload::TransferAck(_)
+    )
+}
+
+/// Maps a 1-indexed external input number to a [VideoSource].
+fn input_number_to_source(n: u8) -> Result<VideoSource, Error> {
+    VideoSource::from_input_number(n).ok_or_else(|| {
+        error!("input #{n} does not exist");
+        Error::ParameterOutOfRange
+    })
+}
+
+/// Makes a packet which ends the session `session_id`.
+fn disconnect_packet(session_id: u16) -> AtemPacket {
+    AtemPacket::new_control(
+        AtemPacketFlags::new().with_control(true),
+        session_id,
+        0,
+        0,
+        0,
+        AtemControl::Disconnect,
+    )
+}
+
+/// Sends a single [AtemPacket] to `addr` on an unconnected socket.
+async fn send_packet_to(
+    sock: &UdpSocket,
+    addr: SocketAddrV4,
+    pkt: &AtemPacket,
+) -> Result<(), Error> {
+    let mut out = Cursor::new(Vec::new());
+    pkt.write(&mut out)?;
+    sock.send_to(&out.into_inner(), addr).await?;
+    Ok(())
+}
 
 /// Internal type for asynchronous message passing.
 enum AsyncCommand {
@@ -69,6 +136,453 @@ enum AsyncCommand {
     },
 }
 
+/// Counts of atoms received from the switcher, keyed by FourCC.
+type ObservedAtoms = Arc<Mutex<BTreeMap<[u8; 4], usize>>>;
+
+/// [VideoModeHold] shared between [AtemReceiver] and [AtemController].
+type SharedVideoModeHold = Arc<Mutex<VideoModeHold>>;
+
+/// Holds back state update broadcasts during
+/// [`AtemController::set_video_mode()`].
+///
+/// Changing video mode makes the switcher re-initialise, and re-send much of
+/// its state. While a hold is active, updated fields are accumulated rather
+/// than broadcast, until the switcher has reported the new video mode (`VidM`)
+/// and then gone [quiet][Self::QUIET_PERIOD], or the hold reaches its
+/// [deadline][Self::MAX_DURATION].
+///
+/// Because a hold can end without any further update from the switcher, the
+/// held fields are also broadcast by a task from
+/// [`AtemController::spawn_video_mode_hold_release()`], which runs even if
+/// the caller stops waiting for `set_video_mode()`.
+#[derive(Debug, Default)]
+struct VideoModeHold {
+    /// The video mode being switched to, if a hold is active.
+    target: Option<VideoMode>,
+    /// When the hold ends, regardless of updates from the switcher.
+    deadline: Option<Instant>,
+    /// When the last update was received after the switcher reported the
+    /// [target][Self::target] video mode, or `None` if it hasn't been
+    /// reported yet.
+    last_update: Option<Instant>,
+    /// Notified when the switcher reports the [target][Self::target] video
+    /// mode.
+    mode_reported_tx: Option<oneshot::Sender<()>>,
+    /// Fields updated since the last broadcast.
+    held: StateUpdate,
+}
+
+impl VideoModeHold {
+    /// How long to wait for further updates after the switcher reports the
+    /// new video mode, before releasing the hold.
+    const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+    /// The longest a hold may last.
+    const MAX_DURATION: Duration = Duration::from_secs(10);
+
+    /// Starts a hold, returning a channel which is notified when the switcher
+    /// reports the `target` video mode.
+    ///
+    /// The notification isn't held, so it can be used to wait for the mode
+    /// change while the hold is still active.
+    fn start(&mut self, target: VideoMode) -> oneshot::Receiver<()> {
+        let (tx, rx) = oneshot::channel();
+        self.target = Some(target);
+        self.deadline = Some(Instant::now() + Self::MAX_DURATION);
+        self.last_update = None;
+        self.mode_reported_tx = Some(tx);
+        rx
+    }
+
+    /// Gets when the active hold should be released, or `None` if there is no
+    /// active hold.
+    fn release_at(&self) -> Option<Instant> {
+        self.target?;
+        let deadline = self.deadline?;
+        Some(match self.last_update {
+            Some(t) => (t + Self::QUIET_PERIOD).min(deadline),
+            None => deadline,
+        })
+    }
+
+    /// Ends the hold, and returns the fields which were held.
+    fn release(&mut self) -> StateUpdate {
+        self.target = None;
+        self.deadline = None;
+        self.last_update = None;
+        self.mode_reported_tx = None;
+        std::mem::take(&mut self.held)
+    }
+
+    /// Returns the fields to broadcast after `updated_fields` were applied to
+    /// `state`, or [`StateUpdate::empty()`] if the update is held.
+    fn coalesce(&mut self, state: &AtemState, updated_fields: StateUpdate) -> StateUpdate {
+        self.held |= updated_fields;
+        let Some(target) = self.target else {
+            return std::mem::take(&mut self.held);
+        };
+
+        let now = Instant::now();
+        if updated_fields.intersects(StateUpdate::VIDEO_MODE) && state.video_mode == target {
+            if let Some(tx) = self.mode_reported_tx.take() {
+                let _ = tx.send(());
+            }
+            self.last_update = Some(now);
+        } else if self.last_update.is_some() {
+            self.last_update = Some(now);
+        }
+
+        if self.release_at().is_some_and(|t| now >= t) {
+            debug!(?target, "video mode hold expired, releasing state updates");
+            return self.release();
+        }
+
+        StateUpdate::empty()
+    }
+}
+
+/// Round-trip times of clock requests, shared between [AtemReceiver] and
+/// [AtemController].
+#[derive(Debug, Default, Clone, Copy)]
+struct RoundTripTime {
+    /// The most recent round-trip time.
+    last: Option<Duration>,
+    /// Exponentially-weighted moving average of recent round-trip times.
+    average: Option<Duration>,
+}
+
+impl RoundTripTime {
+    /// Each new sample contributes `1 / AVERAGE_WEIGHT` of the
+    /// [average][Self::average].
+    const AVERAGE_WEIGHT: u32 = 8;
+
+    fn record(&mut self, rtt: Duration) {
+        self.last = Some(rtt);
+        self.average = Some(match self.average {
+            None => rtt,
+            Some(average) => (average * (Self::AVERAGE_WEIGHT - 1) + rtt) / Self::AVERAGE_WEIGHT,
+        });
+    }
+}
+
+/// Direction of a file transfer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransferDirection {
+    /// Transfer from the switcher to the client.
+    Download,
+    /// Transfer from the client to the switcher.
+    Upload,
+}
+
+/// File transfer lifecycle events, from [`AtemController::transfer_events()`].
+///
+/// Each transfer is identified by its transfer `id`, which is shared by all
+/// events for that transfer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransferEvent {
+    /// A transfer was requested from the switcher.
+    Started {
+        id: u16,
+        direction: TransferDirection,
+        store_id: u16,
+        index: u8,
+    },
+
+    /// Data has been transferred.
+    Progress {
+        id: u16,
+        /// Total number of bytes transferred so far.
+        bytes: usize,
+    },
+
+    /// The switcher reported that the transfer completed successfully.
+    Completed { id: u16 },
+
+    /// The switcher reported an error during the transfer.
+    Failed { id: u16, code: u8 },
+
+    /// The transfer was abandoned by the client, or lost on reconnection.
+    Cancelled { id: u16 },
+}
+
+/// Options for [`AtemController::connect_udp_with_options()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionOptions {
+    /// If `true`, reconnect after failures.
+    pub reconnect: bool,
+
+    /// Delay before retransmitting a command which hasn't been acknowledged
+    /// by the switcher.
+    pub retransmit_interval: Duration,
+
+    /// Number of times to retransmit a command which hasn't been acknowledged
+    /// by the switcher, before giving up.
+    pub retransmit_limit: u8,
+
+    /// How long to wait for the switcher to respond to a connection request.
+    pub init_timeout: Duration,
+
+    /// During a frame upload, the number of chunks that will be sent as a
+    /// "burst" before waiting for the switcher to acknowledge them all.
+    ///
+    /// ## Background
+    ///
+    /// The switcher indicates its transfer capacity with
+    /// [`FileTransferChunkParams`], but this doesn't seem to be the *only*
+    /// bottleneck in the switcher's file transfer pipeline.
+    ///
+    /// The switcher can batch-acknowledge up to about 32 packets (with the
+    /// ATEM Mini), and will wait about 20ms after the last packet before
+    /// sending *any* acknowledgement. We also need to manage size of the
+    /// command and acknowledgement queues – we don't want to gum those up with
+    /// too many file transfer chunks or require retransmits.
+    ///
+    /// ATEM's SDK/tools seem to just take the [`FileTransferChunkParams`] at
+    /// face value, and DoS the switcher. This also means it ends up
+    /// retransmitting nearly every chunk of a frame at least once, and ends up
+    /// wasting a bunch of bandwidth *and* being slower.
+    ///
+    /// ## Benchmarks
+    ///
+    /// This benchmark is sending a mostly-uncompressed (7,204,696 bytes, 5178
+    /// [chunks][chunk]) 1080p image to the ATEM Mini, using a wired gigabit
+    /// ethernet network, on the same switch.
+    ///
+    /// Waiting for ack after... | Duration  | Packets sent
+    /// ------------------------ | --------- | ------------
+    /// each [chunk][]           | 86.38 sec | 5,178 (1x)
+    /// every 10 [chunks][chunk] | 8.77 sec  | 5,178 (1x)
+    /// every 24 [chunks][chunk] | 3.77 sec  | 5,178 (1x)
+    /// vs. ATEM's SDK/tools     | 6.66 sec  | 15,672 (3.03x)
+    ///
+    /// "Duration" is measured from [`SetupFileUpload`] (`FTSD`) to the switcher
+    /// sending [`TransferCompleted`][crate::protocol::atom::TransferCompleted] (`FTDC`).
+    /// _Lower is better._
+    ///
+    /// "Packets sent" is the number of packets containing a [chunk][]. A number
+    /// higher than 5,178 (1x) indicates that the client retransmitted some
+    /// chunks because the switcher couldn't keep up. _Lower is better_.
+    ///
+    /// The ideal value depends on the switcher and network; the default is 24.
+    ///
+    /// [chunk]: TransferChunk
+    pub upload_burst_size: usize,
+
+    /// Interval between requests for the switcher's clock, which are used as
+    /// a keep-alive / health check.
+    pub heartbeat_interval: Duration,
+
+    /// Maximum number of out-of-order packets from the switcher to hold while
+    /// waiting for a missing packet to be retransmitted. When this is
+    /// exceeded, [`rx_queue_policy`][Self::rx_queue_policy] applies.
+    ///
+    /// This is limited to half of the packet ID space.
+    pub max_rx_queue_length: usize,
+
+    /// How long to wait without forwarding any packets from the switcher
+    /// before disconnecting.
+    ///
+    /// Packets are only forwarded in order, so packets which are held in the
+    /// receive queue behind a missing packet (or are duplicates) don't reset
+    /// this timer.
+    ///
+    /// High-latency links may need a longer time.
+    pub max_rx_queue_time: Duration,
+
+    /// How long to wait for a missing packet to be retransmitted by the
+    /// switcher, while later packets are held in the receive queue. When this
+    /// is exceeded, [`rx_queue_policy`][Self::rx_queue_policy] applies.
+    ///
+    /// This should be shorter than
+    /// [`max_rx_queue_time`][Self::max_rx_queue_time], otherwise a stalled
+    /// queue disconnects regardless of the policy.
+    pub max_rx_gap_time: Duration,
+
+    /// What to do when a packet from the switcher goes missing, and isn't
+    /// retransmitted before the receive queue stalls.
+    ///
+    /// Defaults to [`RxQueuePolicy::Disconnect`].
+    pub rx_queue_policy: RxQueuePolicy,
+
+    /// If `true`, count every atom received from the switcher, for
+    /// [`AtemController::observed_atoms()`].
+    pub record_observed_atoms: bool,
+
+    /// If `true`, names in atoms from the switcher (input labels, media pool
+    /// file names, macro names, etc.) which aren't valid UTF-8 are
+    /// [decoded lossily][crate::protocol::atom::Atom#lossy-utf-8-decoding],
+    /// rather than the whole atom being ignored.
+    pub lossy_utf8: bool,
+
+    /// If `true`, [`AtemController::set_video_mode()`] holds back state
+    /// updates while the switcher re-initialises, and then broadcasts them as
+    /// a single update.
+    ///
+    /// If `false`, every intermediate update is broadcast as it arrives.
+    pub coalesce_video_mode_change: bool,
+
+    /// If `true` (and [reconnecting][Self::reconnect]), commands which the
+    /// switcher hadn't acknowledged when the connection was lost are sent
+    /// again once the switcher has finished sending its initial state.
+    ///
+    /// Clock requests, file transfers and storage locks are never replayed,
+    /// because they don't survive a reconnection.
+    ///
+    /// Callers waiting on a replayed command are only notified once the
+    /// switcher acknowledges the replay. If `false`, they get
+    /// [`Error::NotAcknowledged`] as soon as the connection is lost.
+    pub replay_on_reconnect: bool,
+}
+
+impl Default for ConnectionOptions {
+    fn default() -> Self {
+        Self {
+            reconnect: false,
+            retransmit_interval: Duration::from_millis(500),
+            retransmit_limit: 3,
+            init_timeout: Duration::from_secs(1),
+            upload_burst_size: 24,
+            heartbeat_interval: Duration::from_millis(500),
+            max_rx_queue_length: 64,
+            max_rx_queue_time: Duration::from_secs(2),
+            max_rx_gap_time: Duration::from_secs(1),
+            rx_queue_policy: RxQueuePolicy::default(),
+            record_observed_atoms: false,
+            lossy_utf8: false,
+            coalesce_video_mode_change: true,
+            replay_on_reconnect: false,
+        }
+    }
+}
+
+/// What to do when the receive queue stalls, because a packet from the
+/// switcher went missing and wasn't retransmitted in time, or too many packets
+/// arrived after it.
+///
+/// See [`ConnectionOptions::rx_queue_policy`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RxQueuePolicy {
+    /// Disconnect from the switcher (and [reconnect][ConnectionOptions::reconnect],
+    /// if enabled), which resynchronises the entire state.
+    #[default]
+    Disconnect,
+
+    /// Skip over the missing packets, forward everything queued after them in
+    /// order, and keep the session alive.
+    ///
+    /// Any state carried by the missing packets is lost, until the switcher
+    /// next reports it. This is more forgiving of lossy links (eg: WiFi).
+    FlushAndContinue,
+}
+
+/// Sets of [`client_packet_id`][AtemPacket::client_packet_id] values which a
+/// client sends during the connection handshake, and when acknowledging
+/// packets from the switcher.
+///
+/// It's not known what these values mean, or whether other switcher
+/// generations (or firmware versions) expect different values. Only profiles
+/// which have been observed with a real client are listed here, and
+/// necromancer always uses [`HANDSHAKE_PROFILE`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum HandshakeProfile {
+    /// Values sent by `BMDSwitcherAPI` 9.x: `0xb1` when connecting, `0xd4`
+    /// when requesting the switcher's initial state, and `0xd2` when
+    /// acknowledging packets.
+    SwitcherApi,
+}
+
+impl HandshakeProfile {
+    /// `client_packet_id` for the [`Connect`][AtemControl::Connect] request.
+    pub const fn connect_client_packet_id(&self) -> u16 {
+        match self {
+            Self::SwitcherApi => 0xb1,
+        }
+    }
+
+    /// `client_packet_id` for the acknowledgement of the switcher's
+    /// [`ConnectAck`][AtemControl::ConnectAck], which requests the switcher's
+    /// initial state.
+    pub const fn request_state_client_packet_id(&self) -> u16 {
+        match self {
+            Self::SwitcherApi => 0xd4,
+        }
+    }
+
+    /// `client_packet_id` for [acknowledgements][AtemPacket::make_ack] of
+    /// packets from the switcher.
+    pub const fn ack_client_packet_id(&self) -> u16 {
+        match self {
+            Self::SwitcherApi => 0xd2,
+        }
+    }
+
+    /// Makes an acknowledgement of `pkt` (if it needs one) with this profile's
+    /// [`ack_client_packet_id()`][Self::ack_client_packet_id].
+    fn make_ack(&self, pkt: &AtemPacket) -> Option<AtemPacket> {
+        let mut ack = pkt.make_ack()?;
+        ack.client_packet_id = self.ack_client_packet_id();
+        Some(ack)
+    }
+}
+
+/// The [HandshakeProfile] used for every connection.
+const HANDSHAKE_PROFILE: HandshakeProfile = HandshakeProfile::SwitcherApi;
+
+/// Confirmation token for [`AtemController::factory_reset()`].
+///
+/// This can only be created with
+/// [`AtemController::request_factory_reset()`], and is only valid for the
+/// [AtemController] which issued it, for [`FactoryResetConfirm::VALIDITY`].
+/// Requesting a new token invalidates any previous token.
+#[derive(Debug)]
+pub struct FactoryResetConfirm {
+    nonce: u64,
+    issued: Instant,
+}
+
+impl FactoryResetConfirm {
+    /// How long a token is valid for after it was issued.
+    pub const VALIDITY: Duration = Duration::from_secs(10);
+}
+
+/// A file download in progress, from
+/// [`AtemController::start_file_download()`].
+///
+/// This dereferences to a [`mpsc::Receiver`], which yields each chunk of the
+/// file as it arrives, and closes once the download has finished.
+///
+/// If the [expected MD5][Self::expected_md5] is known, the download is
+/// checked against it when it finishes. On mismatch, the last item is
+/// [`Error::ChecksumMismatch`].
+pub struct FileDownload {
+    md5: Option<[u8; 16]>,
+    rx: mpsc::Receiver<Result<Vec<u8>, Error>>,
+}
+
+impl FileDownload {
+    /// Gets the MD5 of the file's uncompressed contents, as reported by the
+    /// switcher, if known.
+    ///
+    /// This can be used to verify the download out-of-band.
+    pub fn expected_md5(&self) -> Option<[u8; 16]> {
+        self.md5
+    }
+}
+
+impl std::ops::Deref for FileDownload {
+    type Target = mpsc::Receiver<Result<Vec<u8>, Error>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.rx
+    }
+}
+
+impl std::ops::DerefMut for FileDownload {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.rx
+    }
+}
+
 /// Asynchronous file download request state.
 struct AsyncFileDownloadRequest {
     store_id: u16,
@@ -77,10 +591,64 @@ struct AsyncFileDownloadRequest {
     tx: Arc<mpsc::Sender<Result<Vec<u8>, Error>>>,
     bytes_received: usize,
     bytes_since_last_ack: usize,
-    /// Storage lock.
+    /// Expected MD5 of the file's uncompressed contents, if known.
+    md5: Option<[u8; 16]>,
+    /// MD5 of the data received so far, to [verify][Self::verify] the
+    /// download when it finishes. This is only used when the [MD5][Self::md5]
+    /// is known.
+    received_md5: DownloadMd5,
+    /// Storage lock, if `store_id` needs one.
     ///
     /// This needs to be kept alive while the download is still in progress.
-    storage_lock: Arc<StorageLock>,
+    storage_lock: Option<Arc<StorageLock>>,
+}
+
+impl AsyncFileDownloadRequest {
+    /// Checks the data received against the expected MD5, if known.
+    fn verify(&self) -> Result<(), Error> {
+        let Some(expected) = self.md5 else {
+            return Ok(());
+        };
+
+        let actual: [u8; 16] = match self.received_md5.clone() {
+            DownloadMd5::Raw(md5) => md5.finalize().into(),
+            DownloadMd5::Rle(md5) => md5.finalize().0,
+        };
+
+        if actual != expected {
+            error!(
+                "download MD5 mismatch: expected {}, got {}",
+                hex::encode(expected),
+                hex::encode(actual)
+            );
+            return Err(Error::ChecksumMismatch);
+        }
+        Ok(())
+    }
+}
+
+/// MD5 of a file's uncompressed contents, computed as it is downloaded.
+#[derive(Clone)]
+enum DownloadMd5 {
+    Raw(md5::Context),
+    Rle(RleMd5),
+}
+
+impl DownloadMd5 {
+    fn new(typ: FileType) -> Self {
+        if typ.uses_rle() {
+            Self::Rle(RleMd5::default())
+        } else {
+            Self::Raw(md5::Context::new())
+        }
+    }
+
+    fn consume(&mut self, data: &[u8]) {
+        match self {
+            Self::Raw(md5) => md5.consume(data),
+            Self::Rle(md5) => md5.consume(data),
+        }
+    }
 }
 
 /// Asynchronous file upload request state.
@@ -94,14 +662,15 @@ struct AsyncFileUploadRequest {
     name: String,
     description: String,
     md5: [u8; 16],
+    bytes_sent: usize,
     chunk_size: u16,
     chunks_remaining: u16,
     responder: Option<oneshot::Sender<Result<(), Error>>>,
     semaphore: Arc<Semaphore>,
-    /// Storage lock.
+    /// Storage lock, if `store_id` needs one.
     ///
     /// This needs to be kept alive while the upload is in progress.
-    storage_lock: Arc<StorageLock>,
+    storage_lock: Option<Arc<StorageLock>>,
 }
 
 #[allow(rustdoc::private_intra_doc_links)]
@@ -130,6 +699,24 @@ pub struct AtemController {
     /// State associated with the connection.
     state: Arc<CowCell<AtemState>>,
     state_rx: Receiver<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    transfer_rx: Receiver<TransferEvent>,
+    /// The [FactoryResetConfirm] token which was last issued.
+    factory_reset_nonce: Mutex<Option<u64>>,
+    /// Counts of atoms received from the switcher, if
+    /// [`ConnectionOptions::record_observed_atoms`] is set.
+    observed_atoms: Option<ObservedAtoms>,
+    /// Round-trip times of clock requests.
+    rtt: Arc<Mutex<RoundTripTime>>,
+    /// Number of commands waiting for an acknowledgement from the switcher.
+    outstanding_commands: Arc<AtomicUsize>,
+    /// Whether the switcher has finished sending its initial state.
+    initialised: Arc<AtomicBool>,
+    /// Holds back state updates during [`Self::set_video_mode()`], if
+    /// [`ConnectionOptions::coalesce_video_mode_change`] is set.
+    video_mode_hold: Option<SharedVideoModeHold>,
+    /// Used to broadcast state updates released from the
+    /// [`video_mode_hold`][Self::video_mode_hold].
+    state_tx: broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
 }
 
 impl AtemController {
@@ -137,14 +724,39 @@ impl AtemController {
     ///
     /// ## Args
     ///
-    /// * `addr`: The UDP socket address to connect to
+    /// * `addr`: The UDP socket address to connect to. This may be an IPv4
+    ///   ([`SocketAddrV4`][std::net::SocketAddrV4]) or IPv6
+    ///   ([`SocketAddrV6`][std::net::SocketAddrV6]) address.
     /// * `reconnect`: If `true`, reconnect after failures.
-    pub async fn connect_udp(addr: SocketAddrV4, reconnect: bool) -> Result<Self, Error> {
+    pub async fn connect_udp(addr: impl Into<SocketAddr>, reconnect: bool) -> Result<Self, Error> {
+        Self::connect_udp_with_options(
+            addr,
+            ConnectionOptions {
+                reconnect,
+                ..Default::default()
+            },
+        )
+        .await
+    }
+
+    /// Connects to an ATEM controller over UDP, with extended
+    /// [ConnectionOptions].
+    pub async fn connect_udp_with_options(
+        addr: impl Into<SocketAddr>,
+        options: ConnectionOptions,
+    ) -> Result<Self, Error> {
         info!("Initialising connection to switcher...");
-        let (mut receiver, cmd_tx) = AtemReceiver::new(addr, reconnect);
+        let (mut receiver, cmd_tx) = AtemReceiver::new(addr.into(), options);
         let initialised_rx = receiver.initialise().await?;
         let state = receiver.state.clone();
         let state_rx = receiver.state_rx.resubscribe();
+        let transfer_rx = receiver.transfer_tx.subscribe();
+        let observed_atoms = receiver.observed_atoms.clone();
+        let rtt = receiver.rtt.clone();
+        let outstanding_commands = receiver.outstanding_commands.clone();
+        let initialised = receiver.initialised.clone();
+        let video_mode_hold = receiver.video_mode_hold.clone();
+        let state_tx = receiver.state_tx.clone();
 
         debug!("Spawning receiver task...");
         let recv_task = tokio::task::spawn(async move { receiver.run().await });
@@ -157,6 +769,14 @@ impl AtemController {
                 cmd_tx,
                 state,
                 state_rx,
+                transfer_rx,
+                factory_reset_nonce: Mutex::new(None),
+                observed_atoms,
+                rtt,
+                outstanding_commands,
+                initialised,
+                video_mode_hold,
+                state_tx,
             };
             return Ok(c);
         }
@@ -175,7 +795,139 @@ impl AtemController {
         }
     }
 
-    /// Sends [Atom]s to the controller, and waits for a response.
+    /// Discovers ATEM switchers on the local network.
+    ///
+    /// This broadcasts a connection request to UDP port
+    /// [9910][AtemUdpChannel::DEFAULT_PORT], and collects the [ProductName]
+    /// of every switcher which responds within `timeout`.
+    ///
+    /// Each switcher which responds is disconnected again before this
+    /// returns. Results are sorted by address, and each switcher is only
+    /// listed once.
+    pub async fn discover(timeout: Duration) -> Result<Vec<(SocketAddrV4, ProductName)>, Error> {
+        Self::discover_at(
+            SocketAddrV4::new(Ipv4Addr::BROADCAST, AtemUdpChannel::DEFAULT_PORT),
+            timeout,
+        )
+        .await
+    }
+
+    /// Discovers ATEM switchers at `target`, which may be a unicast or
+    /// broadcast address.
+    async fn discover_at(
+        target: SocketAddrV4,
+        timeout: Duration,
+    ) -> Result<Vec<(SocketAddrV4, ProductName)>, Error> {
+        let sock = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+        sock.set_broadcast(true)?;
+
+        let initial_session_id = rand::rng().random_range(1..=0x7fff);
+        debug!("Discovering switchers at {target} on session {initial_session_id:#x}...");
+        send_packet_to(
+            &sock,
+            target,
+            &AtemPacket::new_control(
+                AtemPacketFlags::new().with_control(true),
+                initial_session_id,
+                0,
+                HANDSHAKE_PROFILE.connect_client_packet_id(),
+                0,
+                AtemControl::Connect,
+            ),
+        )
+        .await?;
+
+        // Switchers we have a session with, but don't know the name of yet.
+        let mut sessions: BTreeMap<SocketAddrV4, u16> = BTreeMap::new();
+        let mut found: BTreeMap<SocketAddrV4, ProductName> = BTreeMap::new();
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        while let Ok(r) = tokio::time::timeout_at(deadline, sock.recv_from(&mut b)).await {
+            let (l, SocketAddr::V4(from)) = r? else {
+                continue;
+            };
+            let Ok(pkt) = AtemPacket::read(&mut Cursor::new(&b[..l])) else {
+                continue;
+            };
+
+            if let Some(control) = pkt.control() {
+                let AtemControl::ConnectAck { session_id } = control else {
+                    continue;
+                };
+                if pkt.session_id != initial_session_id
+                    || sessions.contains_key(&from)
+                    || found.contains_key(&from)
+                {
+                    // Not for us, or a duplicate response (eg: the switcher
+                    // got our broadcast on multiple interfaces).
+                    continue;
+                }
+
+                let session_id = session_id | 0x8000;
+                debug!("{from} accepted session {session_id:#x}");
+                sessions.insert(from, session_id);
+                send_packet_to(
+                    &sock,
+                    from,
+                    &AtemPacket::new(
+                        AtemPacketFlags::new().with_response(true),
+                        initial_session_id,
+                        pkt.sender_packet_id,
+                        HANDSHAKE_PROFILE.request_state_client_packet_id(),
+                        0,
+                    ),
+                )
+                .await?;
+                continue;
+            }
+
+            let Some(&session_id) = sessions.get(&from) else {
+                continue;
+            };
+            if pkt.session_id != session_id {
+                continue;
+            }
+            if let Some(ack) = HANDSHAKE_PROFILE.make_ack(&pkt) {
+                send_packet_to(&sock, from, &ack).await?;
+            }
+
+            let Some(name) = pkt.atoms().into_iter().flatten().find_map(|atom| {
+                if let Payload::ProductName(name) = &atom.payload {
+                    Some(name.clone())
+                } else {
+                    None
+                }
+            }) else {
+                continue;
+            };
+
+            info!("found {name:?} at {from}");
+            sessions.remove(&from);
+            found.insert(from, name);
+            send_packet_to(&sock, from, &disconnect_packet(session_id)).await?;
+        }
+
+        // Don't leave sessions open on switchers which never told us their
+        // name.
+        for (addr, session_id) in sessions {
+            warn!("{addr} accepted session {session_id:#x}, but didn't report a product name");
+            send_packet_to(&sock, addr, &disconnect_packet(session_id)).await?;
+        }
+
+        Ok(found.into_iter().collect())
+    }
+
+    /// Sends [Atom]s to the controller, and waits for the switcher to
+    /// acknowledge them.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ChannelUnavailable`] if the command could not be queued
+    /// * [`Error::Timeout`] if the switcher didn't acknowledge the command
+    ///   after all retransmission attempts
+    /// * [`Error::NotAcknowledged`] if the command was queued, but abandoned
+    ///   before the switcher acknowledged it (eg: on reconnection)
+    #[must_use = "the switcher may not have acknowledged the command"]
     async fn send(&self, cmds: Vec<Atom>) -> Result<(), Error> {
         // https://tokio.rs/tokio/tutorial/channels#receive-responses
         let (responder, resp_rx) = oneshot::channel();
@@ -184,40 +936,150 @@ impl AtemController {
             responder: Some(responder),
         })
         .await?;
-        resp_rx.await.map_err(|_| Error::Timeout)?
-    }
-
-    /// Sends [Atom]s to the controller with extended options, and waits
-    /// for a response.
-    async fn send_ex(&self, async_cmd: AsyncCommand) -> Result<(), Error> {
-        self.cmd_tx
-            .send(async_cmd)
-            .await
-            .map_err(|_| Error::ChannelUnavailable)
+        resp_rx.await.map_err(|_| Error::NotAcknowledged)?
     }
 
-    /// Start an image frame download.
+    /// Sends a raw command to the switcher, and waits for the switcher to
+    /// acknowledge it.
     ///
-    /// **WARNING:** this is unreliable when high logging levels are
-    /// enabled, and may return corrupted data.
+    /// This is intended for experimenting with commands which `necromancer`
+    /// doesn't implement yet. `payload` is sent as-is, without any validation
+    /// or padding.
     ///
     /// ## Errors
     ///
-    /// * [`Error::ParameterOutOfRange`] when `index` is not a valid frame ID
+    /// * [`Error::ParameterOutOfRange`] if `payload` is longer than
+    ///   [`Atom::MAX_PAYLOAD_LENGTH`]
+    /// * any error from sending the command
+    #[must_use = "the switcher may not have acknowledged the command"]
+    pub async fn send_raw(&self, magic: [u8; 4], payload: Vec<u8>) -> Result<(), Error> {
+        if payload.len() > usize::from(Atom::MAX_PAYLOAD_LENGTH) {
+            error!(
+                "raw {} payload is too long: {} bytes",
+                magic.escape_ascii(),
+                payload.len()
+            );
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        self.send(vec![Atom::new(Payload::Unknown(magic, payload))])
+            .await
+    }
+
+    /// Sends [Atom]s to the switcher, waits for them to be acknowledged, and
+    /// then waits for the switcher's state to reflect the change.
+    ///
+    /// Some commands are acknowledged by the switcher, but then silently
+    /// ignored (eg: selecting a source which is unavailable on an ME).
+    ///
+    /// `confirmed` is checked against the switcher's state after the commands
+    /// are acknowledged, and then after every state update, until it returns
+    /// `true`. If the state already matched the requested change, this
+    /// returns immediately.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::CommandIgnored`] if `confirmed` didn't return `true` within
+    ///   `timeout` of the commands being acknowledged
+    /// * any error from sending the commands
+    #[must_use = "the switcher may not have acknowledged the command"]
+    pub async fn set_and_confirm(
+        &self,
+        cmds: Vec<Atom>,
+        timeout: Duration,
+        confirmed: impl Fn(&AtemState) -> bool,
+    ) -> Result<(), Error> {
+        // Subscribe before sending, so we don't miss the echo.
+        let mut state_rx = self.state_update_events();
+        self.send(cmds).await?;
+
+        if confirmed(&*self.get_state().await) {
+            return Ok(());
+        }
+
+        tokio::time::timeout(
+            timeout,
+            wait_for_state(&mut state_rx, |state, _| confirmed(state)),
+        )
+        .await
+        .map_err(|_| Error::CommandIgnored)?
+    }
+
+    /// Sends [Atom]s to the controller with extended options, and waits
+    /// for a response.
+    #[must_use = "the command may not have been queued"]
+    async fn send_ex(&self, async_cmd: AsyncCommand) -> Result<(), Error> {
+        self.cmd_tx
+            .send(async_cmd)
+            .await
+            .map_err(|_| Error::ChannelUnavailable)
+    }
+
+    /// Gets a [StorageLock] for `store_id`, and waits for the switcher to
+    /// grant it.
+    ///
+    /// If this client already holds the lock, this returns the existing lock.
+    async fn lock_storage(&self, store_id: u16) -> Result<Arc<StorageLock>, Error> {
+        let (responder, resp_rx) = oneshot::channel();
+        self.send_ex(AsyncCommand::StorageLock {
+            store_id,
+            responder,
+        })
+        .await?;
+        debug!("waiting for storage lock availability");
+        let storage_lock = resp_rx.await.map_err(|_| Error::ChannelUnavailable)??;
+        storage_lock.await_availability().await?;
+        debug!("have storage lock");
+        Ok(storage_lock)
+    }
+
+    /// Gets a [StorageLock] for `store_id`, if the store needs one.
+    ///
+    /// Multiview labels aren't part of the media pool, and can be transferred
+    /// without a lock.
+    async fn lock_storage_if_needed(
+        &self,
+        store_id: u16,
+    ) -> Result<Option<Arc<StorageLock>>, Error> {
+        if store_id == MULTIVIEW_LABEL_STORE_ID {
+            return Ok(None);
+        }
+        Ok(Some(self.lock_storage(store_id).await?))
+    }
+
+    /// Start an image frame download.
+    ///
+    /// **WARNING:** this is unreliable when high logging levels are
+    /// enabled, and may return corrupted data.
+    ///
+    /// If you already have a copy of the frame, compare its MD5 with
+    /// [`AtemState::still_md5()`] first – if they match, there is no need to
+    /// download it again.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `index` is not a valid frame ID
     /// * [`Error::NotFound`] when `index` is a valid slot, but does not contain
     ///   any data
+    /// * [`Error::ChecksumMismatch`] (as the last item) when the downloaded
+    ///   frame doesn't match [its MD5][FileDownload::expected_md5]
+    ///
+    /// ## Cancellation
+    ///
+    /// [Closing][mpsc::Receiver::close] or dropping the returned receiver
+    /// cancels the download: any further data from the switcher is discarded,
+    /// and the storage lock is released.
     pub async fn start_file_download(
         &self,
         store_id: u16,
         index: u8,
-    ) -> Result<mpsc::Receiver<Result<Vec<u8>, Error>>, Error> {
-        warn!("File transfers are not reliable - DATA MAY BE CORRUPTED, especially when 'trace' logging is enabled!");
+    ) -> Result<FileDownload, Error> {
         if store_id != 0 {
             error!("unsupported store ID: {store_id:#04x}");
             return Err(Error::UnknownParameter);
         }
 
-        {
+        let md5 = {
             let state = self.get_state().await;
             if index >= state.media_player_capabilities.still_count {
                 error!("unknown frame ID: {index:#04x}");
@@ -234,19 +1096,26 @@ impl AtemController {
                 return Err(Error::NotFound);
             }
 
-            // TODO: get MD5 and check it
-        }
+            frame_info.md5
+        };
 
-        let (responder, resp_rx) = oneshot::channel();
-        self.send_ex(AsyncCommand::StorageLock {
-            store_id,
-            responder,
-        })
-        .await?;
-        debug!("waiting for storage lock availability");
-        let storage_lock = resp_rx.await.map_err(|_| Error::ChannelUnavailable)??;
-        storage_lock.await_availability().await?;
-        debug!("have storage lock");
+        self.start_typed_file_download(store_id, index, FileType::StillFrame, Some(md5))
+            .await
+    }
+
+    /// Starts a download of any [FileType] from `store_id`, without checking
+    /// whether `index` contains any data.
+    ///
+    /// If `md5` is set, the download is checked against it when finished.
+    async fn start_typed_file_download(
+        &self,
+        store_id: u16,
+        index: u8,
+        typ: FileType,
+        md5: Option<[u8; 16]>,
+    ) -> Result<FileDownload, Error> {
+        warn!("File transfers are not reliable - DATA MAY BE CORRUPTED, especially when 'trace' logging is enabled!");
+        let storage_lock = self.lock_storage_if_needed(store_id).await?;
 
         let (tx, rx) = mpsc::channel(128);
         let tx = Arc::new(tx);
@@ -254,17 +1123,82 @@ impl AtemController {
             store_id,
             index,
             tx,
-            typ: FileType::StillFrame,
+            typ,
             bytes_received: 0,
             bytes_since_last_ack: 0,
+            md5,
+            received_md5: DownloadMd5::new(typ),
             storage_lock,
         };
         self.send_ex(AsyncCommand::FileDownload(req)).await?;
 
-        Ok(rx)
+        Ok(FileDownload { md5, rx })
+    }
+
+    /// Downloads a still image frame, and waits for the transfer to finish.
+    ///
+    /// See [`AtemController::start_file_download()`] for possible errors.
+    async fn download_still(&self, index: u8) -> Result<Vec<u8>, Error> {
+        let mut rx = self.start_file_download(0, index).await?;
+        let mut o = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            o.extend_from_slice(&chunk?);
+        }
+
+        Ok(o)
+    }
+
+    /// Downloads all still image frames which contain valid data, one at a
+    /// time.
+    ///
+    /// Yields `(index, data)` for each frame, in index order. A failed
+    /// download does not stop the stream.
+    ///
+    /// The media pool is locked once for the whole stream, and unlocked when
+    /// the stream is finished or dropped.
+    ///
+    /// **WARNING:** this has the same reliability issues as
+    /// [`AtemController::start_file_download()`].
+    pub fn download_all_stills(&self) -> impl Stream<Item = (u8, Result<Vec<u8>, Error>)> + '_ {
+        let initial: (Option<VecDeque<u8>>, Option<Arc<StorageLock>>) = (None, None);
+        futures::stream::unfold(initial, move |(indexes, storage_lock)| async move {
+            let mut indexes = match indexes {
+                Some(indexes) => indexes,
+                None => {
+                    let state = self.get_state().await;
+                    let still_count = state.media_player_capabilities.still_count;
+                    let mut indexes: Vec<u8> = state
+                        .media_player_frame_descriptions
+                        .iter()
+                        .filter(|(index, frame_info)| **index < still_count && frame_info.is_valid)
+                        .map(|(index, _)| *index)
+                        .collect();
+                    indexes.sort_unstable();
+                    indexes.into()
+                }
+            };
+
+            let index = indexes.pop_front()?;
+            let storage_lock = match storage_lock {
+                Some(storage_lock) => storage_lock,
+                None => match self.lock_storage(0).await {
+                    Ok(storage_lock) => storage_lock,
+                    Err(e) => {
+                        // Give up on everything else
+                        return Some(((index, Err(e)), (Some(VecDeque::new()), None)));
+                    }
+                },
+            };
+
+            let r = self.download_still(index).await;
+            Some(((index, r), (Some(indexes), Some(storage_lock))))
+        })
     }
 
     /// Upload [an RLE-compressed image][crate::protocol::rle] to the switcher.
+    ///
+    /// [`rle_compress_rgba()`][crate::protocol::rle::rle_compress_rgba] can
+    /// produce `buffer` from an RGBA image.
     pub async fn upload_still_image(
         &self,
         index: u8,
@@ -272,8 +1206,6 @@ impl AtemController {
         description: String,
         buffer: VecDeque<u64>,
     ) -> Result<(), Error> {
-        warn!("File transfers are unreliable when 'trace' logging is enabled!");
-
         let frame_size = {
             let state = self.get_state().await;
             if index >= state.media_player_capabilities.still_count {
@@ -289,35 +1221,155 @@ impl AtemController {
             state.video_mode.pixels() * 4
         };
 
-        let (md5, size) = rle_md5_size(buffer.iter().copied());
-        let size = size.try_into().map_err(|_| Error::InvalidLength)?;
-        if size != frame_size {
-            error!("incorrect frame size: expected {frame_size}, got {size}");
+        self.upload_file(
+            0,
+            index,
+            FileType::StillFrame,
+            frame_size,
+            name,
+            description,
+            buffer,
+        )
+        .await
+    }
+
+    /// Uploads a client-rendered MultiView label for input `index`.
+    ///
+    /// `rgba` is `width * height` pixels of 32-bit RGBA, which is
+    /// [RLE-compressed][crate::protocol::rle] before sending it to the
+    /// switcher.
+    ///
+    /// RLE compression works on 64-bit words (2 pixels), so if the label has
+    /// an odd number of pixels, it is padded with a transparent pixel.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher has no MultiView
+    /// * [`Error::InvalidLength`] when `rgba` is not `width * height` pixels
+    pub async fn upload_multiview_label(
+        &self,
+        index: u8,
+        rgba: &[u8],
+        width: u32,
+        height: u32,
+    ) -> Result<(), Error> {
+        if !self.supports(Capability::Multiview).await {
+            error!("switcher does not support MultiView");
+            return Err(Error::FeatureUnavailable);
+        }
+
+        let size = width
+            .checked_mul(height)
+            .and_then(|pixels| pixels.checked_mul(4))
+            .ok_or(Error::InvalidLength)?;
+        if usize::try_from(size).ok() != Some(rgba.len()) {
+            error!(
+                "incorrect label size: expected {width}x{height} pixels, got {} bytes",
+                rgba.len()
+            );
+            return Err(Error::InvalidLength);
+        }
+
+        let size = size.next_multiple_of(8);
+        let buffer = RleCompressor::new(rgba.chunks(8).map(|c| {
+            let mut word = [0; 8];
+            word[..c.len()].copy_from_slice(c);
+            u64::from_be_bytes(word)
+        }))
+        .collect();
+        self.upload_file(
+            MULTIVIEW_LABEL_STORE_ID,
+            index,
+            FileType::MultiViewLabel,
+            size,
+            String::new(),
+            String::new(),
+            buffer,
+        )
+        .await
+    }
+
+    /// Downloads the MultiView label for input `index`, as uploaded with
+    /// [`AtemController::upload_multiview_label()`].
+    ///
+    /// **WARNING:** this has the same reliability issues as
+    /// [`AtemController::start_file_download()`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher has no MultiView
+    pub async fn download_multiview_label(&self, index: u8) -> Result<Vec<u8>, Error> {
+        if !self.supports(Capability::Multiview).await {
+            error!("switcher does not support MultiView");
+            return Err(Error::FeatureUnavailable);
+        }
+
+        let mut rx = self
+            .start_typed_file_download(
+                MULTIVIEW_LABEL_STORE_ID,
+                index,
+                FileType::MultiViewLabel,
+                None,
+            )
+            .await?;
+        let mut o = Vec::new();
+        while let Some(chunk) = rx.recv().await {
+            o.extend_from_slice(&chunk?);
+        }
+
+        Ok(o)
+    }
+
+    /// Uploads a file of any [FileType] to `store_id`, and waits for the
+    /// transfer to finish.
+    ///
+    /// `size` is the expected uncompressed size of the file, in bytes. If `typ`
+    /// [uses RLE][FileType::uses_rle], `buffer` must be
+    /// [RLE-compressed][crate::protocol::rle].
+    #[allow(clippy::too_many_arguments)]
+    async fn upload_file(
+        &self,
+        store_id: u16,
+        index: u8,
+        typ: FileType,
+        size: u32,
+        name: String,
+        description: String,
+        buffer: VecDeque<u64>,
+    ) -> Result<(), Error> {
+        warn!("File transfers are unreliable when 'trace' logging is enabled!");
+
+        let is_rle = typ.uses_rle();
+
+        let (md5, actual_size) = if is_rle {
+            rle_md5_size(buffer.iter().copied())
+        } else {
+            let mut md5 = md5::Context::new();
+            for w in &buffer {
+                md5.consume(w.to_be_bytes());
+            }
+            (md5.finalize().into(), buffer.len() as u64 * 8)
+        };
+        let actual_size: u32 = actual_size.try_into().map_err(|_| Error::InvalidLength)?;
+        if actual_size != size {
+            error!("incorrect file size: expected {size}, got {actual_size}");
             return Err(Error::InvalidLength);
         }
 
-        let (responder, resp_rx) = oneshot::channel();
-        self.send_ex(AsyncCommand::StorageLock {
-            store_id: 0,
-            responder,
-        })
-        .await?;
-        debug!("waiting for storage lock availability");
-        let storage_lock = resp_rx.await.map_err(|_| Error::ChannelUnavailable)??;
-        storage_lock.await_availability().await?;
-        debug!("have storage lock");
+        let storage_lock = self.lock_storage_if_needed(store_id).await?;
 
         let (responder, resp_rx) = oneshot::channel();
         let req = AsyncFileUploadRequest {
-            store_id: 0,
+            store_id,
             index,
             buffer,
-            typ: FileType::StillFrame,
-            is_rle: true,
+            typ,
+            is_rle,
             size,
             name,
             description,
             md5,
+            bytes_sent: 0,
             chunk_size: 0,
             chunks_remaining: 0,
             responder: Some(responder),
@@ -329,1626 +1381,7344 @@ impl AtemController {
     }
 
     /// Sets the current program input for a given media encoder.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME, or
+    ///   `video_source` can't be used on `me` (see
+    ///   [`AtemState::routable_sources()`])
     pub async fn set_program_input(&self, me: u8, video_source: VideoSource) -> Result<(), Error> {
+        self.validate_me_source(me, video_source).await?;
         let cmd = Atom::new(SetProgramInput { me, video_source });
         self.send(vec![cmd]).await
     }
 
-    /// Sets the current program input for a given media encoder.
+    /// Sets the current program input for a given media encoder, and waits for
+    /// the switcher to report the change.
+    ///
+    /// See [`AtemController::set_and_confirm()`] for details.
+    pub async fn set_program_input_and_confirm(
+        &self,
+        me: u8,
+        video_source: VideoSource,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        self.validate_me_source(me, video_source).await?;
+        let cmd = Atom::new(SetProgramInput { me, video_source });
+        self.set_and_confirm(vec![cmd], timeout, |state| {
+            state.get_program_source(me) == Some(video_source)
+        })
+        .await
+    }
+
+    /// Sets the current preview input for a given media encoder.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME, or
+    ///   `video_source` can't be used on `me` (see
+    ///   [`AtemState::routable_sources()`])
     pub async fn set_preview_input(&self, me: u8, video_source: VideoSource) -> Result<(), Error> {
+        self.validate_me_source(me, video_source).await?;
         let cmd = Atom::new(SetPreviewInput { me, video_source });
         self.send(vec![cmd]).await
     }
 
-    /// Swaps the current preview and program inputs for a given media encoder
-    /// immediately with no transition.
-    pub async fn cut(&self, me: u8) -> Result<(), Error> {
-        let cmd = Atom::new(Cut { me });
-        self.send(vec![cmd]).await
-    }
+    /// Routes `video_source` to an auxiliary output.
+    ///
+    /// The current source of each output can be read from
+    /// [`AtemState::get_aux_source()`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `aux` is not a valid auxiliary
+    ///   output, or `video_source` can't be routed to an auxiliary output
+    pub async fn set_aux_source(&self, aux: u8, video_source: VideoSource) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if aux >= state.topology.auxs {
+                error!(
+                    "aux #{aux} does not exist, switcher has {} aux output(s)",
+                    state.topology.auxs
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
 
-    /// Swaps the current preview and program inputs for a given media encoder
-    /// with the currently-selected transition.
-    pub async fn auto(&self, me: u8) -> Result<(), Error> {
-        let cmd = Atom::new(Auto { me });
-        self.send(vec![cmd]).await
-    }
+            if !state.validate_source(video_source, SourceBus::Auxiliary) {
+                error!("{video_source:?} can't be routed to an aux output");
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
 
-    pub async fn cut_black(&self, me: u8, black: bool) -> Result<(), Error> {
-        let cmd = Atom::new(CutToBlack { me, black });
+        let cmd = Atom::new(SetAuxSource { aux, video_source });
         self.send(vec![cmd]).await
     }
 
-    pub async fn toggle_auto_black(&self, me: u8) -> Result<(), Error> {
-        let cmd = Atom::new(FadeToBlackAuto { me });
-        self.send(vec![cmd]).await
+    /// Routes an external input to an auxiliary output, by its 1-indexed
+    /// external input number (eg: `3` is [VideoSource::Input3]).
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `aux` is not a valid auxiliary
+    ///   output, or input `n` does not exist or can't be routed to an
+    ///   auxiliary output
+    pub async fn set_aux_source_number(&self, aux: u8, n: u8) -> Result<(), Error> {
+        self.set_aux_source(aux, input_number_to_source(n)?).await
     }
 
-    /// Captures the primary program output as a still image.
-    pub async fn capture(&self) -> Result<(), Error> {
-        let state = self.get_state().await;
-        if !state.media_player_capabilities.supports_still_capture {
-            error!("switcher does not support still image capture");
-            return Err(Error::FeatureUnavailable);
+    /// Sets the position, size, crop and source of a box on the first
+    /// SuperSource.
+    ///
+    /// The current parameters of each box can be read from
+    /// [`AtemState::get_super_source_box()`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not have a
+    ///   SuperSource
+    /// * [`Error::ParameterOutOfRange`] when `box_id` is not a valid box, the
+    ///   source can't be used in a SuperSource box, or any parameter is out of
+    ///   the range described in [SuperSourceBoxParams]
+    pub async fn set_supersource_box(
+        &self,
+        box_id: u8,
+        params: SuperSourceBoxParams,
+    ) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if !state.supports(Capability::SuperSource) {
+                error!("switcher does not have a SuperSource");
+                return Err(Error::FeatureUnavailable);
+            }
+            if box_id >= crate::protocol::atom::SUPER_SOURCE_BOXES {
+                error!("SuperSource box #{box_id} does not exist");
+                return Err(Error::ParameterOutOfRange);
+            }
+            if !state.validate_source(params.video_source, SourceBus::SuperSourceBox) {
+                error!(
+                    "{:?} can't be used in a SuperSource box",
+                    params.video_source
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+            if !params.is_in_range() {
+                error!(?params, "SuperSource box parameters out of range");
+                return Err(Error::ParameterOutOfRange);
+            }
         }
-        let cmd = Atom::new(CAPTURE_STILL);
+
+        let cmd = Atom::new(SetSuperSourceBoxProperties::from_params(0, box_id, &params));
         self.send(vec![cmd]).await
     }
 
-    /// Change a media player's source.
-    pub async fn set_media_player_source(
-        &self,
-        media_player: u8,
-        source: MediaPlayerSourceID,
-    ) -> Result<(), Error> {
-        let state = self.get_state().await;
-        if media_player >= state.topology.media_players {
-            error!(
-                "media player #{media_player} does not exist, switcher has {} media player(s)",
-                state.topology.media_players
-            );
-            return Err(Error::ParameterOutOfRange);
-        }
+    /// Starts playback on a HyperDeck.
+    ///
+    /// The current transport mode of each HyperDeck can be read from
+    /// [`AtemState::get_hyperdeck_transport_state()`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `id` is not a valid HyperDeck
+    pub async fn hyperdeck_play(&self, id: u8) -> Result<(), Error> {
+        self.set_hyperdeck_transport(id, HyperDeckTransportState::Play)
+            .await
+    }
 
-        match source {
-            MediaPlayerSourceID::Still(index) => {
-                if state.media_player_capabilities.still_count == 0 {
-                    error!("media player does not support still images");
-                    return Err(Error::FeatureUnavailable);
-                }
+    /// Stops playback or recording on a HyperDeck.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `id` is not a valid HyperDeck
+    pub async fn hyperdeck_stop(&self, id: u8) -> Result<(), Error> {
+        self.set_hyperdeck_transport(id, HyperDeckTransportState::Idle)
+            .await
+    }
 
-                if index >= state.media_player_capabilities.still_count {
-                    error!(
-                        "still #{index} does not exist, switcher supports {} still(s)",
-                        state.media_player_capabilities.still_count
-                    );
-                    return Err(Error::ParameterOutOfRange);
-                }
+    /// Starts recording on a HyperDeck.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `id` is not a valid HyperDeck
+    pub async fn hyperdeck_record(&self, id: u8) -> Result<(), Error> {
+        self.set_hyperdeck_transport(id, HyperDeckTransportState::Record)
+            .await
+    }
 
-                if !state
-                    .media_player_frame_descriptions
-                    .get(&index)
-                    .is_some_and(|mpfd| mpfd.is_valid)
-                {
-                    error!("still #{index} does not contain a valid frame");
-                    return Err(Error::NotFound);
-                }
+    /// Sets the transport mode of a HyperDeck.
+    async fn set_hyperdeck_transport(
+        &self,
+        id: u8,
+        transport_state: HyperDeckTransportState,
+    ) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if id >= state.topology.hyperdecks {
+                error!(
+                    "HyperDeck #{id} does not exist, switcher has {} HyperDeck(s)",
+                    state.topology.hyperdecks
+                );
+                return Err(Error::ParameterOutOfRange);
             }
+        }
 
-            MediaPlayerSourceID::VideoClip(index) => {
-                if state.media_player_capabilities.clip_count == 0 {
-                    error!("media player does not support video clips");
-                    return Err(Error::FeatureUnavailable);
-                }
+        let cmd = Atom::new(SetHyperDeckClipPlayStatus::transport(
+            id.into(),
+            transport_state,
+        ));
+        self.send(vec![cmd]).await
+    }
 
-                if index >= state.media_player_capabilities.clip_count {
-                    error!(
-                        "clip #{index} does not exist, switcher supports {} clip(s)",
-                        state.media_player_capabilities.clip_count
-                    );
-                    return Err(Error::ParameterOutOfRange);
-                }
-            }
+    /// Sets the current program input for a given media encoder, by its
+    /// 1-indexed external input number (eg: `3` is [VideoSource::Input3]).
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME, or input
+    ///   `n` does not exist or can't be used on `me`
+    pub async fn set_program_input_number(&self, me: u8, n: u8) -> Result<(), Error> {
+        let video_source = input_number_to_source(n)?;
+        self.set_program_input(me, video_source).await
+    }
+
+    /// Sets the current preview input for a given media encoder, by its
+    /// 1-indexed external input number (eg: `3` is [VideoSource::Input3]).
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME, or input
+    ///   `n` does not exist or can't be used on `me`
+    pub async fn set_preview_input_number(&self, me: u8, n: u8) -> Result<(), Error> {
+        let video_source = input_number_to_source(n)?;
+        self.set_preview_input(me, video_source).await
+    }
+
+    /// Renames an input.
+    ///
+    /// The switcher reports the new names in
+    /// [`InputProperties`][crate::protocol::atom::InputProperties], which
+    /// updates [`AtemState::input_properties`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `source` does not exist, `long` is
+    ///   longer than 20 bytes, or `short` is longer than 4 bytes
+    pub async fn set_input_label(
+        &self,
+        source: VideoSource,
+        long: String,
+        short: String,
+    ) -> Result<(), Error> {
+        if !self
+            .get_state()
+            .await
+            .input_properties
+            .contains_key(&source)
+        {
+            error!("{source:?} does not exist");
+            return Err(Error::ParameterOutOfRange);
         }
-        drop(state);
 
-        let cmd = Atom::new(SetMediaPlayerSource {
-            enable: true,
-            id: media_player,
-            source,
+        if long.len() > SetInputProperties::MAX_LONG_NAME_LENGTH {
+            error!("input long name is too long: {} bytes", long.len());
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        if short.len() > SetInputProperties::MAX_SHORT_NAME_LENGTH {
+            error!("input short name is too long: {} bytes", short.len());
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        let cmd = Atom::new(SetInputProperties {
+            long_name: Some(long),
+            short_name: Some(short),
+            ..SetInputProperties::new(source)
         });
         self.send(vec![cmd]).await
     }
 
-    /// Delete all items in the media pool.
-    pub async fn clear_media_pool(&self) -> Result<(), Error> {
-        let cmd = Atom::new(CLEAR_MEDIA_POOL);
+    /// Checks that `video_source` can be used as a program or preview source
+    /// on `me`.
+    async fn validate_me_source(&self, me: u8, video_source: VideoSource) -> Result<(), Error> {
+        let state = self.get_state().await;
+        if me >= state.topology.mes {
+            error!(
+                "ME #{me} does not exist, switcher has {} ME(s)",
+                state.topology.mes
+            );
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        if !state.validate_source(video_source, SourceBus::Me(me)) {
+            error!("{video_source:?} is not available on ME #{me}");
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        Ok(())
+    }
+
+    /// Swaps the current preview and program inputs for a given media encoder
+    /// immediately with no transition.
+    pub async fn cut(&self, me: u8) -> Result<(), Error> {
+        let cmd = Atom::new(Cut { me });
         self.send(vec![cmd]).await
     }
 
-    pub async fn rtmp_stream_duration(&self) -> Result<(), Error> {
-        let cmd = Atom::new(RTMP_DURATION_REQUEST);
+    /// Swaps the current preview and program inputs for a given media encoder
+    /// with the currently-selected transition.
+    ///
+    /// The switcher transitions every downstream keyer which is
+    /// [tied][AtemState::get_dsk_tie] to the ME as part of the ME's
+    /// transition, so there is no need to send a
+    /// [`DownstreamKeyerAuto`][crate::protocol::atom::DownstreamKeyerAuto]
+    /// for them (which would transition them a second time).
+    pub async fn auto(&self, me: u8) -> Result<(), Error> {
+        let cmd = Atom::new(Auto { me });
         self.send(vec![cmd]).await
     }
 
-    /// Saves the current settings to the start-up configuration.
-    pub async fn save_startup_settings(&self) -> Result<(), Error> {
-        let cmd = Atom::new(SAVE_STARTUP_SETTINGS);
+    /// Sets the style of the next transition on `me`.
+    ///
+    /// The current style and next transition selection can be read from
+    /// [`AtemState::transition_settings`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME
+    /// * [`Error::FeatureUnavailable`] when `style` is not one of
+    ///   [`AtemState::supported_transition_styles()`] (eg:
+    ///   [`TransitionStyle::DVE`] on a switcher without DVE)
+    pub async fn set_transition_style(&self, me: u8, style: TransitionStyle) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if me >= state.topology.mes {
+                error!(
+                    "ME #{me} does not exist, switcher has {} ME(s)",
+                    state.topology.mes
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+
+            if !state.supported_transition_styles(me).contains(&style) {
+                error!("ME #{me} does not support {style:?} transitions");
+                return Err(Error::FeatureUnavailable);
+            }
+        }
+
+        let cmd = Atom::new(SetTransitionSettings {
+            me,
+            style: Some(style),
+            selection: None,
+        });
         self.send(vec![cmd]).await
     }
 
-    /// Clears the start-up configuration.
-    pub async fn clear_startup_settings(&self) -> Result<(), Error> {
-        let cmd = Atom::new(CLEAR_STARTUP_SETTINGS);
+    /// Enables or disables transition preview ("PREV TRANS") on `me`.
+    ///
+    /// The current state can be read from
+    /// [`AtemState::transition_preview`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME
+    pub async fn set_transition_preview(&self, me: u8, enabled: bool) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if me >= state.topology.mes {
+                error!(
+                    "ME #{me} does not exist, switcher has {} ME(s)",
+                    state.topology.mes
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let cmd = Atom::new(SetTransitionPreview { me, enabled });
         self.send(vec![cmd]).await
     }
 
-    /// Restores settings from the start-up configuration
+    /// Sets the parameters of the wipe transition on `me`.
     ///
-    /// **Warning:** this method is never used by the SDK
-    pub async fn restore_startup_settings(&self) -> Result<(), Error> {
-        let cmd = Atom::new(RESTORE_STARTUP_SETTINGS);
+    /// The current parameters can be read from
+    /// [`AtemState::transition_wipe_params`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME, the
+    ///   border source can't be used on `me`, or any parameter is out of the
+    ///   range described in [WipeTransitionParams]
+    pub async fn set_wipe_params(&self, me: u8, params: WipeTransitionParams) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if me >= state.topology.mes {
+                error!(
+                    "ME #{me} does not exist, switcher has {} ME(s)",
+                    state.topology.mes
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+            if !state.validate_source(params.border_input, SourceBus::Me(me)) {
+                error!(
+                    "{:?} can't be used as a wipe border on ME #{me}",
+                    params.border_input
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+            if !params.is_valid() {
+                error!(?params, "wipe transition parameters out of range");
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let cmd = Atom::new(SetTransitionWipeProperties::from_params(me, &params));
         self.send(vec![cmd]).await
     }
 
-    /// Sets colour generator parameters.
-    pub async fn set_colour_generator_params(
-        &self,
-        params: SetColourGeneratorParams,
-    ) -> Result<(), Error> {
-        let cmd = Atom::new(params);
+    /// Sets the parameters of the DVE transition on `me`.
+    ///
+    /// The current parameters can be read from
+    /// [`AtemState::transition_dve_params`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support DVE
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME, the
+    ///   switcher does not support `params.style`, the key is enabled and the
+    ///   fill or key source can't be used as a key source, or any parameter is
+    ///   out of the range described in [DVETransitionParams]
+    pub async fn set_dve_params(&self, me: u8, params: DVETransitionParams) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if me >= state.topology.mes {
+                error!(
+                    "ME #{me} does not exist, switcher has {} ME(s)",
+                    state.topology.mes
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+            if !state.supports(Capability::Dve) {
+                error!("switcher does not support DVE");
+                return Err(Error::FeatureUnavailable);
+            }
+            if !state
+                .dve_supported_transition_styles
+                .contains(&params.style)
+            {
+                error!(
+                    "switcher does not support DVE transition {:?}",
+                    params.style
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+            if params.key_enabled {
+                for source in [params.fill_source, params.key_source] {
+                    if !state.validate_source(source, SourceBus::KeySource) {
+                        error!("{source:?} can't be used as a key source");
+                        return Err(Error::ParameterOutOfRange);
+                    }
+                }
+            }
+            if !params.is_valid() {
+                error!(?params, "DVE transition parameters out of range");
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let cmd = Atom::new(SetTransitionDVEProperties::from_params(me, &params));
         self.send(vec![cmd]).await
     }
 
-    pub async fn get_state(&self) -> impl std::ops::Deref<Target = AtemState> {
-        self.state.read().await
+    /// Waits for the transition on a given media encoder to complete.
+    ///
+    /// This resolves as soon as the switcher reports (with
+    /// [`TransitionPosition`][crate::protocol::atom::TransitionPosition]) that
+    /// the transition on `me` is no longer in progress. If there is no
+    /// transition in progress when this is called, it returns immediately.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME
+    /// * [`Error::Timeout`] when the transition didn't complete within
+    ///   `timeout`
+    pub async fn await_transition_complete(&self, me: u8, timeout: Duration) -> Result<(), Error> {
+        // Subscribe before checking the current state, so we don't miss an
+        // update in between.
+        let mut state_rx = self.state_update_events();
+        {
+            let state = self.get_state().await;
+            if me >= state.topology.mes {
+                error!(
+                    "ME #{me} does not exist, switcher has {} ME(s)",
+                    state.topology.mes
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+
+            if !state
+                .transition_position
+                .get(&me)
+                .is_some_and(|pos| pos.in_progress)
+            {
+                return Ok(());
+            }
+        }
+
+        tokio::time::timeout(timeout, wait_for_transition_complete(&mut state_rx, me))
+            .await
+            .map_err(|_| Error::Timeout)?
     }
 
-    pub fn state_update_events(&self) -> Receiver<(CowCellReadTxn<AtemState>, StateUpdate)> {
-        self.state_rx.resubscribe()
+    /// Waits for the switcher's state to match `predicate`.
+    ///
+    /// `predicate` is checked against the current state, and then after every
+    /// state update, until it returns `true`. If the current state already
+    /// matches, this returns immediately.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::Timeout`] when `predicate` didn't return `true` within
+    ///   `timeout`
+    /// * [`Error::ChannelUnavailable`] when the connection to the switcher was
+    ///   closed
+    pub async fn await_state<F: Fn(&AtemState) -> bool>(
+        &self,
+        predicate: F,
+        timeout: Duration,
+    ) -> Result<(), Error> {
+        // Subscribe before checking the current state, so we don't miss an
+        // update in between.
+        let mut state_rx = self.state_update_events();
+        if predicate(&*self.get_state().await) {
+            return Ok(());
+        }
+
+        tokio::time::timeout(
+            timeout,
+            wait_for_state(&mut state_rx, |state, _| predicate(state)),
+        )
+        .await
+        .map_err(|_| Error::Timeout)?
     }
-}
 
-/// A packet to retry sending if there is no response from the switcher.
-struct PacketWaitingForResponse {
-    /// The packet which will be re-sent after a timeout.
-    pkt: AtemPacket,
-    /// Channel which was awaited for a response.
-    responder: Option<oneshot::Sender<Result<(), Error>>>,
-    /// Number of remaining retries for the packet.
-    retry_limit: u8,
-    /// Time when a retry was last attempted. The packet will be retried after
-    /// [AtemReceiver::RETRANSMIT_TIME] has passed.
-    last_attempt: Instant,
-}
+    /// How long [`set_video_mode()`][Self::set_video_mode] waits for the
+    /// switcher to report the new video mode.
+    const VIDEO_MODE_CHANGE_TIMEOUT: Duration = Duration::from_secs(5);
 
-/// Coordinates the connection with the device.
-///
-/// This runs its own event loop ([`AtemReceiver::run()`]).
-struct AtemReceiver {
-    addr: SocketAddrV4,
-    channel: AtemUdpChannel,
-    cmd_rx: mpsc::Receiver<AsyncCommand>,
-    cmd_tx_weak: mpsc::WeakSender<AsyncCommand>,
-    /// Packets which have been received from the switcher. Events will be
-    /// sorted by [`AtemPacket::sender_packet_id`].
-    tx: Option<mpsc::Sender<AtemPacket>>,
-    /// The current session ID for the connection.
-    session_id: u16,
-    /// Packets which have not yet been forwarded to [Self::tx]. This must
-    /// remain sorted by [`AtemPacket::sender_packet_id`].
-    rx_queue: VecDeque<AtemPacket>,
-    /// The last time we tried to send to [Self::tx]
-    last_rx_time: Instant,
-    /// The next packet ID we expect to forward to [Self::tx]
-    next_pkt_forward: u16,
-    /// Atomic counter to track which [`AtemPacket::sender_packet_id`] should be
-    /// used next.
-    sender_packet_id: AtomicU16,
-    /// Packets that have been sent which are waiting for a response from the
-    /// switcher. This must remain sorted by [`AtemPacket::sender_packet_id`].
+    /// Changes the switcher's video mode, and waits for the switcher to report
+    /// it.
     ///
-    /// This is limited to [`Self::MAX_ACK_QUEUE_LENGTH`].
-    ack_queue: VecDeque<PacketWaitingForResponse>,
-    /// [Notify] used to track when a request for the device's clock times out.
-    clock_notifier: Arc<Notify>,
-    /// Currently locked storage domains by any client.
-    storage_locks: HashSet<u16>,
-    owned_storage_locks: HashMap<u16, Weak<StorageLock>>,
-    /// File downloads which are still in progress.
-    downloads: HashMap<u16, AsyncFileDownloadRequest>,
-    /// File uploads which are still in progress.
-    uploads: HashMap<u16, AsyncFileUploadRequest>,
-    upload_chunk_params_rx: mpsc::Receiver<FileTransferChunkParams>,
-    upload_chunk_params_tx: mpsc::Sender<FileTransferChunkParams>,
-    /// File uploads which are completed, and awaiting confirmation from the
-    /// switcher ([TransferCompleted][crate::protocol::atom::TransferCompleted]).
-    finished_uploads: HashMap<u16, (Option<oneshot::Sender<Result<(), Error>>>, Arc<StorageLock>)>,
-    /// [Notify] used to track when we need to stop our main event loop.
-    stop_main_loop: Arc<Notify>,
-    /// We've already issued a disconnect command, or the switcher is
-    /// disconnecting us.
-    disconnected: AtomicBool,
-    state: Arc<CowCell<AtemState>>,
-    state_rx: broadcast::Receiver<(CowCellReadTxn<AtemState>, StateUpdate)>,
-    state_tx: broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
-    state_task: Option<JoinHandle<Result<(), Error>>>,
-    reconnect: bool,
-    reconnection_signal: Option<oneshot::Receiver<()>>,
-    initialisation_complete: bool,
-}
+    /// The switcher re-initialises after changing video mode, which causes a
+    /// burst of state updates. With
+    /// [`ConnectionOptions::coalesce_video_mode_change`], these are held back
+    /// until the switcher has reported the new video mode and then stopped
+    /// sending updates for a short time, and then broadcast as a single
+    /// update. This may happen after this function returns.
+    ///
+    /// `mode` must be one of the
+    /// [supported video modes][AtemState::supported_video_modes].
+    pub async fn set_video_mode(&self, mode: VideoMode) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if !state.supported_video_modes.contains(&mode) {
+                error!("video mode {mode:?} is not supported by the switcher");
+                return Err(Error::ParameterOutOfRange);
+            }
 
-impl AtemReceiver {
-    const INIT_TIMEOUT: Duration = Duration::from_secs(1);
-    const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
-    const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+            if state.video_mode == mode {
+                return Ok(());
+            }
+        }
 
-    /// Depth of the upload chunk params receive buffer.
-    const UPLOAD_CHUNK_PARAMS_SIZE: usize = 16;
+        let Some(hold) = &self.video_mode_hold else {
+            // Subscribe before sending the command, so we don't miss the
+            // update.
+            let mut state_rx = self.state_update_events();
+            self.send(vec![Atom::new(SetVideoMode(mode))]).await?;
+            return tokio::time::timeout(
+                Self::VIDEO_MODE_CHANGE_TIMEOUT,
+                wait_for_state(&mut state_rx, |state, updated_fields| {
+                    updated_fields.intersects(StateUpdate::VIDEO_MODE) && state.video_mode == mode
+                }),
+            )
+            .await
+            .map_err(|_| Error::Timeout)?;
+        };
 
-    /// Depth of the command transmit buffer.
+        // State updates are held until the switcher goes quiet, so wait for
+        // the hold to see the new mode instead.
+        let mode_reported_rx = hold
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .start(mode);
+
+        // Release the hold even if this future is dropped before the switcher
+        // reports the new mode.
+        self.spawn_video_mode_hold_release(hold);
+
+        let r = async {
+            self.send(vec![Atom::new(SetVideoMode(mode))]).await?;
+            tokio::time::timeout(Self::VIDEO_MODE_CHANGE_TIMEOUT, mode_reported_rx)
+                .await
+                .map_err(|_| Error::Timeout)?
+                .map_err(|_| Error::Timeout)
+        }
+        .await;
+
+        if r.is_err() {
+            // Broadcast anything which was held straight away.
+            self.release_video_mode_hold().await;
+        }
+        r
+    }
+
+    /// Spawns a task which releases the active [video mode hold][VideoModeHold]
+    /// once it [should be released][VideoModeHold::release_at], even if the
+    /// switcher sends no further updates.
     ///
-    /// This affects how many [`AsyncCommand`]s can sit in
-    /// [`cmd_rx`][Self::cmd_rx].
+    /// The release time moves earlier once the switcher reports the new video
+    /// mode, so this checks it at least every
+    /// [quiet period][VideoModeHold::QUIET_PERIOD].
+    fn spawn_video_mode_hold_release(&self, hold: &SharedVideoModeHold) {
+        let hold = hold.clone();
+        let state = self.state.clone();
+        let state_tx = self.state_tx.clone();
+        tokio::task::spawn(async move {
+            loop {
+                let release_at = hold
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .release_at();
+                let Some(release_at) = release_at else {
+                    // Already released by a state update, or an error.
+                    break;
+                };
+                let now = Instant::now();
+                if now >= release_at {
+                    Self::broadcast_released(&hold, &state, &state_tx).await;
+                    break;
+                }
+                let wake_at = release_at.min(now + VideoModeHold::QUIET_PERIOD);
+                tokio::time::sleep_until(wake_at.into()).await;
+            }
+        });
+    }
+
+    /// Ends any active [video mode hold][Self::set_video_mode], and
+    /// immediately broadcasts the fields which were held.
+    async fn release_video_mode_hold(&self) {
+        if let Some(hold) = &self.video_mode_hold {
+            Self::broadcast_released(hold, &self.state, &self.state_tx).await;
+        }
+    }
+
+    async fn broadcast_released(
+        hold: &SharedVideoModeHold,
+        state: &CowCell<AtemState>,
+        state_tx: &broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    ) {
+        let held = hold
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .release();
+        if !held.is_empty() {
+            debug!("releasing held state updates");
+            // It doesn't matter whether this actually succeeds
+            let _ = state_tx.send((state.read().await, held));
+        }
+    }
+
+    /// Sets the colorimetry mode used for video processing.
     ///
-    /// Once a packet has been sent once, it enters
-    /// [`ack_queue`][Self::ack_queue], which is affected by
-    /// [`MAX_ACK_QUEUE_LENGTH`][Self::MAX_ACK_QUEUE_LENGTH].
-    const COMMAND_CHANNEL_SIZE: usize = 16;
+    /// This sends a [`Colorimetry`] (`ClrM`) atom, which has not been verified
+    /// to work with a switcher.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher hasn't reported a
+    ///   [colorimetry mode][AtemState::colorimetry]
+    pub async fn set_colorimetry(&self, mode: ColorimetryMode) -> Result<(), Error> {
+        if self.get_state().await.colorimetry.is_none() {
+            error!("switcher does not support changing colorimetry mode");
+            return Err(Error::FeatureUnavailable);
+        }
 
-    /// During a frame upload, the number of chunks that will be sent as a
-    /// "burst" before waiting for the switcher to acknowledge them all.
+        let cmd = Atom::new(Colorimetry(mode));
+        self.send(vec![cmd]).await
+    }
+
+    /// Sets the 3G-SDI output level.
     ///
-    /// ## Background
+    /// ## Errors
     ///
-    /// The switcher indicates its transfer capacity with
-    /// [`FileTransferChunkParams`], but this doesn't seem to be the *only*
-    /// bottleneck in the switcher's file transfer pipeline.
+    /// * [`Error::FeatureUnavailable`] when the switcher hasn't reported a
+    ///   [3G-SDI output level][AtemState::sdi_3g_level]
+    pub async fn set_sdi_3g_level(&self, level: Sdi3gLevel) -> Result<(), Error> {
+        if self.get_state().await.sdi_3g_level.is_none() {
+            error!("switcher does not support changing 3G-SDI output level");
+            return Err(Error::FeatureUnavailable);
+        }
+
+        let cmd = Atom::new(SetSdi3gOutputLevel(level));
+        self.send(vec![cmd]).await
+    }
+
+    pub async fn cut_black(&self, me: u8, black: bool) -> Result<(), Error> {
+        let cmd = Atom::new(CutToBlack { me, black });
+        self.send(vec![cmd]).await
+    }
+
+    pub async fn toggle_auto_black(&self, me: u8) -> Result<(), Error> {
+        let cmd = Atom::new(FadeToBlackAuto { me });
+        self.send(vec![cmd]).await
+    }
+
+    /// Puts a downstream keyer on or off air immediately, without a
+    /// transition.
     ///
-    /// The switcher can batch-acknowledge up to about 32 packets (with the
-    /// ATEM Mini), and will wait about 20ms after the last packet before
-    /// sending *any* acknowledgement. We also need to manage size of
-    /// [`cmd_rx`][Self::cmd_rx] and [`ack_queue`][Self::ack_queue] – we don't
-    /// want to gum those up with too many file transfer chunks or require
-    /// retransmits.
+    /// ## Errors
     ///
-    /// ATEM's SDK/tools seem to just take the [`FileTransferChunkParams`] at
-    /// face value, and DoS the switcher. This also means it ends up
-    /// retransmitting nearly every chunk of a frame at least once, and ends up
-    /// wasting a bunch of bandwidth *and* being slower.
+    /// * [`Error::ParameterOutOfRange`] when `keyer` is not a valid
+    ///   downstream keyer
+    pub async fn set_dsk_on_air(&self, keyer: u8, on_air: bool) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if keyer >= state.topology.downstream_keys {
+                error!(
+                    "DSK #{keyer} does not exist, switcher has {} DSK(s)",
+                    state.topology.downstream_keys
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let cmd = Atom::new(SetDownstreamKeyerOnAir { keyer, on_air });
+        self.send(vec![cmd]).await
+    }
+
+    /// Sets the duration of the fade-to-black transition on `me`, in
+    /// `frames`.
     ///
-    /// ## Benchmarks
+    /// This uses the same units as
+    /// [`AtemState::get_fade_to_black_rate()`].
     ///
-    /// This benchmark is sending a mostly-uncompressed (7,204,696 bytes, 5178
-    /// [chunks][chunk]) 1080p image to the ATEM Mini, using a wired gigabit
-    /// ethernet network, on the same switch.
+    /// ## Errors
     ///
-    /// Waiting for ack after... | Duration  | Packets sent
-    /// ------------------------ | --------- | ------------
-    /// each [chunk][]           | 86.38 sec | 5,178 (1x)
-    /// every 10 [chunks][chunk] | 8.77 sec  | 5,178 (1x)
-    /// every 24 [chunks][chunk] | 3.77 sec  | 5,178 (1x)
-    /// vs. ATEM's SDK/tools     | 6.66 sec  | 15,672 (3.03x)
+    /// * [`Error::ParameterOutOfRange`] when `me` is not a valid ME
+    pub async fn set_fade_to_black_rate(&self, me: u8, frames: u8) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if me >= state.topology.mes {
+                error!(
+                    "ME #{me} does not exist, switcher has {} ME(s)",
+                    state.topology.mes
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let cmd = Atom::new(SetFadeToBlackParams {
+            me,
+            rate: Some(frames),
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Sets the gain of a classic (non-Fairlight) audio mixer input, in
+    /// decibels.
     ///
-    /// "Duration" is measured from [`SetupFileUpload`] (`FTSD`) to the switcher
-    /// sending [`TransferCompleted`][crate::protocol::atom::TransferCompleted] (`FTDC`).
-    /// _Lower is better._
+    /// The switcher represents gain as a linear value, so `gain_db` is
+    /// rounded to the nearest step (see
+    /// [`audio_gain_from_db()`][crate::protocol::atom::audio_gain_from_db]).
+    /// [`f32::NEG_INFINITY`] silences the input.
     ///
-    /// "Packets sent" is the number of packets containing a [chunk][]. A number
-    /// higher than 5,178 (1x) indicates that the client retransmitted some
-    /// chunks because the switcher couldn't keep up. _Lower is better_.
+    /// ## Errors
     ///
-    /// [chunk]: TransferChunk
-    const UPLOAD_BURST_SIZE: usize = 24;
+    /// * [`Error::FeatureUnavailable`] when the switcher doesn't have a
+    ///   classic audio mixer
+    /// * [`Error::ParameterOutOfRange`] when `source` is not a known audio
+    ///   mixer input, or `gain_db` can't be represented by the switcher
+    pub async fn set_audio_input_gain(&self, source: u16, gain_db: f32) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if !state.supports(Capability::AudioMixer) {
+                error!("switcher does not have a classic audio mixer");
+                return Err(Error::FeatureUnavailable);
+            }
 
-    /// Maximum length which [`ack_queue`][Self::ack_queue] may grow to.
-    const MAX_ACK_QUEUE_LENGTH: usize = 512;
-    const OVERFLOW_MARGIN: u16 = AtemPacket::MAX_PACKET_ID - (Self::MAX_ACK_QUEUE_LENGTH as u16);
-    /// Number of retries to send.
-    const RETRANSMIT_LIMIT: u8 = 3;
-    /// Delay before next retry.
-    const RETRANSMIT_TIME: Duration = Duration::from_millis(500);
+            if !state.audio_mixer_inputs.contains_key(&source) {
+                error!("audio mixer input #{source} does not exist");
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
 
-    /// Create a new `recv_task`.
+        let Some(gain) = crate::protocol::atom::audio_gain_from_db(gain_db) else {
+            error!("audio gain {gain_db} dB is out of range");
+            return Err(Error::ParameterOutOfRange);
+        };
+
+        let cmd = Atom::new(SetAudioMixerInputProperties {
+            gain: Some(gain),
+            ..SetAudioMixerInputProperties::new(source)
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Mutes or unmutes a Fairlight audio mixer input.
     ///
-    /// ## Returns
+    /// Unmuting an input sets it to be always on, which also turns off
+    /// audio-follows-video.
     ///
-    /// * `channel`: UDP connection to work with
-    /// * `tx`: [broadcast::Sender] where incoming packets from the device go to
-    fn new(addr: SocketAddrV4, reconnect: bool) -> (Self, mpsc::Sender<AsyncCommand>) {
-        let (cmd_tx, cmd_rx) = mpsc::channel(Self::COMMAND_CHANNEL_SIZE);
-        let cmd_tx_weak = cmd_tx.downgrade();
-        let (state_tx, state_rx) = broadcast::channel(16);
-        let (upload_chunk_params_tx, upload_chunk_params_rx) =
-            mpsc::channel(Self::UPLOAD_CHUNK_PARAMS_SIZE);
-        (
-            Self {
-                addr,
-                channel: AtemUdpChannel::new(),
-                cmd_rx,
-                cmd_tx_weak,
-                tx: None,
-                session_id: 0,
-                rx_queue: VecDeque::new(),
-                last_rx_time: Instant::now(),
-                next_pkt_forward: 1,
-                sender_packet_id: AtomicU16::new(1),
-                ack_queue: VecDeque::new(),
-                clock_notifier: Arc::new(Notify::new()),
-                storage_locks: HashSet::new(),
-                owned_storage_locks: HashMap::new(),
-                downloads: HashMap::new(),
-                uploads: HashMap::new(),
-                finished_uploads: HashMap::new(),
-                upload_chunk_params_rx,
-                upload_chunk_params_tx,
-                stop_main_loop: Arc::new(Notify::new()),
-                disconnected: AtomicBool::new(false),
-                state: Arc::new(CowCell::new(AtemState::default())),
-                state_tx,
-                state_rx,
-                state_task: None,
-                reconnect,
-                reconnection_signal: None,
-                initialisation_complete: false,
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher doesn't have a
+    ///   Fairlight audio mixer
+    /// * [`Error::ParameterOutOfRange`] when `source` is not a known Fairlight
+    ///   audio mixer input
+    pub async fn mute_audio_input(&self, source: u16, muted: bool) -> Result<(), Error> {
+        self.set_fairlight_input_state(
+            source,
+            if muted {
+                FairlightAudioInputSourceState::Off
+            } else {
+                FairlightAudioInputSourceState::On
             },
-            cmd_tx,
         )
+        .await
     }
 
-    async fn spawn_state_task(
-        &mut self,
-        mut rx: mpsc::Receiver<AtemPacket>,
-    ) -> Result<oneshot::Receiver<()>, Error> {
-        let (initialised_tx, initialised_rx) = oneshot::channel();
-        debug!("Spawning state_task");
-        let state_state = self.state.clone();
-        let state_tx = self.state_tx.clone();
+    /// Sets whether a Fairlight audio mixer input follows video, such that it
+    /// is only on when its video source is on program.
+    ///
+    /// Turning off audio-follows-video sets the input to be always on.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher doesn't have a
+    ///   Fairlight audio mixer
+    /// * [`Error::ParameterOutOfRange`] when `source` is not a known Fairlight
+    ///   audio mixer input
+    pub async fn set_audio_follows_video(&self, source: u16, afv: bool) -> Result<(), Error> {
+        self.set_fairlight_input_state(
+            source,
+            if afv {
+                FairlightAudioInputSourceState::AudioFollowsVideo
+            } else {
+                FairlightAudioInputSourceState::On
+            },
+        )
+        .await
+    }
 
-        self.state_task = Some(tokio::task::spawn(async move {
-            let mut initialised_tx = Some(initialised_tx);
-            while let Some(pkt) = rx.recv().await {
-                if let Some(cmds) = pkt.atoms() {
-                    let mut w = state_state.write().await;
-                    let updated_fields = w.update_state(cmds)?;
+    async fn set_fairlight_input_state(
+        &self,
+        source: u16,
+        input_state: FairlightAudioInputSourceState,
+    ) -> Result<(), Error> {
+        let channel_id = {
+            let state = self.get_state().await;
+            if !state.supports(Capability::Fairlight) {
+                error!("switcher does not have a Fairlight audio mixer");
+                return Err(Error::FeatureUnavailable);
+            }
+
+            let Some(fasp) = state.fairlight_audio_mixer_input_props.get(&source) else {
+                error!("Fairlight audio mixer input #{source} does not exist");
+                return Err(Error::ParameterOutOfRange);
+            };
+            fasp.channel_id
+        };
+
+        let cmd = Atom::new(SetFairlightAudioMixerInputSourceProperties {
+            state: Some(input_state),
+            ..SetFairlightAudioMixerInputSourceProperties::new(source, channel_id)
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Runs the macro in slot `index`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `index` is not a valid macro slot
+    /// * [`Error::NotFound`] when the macro slot is empty
+    pub async fn run_macro(&self, index: u16) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            let Some(mprp) = state.macros.get(&index) else {
+                error!("macro #{index} does not exist");
+                return Err(Error::ParameterOutOfRange);
+            };
+
+            if !mprp.is_used {
+                error!("macro #{index} is empty");
+                return Err(Error::NotFound);
+            }
+        }
+
+        self.macro_action(index, MacroActionType::Run).await
+    }
+
+    /// Stops the currently-running macro.
+    pub async fn stop_macro(&self) -> Result<(), Error> {
+        self.macro_action(NO_MACRO, MacroActionType::Stop).await
+    }
+
+    /// Starts recording a new macro into slot `index`, replacing any macro
+    /// which is already there.
+    ///
+    /// Recording continues until [`AtemController::stop_macro_recording()`]
+    /// is called.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `index` is not a valid macro slot
+    pub async fn start_macro_recording(
+        &self,
+        index: u16,
+        name: String,
+        description: String,
+    ) -> Result<(), Error> {
+        if !self.get_state().await.macros.contains_key(&index) {
+            error!("macro #{index} does not exist");
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        let cmd = Atom::new(MacroStartRecord {
+            index,
+            name,
+            description,
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Stops recording a macro.
+    pub async fn stop_macro_recording(&self) -> Result<(), Error> {
+        self.macro_action(NO_MACRO, MacroActionType::StopRecording)
+            .await
+    }
+
+    /// Deletes the macro in slot `index`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `index` is not a valid macro slot
+    pub async fn delete_macro(&self, index: u16) -> Result<(), Error> {
+        if !self.get_state().await.macros.contains_key(&index) {
+            error!("macro #{index} does not exist");
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        self.macro_action(index, MacroActionType::Delete).await
+    }
+
+    async fn macro_action(&self, index: u16, action: MacroActionType) -> Result<(), Error> {
+        let cmd = Atom::new(MacroAction { index, action });
+        self.send(vec![cmd]).await
+    }
+
+    /// Captures the primary program output as a still image.
+    pub async fn capture(&self) -> Result<(), Error> {
+        if !self.supports(Capability::StillCapture).await {
+            error!("switcher does not support still image capture");
+            return Err(Error::FeatureUnavailable);
+        }
+        let cmd = Atom::new(CAPTURE_STILL);
+        self.send(vec![cmd]).await
+    }
+
+    /// Resets every media player on the switcher to still #0, in a single
+    /// batch of commands.
+    ///
+    /// Unlike [`set_media_player_source()`][Self::set_media_player_source],
+    /// this doesn't check that still #0 contains a valid frame.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher has no media players,
+    ///   or its media players don't support still images
+    pub async fn reset_all_media_player_sources(&self) -> Result<(), Error> {
+        let media_players = {
+            let state = self.get_state().await;
+            if state.topology.media_players == 0 {
+                error!("switcher does not have any media players");
+                return Err(Error::FeatureUnavailable);
+            }
+
+            if state.media_player_capabilities.still_count == 0 {
+                error!("media player does not support still images");
+                return Err(Error::FeatureUnavailable);
+            }
+            state.topology.media_players
+        };
+
+        let cmds = (0..media_players)
+            .map(|id| {
+                Atom::new(SetMediaPlayerSource {
+                    enable: true,
+                    id,
+                    source: MediaPlayerSourceID::Still(0),
+                })
+            })
+            .collect();
+        self.send(cmds).await
+    }
+
+    /// Change a media player's source.
+    pub async fn set_media_player_source(
+        &self,
+        media_player: u8,
+        source: MediaPlayerSourceID,
+    ) -> Result<(), Error> {
+        self.send_media_player_source(media_player, source, true)
+            .await
+    }
+
+    /// Changes the still or clip index of a media player, without changing
+    /// whether it plays a still or a clip.
+    ///
+    /// This sends a [`SetMediaPlayerSource`] command with the
+    /// [`enable`][SetMediaPlayerSource::enable] bit of its setting mask
+    /// cleared, so only the still or clip index for `source`'s type is set.
+    pub async fn set_media_player_source_index(
+        &self,
+        media_player: u8,
+        source: MediaPlayerSourceID,
+    ) -> Result<(), Error> {
+        self.send_media_player_source(media_player, source, false)
+            .await
+    }
+
+    /// Validates `source`, and sends a [`SetMediaPlayerSource`] command for
+    /// it with the given [`enable`][SetMediaPlayerSource::enable] bit.
+    async fn send_media_player_source(
+        &self,
+        media_player: u8,
+        source: MediaPlayerSourceID,
+        enable: bool,
+    ) -> Result<(), Error> {
+        let state = self.get_state().await;
+        if media_player >= state.topology.media_players {
+            error!(
+                "media player #{media_player} does not exist, switcher has {} media player(s)",
+                state.topology.media_players
+            );
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        match source {
+            MediaPlayerSourceID::Still(index) => {
+                if state.media_player_capabilities.still_count == 0 {
+                    error!("media player does not support still images");
+                    return Err(Error::FeatureUnavailable);
+                }
+
+                if index >= state.media_player_capabilities.still_count {
+                    error!(
+                        "still #{index} does not exist, switcher supports {} still(s)",
+                        state.media_player_capabilities.still_count
+                    );
+                    return Err(Error::ParameterOutOfRange);
+                }
+
+                if !state
+                    .media_player_frame_descriptions
+                    .get(&index)
+                    .is_some_and(|mpfd| mpfd.is_valid)
+                {
+                    error!("still #{index} does not contain a valid frame");
+                    return Err(Error::NotFound);
+                }
+            }
+
+            MediaPlayerSourceID::VideoClip(index) => {
+                if state.media_player_capabilities.clip_count == 0 {
+                    error!("media player does not support video clips");
+                    return Err(Error::FeatureUnavailable);
+                }
+
+                if index >= state.media_player_capabilities.clip_count {
+                    error!(
+                        "clip #{index} does not exist, switcher supports {} clip(s)",
+                        state.media_player_capabilities.clip_count
+                    );
+                    return Err(Error::ParameterOutOfRange);
+                }
+            }
+        }
+        drop(state);
+
+        let cmd = Atom::new(SetMediaPlayerSource {
+            enable,
+            id: media_player,
+            source,
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Sets the name of the audio attached to video clip slot `index`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher doesn't support video
+    ///   clips
+    /// * [`Error::ParameterOutOfRange`] when `index` is not a valid clip slot,
+    ///   or `name` is longer than 64 bytes
+    pub async fn set_media_player_audio_name(&self, index: u8, name: &str) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if state.media_player_capabilities.clip_count == 0 {
+                error!("media player does not support video clips");
+                return Err(Error::FeatureUnavailable);
+            }
+
+            if index >= state.media_player_capabilities.clip_count {
+                error!(
+                    "clip #{index} does not exist, switcher supports {} clip(s)",
+                    state.media_player_capabilities.clip_count
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        if name.len() > 64 {
+            error!("clip audio name is too long: {} bytes", name.len());
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        let cmd = Atom::new(SetMediaPlayerAudio {
+            index,
+            name: name.to_string(),
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Delete all items in the media pool.
+    pub async fn clear_media_pool(&self) -> Result<(), Error> {
+        let cmd = Atom::new(CLEAR_MEDIA_POOL);
+        self.send(vec![cmd]).await
+    }
+
+    /// Starts recording to media (eg: USB disk).
+    ///
+    /// The current recording status can be read from
+    /// [`AtemState::recording_status`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   recording
+    pub async fn start_recording(&self) -> Result<(), Error> {
+        self.record_to_media(true).await
+    }
+
+    /// Stops recording to media.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   recording
+    pub async fn stop_recording(&self) -> Result<(), Error> {
+        self.record_to_media(false).await
+    }
+
+    async fn record_to_media(&self, start: bool) -> Result<(), Error> {
+        if !self.supports(Capability::Recording).await {
+            error!("switcher does not support recording");
+            return Err(Error::FeatureUnavailable);
+        }
+        let cmd = Atom::new(RecordToMedia { start });
+        self.send(vec![cmd]).await
+    }
+
+    /// Switches recording to the switcher's other disk, for continuous
+    /// recording when the current disk is nearly full.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   recording
+    pub async fn switch_recording_disk(&self) -> Result<(), Error> {
+        if !self.supports(Capability::Recording).await {
+            error!("switcher does not support recording");
+            return Err(Error::FeatureUnavailable);
+        }
+        let cmd = Atom::new(RECORD_TO_MEDIA_SWITCH_DISK);
+        self.send(vec![cmd]).await
+    }
+
+    /// Enables or disables a talkback (intercom) channel on the switcher's SDI
+    /// outputs.
+    ///
+    /// The current state can be read from [`AtemState::talkback_channels`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   talkback
+    /// * [`Error::ParameterOutOfRange`] when `channel` is not a known talkback
+    ///   channel
+    pub async fn set_talkback_channel(&self, channel: u8, enabled: bool) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if !state.supports(Capability::Talkback) {
+                error!("switcher does not support talkback");
+                return Err(Error::FeatureUnavailable);
+            }
+
+            if !state.talkback_channels.contains_key(&channel) {
+                error!("talkback channel #{channel} does not exist");
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let cmd = Atom::new(SetAudioTalkbackMixerProperties {
+            channel,
+            mute_sdi: !enabled,
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Starts RTMP streaming, using the switcher's current streaming settings.
+    ///
+    /// The current streaming status can be read from
+    /// [`AtemState::streaming_status`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   streaming
+    pub async fn start_streaming(&self) -> Result<(), Error> {
+        self.stream_rtmp(true).await
+    }
+
+    /// Stops RTMP streaming.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   streaming
+    pub async fn stop_streaming(&self) -> Result<(), Error> {
+        self.stream_rtmp(false).await
+    }
+
+    async fn stream_rtmp(&self, start: bool) -> Result<(), Error> {
+        if !self.supports(Capability::Streaming).await {
+            error!("switcher does not support streaming");
+            return Err(Error::FeatureUnavailable);
+        }
+        let cmd = Atom::new(StreamRtmp { start });
+        self.send(vec![cmd]).await
+    }
+
+    pub async fn rtmp_stream_duration(&self) -> Result<(), Error> {
+        let cmd = Atom::new(RTMP_DURATION_REQUEST);
+        self.send(vec![cmd]).await
+    }
+
+    /// Saves the current settings to the start-up configuration.
+    ///
+    /// This returns once the switcher has acknowledged the command. The
+    /// switcher doesn't report when it has finished writing the settings to
+    /// its storage, so power-cycling the switcher immediately afterwards may
+    /// lose them.
+    pub async fn save_startup_settings(&self) -> Result<(), Error> {
+        let cmd = Atom::new(SAVE_STARTUP_SETTINGS);
+        self.send(vec![cmd]).await
+    }
+
+    /// Clears the start-up configuration.
+    ///
+    /// Like [`save_startup_settings()`][Self::save_startup_settings], this
+    /// only waits for the switcher to acknowledge the command.
+    pub async fn clear_startup_settings(&self) -> Result<(), Error> {
+        let cmd = Atom::new(CLEAR_STARTUP_SETTINGS);
+        self.send(vec![cmd]).await
+    }
+
+    /// Requests a confirmation token for
+    /// [`factory_reset()`][Self::factory_reset].
+    pub fn request_factory_reset(&self) -> Result<FactoryResetConfirm, Error> {
+        let nonce = rand::random();
+        *self
+            .factory_reset_nonce
+            .lock()
+            .map_err(|_| Error::Internal)? = Some(nonce);
+        Ok(FactoryResetConfirm {
+            nonce,
+            issued: Instant::now(),
+        })
+    }
+
+    /// Clears the start-up configuration, so the switcher will use its
+    /// factory default settings when it is next powered on.
+    ///
+    /// This is a destructive operation, so requires a `confirm` token from
+    /// [`request_factory_reset()`][Self::request_factory_reset].
+    ///
+    /// The switcher does not send any event after clearing settings, so this
+    /// only waits for the switcher to acknowledge the command. Current
+    /// settings are unaffected until the switcher is restarted.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ConfirmationRequired`] if `confirm` was not issued by this
+    ///   controller, has been superseded by another token, or has expired;
+    ///   nothing is sent to the switcher
+    pub async fn factory_reset(&self, confirm: FactoryResetConfirm) -> Result<(), Error> {
+        let expected = self
+            .factory_reset_nonce
+            .lock()
+            .map_err(|_| Error::Internal)?
+            .take();
+        if expected != Some(confirm.nonce)
+            || confirm.issued.elapsed() > FactoryResetConfirm::VALIDITY
+        {
+            error!("factory reset confirmation token is invalid or expired");
+            return Err(Error::ConfirmationRequired);
+        }
+
+        self.clear_startup_settings().await
+    }
+
+    /// Restores settings from the start-up configuration
+    ///
+    /// This only waits for the switcher to acknowledge the command; restored
+    /// settings arrive later as regular [state updates][Self::state_update_events].
+    ///
+    /// **Warning:** this method is never used by the SDK
+    pub async fn restore_startup_settings(&self) -> Result<(), Error> {
+        let cmd = Atom::new(RESTORE_STARTUP_SETTINGS);
+        self.send(vec![cmd]).await
+    }
+
+    /// Sets colour generator parameters.
+    pub async fn set_colour_generator_params(
+        &self,
+        params: SetColourGeneratorParams,
+    ) -> Result<(), Error> {
+        let cmd = Atom::new(params);
+        self.send(vec![cmd]).await
+    }
+
+    /// Sets a colour generator's colour from an sRGB colour (`[r, g, b]`).
+    ///
+    /// See [`SetColourGeneratorParams::from_rgb()`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `id` is not a valid colour
+    ///   generator
+    pub async fn set_colour_rgb(&self, id: u8, [r, g, b]: [u8; 3]) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if id >= state.colour_generators {
+                error!(
+                    "colour generator #{id} does not exist, switcher has {} colour generator(s)",
+                    state.colour_generators
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        self.set_colour_generator_params(SetColourGeneratorParams::from_rgb(id, r, g, b))
+            .await
+    }
+
+    /// Sets a colour generator's colour from a [palette::Hsl] colour.
+    ///
+    /// Saturation and lightness outside of `0.0..=1.0` are clamped to the
+    /// switcher's range.
+    ///
+    /// See [`SetColourGeneratorParams::with_colour()`].
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `id` is not a valid colour
+    ///   generator
+    #[cfg(feature = "palette")]
+    pub async fn set_colour_generator_hsl(
+        &self,
+        id: u8,
+        colour: palette::Hsl,
+    ) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if crate::protocol::atom::generator_id_to_video_source(id) == VideoSource::Unknown
+                || id >= state.colour_generators
+            {
+                error!(
+                    "colour generator #{id} does not exist, switcher has {} colour generator(s)",
+                    state.colour_generators
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let colour = palette::Hsl::new_srgb(
+            colour.hue,
+            colour.saturation.clamp(0., 1.),
+            colour.lightness.clamp(0., 1.),
+        );
+        let params = SetColourGeneratorParams::new(id).with_colour(colour)?;
+        self.set_colour_generator_params(params).await
+    }
+
+    /// Shows or hides overlays on a MultiView window.
+    ///
+    /// Overlays which are `None` are left unchanged.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `multiview` or `window` does not
+    ///   exist
+    pub async fn set_multiview_overlays(
+        &self,
+        multiview: u8,
+        window: u8,
+        safe_area: Option<bool>,
+        vu_meter: Option<bool>,
+    ) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if multiview >= state.topology.multiviewers {
+                error!(
+                    "multiview #{multiview} does not exist, switcher has {} multiview(s)",
+                    state.topology.multiviewers
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+            if window >= state.multiview_capabilities.windows {
+                error!(
+                    "multiview window #{window} does not exist, switcher has {} window(s) per multiview",
+                    state.multiview_capabilities.windows
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let mut cmds = Vec::with_capacity(2);
+        if let Some(enabled) = safe_area {
+            cmds.push(Atom::new(MultiViewSafeArea {
+                multiview,
+                window,
+                enabled,
+            }));
+        }
+        if let Some(enabled) = vu_meter {
+            cmds.push(Atom::new(SetMultiViewVuMeterEnabled {
+                multiview,
+                window,
+                enabled,
+            }));
+        }
+        if cmds.is_empty() {
+            return Ok(());
+        }
+        self.send(cmds).await
+    }
+
+    /// Moves, resizes and rotates an upstream keyer with DVE.
+    ///
+    /// `position` is `(x, y)` and `size` is `(x, y)`, in the units described
+    /// in [`KeyDVEProperties`][crate::protocol::atom::KeyDVEProperties].
+    /// `rotation` is in tenths of a degree. Parameters which are `None` are
+    /// left unchanged.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ParameterOutOfRange`] when `me` or `keyer` does not exist
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support DVE,
+    ///   `rotation` is set but the switcher can't rotate keys, or `size` is
+    ///   larger than 100% but the switcher can't scale up keys
+    pub async fn set_key_dve(
+        &self,
+        me: u8,
+        keyer: u8,
+        position: Option<(i32, i32)>,
+        size: Option<(u32, u32)>,
+        rotation: Option<i32>,
+    ) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            let Some(mec) = state.get_me_capabilities(me) else {
+                error!(
+                    "ME #{me} does not exist, switcher has {} ME(s)",
+                    state.topology.mes
+                );
+                return Err(Error::ParameterOutOfRange);
+            };
+            if keyer >= mec.keyers {
+                error!(
+                    "keyer #{keyer} does not exist, ME #{me} has {} keyer(s)",
+                    mec.keyers
+                );
+                return Err(Error::ParameterOutOfRange);
+            }
+
+            if !state.supports(Capability::Dve) {
+                error!("switcher does not support DVE");
+                return Err(Error::FeatureUnavailable);
+            }
+            if rotation.is_some() && !state.dve_can_rotate {
+                error!("switcher does not support rotating keys");
+                return Err(Error::FeatureUnavailable);
+            }
+            if size.is_some_and(|(x, y)| x > 1000 || y > 1000) && !state.dve_can_scale_up {
+                error!("switcher does not support scaling keys larger than 100%");
+                return Err(Error::FeatureUnavailable);
+            }
+        }
+
+        let cmd = Atom::new(SetKeyDVEProperties {
+            position_x: position.map(|(x, _)| x),
+            position_y: position.map(|(_, y)| y),
+            size_x: size.map(|(x, _)| x),
+            size_y: size.map(|(_, y)| y),
+            rotation,
+            ..SetKeyDVEProperties::new(me, keyer)
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Sets the focus of the camera connected to external input number
+    /// `input`.
+    ///
+    /// `focus` ranges from `0.0` (near) to `1.0` (far).
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   camera control
+    /// * [`Error::ParameterOutOfRange`] when `input` is not an external input
+    ///   on the switcher
+    pub async fn camera_set_focus(&self, input: u8, focus: I5F11) -> Result<(), Error> {
+        self.send_camera_command(
+            input,
+            CameraParameterID::Lens(LensParam::Focus),
+            vec![focus].into(),
+        )
+        .await
+    }
+
+    /// Sets the aperture (iris) of the camera connected to external input
+    /// number `input`.
+    ///
+    /// `normalised` ranges from `0.0` (smallest aperture) to `1.0` (largest
+    /// aperture).
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   camera control
+    /// * [`Error::ParameterOutOfRange`] when `input` is not an external input
+    ///   on the switcher
+    pub async fn camera_set_iris(&self, input: u8, normalised: I5F11) -> Result<(), Error> {
+        self.send_camera_command(
+            input,
+            CameraParameterID::Lens(LensParam::ApertureNormalised),
+            vec![normalised].into(),
+        )
+        .await
+    }
+
+    /// Triggers a one-shot autofocus on the camera connected to external input
+    /// number `input`.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::FeatureUnavailable`] when the switcher does not support
+    ///   camera control
+    /// * [`Error::ParameterOutOfRange`] when `input` is not an external input
+    ///   on the switcher
+    pub async fn camera_trigger_autofocus(&self, input: u8) -> Result<(), Error> {
+        self.send_camera_command(
+            input,
+            CameraParameterID::Lens(LensParam::AutoFocus),
+            CameraParameterValue::Bool(vec![]),
+        )
+        .await
+    }
+
+    /// Sends an absolute [CameraCommand] to the camera connected to external
+    /// input number `input`.
+    async fn send_camera_command(
+        &self,
+        input: u8,
+        parameter: CameraParameterID,
+        value: CameraParameterValue,
+    ) -> Result<(), Error> {
+        {
+            let state = self.get_state().await;
+            if !state.supports(Capability::CameraControl) {
+                error!("switcher does not support camera control");
+                return Err(Error::FeatureUnavailable);
+            }
+
+            let Some(video_source) = VideoSource::from_input_number(input) else {
+                error!("input #{input} does not exist");
+                return Err(Error::ParameterOutOfRange);
+            };
+            if !state.input_properties.contains_key(&video_source) {
+                error!("input #{input} does not exist on this switcher");
+                return Err(Error::ParameterOutOfRange);
+            }
+        }
+
+        let cmd = Atom::new(CameraCommand {
+            input,
+            parameter,
+            relative: false,
+            value,
+        });
+        self.send(vec![cmd]).await
+    }
+
+    /// Returns the number of times each type of atom (by FourCC) has been
+    /// received from the switcher during this session, including atoms which
+    /// necromancer doesn't implement yet.
+    ///
+    /// This is intended for coverage reporting, to find which atoms a switcher
+    /// actually uses. It is only recorded when connecting with
+    /// [`ConnectionOptions::record_observed_atoms`]; otherwise this is always
+    /// empty.
+    pub fn observed_atoms(&self) -> BTreeMap<[u8; 4], usize> {
+        self.observed_atoms
+            .as_ref()
+            .map(|o| o.lock().expect("observed atoms lock poisoned").clone())
+            .unwrap_or_default()
+    }
+
+    /// Returns the round-trip time of the most recent clock request, which
+    /// is sent every [`heartbeat_interval`][ConnectionOptions::heartbeat_interval].
+    ///
+    /// This is measured from sending the request to the switcher
+    /// acknowledging it (with its current [`Time`][crate::protocol::atom::Time]),
+    /// and includes any retransmissions.
+    ///
+    /// Returns `None` if no clock request has been acknowledged in the current
+    /// session yet.
+    pub fn last_rtt(&self) -> Option<Duration> {
+        self.rtt.lock().expect("rtt lock poisoned").last
+    }
+
+    /// Returns a moving average of recent [round-trip times][Self::last_rtt].
+    ///
+    /// This changes more slowly than [`last_rtt()`][Self::last_rtt], so is
+    /// better suited to spotting a degrading link.
+    ///
+    /// This is reset when a new session starts (eg: on reconnection).
+    ///
+    /// Returns `None` if no clock request has been acknowledged in the current
+    /// session yet.
+    pub fn average_rtt(&self) -> Option<Duration> {
+        self.rtt.lock().expect("rtt lock poisoned").average
+    }
+
+    /// Returns the number of commands which have been sent to the switcher,
+    /// but not yet acknowledged.
+    ///
+    /// This includes internal requests (such as clock requests), and
+    /// commands [journalled][ConnectionOptions::replay_on_reconnect] while
+    /// reconnecting. It does not include commands which are still waiting to
+    /// be picked up by the receiver task.
+    ///
+    /// Callers sending a large number of commands can use this to apply
+    /// backpressure, rather than waiting for the switcher to stall.
+    pub fn outstanding_commands(&self) -> usize {
+        self.outstanding_commands.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if the switcher has finished sending its initial state
+    /// for the current session.
+    ///
+    /// This is always `true` immediately after
+    /// [`connect_udp()`][Self::connect_udp] returns, but becomes `false` while
+    /// [reconnecting][ConnectionOptions::reconnect], until the switcher has
+    /// sent its initial state again.
+    pub fn is_initialised(&self) -> bool {
+        self.initialised.load(Ordering::Relaxed)
+    }
+
+    /// Waits for the switcher to finish sending its initial state.
+    ///
+    /// If [initialisation has already completed][Self::is_initialised], this
+    /// returns immediately. Otherwise, this resolves on the next
+    /// [`StateUpdate::INITIALISATION_COMPLETE`], which allows callers to
+    /// block input until the state is coherent again after a reconnection.
+    ///
+    /// ## Errors
+    ///
+    /// * [`Error::ChannelUnavailable`] when the connection to the switcher was
+    ///   closed
+    pub async fn wait_for_initialisation(&self) -> Result<(), Error> {
+        // Subscribe before checking the current state, so we don't miss an
+        // update in between.
+        let mut state_rx = self.state_update_events();
+        if self.is_initialised() {
+            return Ok(());
+        }
+
+        wait_for_state(&mut state_rx, |_, updated_fields| {
+            updated_fields.intersects(StateUpdate::INITIALISATION_COMPLETE)
+        })
+        .await
+    }
+
+    /// Returns `true` if the switcher supports a given [Capability].
+    ///
+    /// This is only accurate once initialisation has completed.
+    pub async fn supports(&self, capability: Capability) -> bool {
+        self.get_state().await.supports(capability)
+    }
+
+    /// Gets the tally state of a given source.
+    ///
+    /// See [`AtemState::get_tally()`].
+    pub async fn get_tally(&self, source: VideoSource) -> TallyFlags {
+        self.get_state().await.get_tally(source)
+    }
+
+    /// Gets all video modes supported by the switcher, along with the
+    /// MultiView and down-conversion modes supported in each.
+    ///
+    /// This can be used to find video modes which are incompatible with the
+    /// current MultiView configuration.
+    ///
+    /// See [`AtemState::supported_video_mode_capabilities`].
+    pub async fn get_supported_video_modes(&self) -> Vec<SupportedVideoMode> {
+        self.get_state()
+            .await
+            .supported_video_mode_capabilities
+            .clone()
+    }
+
+    /// Gets the source routed to every auxiliary output, indexed by aux.
+    ///
+    /// See [`AtemState::aux_routing()`].
+    pub async fn aux_routing(&self) -> Vec<VideoSource> {
+        self.get_state().await.aux_routing()
+    }
+
+    pub async fn get_state(&self) -> impl std::ops::Deref<Target = AtemState> {
+        self.state.read().await
+    }
+
+    /// Subscribes to updates of the switcher's state.
+    ///
+    /// When the connection to the switcher is lost, this emits
+    /// [`StateUpdate::CONNECTION_LOST`]. If
+    /// [reconnecting][ConnectionOptions::reconnect], this emits
+    /// [`StateUpdate::CONNECTION_RESTORED`] once the switcher has sent its
+    /// initial state again.
+    pub fn state_update_events(&self) -> Receiver<(CowCellReadTxn<AtemState>, StateUpdate)> {
+        self.state_rx.resubscribe()
+    }
+
+    /// Subscribes to updates of the switcher's state, like
+    /// [`state_update_events()`][Self::state_update_events], but only
+    /// returns events which intersect with `mask`.
+    ///
+    /// Connection events are also filtered, so include
+    /// [`StateUpdate::CONNECTION_LOST`] and
+    /// [`StateUpdate::CONNECTION_RESTORED`] in `mask` to receive them.
+    ///
+    /// If the subscriber falls too far behind, older events are dropped.
+    pub fn subscribe_filtered(
+        &self,
+        mask: StateUpdate,
+    ) -> impl Stream<Item = (CowCellReadTxn<AtemState>, StateUpdate)> {
+        BroadcastStream::new(self.state_rx.resubscribe()).filter_map(move |r| async move {
+            match r {
+                Ok((state, updated)) if updated.intersects(mask) => Some((state, updated)),
+                Ok(_) => None,
+                Err(BroadcastStreamRecvError::Lagged(n)) => {
+                    warn!("state update subscriber lagged, dropped {n} event(s)");
+                    None
+                }
+            }
+        })
+    }
+
+    /// Subscribes to [lifecycle events][TransferEvent] for all file uploads
+    /// and downloads on this connection.
+    ///
+    /// Only events which happen after subscribing are returned. If the
+    /// subscriber falls too far behind, older events are dropped.
+    pub fn transfer_events(&self) -> impl Stream<Item = TransferEvent> {
+        BroadcastStream::new(self.transfer_rx.resubscribe()).filter_map(|r| async move {
+            match r {
+                Ok(event) => Some(event),
+                Err(BroadcastStreamRecvError::Lagged(n)) => {
+                    warn!("transfer event subscriber lagged, dropped {n} event(s)");
+                    None
+                }
+            }
+        })
+    }
+
+    /// Subscribes to changes of
+    /// [camera control parameters][AtemState::camera_parameters].
+    ///
+    /// This emits `(input, parameter, value)` whenever the switcher reports a
+    /// new value for a parameter, compared to when this was called. Parameters
+    /// which the switcher re-reports with the same value are skipped.
+    ///
+    /// If the subscriber falls too far behind, intermediate values are dropped,
+    /// but the latest value of every changed parameter is still emitted.
+    pub async fn camera_events(
+        &self,
+    ) -> impl Stream<Item = (u8, CameraParameterID, CameraParameterValue)> {
+        // Subscribe before taking a snapshot, so we don't miss an update in
+        // between.
+        let updates = self.subscribe_filtered(StateUpdate::CAMERA_PARAMETERS);
+        let known = self.get_state().await.camera_parameters.clone();
+
+        updates
+            .scan(known, |known, (state, _)| {
+                let changes: Vec<_> = state
+                    .camera_parameters
+                    .iter()
+                    .filter(|(k, v)| known.get(k) != Some(v))
+                    .map(|(&(input, parameter), value)| (input, parameter, value.clone()))
+                    .collect();
+                known.clone_from(&state.camera_parameters);
+                futures::future::ready(Some(futures::stream::iter(changes)))
+            })
+            .flatten()
+    }
+
+    /// Subscribes to changes of each source's
+    /// [tally state][AtemState::tally_by_source].
+    ///
+    /// The switcher sends a complete tally snapshot whenever _any_ source's
+    /// tally changes. This only emits `(source, flags)` for sources whose
+    /// flags differ from the last emitted value (or when this was called),
+    /// which is useful for driving tally lamps over a slow bus. Sources which
+    /// are no longer in the snapshot are emitted with empty [TallyFlags].
+    ///
+    /// Changes in each snapshot are emitted in source ID order.
+    ///
+    /// If the subscriber falls too far behind, intermediate snapshots are
+    /// dropped, but the latest flags of every changed source are still
+    /// emitted.
+    pub async fn tally_changes(&self) -> impl Stream<Item = (VideoSource, TallyFlags)> {
+        // Subscribe before taking a snapshot, so we don't miss an update in
+        // between.
+        let updates = self.subscribe_filtered(StateUpdate::TALLY_BY_SOURCE);
+        let known = self.get_state().await.tally_by_source.clone();
+
+        updates
+            .scan(known, |known, (state, _)| {
+                let removed = known
+                    .iter()
+                    .filter(|(source, flags)| {
+                        !state.tally_by_source.contains_key(source)
+                            && **flags != TallyFlags::default()
+                    })
+                    .map(|(&source, _)| (source, TallyFlags::default()));
+                let mut changes: Vec<_> = state
+                    .tally_by_source
+                    .iter()
+                    .filter(|(source, flags)| {
+                        known.get(source).copied().unwrap_or_default() != **flags
+                    })
+                    .map(|(&source, &flags)| (source, flags))
+                    .chain(removed)
+                    .collect();
+                changes.sort_by_key(|(source, _)| *source as u16);
+                known.clone_from(&state.tally_by_source);
+                futures::future::ready(Some(futures::stream::iter(changes)))
+            })
+            .flatten()
+    }
+}
+
+/// A packet to retry sending if there is no response from the switcher.
+struct PacketWaitingForResponse {
+    /// The packet which will be re-sent after a timeout.
+    pkt: AtemPacket,
+    /// Channel which was awaited for a response.
+    responder: Option<oneshot::Sender<Result<(), Error>>>,
+    /// Number of remaining retries for the packet.
+    retry_limit: u8,
+    /// Time when a retry was last attempted. The packet will be retried after
+    /// the [retransmit interval][ConnectionOptions::retransmit_interval] has
+    /// passed.
+    last_attempt: Instant,
+}
+
+/// Coordinates the connection with the device.
+///
+/// This runs its own event loop ([`AtemReceiver::run()`]).
+struct AtemReceiver {
+    addr: SocketAddr,
+    channel: AtemUdpChannel,
+    cmd_rx: mpsc::Receiver<AsyncCommand>,
+    cmd_tx_weak: mpsc::WeakSender<AsyncCommand>,
+    /// Packets which have been received from the switcher. Events will be
+    /// sorted by [`AtemPacket::sender_packet_id`].
+    tx: Option<mpsc::Sender<AtemPacket>>,
+    /// The current session ID for the connection.
+    session_id: u16,
+    /// Packets which have not yet been forwarded to [Self::tx]. This must
+    /// remain sorted by [`AtemPacket::sender_packet_id`].
+    rx_queue: VecDeque<AtemPacket>,
+    /// The last time we tried to send to [Self::tx]
+    last_rx_time: Instant,
+    /// The next packet ID we expect to forward to [Self::tx]
+    next_pkt_forward: u16,
+    /// Atomic counter to track which [`AtemPacket::sender_packet_id`] should be
+    /// used next.
+    sender_packet_id: AtomicU16,
+    /// Packets that have been sent which are waiting for a response from the
+    /// switcher. This must remain sorted by [`AtemPacket::sender_packet_id`].
+    ///
+    /// This is limited to [`Self::MAX_ACK_QUEUE_LENGTH`].
+    ack_queue: VecDeque<PacketWaitingForResponse>,
+    /// [Notify] used to track when a request for the device's clock times out.
+    clock_notifier: Arc<Notify>,
+    /// Currently locked storage domains by any client.
+    storage_locks: HashSet<u16>,
+    owned_storage_locks: HashMap<u16, Weak<StorageLock>>,
+    /// File downloads which are still in progress.
+    downloads: HashMap<u16, AsyncFileDownloadRequest>,
+    /// File uploads which are still in progress.
+    uploads: HashMap<u16, AsyncFileUploadRequest>,
+    upload_chunk_params_rx: mpsc::Receiver<FileTransferChunkParams>,
+    upload_chunk_params_tx: mpsc::Sender<FileTransferChunkParams>,
+    /// File uploads which are completed, and awaiting confirmation from the
+    /// switcher ([TransferCompleted][crate::protocol::atom::TransferCompleted]).
+    finished_uploads: HashMap<
+        u16,
+        (
+            Option<oneshot::Sender<Result<(), Error>>>,
+            Option<Arc<StorageLock>>,
+        ),
+    >,
+    /// [Notify] used to track when we need to stop our main event loop.
+    stop_main_loop: Arc<Notify>,
+    /// We've already issued a disconnect command, or the switcher is
+    /// disconnecting us.
+    disconnected: AtomicBool,
+    state: Arc<CowCell<AtemState>>,
+    state_rx: broadcast::Receiver<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    state_tx: broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    /// Broadcasts [TransferEvent]s to [AtemController::transfer_events()].
+    transfer_tx: broadcast::Sender<TransferEvent>,
+    state_task: Option<JoinHandle<Result<(), Error>>>,
+    /// Counts of atoms received from the switcher, shared with
+    /// [AtemController].
+    observed_atoms: Option<ObservedAtoms>,
+    /// Round-trip times of clock requests, shared with [AtemController].
+    rtt: Arc<Mutex<RoundTripTime>>,
+    /// Length of [Self::ack_queue] and [Self::journal], shared with
+    /// [AtemController].
+    outstanding_commands: Arc<AtomicUsize>,
+    options: ConnectionOptions,
+    reconnection_signal: Option<oneshot::Receiver<()>>,
+    initialisation_complete: bool,
+    /// Set once the state task has applied
+    /// [`InitialisationComplete`][crate::protocol::atom::InitialisationComplete]
+    /// for the current session, shared with [AtemController].
+    initialised: Arc<AtomicBool>,
+    /// Holds back state updates during [`AtemController::set_video_mode()`],
+    /// shared with [AtemController].
+    video_mode_hold: Option<SharedVideoModeHold>,
+    /// Unacknowledged commands from a lost session, which will be re-sent
+    /// once the switcher has finished initialising.
+    ///
+    /// This is only used with [`ConnectionOptions::replay_on_reconnect`].
+    journal: Vec<AsyncCommand>,
+}
+
+impl AtemReceiver {
+    const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(1);
+    const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+    /// Depth of the [TransferEvent] broadcast buffer.
+    const TRANSFER_EVENT_CHANNEL_SIZE: usize = 64;
+
+    /// Depth of the upload chunk params receive buffer.
+    const UPLOAD_CHUNK_PARAMS_SIZE: usize = 16;
+
+    /// Depth of the command transmit buffer.
+    ///
+    /// This affects how many [`AsyncCommand`]s can sit in
+    /// [`cmd_rx`][Self::cmd_rx].
+    ///
+    /// Once a packet has been sent once, it enters
+    /// [`ack_queue`][Self::ack_queue], which is affected by
+    /// [`MAX_ACK_QUEUE_LENGTH`][Self::MAX_ACK_QUEUE_LENGTH].
+    const COMMAND_CHANNEL_SIZE: usize = 16;
+
+    /// Maximum length which [`ack_queue`][Self::ack_queue] may grow to.
+    const MAX_ACK_QUEUE_LENGTH: usize = 512;
+    const OVERFLOW_MARGIN: u16 = AtemPacket::MAX_PACKET_ID - (Self::MAX_ACK_QUEUE_LENGTH as u16);
+    /// [`client_packet_id`][AtemPacket::client_packet_id] for packets
+    /// containing commands.
+    ///
+    /// `BMDSwitcherAPI` has been observed sending either `0x00` or `0x01`
+    /// here, but no pattern (alternating, incrementing, or tied to the
+    /// command type) has been identified, and switchers act on commands with
+    /// either value. This is most likely uninitialised memory, so we always
+    /// send `0x00`.
+    const COMMAND_CLIENT_PACKET_ID: u16 = 0;
+
+    /// Create a new `recv_task`.
+    ///
+    /// ## Returns
+    ///
+    /// * `channel`: UDP connection to work with
+    /// * `tx`: [broadcast::Sender] where incoming packets from the device go to
+    fn new(addr: SocketAddr, options: ConnectionOptions) -> (Self, mpsc::Sender<AsyncCommand>) {
+        let (cmd_tx, cmd_rx) = mpsc::channel(Self::COMMAND_CHANNEL_SIZE);
+        let cmd_tx_weak = cmd_tx.downgrade();
+        let (state_tx, state_rx) = broadcast::channel(16);
+        let (transfer_tx, _) = broadcast::channel(Self::TRANSFER_EVENT_CHANNEL_SIZE);
+        let (upload_chunk_params_tx, upload_chunk_params_rx) =
+            mpsc::channel(Self::UPLOAD_CHUNK_PARAMS_SIZE);
+        let mut channel = AtemUdpChannel::new();
+        channel.set_lossy_utf8(options.lossy_utf8);
+        (
+            Self {
+                addr,
+                channel,
+                cmd_rx,
+                cmd_tx_weak,
+                tx: None,
+                session_id: 0,
+                rx_queue: VecDeque::new(),
+                last_rx_time: Instant::now(),
+                next_pkt_forward: 1,
+                sender_packet_id: AtomicU16::new(1),
+                ack_queue: VecDeque::new(),
+                clock_notifier: Arc::new(Notify::new()),
+                storage_locks: HashSet::new(),
+                owned_storage_locks: HashMap::new(),
+                downloads: HashMap::new(),
+                uploads: HashMap::new(),
+                finished_uploads: HashMap::new(),
+                upload_chunk_params_rx,
+                upload_chunk_params_tx,
+                stop_main_loop: Arc::new(Notify::new()),
+                disconnected: AtomicBool::new(false),
+                state: Arc::new(CowCell::new(AtemState::default())),
+                state_tx,
+                state_rx,
+                transfer_tx,
+                state_task: None,
+                observed_atoms: options
+                    .record_observed_atoms
+                    .then(|| Arc::new(Mutex::new(BTreeMap::new()))),
+                rtt: Arc::new(Mutex::new(RoundTripTime::default())),
+                outstanding_commands: Arc::new(AtomicUsize::new(0)),
+                options,
+                reconnection_signal: None,
+                initialisation_complete: false,
+                initialised: Arc::new(AtomicBool::new(false)),
+                video_mode_hold: options
+                    .coalesce_video_mode_change
+                    .then(|| Arc::new(Mutex::new(VideoModeHold::default()))),
+                journal: Vec::new(),
+            },
+            cmd_tx,
+        )
+    }
+
+    async fn spawn_state_task(
+        &mut self,
+        mut rx: mpsc::Receiver<AtemPacket>,
+    ) -> Result<oneshot::Receiver<()>, Error> {
+        let (initialised_tx, initialised_rx) = oneshot::channel();
+        debug!("Spawning state_task");
+        let state_state = self.state.clone();
+        let state_tx = self.state_tx.clone();
+        let observed_atoms = self.observed_atoms.clone();
+        let initialised = self.initialised.clone();
+        let video_mode_hold = self.video_mode_hold.clone();
+
+        self.state_task = Some(tokio::task::spawn(async move {
+            let mut initialised_tx = Some(initialised_tx);
+            while let Some(pkt) = rx.recv().await {
+                if let Some(cmds) = pkt.atoms() {
+                    if let Some(observed_atoms) = &observed_atoms {
+                        let mut o = observed_atoms.lock().expect("observed atoms lock poisoned");
+                        for cmd in cmds {
+                            *o.entry(cmd.payload.magic()).or_default() += 1;
+                        }
+                    }
+
+                    let mut w = state_state.write().await;
+                    let updated_fields = w.update_state(cmds)?;
                     if !updated_fields.is_empty() {
                         w.commit().await;
 
-                        // It doesn't matter whether this actually succeeds
-                        let _ = state_tx.send((state_state.read().await, updated_fields));
+                        // Set this before sending the update, so that
+                        // wait_for_initialisation() can't miss it.
+                        if updated_fields.intersects(StateUpdate::INITIALISATION_COMPLETE) {
+                            initialised.store(true, Ordering::Relaxed);
+                        }
+
+                        let state = state_state.read().await;
+                        let broadcast_fields = match &video_mode_hold {
+                            Some(hold) => hold
+                                .lock()
+                                .unwrap_or_else(PoisonError::into_inner)
+                                .coalesce(&state, updated_fields),
+                            None => updated_fields,
+                        };
+
+                        if !broadcast_fields.is_empty() {
+                            // It doesn't matter whether this actually succeeds
+                            let _ = state_tx.send((state, broadcast_fields));
+                        }
+
+                        // Signal initialisation completion after updating our
+                        // state.
+                        if updated_fields.intersects(StateUpdate::INITIALISATION_COMPLETE) {
+                            if let Some(t) = initialised_tx.take() {
+                                if t.send(()).is_err() {
+                                    error!("Could not signal initialisation completion");
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+
+            debug!("state_task done");
+            Ok::<(), Error>(())
+        }));
+
+        Ok(initialised_rx)
+    }
+
+    /// Initialises the connection to the switcher, and requests the current state.
+    async fn initialise(&mut self) -> Result<oneshot::Receiver<()>, Error> {
+        // Explicitly clear internal states, in case some other task has a copy
+        // of this.
+        self.sender_packet_id.store(1, Ordering::SeqCst);
+        self.session_id = 0;
+        self.rx_queue.clear();
+        self.next_pkt_forward = 1;
+        self.last_rx_time = Instant::now();
+        *self.rtt.lock().expect("rtt lock poisoned") = RoundTripTime::default();
+        self.disconnected.store(false, Ordering::SeqCst);
+        self.storage_locks.clear();
+        self.owned_storage_locks.clear();
+        let ids: Vec<u16> = self
+            .downloads
+            .keys()
+            .chain(self.uploads.keys())
+            .chain(self.finished_uploads.keys())
+            .copied()
+            .collect();
+        for id in ids {
+            self.emit_transfer_event(TransferEvent::Cancelled { id });
+        }
+        self.downloads.clear();
+        self.uploads.clear();
+        self.finished_uploads.clear();
+        self.initialisation_complete = false;
+        self.initialised.store(false, Ordering::Relaxed);
+        self.clock_notifier = Arc::new(Notify::new());
+        self.stop_main_loop = Arc::new(Notify::new());
+        (self.upload_chunk_params_tx, self.upload_chunk_params_rx) =
+            mpsc::channel(Self::UPLOAD_CHUNK_PARAMS_SIZE);
+        if self.options.replay_on_reconnect {
+            self.journal_ack_queue();
+        }
+        self.abandon_ack_queue();
+        {
+            let mut s = self.state.write().await;
+            AtemState::default().clone_into(&mut s);
+            s.commit().await;
+        }
+
+        self.channel.connect(self.addr).await?;
+        let (tx, rx) = mpsc::channel::<AtemPacket>(16);
+        self.tx = Some(tx);
+        let initialised_rx = self.spawn_state_task(rx).await?;
+
+        self.handshake().await?;
+
+        debug!("Yielding further processing to main loop...");
+        Ok(initialised_rx)
+    }
+
+    /// Attempts to establish a session with the switcher using
+    /// [`HANDSHAKE_PROFILE`], and then requests the switcher's current state.
+    ///
+    /// Returns [`Error::Timeout`] if the switcher didn't respond to our
+    /// connection request.
+    async fn handshake(&mut self) -> Result<(), Error> {
+        // Our initial session ID should be random, and not set the highest bit.
+        let initial_session_id = rand::rng().random_range(1..=0x7fff);
+
+        debug!("Connecting on session {initial_session_id:#x}...");
+        self.channel
+            .send(&AtemPacket::new_control(
+                AtemPacketFlags::new().with_control(true),
+                initial_session_id,
+                0,
+                HANDSHAKE_PROFILE.connect_client_packet_id(),
+                0,
+                AtemControl::Connect,
+            ))
+            .await?;
+
+        debug!("Waiting for init packet for session {initial_session_id:#x}...");
+        let (switcher_packet_id, session_id) = tokio::time::timeout(self.options.init_timeout, async {
+            loop {
+                let resp = self.channel.recv().await?;
+                if resp.session_id != initial_session_id {
+                    // wrong session ID
+                    continue;
+                }
+
+                let Some(control) = resp.control() else {
+                    // wrong payload type
+                    continue;
+                };
+
+                match control {
+                    AtemControl::ConnectAck { session_id } => {
+                        return Ok((resp.sender_packet_id, session_id | 0x8000));
+                    }
+
+                    AtemControl::ConnectNack => {
+                        error!("switcher rejected connection attempt for session {initial_session_id:#x}");
+                        return Err(Error::MixerOverloaded);
+                    }
+
+                    _ => {
+                        error!("Unexpected control code {control:?} on session {initial_session_id:#x}");
+                        return Err(Error::UnexpectedState);
+                    }
+                }
+            }
+        })
+        .await
+        .map_err(|_| {
+            error!("timeout waiting for INIT response from switcher on session {initial_session_id:#x}");
+            Error::Timeout
+        })??;
+
+        // The proper session ID to use for later packets
+        self.session_id = session_id;
+
+        // Acknowledge the INIT response using `initial_session_id`, which
+        // triggers a request for current state. The response will be on
+        // `session_id`.
+        debug!("Established session {session_id:#x} from {initial_session_id:#x}, requesting current switcher state...");
+        self.channel
+            .send(&AtemPacket::new(
+                AtemPacketFlags::new().with_response(true),
+                initial_session_id,
+                switcher_packet_id,
+                HANDSHAKE_PROFILE.request_state_client_packet_id(),
+                0,
+            ))
+            .await?;
+
+        Ok(())
+    }
+
+    /// Disconnects from the switcher.
+    fn disconnect(&mut self) -> Result<(), Error> {
+        info!("AtemReceiver disconnecting...");
+        // Need to stop the main loop
+        self.stop_main_loop.notify_waiters();
+        if self.disconnected.swap(true, Ordering::SeqCst) {
+            debug!("switcher already disconnected us!");
+            return Ok(());
+        }
+
+        // Take ownership of the connection, because this isn't run in an async
+        // context, and the tokio runtime may be shutting down.
+        let sock = self.channel.take_std_socket()?;
+        sock.set_nonblocking(false)?;
+        sock.set_read_timeout(Some(Self::DISCONNECT_TIMEOUT))?;
+        sock.set_write_timeout(Some(Self::DISCONNECT_TIMEOUT))?;
+
+        // Release anything held by transfers which are still in progress, so
+        // that the switcher isn't left locked.
+        let cmds = self.abandon_transfers_atoms();
+        if !cmds.is_empty() {
+            let pkt = AtemPacket::new_atoms(
+                AtemPacketFlags::new().with_ack(true),
+                self.session_id,
+                0,
+                Self::COMMAND_CLIENT_PACKET_ID,
+                self.sender_packet_id.fetch_add(1, Ordering::SeqCst),
+                cmds,
+            );
+            let mut out = Cursor::new(Vec::new());
+            pkt.write(&mut out)?;
+            sock.send(&out.into_inner())?;
+            debug!("sent transfer cancellation");
+        }
+
+        let pkt = disconnect_packet(self.session_id);
+        let mut out = Cursor::new(Vec::new());
+        pkt.write(&mut out)?;
+        sock.send(&out.into_inner())?;
+
+        debug!("sent disconnect");
+
+        // Don't worry about waiting for a response.
+        Ok(())
+    }
+
+    /// Makes [Atom]s which cancel every [file download][SetupFileDownload]
+    /// (client to switcher) in progress, and release the storage locks held by
+    /// every transfer in progress.
+    ///
+    /// This is only used on disconnection, where the [StorageLock]s' own
+    /// unlock requests can't be sent.
+    fn abandon_transfers_atoms(&self) -> Vec<Atom> {
+        let store_ids: BTreeSet<u16> = self
+            .downloads
+            .values()
+            .filter_map(|req| req.storage_lock.as_ref())
+            .chain(
+                self.uploads
+                    .values()
+                    .filter_map(|req| req.storage_lock.as_ref()),
+            )
+            .chain(
+                self.finished_uploads
+                    .values()
+                    .filter_map(|(_, lock)| lock.as_ref()),
+            )
+            .map(|lock| {
+                lock.make_unavailable();
+                lock.store_id
+            })
+            .collect();
+
+        let mut ids: Vec<u16> = self.uploads.keys().copied().collect();
+        ids.sort_unstable();
+        ids.into_iter()
+            .map(|id| Atom::new(CancelFileDownload { id }))
+            .chain(
+                store_ids
+                    .into_iter()
+                    .map(|store_id| Atom::new(MediaPoolLock::unlock(store_id))),
+            )
+            .collect()
+    }
+
+    /// Starts the main event loop.  There are four sources of events:
+    ///
+    /// * `cmd_rx.recv`: [handles queued commands to send to the switcher][Self::handle_queued_command]
+    ///
+    ///   Commands are logged in a buffer if retransmission is required.
+    ///
+    /// * `channel.recv`: [collects new packets from the switcher to process][Self::handle_incoming_packet],
+    ///   and pushes them into the receiver queue (in order).
+    ///
+    /// * `heartbeat_wakeup`: periodically
+    ///   [requests the current clock state][Self::request_clock], every
+    ///   [`heartbeat_interval`][ConnectionOptions::heartbeat_interval].
+    ///
+    ///   This acts as a keep-alive / health check, if it fails,
+    ///   `clock_notifier` is triggered.
+    ///
+    /// * `retransmit_wakeup`: every
+    ///   [`retransmit_interval`][ConnectionOptions::retransmit_interval],
+    ///   runs a few tasks:
+    ///
+    ///   * [limit the receiver queue][Self::limit_rx_queue]; if it has grown
+    ///     [too large][ConnectionOptions::max_rx_queue_length], or it has been
+    ///     [too long][ConnectionOptions::max_rx_gap_time] since it was
+    ///     [last forwarded][Self::forward_rx_queue], it applies the
+    ///     [receive queue policy][ConnectionOptions::rx_queue_policy].
+    ///
+    ///   * [retransmit any unacknowledged commands][Self::do_retransmits], and
+    ///     notify receivers of any unackowledged commands.
+    ///
+    ///   * [cancel any downloads][Self::cancel_closed_downloads] whose
+    ///     receivers have been closed.
+    ///
+    /// * `clock_notifier`: aborts the event loop if there was no
+    ///   acknowledgement of a clock state request.
+    async fn main_loop(&mut self) -> Result<(), Error> {
+        let retransmit_wakeup = IntervalStream::new(interval(self.options.retransmit_interval));
+        pin_mut!(retransmit_wakeup);
+        let heartbeat_wakeup = IntervalStream::new(interval(self.options.heartbeat_interval));
+        pin_mut!(heartbeat_wakeup);
+
+        trace!(
+            "starting loop, reconnection_signal.is_some() = {}",
+            self.reconnection_signal.is_some()
+        );
+        let reconnection_signal = self.reconnection_signal.take();
+        let reconnection_notify = Arc::new(Notify::new());
+        let reconnection_notifier = reconnection_notify.clone();
+        let state = self.state.clone();
+        let state_tx = self.state_tx.clone();
+        tokio::task::spawn(async move {
+            if let Some(reconnection_signal) = reconnection_signal {
+                if reconnection_signal.await.is_ok() {
+                    let _ = state_tx.send((state.read().await, StateUpdate::CONNECTION_RESTORED));
+                }
+                reconnection_notifier.notify_waiters();
+            }
+        });
+
+        loop {
+            select! {
+                () = self.stop_main_loop.notified() => {
+                    // This might not execute!
+                    info!("stopping main_loop");
+                    return Ok(());
+                }
+
+                () = self.clock_notifier.notified() => {
+                    error!("clock request timed out - loss of connectivity?");
+                    return Err(Error::Timeout);
+                }
+
+                // Look for something to send
+                Some(async_cmd) = self.cmd_rx.recv() => {
+                    self.handle_queued_command(async_cmd).await?;
+                }
+
+                // Get packets from the switcher
+                Ok(resp) = self.channel.recv() => {
+                    self.handle_incoming_packet(resp).await?;
+                }
+
+                Some(_) = heartbeat_wakeup.next() => {
+                    if self.initialisation_complete {
+                        self.request_clock().await?;
+                    }
+                }
+
+                Some(_) = retransmit_wakeup.next() => {
+                    self.limit_rx_queue().await?;
+                    self.do_retransmits().await?;
+                    self.cancel_closed_downloads();
+                }
+
+                Some(params) = self.upload_chunk_params_rx.recv() => {
+                    self.handle_file_transfer_chunk_params(params).await?;
+                }
+
+                () = reconnection_notify.notified() => {
+                    debug!("reconnection_signal fired");
+                }
+            }
+        }
+    }
+
+    async fn run(mut self) -> Result<(), Error> {
+        loop {
+            debug!("starting main_loop");
+            let r = self.main_loop().await;
+            if r.is_err() {
+                // Let subscribers know that there won't be any more updates
+                // for a while.
+                self.initialised.store(false, Ordering::Relaxed);
+                let _ = self
+                    .state_tx
+                    .send((self.state.read().await, StateUpdate::CONNECTION_LOST));
+            }
+
+            // Abort the state_task, and check if it returned an error
+            if let Some(state_task) = self.state_task.take() {
+                state_task.abort();
+                match state_task.await {
+                    Err(join_error) => {
+                        if join_error.is_panic() {
+                            error!("state_task paniced: {}", join_error.to_string());
+                            return Err(Error::Internal);
+                        }
+
+                        // Cancellation is expected here...
+                    }
+
+                    Ok(Err(e)) => {
+                        error!("state_task reported error: {e:?}");
+                        return Err(e);
+                    }
+
+                    Ok(Ok(())) => (),
+                }
+            }
+
+            if !self.options.reconnect {
+                // Don't automatically reconnect.
+                return r;
+            }
+
+            loop {
+                info!("disconnected; waiting for reconnection");
+                tokio::time::sleep(Self::RECONNECT_DELAY).await;
+
+                info!("reconnecting...");
+                match self.initialise().await {
+                    Err(e) => {
+                        error!("error initialising reconnection: {e:?}");
+                        continue;
+                    }
+
+                    Ok(o) => {
+                        // The initialisation can't happen until the main_loop
+                        // is running again; so we can't stop here and wait.
+                        self.reconnection_signal = Some(o);
+                    }
+                }
+
+                info!("reconnected, waiting for new initialisation");
+                break;
+            }
+        }
+    }
+
+    /// Sends some commands to the switcher, returning the `sender_packet_id`
+    /// used for that packet.
+    async fn handle_queued_command(&mut self, async_cmd: AsyncCommand) -> Result<(), Error> {
+        let (cmds, responder) = match async_cmd {
+            AsyncCommand::Commands { cmds, responder } => (cmds, responder),
+            AsyncCommand::FileDownload(req) => {
+                let id = rand::random();
+                let cmd = Atom::new(SetupFileUpload {
+                    id,
+                    store_id: req.store_id,
+                    index: req.index.into(),
+                    typ: req.typ,
+                });
+
+                self.emit_transfer_event(TransferEvent::Started {
+                    id,
+                    direction: TransferDirection::Download,
+                    store_id: req.store_id,
+                    index: req.index,
+                });
+
+                // Now also register a handler
+                self.downloads.insert(id, req);
+
+                // TODO: handle leakage on errors
+                (vec![cmd], None)
+            }
+            AsyncCommand::FileUpload(req) => {
+                let id = rand::random();
+                let cmd = Atom::new(SetupFileDownload {
+                    id,
+                    store_id: req.store_id,
+                    index: req.index.into(),
+                    size: req.size,
+                    typ: req.typ,
+                    is_rle: req.is_rle,
+                });
+                self.emit_transfer_event(TransferEvent::Started {
+                    id,
+                    direction: TransferDirection::Upload,
+                    store_id: req.store_id,
+                    index: req.index,
+                });
+                self.uploads.insert(id, req);
+                // TODO: handle leakage on errors
+                (vec![cmd], None)
+            }
+            AsyncCommand::StorageLock {
+                store_id,
+                responder,
+            } => {
+                if store_id >= 64 {
+                    let _ = responder.send(Err(Error::ParameterOutOfRange));
+                    return Ok(());
+                }
+
+                // TODO: replace with try_insert: https://github.com/rust-lang/rust/issues/82766
+                let owned_storage_lock = match self.owned_storage_locks.get_mut(&store_id) {
+                    Some(l) => l,
+                    None => {
+                        let w = Weak::new();
+                        assert!(self.owned_storage_locks.insert(store_id, w).is_none());
+                        self.owned_storage_locks
+                            .get_mut(&store_id)
+                            .expect("expected storage_lock to exist now")
+                    }
+                };
+
+                match owned_storage_lock.upgrade() {
+                    None => {
+                        // The weak pointer was dropped, need to make another storage lock.
+
+                        // Check if the lock is owned by another client
+                        if self.storage_locks.contains(&store_id) {
+                            warn!("storage {} is locked by another client", store_id);
+                            // let _ = responder.send(Err(Error::StorageLocked));
+                            // return Ok(());
+                        }
+
+                        let Some(cmd_tx) = self.cmd_tx_weak.upgrade() else {
+                            error!("could not upgrade cmd_tx");
+                            let _ = responder.send(Err(Error::Internal));
+                            return Err(Error::Internal);
+                        };
+
+                        // Create a lock entity to fill later
+                        let storage_lock = Arc::new(StorageLock::new(store_id, cmd_tx));
+                        *owned_storage_lock = Arc::downgrade(&storage_lock);
+
+                        // Now try to lock it
+                        let cmd = Atom::new(MediaPoolLock::lock(store_id));
+
+                        let _ = responder.send(Ok(storage_lock));
+                        (vec![cmd], None)
+                    }
+
+                    Some(storage_lock) => {
+                        // We already have a storage lock, so we can just pass that
+                        // back to the client.
+                        let _ = responder.send(Ok(storage_lock.clone()));
+
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        let r = async {
+            let sender_packet_id = self.sender_packet_id.fetch_add(1, Ordering::SeqCst);
+            trace!(">>> 0x{sender_packet_id:04X}: {:?}", cmds);
+            let pkt = AtemPacket::new_atoms(
+                AtemPacketFlags::new().with_ack(true),
+                self.session_id,
+                0,
+                Self::COMMAND_CLIENT_PACKET_ID,
+                sender_packet_id,
+                cmds,
+            );
+
+            self.channel.send(&pkt).await?;
+
+            Ok(pkt)
+        }
+        .await;
+
+        let mut pkt = match r {
+            Err(e) => {
+                if let Some(responder) = responder {
+                    if let Err(e) = responder.send(Err(e)) {
+                        error!("error sending error response to responder: {e:?}");
+                    }
+                }
+
+                return Err(Error::Internal);
+            }
+
+            Ok(p) => p,
+        };
+
+        // Add an entry to the ack_queue for this packet. Mark it as a
+        // retransmission for future sends.
+        pkt.flags.set_retransmission(true);
+        let waiting = PacketWaitingForResponse {
+            pkt,
+            responder,
+            retry_limit: self.options.retransmit_limit,
+            last_attempt: Instant::now(),
+        };
+
+        if self.ack_queue.len() >= Self::MAX_ACK_QUEUE_LENGTH {
+            warn!(
+                "ack_queue is long ({} entries), has the switcher stalled?",
+                self.ack_queue.len()
+            );
+            self.clear_ack_queue(|| Error::ChannelUnavailable);
+        }
+
+        let sender_packet_id = waiting.pkt.sender_packet_id;
+        let idx = self
+            .ack_queue
+            .partition_point(|p| p.pkt.sender_packet_id < waiting.pkt.sender_packet_id);
+        self.ack_queue.insert(idx, waiting);
+        self.update_outstanding_commands();
+        trace!(
+            ack_queue_len = self.ack_queue.len(),
+            "sent command: 0x{sender_packet_id:04X}",
+        );
+
+        Ok(())
+    }
+
+    /// Upper bound for
+    /// [`max_rx_queue_length`][ConnectionOptions::max_rx_queue_length], so
+    /// that the queue can't span more than half of the packet ID space.
+    const MAX_RX_QUEUE_LENGTH_LIMIT: usize = (AtemPacket::MAX_PACKET_ID / 2) as usize;
+
+    /// Maximum number of packets which may be held in
+    /// [`rx_queue`][Self::rx_queue].
+    fn max_rx_queue_length(&self) -> usize {
+        self.options
+            .max_rx_queue_length
+            .min(Self::MAX_RX_QUEUE_LENGTH_LIMIT)
+    }
+
+    /// Packet ID after which [`rx_queue`][Self::rx_queue] may contain packets
+    /// from both before and after the packet ID wraps around.
+    fn rx_queue_overflow_margin(&self) -> u16 {
+        // max_rx_queue_length() is always less than MAX_PACKET_ID.
+        AtemPacket::MAX_PACKET_ID - self.max_rx_queue_length() as u16
+    }
+
+    /// Handles an incoming packet from the device.
+    ///
+    /// If the packet is an acknowledgement of a previously-sent command, this
+    /// will [notify any waiting tasks][Self::handle_ack]. This handles when
+    /// the switcher batch-acknowledges packets.
+    ///
+    /// If the packet has a `sender_packet_id`, then it contains an event. This
+    /// is pushed (in order) into the receiver queue, and when we have the next
+    /// expected `sender_packet_id`, any outstanding packets are
+    /// [forwarded to listeners][Self::forward_rx_queue].
+    ///
+    /// This also [checks the receiver queue][Self::limit_rx_queue].
+    async fn handle_incoming_packet(&mut self, resp: AtemPacket) -> Result<(), Error> {
+        // Check that the incoming packet is for our session ID
+        assert_ne!(
+            0,
+            self.session_id & 0x8000,
+            "handling an incoming packet before establishing a session ID!"
+        );
+        if self.session_id != resp.session_id {
+            warn!(
+                "unexpected session ID: {:#X} != {:#X}",
+                resp.session_id, self.session_id,
+            );
+            return Ok(());
+        }
+
+        self.limit_rx_queue().await?;
+        trace!("<<< 0x{:04X}: {resp:X?}", resp.sender_packet_id);
+
+        if let Some(ctrl) = resp.control() {
+            match ctrl {
+                AtemControl::Disconnect => {
+                    error!("switcher disconnected us!");
+                    self.disconnected.store(true, Ordering::SeqCst);
+
+                    // Acknowledge the disconnect immediately, without queueing.
+                    let pkt = AtemPacket::new_control(
+                        AtemPacketFlags::new().with_control(true),
+                        self.session_id,
+                        0,
+                        0,
+                        0,
+                        AtemControl::DisconnectAck,
+                    );
+                    self.channel.send(&pkt).await?;
+                    return Err(Error::Disconnected);
+                }
+
+                ctrl => {
+                    error!("unexpected control packet: {ctrl:?}");
+                    return Err(Error::UnexpectedState);
+                }
+            }
+        }
+
+        if let Some(atoms) = resp.atoms() {
+            if atoms
+                .iter()
+                .any(|c| matches!(c.payload, Payload::InitialisationComplete(_)))
+            {
+                self.initialisation_complete = true;
+                self.replay_journal().await?;
+            }
+        }
+
+        // Check if this is a response to something we sent earlier.
+        if resp.acked_packet_id != 0 && resp.flags.response() {
+            self.handle_ack(resp.acked_packet_id);
+        }
+
+        if resp.sender_packet_id == 0 && !resp.has_atoms() {
+            // Message is likely an acknowledgement of something we sent
+            // earlier; but it was something already in process (eg: pressing
+            // "Auto" when a transition is already in progress).
+            //
+            // Don't do anything with the rx_queue.
+            //
+            // On overflow, the switcher will send real messages with this
+            // packet ID. BMDSwitcherAPI seems to batch-ack this packet when
+            // sender_packet_id = 1 arrives; but this could mean we lost some
+            // data.
+            // debug!("got zero sender_packet_id: {resp:#?}");
+            return Ok(());
+        }
+
+        if resp.sender_packet_id < self.next_pkt_forward {
+            // Switcher is sending us an old packet again, but we're past that
+            // point. This tends to happen a lot on WiFi.
+            trace!(
+                "switcher sent us old packet {:#04X}, we're up to {:#04X}",
+                resp.sender_packet_id,
+                self.next_pkt_forward
+            );
+
+            // forward_rx_queue should have acked this packet already, but maybe
+            // it was lost? But shouldn't matter, as when everything catches up
+            // again, we'll effectively "batch-ack" the messages.
+            // if let Some(mut ack) = resp.make_ack() {
+            //     trace!("re-acking packet: 0x{:04X}", ack.acked_packet_id);
+            //     if ack.has_atoms() && ack.sender_packet_id == 0 {
+            //         // Contains transfer acks, but no sender_packet_id has been
+            //         // allocated.
+            //         ack.sender_packet_id = self.sender_packet_id.fetch_add(1, Ordering::SeqCst);
+            //         trace!("includes transfer acks, sending with sender_packet_id={:#04X}", ack.sender_packet_id);
+            //     }
+            //     self.channel.send(&ack).await?;
+            // }
+            return Ok(());
+        }
+
+        // Put the received packet into the queue
+        let idx = self
+            .rx_queue
+            .partition_point(|p| p.sender_packet_id < resp.sender_packet_id);
+
+        if self
+            .rx_queue
+            .get(idx)
+            .is_some_and(|p| p.sender_packet_id == resp.sender_packet_id)
+        {
+            // Retransmission of a packet we already received
+            // TODO: maybe should ack this again
+            debug!(
+                "repeat incoming existing packet in queue: 0x{:04X}, next_pkt_forward=0x{:04x}",
+                resp.sender_packet_id, self.next_pkt_forward
+            );
+        } else {
+            if idx > 0 {
+                // We _always_ queue packets, but this means we only log
+                // when we're behind (due to packet loss or out-of-order
+                // delivery).
+                trace!(
+                    "queuing packet, next_pkt_forward=0x{:04X}",
+                    self.next_pkt_forward
+                );
+            }
+            self.rx_queue.insert(idx, resp);
+        }
+
+        self.forward_ready_packets().await
+    }
+
+    /// Forwards every packet in the queue which is sequentially next to
+    /// forward, stopping at the first gap.
+    async fn forward_ready_packets(&mut self) -> Result<(), Error> {
+        let overflow_margin = self.rx_queue_overflow_margin();
+        loop {
+            let s = if self.next_pkt_forward >= overflow_margin {
+                // We're close to the overflow point, so do a lower bounds check to
+                // ensure only grab what's "high"
+                self.rx_queue
+                    .partition_point(|p| overflow_margin < p.sender_packet_id)
+            } else {
+                0
+            };
+
+            let idx = self
+                .rx_queue
+                .partition_point(|p| p.sender_packet_id <= self.next_pkt_forward);
+            if idx <= s {
+                return Ok(());
+            }
+            self.forward_rx_queue(s..idx).await?;
+        }
+    }
+
+    /// Skips over missing packets at the head of the receiver queue, by moving
+    /// [`next_pkt_forward`][Self::next_pkt_forward] to the lowest queued
+    /// packet ID, and then forwards everything which follows it.
+    ///
+    /// Any state carried by the missing packets is lost.
+    async fn resync_rx_queue(&mut self) -> Result<(), Error> {
+        let overflow_margin = self.rx_queue_overflow_margin();
+        let high = if self.next_pkt_forward >= overflow_margin {
+            // Packets from before the overflow point come first.
+            self.rx_queue
+                .iter()
+                .find(|p| p.sender_packet_id >= overflow_margin)
+        } else {
+            None
+        };
+        let Some(lowest) = high.or(self.rx_queue.front()).map(|p| p.sender_packet_id) else {
+            return Ok(());
+        };
+
+        warn!(
+            "packet(s) 0x{:04X}..0x{lowest:04X} never arrived, skipping them",
+            self.next_pkt_forward
+        );
+        self.next_pkt_forward = lowest;
+        self.forward_ready_packets().await
+    }
+
+    /// Forwards the contents of the reciever queue to subscribers, and
+    /// acknowledges the packet(s).
+    ///
+    /// Unlike the BM SDK, this acknowledges *every* packet explicitly, and
+    /// doesn't batch acks.
+    async fn forward_rx_queue(
+        &mut self,
+        range: impl std::ops::RangeBounds<usize>,
+    ) -> Result<(), Error> {
+        let packets: Vec<AtemPacket> = self.rx_queue.drain(range).collect();
+        for mut p in packets {
+            // This only handles partial drains of "what should happen next".
+            // If there's a gap at the head of the queue, resync_rx_queue()
+            // moves next_pkt_forward past it first.
+            //
+            // rx_queue is always sorted by ID. On overflow, a normal partial
+            // drain will skip over the "low" values, and come back for them
+            // later.
+            if p.sender_packet_id == 0x7fff {
+                // Next packet after 0x7fff is 0x0
+                self.next_pkt_forward = 0;
+            } else if self.next_pkt_forward == p.sender_packet_id {
+                // This can go backwards if the switcher retransmits an
+                // older packet after sending a newer packet; particularly
+                // an issue over WiFi.
+                //
+                // This forces us to process every packet in order.
+                self.next_pkt_forward = p.sender_packet_id + 1;
+            }
+
+            let ack: Option<AtemPacket> = HANDSHAKE_PROFILE.make_ack(&p);
+
+            if let Some(cmds) = p.atoms_mut() {
+                // Special-case some commands.
+                // TODO: migrate to extract_if when stable
+                let mut i = 0;
+                while i < cmds.len() {
+                    match cmds[i].payload {
+                        Payload::FileTransferChunkParams(_) => {
+                            // Uploads
+                            let ftcp = cmds.remove(i);
+                            self.enqueue_file_transfer_chunk_params(ftcp).await?;
+                        }
+
+                        Payload::TransferCompleted(_) => {
+                            // Used by uploads and downloads
+                            let ftdc = cmds.remove(i);
+                            self.handle_transfer_completed(ftdc).await?;
+                        }
+
+                        Payload::FileTransferError(_) => {
+                            // Used by uploads and downloads
+                            let ftde = cmds.remove(i);
+                            self.handle_transfer_error(ftde).await?;
+                        }
+
+                        Payload::TransferChunk(_) => {
+                            // Downloads
+                            let chunk = cmds.remove(i);
+                            self.handle_download_chunk(chunk).await?;
+                        }
+
+                        Payload::LockObtained(_) => {
+                            let lkob = cmds.remove(i);
+                            self.handle_lock_obtained(lkob).await?;
+                        }
+
+                        Payload::MediaPoolLockStatus(_) => {
+                            let lkst = cmds.remove(i);
+                            self.handle_lock_state_changed(lkst).await?;
+                        }
+
+                        // Not a file transfer command, ignore it.
+                        _ => i += 1,
+                    }
+                }
+            }
+
+            let Some(tx) = self.tx.as_ref() else {
+                error!("state channel missing!");
+                return Err(Error::Internal);
+            };
+
+            if tx.send(p).await.is_err() {
+                error!("state channel gone!");
+                return Err(Error::Internal);
+            }
+
+            if let Some(mut ack) = ack {
+                trace!("acking packet: 0x{:04X}", ack.acked_packet_id);
+                if ack.has_atoms() && ack.sender_packet_id == 0 {
+                    // Contains transfer acks, but no sender_packet_id has been
+                    // allocated.
+                    ack.sender_packet_id = self.sender_packet_id.fetch_add(1, Ordering::SeqCst);
+                    trace!(
+                        "includes transfer acks, sending with sender_packet_id={:#04X}",
+                        ack.sender_packet_id
+                    );
+                }
+                self.channel.send(&ack).await?;
+            }
+            self.last_rx_time = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    /// Limit the `rx_queue` to a maximum age and size.
+    ///
+    /// If packets are queued behind a gap which hasn't been filled within
+    /// [`max_rx_gap_time`][ConnectionOptions::max_rx_gap_time], or the queue is
+    /// [too large][ConnectionOptions::max_rx_queue_length], this either
+    /// [skips over the gap][Self::resync_rx_queue], or returns
+    /// [`Error::Timeout`], depending on the
+    /// [receive queue policy][ConnectionOptions::rx_queue_policy].
+    ///
+    /// If nothing has been forwarded from the switcher within
+    /// [`max_rx_queue_time`][ConnectionOptions::max_rx_queue_time], this returns
+    /// [`Error::Timeout`].
+    async fn limit_rx_queue(&mut self) -> Result<(), Error> {
+        let rx_duration: Duration = self.last_rx_time.elapsed();
+        if !self.rx_queue.is_empty()
+            && (rx_duration >= self.options.max_rx_gap_time
+                || self.rx_queue.len() >= self.max_rx_queue_length())
+        {
+            return match self.options.rx_queue_policy {
+                RxQueuePolicy::Disconnect => {
+                    warn!(
+                        "packet buffer stalled for too long ({} ms) or too large ({}), disconnecting",
+                        rx_duration.as_millis(),
+                        self.rx_queue.len()
+                    );
+                    Err(Error::Timeout)
+                }
+
+                RxQueuePolicy::FlushAndContinue => {
+                    warn!(
+                        "packet buffer stalled for too long ({} ms) or too large ({}), resynchronising",
+                        rx_duration.as_millis(),
+                        self.rx_queue.len()
+                    );
+                    self.resync_rx_queue().await
+                }
+            };
+        }
+
+        if rx_duration >= self.options.max_rx_queue_time {
+            warn!(
+                "nothing forwarded from switcher for too long ({} ms), disconnecting",
+                rx_duration.as_millis(),
+            );
+            return Err(Error::Timeout);
+        }
+
+        Ok(())
+    }
+
+    /// Handle acknowledgements of previously sent packets.
+    fn handle_ack(&mut self, acked_packet_id: u16) {
+        trace!("got ack for command: 0x{acked_packet_id:04X}");
+        if self.ack_queue.is_empty() {
+            // Nothing outstanding to ack
+            return;
+        }
+
+        // ack_queue is always sorted by sender_packet_id, but this is only a
+        // u16, so it could wrap around.
+        let min_pos = if acked_packet_id >= Self::OVERFLOW_MARGIN {
+            // The acked packet is close to the overflow point, so set a minimum
+            // sender_packet_id that we want to check
+            let min_acked_packet_id =
+                acked_packet_id.saturating_sub(Self::MAX_ACK_QUEUE_LENGTH as u16);
+            self.ack_queue
+                .partition_point(|p| p.pkt.sender_packet_id < min_acked_packet_id)
+        } else {
+            0
+        };
+
+        let pos = self
+            .ack_queue
+            .partition_point(|p| p.pkt.sender_packet_id <= acked_packet_id);
+        if pos == 0 {
+            // All packets waiting for ack are newer than this
+            return;
+        }
+
+        for pending in self.ack_queue.drain(min_pos..pos) {
+            let Some(responder) = pending.responder else {
+                continue;
+            };
+
+            if responder.send(Ok(())).is_err() {
+                error!(
+                    "responder remote side gone ({})",
+                    pending.pkt.sender_packet_id
+                );
+                continue;
+            }
+        }
+        self.update_outstanding_commands();
+    }
+
+    /// Publishes the number of outstanding commands to
+    /// [`AtemController::outstanding_commands()`].
+    fn update_outstanding_commands(&self) {
+        self.outstanding_commands
+            .store(self.ack_queue.len() + self.journal.len(), Ordering::Relaxed);
+    }
+
+    /// Moves commands from [Self::ack_queue] into [Self::journal], so that
+    /// they can be replayed after reconnecting.
+    ///
+    /// Packets which only contain [transient atoms][is_transient_atom] are
+    /// left in [Self::ack_queue].
+    fn journal_ack_queue(&mut self) {
+        let mut i = 0;
+        while i < self.ack_queue.len() {
+            let pending = &self.ack_queue[i];
+            let cmds: Vec<Atom> = pending
+                .pkt
+                .atoms()
+                .into_iter()
+                .flatten()
+                .filter(|cmd| !is_transient_atom(cmd))
+                .cloned()
+                .collect();
+            if cmds.is_empty() {
+                i += 1;
+                continue;
+            }
+
+            let Some(pending) = self.ack_queue.remove(i) else {
+                break;
+            };
+            debug!("journalling command: {cmds:?}");
+            self.journal.push(AsyncCommand::Commands {
+                cmds,
+                responder: pending.responder,
+            });
+        }
+        self.update_outstanding_commands();
+    }
+
+    /// Re-sends any commands in [Self::journal].
+    async fn replay_journal(&mut self) -> Result<(), Error> {
+        if self.journal.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "replaying {} command(s) after reconnection",
+            self.journal.len()
+        );
+        for async_cmd in std::mem::take(&mut self.journal) {
+            self.handle_queued_command(async_cmd).await?;
+        }
+        Ok(())
+    }
+
+    /// Removes all packets from [Self::ack_queue], notifying their responders
+    /// with [`Error::NotAcknowledged`].
+    fn abandon_ack_queue(&mut self) {
+        self.clear_ack_queue(|| Error::NotAcknowledged);
+    }
+
+    /// Removes all packets from [Self::ack_queue], notifying their responders
+    /// with `error()`.
+    fn clear_ack_queue(&mut self, error: impl Fn() -> Error) {
+        for pending in self.ack_queue.drain(..) {
+            let Some(responder) = pending.responder else {
+                continue;
+            };
+
+            if responder.send(Err(error())).is_err() {
+                debug!(
+                    "responder remote side gone ({})",
+                    pending.pkt.sender_packet_id
+                );
+            }
+        }
+        self.update_outstanding_commands();
+    }
+
+    /// Work through the [Self::ack_queue] and retransmit any outstanding
+    /// packets beyond the deadline.
+    async fn do_retransmits(&mut self) -> Result<(), Error> {
+        let mut errors = Vec::new();
+
+        for (i, pending) in self.ack_queue.iter_mut().enumerate() {
+            if pending.last_attempt.elapsed() < self.options.retransmit_interval {
+                // too soon to retransmit
+                continue;
+            }
+
+            if pending.retry_limit == 0 {
+                // No retransmits available, send timeout
+                error!("packet timeout: 0x{:04X}", pending.pkt.sender_packet_id);
+                errors.insert(0, (i, Error::Timeout));
+                continue;
+            }
+
+            pending.retry_limit -= 1;
+            pending.last_attempt = Instant::now();
+            trace!("retransmitting packet: {:?}", pending.pkt);
+            if let Err(e) = self.channel.send(&pending.pkt).await {
+                errors.insert(0, (i, e));
+                continue;
+            }
+        }
+
+        // Collect any errored states
+        for (i, e) in errors {
+            let Some(pending) = self.ack_queue.remove(i) else {
+                error!(
+                    "tried to pop entry #{i} from {} entry ack_queue",
+                    self.ack_queue.len()
+                );
+                return Err(Error::Internal);
+            };
+
+            if let Some(responder) = pending.responder {
+                if responder.send(Err(e)).is_err() {
+                    error!(
+                        "responder remote side gone ({})",
+                        pending.pkt.sender_packet_id
+                    );
+                    continue;
+                }
+            }
+        }
+        self.update_outstanding_commands();
+
+        Ok(())
+    }
+
+    /// Queue a request for the device's clock.
+    async fn request_clock(&mut self) -> Result<(), Error> {
+        let (responder, resp_rx) = oneshot::channel();
+        let async_cmd = AsyncCommand::Commands {
+            cmds: vec![TIME_REQUEST_COMMAND.clone()],
+            responder: Some(responder),
+        };
+
+        let sent = Instant::now();
+        self.handle_queued_command(async_cmd).await?;
+
+        let clock_notifier = self.clock_notifier.clone();
+        let rtt = self.rtt.clone();
+        tokio::task::spawn(async move {
+            // Wait for a result from the resp_rx; this has a RecvError layer
+            // then our Error type.
+            if resp_rx.await.is_ok_and(|r| r.is_ok()) {
+                rtt.lock()
+                    .expect("rtt lock poisoned")
+                    .record(sent.elapsed());
+            } else {
+                clock_notifier.notify_waiters();
+            }
+        });
+        Ok(())
+    }
+
+    async fn handle_download_chunk(&mut self, cmd: Atom) -> Result<(), Error> {
+        let Payload::TransferChunk(chunk) = cmd.payload else {
+            return Err(Error::Internal);
+        };
+
+        let Some(download) = self.downloads.get_mut(&chunk.id) else {
+            return Ok(());
+        };
+
+        let chunk_len = chunk.payload.len();
+        download.bytes_received += chunk_len;
+        download.bytes_since_last_ack += chunk_len;
+        let bytes = download.bytes_received;
+        if download.md5.is_some() {
+            download.received_md5.consume(&chunk.payload);
+        }
+
+        if download.tx.send(Ok(chunk.payload)).await.is_err() {
+            debug!("download channel closed");
+            self.cancel_download(chunk.id);
+            return Ok(());
+        }
+
+        self.emit_transfer_event(TransferEvent::Progress {
+            id: chunk.id,
+            bytes,
+        });
+        Ok(())
+    }
+
+    /// Cancels a download in progress: the download is forgotten, and its
+    /// storage lock is released.
+    ///
+    /// The switcher isn't told about this: [`CancelFileDownload`] (`FTAD`)
+    /// only applies to transfers from the client to the switcher. Any further
+    /// chunks for this transfer are ignored.
+    fn cancel_download(&mut self, id: u16) {
+        let Some(download) = self.downloads.remove(&id) else {
+            return;
+        };
 
-                        // Signal initialisation completion after updating our
-                        // state.
-                        if updated_fields.intersects(StateUpdate::INITIALISATION_COMPLETE) {
-                            if let Some(t) = initialised_tx.take() {
-                                if t.send(()).is_err() {
-                                    error!("Could not signal initialisation completion");
-                                    break;
-                                }
-                            }
-                        }
+        debug!("cancelling download: {id:#04x}");
+        drop(download.storage_lock);
+        self.emit_transfer_event(TransferEvent::Cancelled { id });
+    }
+
+    /// [Cancels][Self::cancel_download] every download whose receiver has
+    /// been closed or dropped.
+    ///
+    /// Downloads are otherwise only cancelled when the next chunk arrives,
+    /// which may never happen if the switcher stalls.
+    fn cancel_closed_downloads(&mut self) {
+        let mut ids: Vec<u16> = self
+            .downloads
+            .iter()
+            .filter(|(_, download)| download.tx.is_closed())
+            .map(|(id, _)| *id)
+            .collect();
+        ids.sort_unstable();
+        for id in ids {
+            self.cancel_download(id);
+        }
+    }
+
+    async fn handle_transfer_completed(&mut self, cmd: Atom) -> Result<(), Error> {
+        let Payload::TransferCompleted(completed) = cmd.payload else {
+            return Err(Error::Internal);
+        };
+
+        if let Some(download) = self.downloads.remove(&completed.id) {
+            debug!("finished download: {:#04x}", completed.id);
+            if let Err(e) = download.verify() {
+                let tx = download.tx;
+                tokio::task::spawn(async move {
+                    if tx.send(Err(e)).await.is_err() {
+                        error!("error notifying download channel");
                     }
+                });
+            }
+            drop(download.storage_lock);
+            self.emit_transfer_event(TransferEvent::Completed { id: completed.id });
+        } else if let Some((responder, storage_lock)) = self.finished_uploads.remove(&completed.id)
+        {
+            debug!("finished upload: {:#04x}", completed.id);
+            if let Some(responder) = responder {
+                if responder.send(Ok(())).is_err() {
+                    error!("error notifying upload responder");
                 }
             }
+            drop(storage_lock);
+            self.emit_transfer_event(TransferEvent::Completed { id: completed.id });
+        }
 
-            debug!("state_task done");
-            Ok::<(), Error>(())
-        }));
+        Ok(())
+    }
 
-        Ok(initialised_rx)
+    /// Handle an incoming [FileTransferError][crate::protocol::atom::FileTransferError].
+    async fn handle_transfer_error(&mut self, cmd: Atom) -> Result<(), Error> {
+        let Payload::FileTransferError(error) = cmd.payload else {
+            return Err(Error::Internal);
+        };
+
+        if let Some(download) = self.downloads.remove(&error.id) {
+            error!(
+                "error during download {:#04x}: {:#02x}",
+                error.id, error.code
+            );
+            let tx = download.tx;
+            tokio::task::spawn(async move {
+                if tx
+                    .send(Err(Error::SwitcherTransferError(error.code)))
+                    .await
+                    .is_err()
+                {
+                    error!("error notifying download channel");
+                }
+            });
+            drop(download.storage_lock);
+            self.emit_transfer_event(TransferEvent::Failed {
+                id: error.id,
+                code: error.code,
+            });
+        } else if let Some(upload) = self.uploads.remove(&error.id) {
+            error!("error during upload {:#04x}: {:#02x}", error.id, error.code);
+            if let Some(responder) = upload.responder {
+                if responder
+                    .send(Err(Error::SwitcherTransferError(error.code)))
+                    .is_err()
+                {
+                    error!("error notifying upload responder");
+                }
+            }
+            drop(upload.storage_lock);
+            self.emit_transfer_event(TransferEvent::Failed {
+                id: error.id,
+                code: error.code,
+            });
+        } else if let Some((responder, storage_lock)) = self.finished_uploads.remove(&error.id) {
+            error!(
+                "error finishing upload {:#04x}: {:#02x}",
+                error.id, error.code
+            );
+            if let Some(responder) = responder {
+                if responder
+                    .send(Err(Error::SwitcherTransferError(error.code)))
+                    .is_err()
+                {
+                    error!("error notifying upload responder");
+                }
+            }
+            drop(storage_lock);
+            self.emit_transfer_event(TransferEvent::Failed {
+                id: error.id,
+                code: error.code,
+            });
+        }
+
+        Ok(())
     }
 
-    /// Initialises the connection to the switcher, and requests the current state.
-    async fn initialise(&mut self) -> Result<oneshot::Receiver<()>, Error> {
-        // Explicitly clear internal states, in case some other task has a copy
-        // of this.
-        self.sender_packet_id.store(1, Ordering::SeqCst);
-        self.session_id = 0;
-        self.rx_queue.clear();
-        self.next_pkt_forward = 1;
-        self.last_rx_time = Instant::now();
-        self.disconnected.store(false, Ordering::SeqCst);
-        self.storage_locks.clear();
-        self.owned_storage_locks.clear();
-        self.downloads.clear();
-        self.uploads.clear();
-        self.finished_uploads.clear();
-        self.initialisation_complete = false;
-        self.clock_notifier = Arc::new(Notify::new());
-        self.stop_main_loop = Arc::new(Notify::new());
-        (self.upload_chunk_params_tx, self.upload_chunk_params_rx) =
-            mpsc::channel(Self::UPLOAD_CHUNK_PARAMS_SIZE);
-        self.ack_queue.clear();
-        {
-            let mut s = self.state.write().await;
-            AtemState::default().clone_into(&mut s);
-            s.commit().await;
+    /// Enqueues a [FileTransferChunkParams] for later processing in
+    /// [`upload_chunk_params_rx`][Self::upload_chunk_params_rx].
+    ///
+    /// This ensures we ack the [FileTransferChunkParams] before we start
+    /// sending a bunch of [TransferChunk]s.
+    async fn enqueue_file_transfer_chunk_params(&mut self, cmd: Atom) -> Result<(), Error> {
+        let Payload::FileTransferChunkParams(params) = cmd.payload else {
+            return Err(Error::Internal);
+        };
+
+        self.upload_chunk_params_tx
+            .send(params)
+            .await
+            .map_err(|_| Error::ChannelUnavailable)
+    }
+
+    /// Handles a [FileTransferChunkParams] message from
+    /// [`upload_chunk_params_rx`][Self::upload_chunk_params_rx], and pushes
+    async fn handle_file_transfer_chunk_params(
+        &mut self,
+        params: FileTransferChunkParams,
+    ) -> Result<(), Error> {
+        let Some(cmd_tx) = self.cmd_tx_weak.upgrade() else {
+            error!("unable to upgrade cmd_tx_weak; AtemController dropped it?");
+            return Err(Error::UnexpectedState);
+        };
+
+        let Some(upload) = self.uploads.get_mut(&params.id) else {
+            // The switcher could have given us a FTCD when we've already queued
+            // up the last chunk. So just ignore this.
+            return Ok(());
+        };
+
+        if params.chunk_count == 0 {
+            // The switcher wants us to pause the upload. Keep everything as
+            // it is, and wait for the next FTCD.
+            debug!("switcher paused upload {:#04x}", params.id);
+            upload.chunks_remaining = 0;
+            return Ok(());
+        }
+
+        upload.chunk_size = params.chunk_size;
+        upload.chunks_remaining = params.chunk_count;
+        let mtu = (TransferChunk::MAX_PAYLOAD_LENGTH.min(upload.chunk_size) & !0x7) as usize;
+        if mtu <= 24 {
+            error!(
+                "swicher's MTU was {}, protocol MTU is {}, need at least 24 bytes for RLE",
+                upload.chunk_size,
+                TransferChunk::MAX_PAYLOAD_LENGTH,
+            );
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        // The switcher has given us permission to send
+        // `chunk_count` chunks of data. Now we need to fiddle our
+        // data queue and figure out what we can send.
+        let mut chunks = Vec::with_capacity(upload.chunks_remaining.into());
+        while upload.chunks_remaining > 0 {
+            let mut chunk = TransferChunk::new(params.id, mtu);
+
+            while let Some(b) = upload.buffer.pop_front() {
+                if upload.is_rle && b == RLE_MARKER && (chunk.payload.len() + 24) > mtu {
+                    // We need to be able to push the entire RLE
+                    // sequence (u64 * 3) in the same command, but
+                    // there's not enough space.
+                    // Put it back and save it for later.
+                    upload.buffer.push_front(b);
+                    break;
+                }
+
+                chunk.payload.extend_from_slice(&b.to_be_bytes());
+                assert!(chunk.payload.len() <= mtu);
+                if chunk.payload.len() >= mtu {
+                    // Chunk is now full
+                    break;
+                }
+            }
+
+            if chunk.payload.is_empty() {
+                // We've run out of data to send.
+                break;
+            }
+
+            // We've got a full (enough) packet, now pop it on our
+            // outbound packet queue
+            // TODO: this stuff could propagate the error back to the uploader
+            // properly
+            upload.bytes_sent += chunk.payload.len();
+            chunks.push(Atom::new(chunk));
+            upload.chunks_remaining -= 1;
+        }
+
+        let bytes_sent = upload.bytes_sent;
+        let permit = upload.semaphore.clone().acquire_owned();
+        if upload.buffer.is_empty() {
+            // There's nothing more to upload, finish it.
+            let Some(upload) = self.uploads.remove(&params.id) else {
+                error!("upload ID {:#04x} disappeared?", params.id);
+                return Err(Error::UnexpectedState);
+            };
+
+            chunks.push(Atom::new(FinishFileDownload {
+                id: params.id,
+                name: upload.name,
+                description: upload.description,
+                md5: upload.md5,
+            }));
+            if self
+                .finished_uploads
+                .insert(params.id, (upload.responder, upload.storage_lock))
+                .is_some()
+            {
+                error!("upload {:#04x} already in the finished list?", params.id);
+                return Err(Error::UnexpectedState);
+            } else {
+                info!("last chunk for upload {:#04x} in queue", params.id);
+            };
         }
 
-        self.channel.connect(self.addr).await?;
-        let (tx, rx) = mpsc::channel::<AtemPacket>(16);
-        self.tx = Some(tx);
-        let initialised_rx = self.spawn_state_task(rx).await?;
+        self.emit_transfer_event(TransferEvent::Progress {
+            id: params.id,
+            bytes: bytes_sent,
+        });
+
+        // Make a worker which will do the actual sending
+        // TODO: should signal errors back...
+        let burst_size = self.options.upload_burst_size;
+        tokio::task::spawn(async move {
+            let Ok(permit) = permit.await else {
+                error!("permit error?");
+                return;
+            };
+            let mut burst = Vec::with_capacity(burst_size);
+            for chunk in chunks {
+                let (responder, resp_rx) = oneshot::channel();
+                if cmd_tx
+                    .send(AsyncCommand::Commands {
+                        cmds: vec![chunk],
+                        responder: Some(responder),
+                    })
+                    .await
+                    .is_err()
+                {
+                    error!("failed to transmit chunk to worker");
+                    return;
+                }
+
+                burst.push(resp_rx);
+                if burst.len() >= burst_size {
+                    // We've hit the limit for the number of packets we can send
+                    // in a burst. Wait for the acks to come back before
+                    // continuing.
+                    if wait_for_acks(&mut burst).await.is_err() {
+                        break;
+                    };
+                }
+            }
+            // Wait for any outstanding acks.
+            let _ = wait_for_acks(&mut burst).await;
+            drop(permit);
+        });
+
+        Ok(())
+    }
 
-        // Our initial session ID should be random, and not set the highest bit.
-        let initial_session_id = rand::rng().random_range(1..=0x7fff);
+    /// Notifies [AtemController::transfer_events()] subscribers of a
+    /// [TransferEvent].
+    fn emit_transfer_event(&self, event: TransferEvent) {
+        trace!(?event, "transfer event");
+        // It doesn't matter whether this actually succeeds
+        let _ = self.transfer_tx.send(event);
+    }
 
-        self.channel
-            .send(&AtemPacket::new_control(
-                AtemPacketFlags::new().with_control(true),
-                // AtemPacketFlags::CONTROL,
-                initial_session_id,
-                0,
-                0xb1, /* TODO */
-                0,
-                AtemControl::Connect,
-            ))
-            .await?;
+    async fn handle_lock_obtained(&mut self, cmd: Atom) -> Result<(), Error> {
+        let Payload::LockObtained(lkob) = cmd.payload else {
+            return Err(Error::Internal);
+        };
 
-        debug!("Waiting for init packet for session {initial_session_id:#x}...");
-        let (switcher_packet_id, session_id) = tokio::time::timeout(Self::INIT_TIMEOUT, async {
-            loop {
-                let resp = self.channel.recv().await?;
-                if resp.session_id != initial_session_id {
-                    // wrong session ID
-                    continue;
-                }
+        let store_id = lkob.store_id;
+        if store_id >= 64 {
+            error!("unable to lock storage ID {store_id}, too large");
+            return Err(Error::ParameterOutOfRange);
+        }
 
-                let Some(control) = resp.control() else {
-                    // wrong payload type
-                    continue;
-                };
+        let Some(owned_storage_lock) = self.owned_storage_locks.get(&store_id) else {
+            error!("No matching storage lock requests for storage ID {store_id}");
+            return Err(Error::UnexpectedState);
+        };
 
-                match control {
-                    AtemControl::ConnectAck { session_id } => {
-                        return Ok((resp.sender_packet_id, session_id | 0x8000));
-                    }
+        let Some(storage_lock) = owned_storage_lock.upgrade() else {
+            // TODO: we should probably unlock things if we didn't already?
+            error!("unable to upgrade storage lock {store_id}, no notifier?");
+            return Err(Error::Internal);
+        };
 
-                    AtemControl::ConnectNack => {
-                        error!("switcher rejected connection attempt for session {initial_session_id:#x}");
-                        return Err(Error::MixerOverloaded);
-                    }
+        storage_lock.make_available();
+        Ok(())
+    }
 
-                    _ => {
-                        error!("Unexpected control code {control:?} on session {initial_session_id:#x}");
-                        return Err(Error::UnexpectedState);
-                    }
-                }
-            }
-        })
-        .await
-        .map_err(|_| {
-            error!("timeout waiting for INIT response from switcher on session {initial_session_id:#x}");
-            Error::Timeout
-        })??;
+    async fn handle_lock_state_changed(&mut self, cmd: Atom) -> Result<(), Error> {
+        let Payload::MediaPoolLockStatus(lkst) = cmd.payload else {
+            return Err(Error::Internal);
+        };
 
-        // The proper session ID to use for later packets
-        self.session_id = session_id;
+        if lkst.locked {
+            self.storage_locks.insert(lkst.store_id);
+        } else {
+            self.storage_locks.remove(&lkst.store_id);
+            if let Some(storage_lock) = self
+                .owned_storage_locks
+                .remove(&lkst.store_id)
+                .and_then(|l| l.upgrade())
+            {
+                storage_lock.make_unavailable();
+            }
+        }
 
-        // Acknowledge the INIT response using `initial_session_id`, which
-        // triggers a request for current state. The response will be on
-        // `session_id`.
-        debug!("Established session {session_id:#x} from {initial_session_id:#x}, requesting current switcher state...");
-        self.channel
-            .send(&AtemPacket::new(
-                AtemPacketFlags::new().with_response(true),
-                initial_session_id,
-                switcher_packet_id,
-                0xd4, /* TODO */
-                0,
-            ))
-            .await?;
+        Ok(())
+    }
+}
 
-        debug!("Yielding further processing to main loop...");
-        Ok(initialised_rx)
+/// Wait for acks to a collection of sent packets, draining the `Vec`.
+///
+/// On success, returns `Ok(())`, and `burst` will be empty.
+///
+/// On failure, returns the first [Error]. `burst` will still contain subsequent
+/// `oneshot::Receiver`s after the [Error].
+async fn wait_for_acks(burst: &mut Vec<oneshot::Receiver<Result<(), Error>>>) -> Result<(), Error> {
+    for recv_rx in burst.drain(..) {
+        match recv_rx.await.map_err(|_| Error::ChannelUnavailable) {
+            Ok(Err(e)) | Err(e) => {
+                error!("responder returned error: {e:?}");
+                return Err(e);
+            }
+            Ok(Ok(())) => {}
+        }
     }
 
-    /// Disconnects from the switcher.
-    fn disconnect(&mut self) -> Result<(), Error> {
-        info!("AtemReceiver disconnecting...");
-        // Need to stop the main loop
-        self.stop_main_loop.notify_waiters();
-        if self.disconnected.swap(true, Ordering::SeqCst) {
-            debug!("switcher already disconnected us!");
+    Ok(())
+}
+
+/// Wait for a [`StateUpdate::TRANSITION_POSITION`] event which indicates that
+/// the transition on `me` is no longer in progress.
+async fn wait_for_transition_complete(
+    state_rx: &mut Receiver<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    me: u8,
+) -> Result<(), Error> {
+    wait_for_state(state_rx, |state, updated_fields| {
+        updated_fields.intersects(StateUpdate::TRANSITION_POSITION)
+            && state
+                .transition_position
+                .get(&me)
+                .is_some_and(|pos| !pos.in_progress)
+    })
+    .await
+}
+
+/// Waits for a state update where `predicate` returns `true`.
+async fn wait_for_state(
+    state_rx: &mut Receiver<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    predicate: impl Fn(&AtemState, StateUpdate) -> bool,
+) -> Result<(), Error> {
+    loop {
+        let (state, updated_fields) = match state_rx.recv().await {
+            Ok(v) => v,
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                warn!("missed {n} state update(s)");
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return Err(Error::ChannelUnavailable),
+        };
+
+        if predicate(&state, updated_fields) {
             return Ok(());
         }
+    }
+}
 
-        let pkt = AtemPacket::new_control(
-            AtemPacketFlags::new().with_control(true),
-            self.session_id,
-            0,
-            0,
-            0,
-            AtemControl::Disconnect,
-        );
-        let mut out = Cursor::new(Vec::new());
-        pkt.write(&mut out)?;
+impl Drop for AtemReceiver {
+    fn drop(&mut self) {
+        if let Err(e) = self.disconnect() {
+            error!("disconnection error: {e:?}");
+        }
+    }
+}
 
-        // Take ownership of the connection, because this isn't run in an async
-        // context, and the tokio runtime may be shutting down.
-        let sock = self.channel.take_std_socket()?;
-        sock.set_nonblocking(false)?;
-        sock.set_read_timeout(Some(Self::DISCONNECT_TIMEOUT))?;
-        sock.set_write_timeout(Some(Self::DISCONNECT_TIMEOUT))?;
+/// A storage lock.
+///
+/// This keeps a storage lock alive using [Drop] semantics.
+struct StorageLock {
+    store_id: u16,
+    waiters: Semaphore,
+    cmd_tx: Sender<AsyncCommand>,
+}
 
-        sock.send(&out.into_inner())?;
+impl StorageLock {
+    fn new(store_id: u16, cmd_tx: Sender<AsyncCommand>) -> Self {
+        Self {
+            store_id,
+            waiters: Semaphore::new(0),
+            cmd_tx,
+        }
+    }
 
-        debug!("sent disconnect");
+    fn make_available(&self) {
+        self.waiters.add_permits(Semaphore::MAX_PERMITS);
+    }
 
-        // Don't worry about waiting for a response.
-        Ok(())
+    fn make_unavailable(&self) {
+        self.waiters.close();
     }
 
-    /// Starts the main event loop.  There are four sources of events:
-    ///
-    /// * `cmd_rx.recv`: [handles queued commands to send to the switcher][Self::handle_queued_command]
-    ///
-    ///   Commands are logged in a buffer if retransmission is required.
-    ///
-    /// * `channel.recv`: [collects new packets from the switcher to process][Self::handle_incoming_packet],
-    ///   and pushes them into the receiver queue (in order).
-    ///
-    /// * `retransmit_wakeup`: periodically runs a few tasks:
-    ///
-    ///   * [request the current clock state][Self::request_clock].
-    ///
-    ///     This acts as a keep-alive / health check, if it fails,
-    ///     `clock_notifier` is triggered.
-    ///
-    ///   * [limit the receiver queue][Self::limit_rx_queue]; if it has grown
-    ///     [too large][Self::MAX_RX_QUEUE_LENGTH], or it has been
-    ///     [too long][Self::MAX_RX_QUEUE_TIME] since it was
-    ///     [last forwarded][Self::forward_rx_queue], it flushes the entire
-    ///     queue immediately.
-    ///
-    ///   * [retransmit any unacknowledged commands][Self::do_retransmits], and
-    ///     notify receivers of any unackowledged commands.
-    ///
-    /// * `clock_notifier`: aborts the event loop if there was no
-    ///   acknowledgement of a clock state request.
-    async fn main_loop(&mut self) -> Result<(), Error> {
-        let retransmit_wakeup = IntervalStream::new(interval(Self::RETRANSMIT_TIME));
-        pin_mut!(retransmit_wakeup);
+    async fn await_availability(&self) -> Result<(), Error> {
+        // We don't actually care about the permit itself, we would just drop it
+        // immediately and that's OK.
+        match self.waiters.acquire().await {
+            Ok(_) => Ok(()),
+            Err(_) => Err(Error::ChannelUnavailable),
+        }
+    }
+}
 
-        trace!(
-            "starting loop, reconnection_signal.is_some() = {}",
-            self.reconnection_signal.is_some()
-        );
-        let reconnection_signal = self.reconnection_signal.take();
-        let reconnection_notify = Arc::new(Notify::new());
-        let reconnection_notifier = reconnection_notify.clone();
+impl Drop for StorageLock {
+    fn drop(&mut self) {
+        self.make_unavailable();
+        let cmd = Atom::new(MediaPoolLock::unlock(self.store_id));
+
+        let cmd_tx = self.cmd_tx.clone();
+        let store_id = self.store_id;
         tokio::task::spawn(async move {
-            if let Some(reconnection_signal) = reconnection_signal {
-                let _ = reconnection_signal.await;
-                reconnection_notifier.notify_waiters();
+            if cmd_tx
+                .send(AsyncCommand::Commands {
+                    cmds: vec![cmd],
+                    responder: None,
+                })
+                .await
+                .is_err()
+            {
+                error!("could not unlock storage {store_id}, receiver gone");
             }
         });
+    }
+}
+
+lazy_static! {
+    static ref TIME_REQUEST_COMMAND: Atom = Atom::new(Payload::TimecodeRequest(TimecodeRequest {}));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        protocol::atom::{
+            AudioMixerInputProperties, AudioTalkbackMixerProperties, AuxSource, CoreVideoMode,
+            FadeToBlackParams, InitialisationComplete, MacroProperties, MediaPlayerCapabilities,
+            MediaPlayerFrameDescription, MixEffectBlockCapabilities, ProgramInput, Topology,
+            TransferCompleted, TransitionPosition, VideoParam,
+        },
+        protocol::rle::RleDecompressor,
+        protocol::structs::{DVETransitionStyle, WipePattern},
+        Result,
+    };
+
+    type StateChannel = (
+        CowCell<AtemState>,
+        broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
+        Receiver<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    );
+
+    fn state_channel() -> StateChannel {
+        let (state_tx, state_rx) = broadcast::channel(16);
+        (CowCell::new(AtemState::default()), state_tx, state_rx)
+    }
 
-        loop {
-            select! {
-                () = self.stop_main_loop.notified() => {
-                    // This might not execute!
-                    info!("stopping main_loop");
-                    return Ok(());
-                }
+    /// Creates an [AtemReceiver] which is connected to a fake switcher
+    /// socket on localhost.
+    async fn fake_switcher() -> Result<(AtemReceiver, Sender<AsyncCommand>, UdpSocket)> {
+        fake_switcher_with_options(ConnectionOptions::default()).await
+    }
 
-                () = self.clock_notifier.notified() => {
-                    error!("clock request timed out - loss of connectivity?");
-                    return Err(Error::Timeout);
-                }
+    /// Like [fake_switcher], but with custom [ConnectionOptions].
+    async fn fake_switcher_with_options(
+        options: ConnectionOptions,
+    ) -> Result<(AtemReceiver, Sender<AsyncCommand>, UdpSocket)> {
+        let switcher = UdpSocket::bind("127.0.0.1:0").await?;
+        let addr = switcher.local_addr()?;
+        let (mut receiver, cmd_tx) = AtemReceiver::new(addr, options);
+        receiver.channel.connect(addr).await?;
+        Ok((receiver, cmd_tx, switcher))
+    }
 
-                // Look for something to send
-                Some(async_cmd) = self.cmd_rx.recv() => {
-                    self.handle_queued_command(async_cmd).await?;
-                }
+    /// Creates an [AtemController] without an [AtemReceiver], returning the
+    /// receiving end of its command channel.
+    fn mock_controller(
+        state: Arc<CowCell<AtemState>>,
+        state_rx: Receiver<(CowCellReadTxn<AtemState>, StateUpdate)>,
+    ) -> (AtemController, mpsc::Receiver<AsyncCommand>) {
+        let (cmd_tx, cmd_rx) = mpsc::channel(16);
+        let (_, transfer_rx) = broadcast::channel(1);
+        let controller = AtemController {
+            cmd_tx,
+            state,
+            state_rx,
+            transfer_rx,
+            factory_reset_nonce: Mutex::new(None),
+            observed_atoms: None,
+            rtt: Default::default(),
+            outstanding_commands: Default::default(),
+            initialised: Default::default(),
+            video_mode_hold: None,
+            state_tx: broadcast::channel(1).0,
+        };
+        (controller, cmd_rx)
+    }
 
-                // Get packets from the switcher
-                Ok(resp) = self.channel.recv() => {
-                    self.handle_incoming_packet(resp).await?;
-                }
+    /// Updates `state` with `atoms`, and notifies subscribers, like
+    /// [`AtemReceiver::spawn_state_task()`].
+    async fn push_atoms(
+        state: &CowCell<AtemState>,
+        state_tx: &broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
+        atoms: Vec<Atom>,
+    ) -> Result {
+        let mut w = state.write().await;
+        let updated_fields = w.update_state(&atoms)?;
+        w.commit().await;
+        let _ = state_tx.send((state.read().await, updated_fields));
+        Ok(())
+    }
 
-                Some(_) = retransmit_wakeup.next() => {
-                    if self.initialisation_complete {
-                        self.request_clock().await?;
-                    }
-                    self.limit_rx_queue().await?;
-                    self.do_retransmits().await?;
-                }
+    /// Builds the `_top` atom of an ATEM Mini series switcher.
+    fn atem_mini_topology() -> Result<Atom> {
+        Ok(Atom::read(&mut Cursor::new(hex::decode(
+            "002400005f746f70010e0101000100000401000000000001000001000000010101000000",
+        )?))?)
+    }
 
-                Some(params) = self.upload_chunk_params_rx.recv() => {
-                    self.handle_file_transfer_chunk_params(params).await?;
-                }
+    /// Builds an `InPr` atom for input 3 ("Camera 3" / "CAM3"), with the
+    /// given source and ME availability.
+    fn camera3_inpr(availability3: u16) -> Result<Atom> {
+        Ok(Atom::read(&mut Cursor::new(hex::decode(format!(
+            "002c0000496e5072000343616d657261203300000000000000000000000043414d330100000200020000{availability3:04x}"
+        ))?))?)
+    }
 
-                () = reconnection_notify.notified() => {
-                    debug!("reconnection_signal fired");
-                }
+    /// Acknowledges the next `n` batches of commands sent to `cmd_rx`,
+    /// returning all of the commands which were received.
+    fn ack_commands(mut cmd_rx: mpsc::Receiver<AsyncCommand>, n: usize) -> JoinHandle<Vec<Atom>> {
+        tokio::spawn(async move {
+            let mut o = Vec::new();
+            for _ in 0..n {
+                let Some(AsyncCommand::Commands {
+                    mut cmds,
+                    responder: Some(responder),
+                }) = cmd_rx.recv().await
+                else {
+                    panic!("unexpected command");
+                };
+                let _ = responder.send(Ok(()));
+                o.append(&mut cmds);
             }
-        }
+            o
+        })
     }
 
-    async fn run(mut self) -> Result<(), Error> {
-        loop {
-            debug!("starting main_loop");
-            let r = self.main_loop().await;
-
-            // Abort the state_task, and check if it returned an error
-            if let Some(state_task) = self.state_task.take() {
-                state_task.abort();
-                match state_task.await {
-                    Err(join_error) => {
-                        if join_error.is_panic() {
-                            error!("state_task paniced: {}", join_error.to_string());
-                            return Err(Error::Internal);
-                        }
+    /// Like [push_atoms], but broadcasts through a [VideoModeHold].
+    async fn push_atoms_with_hold(
+        state: &CowCell<AtemState>,
+        state_tx: &broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
+        hold: &SharedVideoModeHold,
+        atoms: Vec<Atom>,
+    ) -> Result {
+        let mut w = state.write().await;
+        let updated_fields = w.update_state(&atoms)?;
+        w.commit().await;
+        let state = state.read().await;
+        let updated_fields = hold.lock().unwrap().coalesce(&state, updated_fields);
+        if !updated_fields.is_empty() {
+            let _ = state_tx.send((state, updated_fields));
+        }
+        Ok(())
+    }
 
-                        // Cancellation is expected here...
-                    }
+    #[tokio::test]
+    async fn transition_complete() -> Result {
+        let (state, state_tx, mut state_rx) = state_channel();
+        let waiter = tokio::spawn(async move {
+            tokio::time::timeout(
+                Duration::from_secs(1),
+                wait_for_transition_complete(&mut state_rx, 0),
+            )
+            .await
+        });
 
-                    Ok(Err(e)) => {
-                        error!("state_task reported error: {e:?}");
-                        return Err(e);
-                    }
+        // Mid-transition
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(TransitionPosition {
+                me: 0,
+                in_progress: true,
+                frames_remaining: 12,
+                position: 5000,
+            })],
+        )
+        .await?;
 
-                    Ok(Ok(())) => (),
-                }
-            }
+        // Transition on another ME finished
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(TransitionPosition {
+                me: 1,
+                in_progress: false,
+                frames_remaining: 0,
+                position: 0,
+            })],
+        )
+        .await?;
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        // Transition completed
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(TransitionPosition {
+                me: 0,
+                in_progress: false,
+                frames_remaining: 0,
+                position: 0,
+            })],
+        )
+        .await?;
 
-            if !self.reconnect {
-                // Don't automatically reconnect.
-                return r;
-            }
+        waiter
+            .await
+            .expect("waiter task failed")
+            .expect("timeout waiting for transition completion")
+    }
 
-            loop {
-                info!("disconnected; waiting for reconnection");
-                tokio::time::sleep(Self::RECONNECT_DELAY).await;
+    #[tokio::test]
+    async fn download_all_stills() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let frame = |index, is_valid| {
+            Atom::new(MediaPlayerFrameDescription {
+                index,
+                is_valid,
+                ..Default::default()
+            })
+        };
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![
+                Atom::new(MediaPlayerCapabilities {
+                    still_count: 4,
+                    ..Default::default()
+                }),
+                frame(0, true),
+                frame(1, false),
+                frame(2, true),
+                frame(3, false),
+            ],
+        )
+        .await?;
 
-                info!("reconnecting...");
-                match self.initialise().await {
-                    Err(e) => {
-                        error!("error initialising reconnection: {e:?}");
-                        continue;
+        let (controller, mut cmd_rx) = mock_controller(Arc::new(state), state_rx);
+        let cmd_tx = controller.cmd_tx.clone();
+
+        // Fake receiver task, which counts the number of storage locks made
+        let switcher = tokio::spawn(async move {
+            let mut storage_lock = Weak::new();
+            let mut lock_count = 0;
+            while let Some(cmd) = cmd_rx.recv().await {
+                match cmd {
+                    AsyncCommand::StorageLock {
+                        store_id,
+                        responder,
+                    } => {
+                        let l = storage_lock.upgrade().unwrap_or_else(|| {
+                            lock_count += 1;
+                            let l = Arc::new(StorageLock::new(store_id, cmd_tx.clone()));
+                            l.make_available();
+                            storage_lock = Arc::downgrade(&l);
+                            l
+                        });
+                        let _ = responder.send(Ok(l));
                     }
-
-                    Ok(o) => {
-                        // The initialisation can't happen until the main_loop
-                        // is running again; so we can't stop here and wait.
-                        self.reconnection_signal = Some(o);
+                    AsyncCommand::FileDownload(req) => {
+                        req.tx.send(Ok(vec![req.index; 4])).await.unwrap();
+                        req.tx.send(Ok(vec![0xff])).await.unwrap();
+                    }
+                    AsyncCommand::Commands { cmds, .. } => {
+                        let Payload::MediaPoolLock(lock) = &cmds[0].payload else {
+                            panic!("unexpected command: {cmds:?}");
+                        };
+                        assert_eq!(&MediaPoolLock::unlock(0), lock);
+                        return lock_count;
                     }
+                    _ => panic!("unexpected command"),
                 }
-
-                info!("reconnected, waiting for new initialisation");
-                break;
             }
-        }
-    }
 
-    /// Sends some commands to the switcher, returning the `sender_packet_id`
-    /// used for that packet.
-    async fn handle_queued_command(&mut self, async_cmd: AsyncCommand) -> Result<(), Error> {
-        let (cmds, responder) = match async_cmd {
-            AsyncCommand::Commands { cmds, responder } => (cmds, responder),
-            AsyncCommand::FileDownload(req) => {
-                let id = rand::random();
-                let cmd = Atom::new(SetupFileUpload {
-                    id,
-                    store_id: req.store_id,
-                    index: req.index.into(),
-                    typ: req.typ,
-                });
+            panic!("command channel closed");
+        });
 
-                // Now also register a handler
-                self.downloads.insert(id, req);
+        let stills: Vec<_> = controller.download_all_stills().collect().await;
+        assert_eq!(2, stills.len());
+        assert!(matches!(&stills[0], (0, Ok(d)) if d == &[0, 0, 0, 0, 0xff]));
+        assert!(matches!(&stills[1], (2, Ok(d)) if d == &[2, 2, 2, 2, 0xff]));
 
-                // TODO: handle leakage on errors
-                (vec![cmd], None)
-            }
-            AsyncCommand::FileUpload(req) => {
-                let id = rand::random();
-                let cmd = Atom::new(SetupFileDownload {
-                    id,
-                    store_id: req.store_id,
-                    index: req.index.into(),
-                    size: req.size,
-                    typ: req.typ,
-                    is_rle: req.is_rle,
-                });
-                self.uploads.insert(id, req);
-                // TODO: handle leakage on errors
-                (vec![cmd], None)
-            }
-            AsyncCommand::StorageLock {
-                store_id,
-                responder,
-            } => {
-                if store_id >= 64 {
-                    let _ = responder.send(Err(Error::ParameterOutOfRange));
-                    return Ok(());
-                }
+        // The media pool should have been locked once, and then unlocked.
+        assert_eq!(1, switcher.await.expect("switcher task failed"));
+        Ok(())
+    }
 
-                // TODO: replace with try_insert: https://github.com/rust-lang/rust/issues/82766
-                let owned_storage_lock = match self.owned_storage_locks.get_mut(&store_id) {
-                    Some(l) => l,
-                    None => {
-                        let w = Weak::new();
-                        assert!(self.owned_storage_locks.insert(store_id, w).is_none());
-                        self.owned_storage_locks
-                            .get_mut(&store_id)
-                            .expect("expected storage_lock to exist now")
-                    }
+    #[tokio::test]
+    async fn set_and_confirm() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let topology = atem_mini_topology()?;
+        // Input 3, available on ME 1
+        let Payload::InputProperties(inpr) = camera3_inpr(0x1101)?.payload else {
+            panic!("wrong command type");
+        };
+        let mut atoms = vec![topology];
+        for video_source in [VideoSource::Input1, VideoSource::Input2] {
+            let mut inpr = inpr.clone();
+            inpr.video_source = video_source;
+            atoms.push(Atom::new(inpr));
+        }
+        push_atoms(&state, &state_tx, atoms).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // Fake receiver task, which acknowledges all commands, but only
+        // applies program input changes to Input2.
+        tokio::spawn(async move {
+            while let Some(cmd) = cmd_rx.recv().await {
+                let AsyncCommand::Commands { cmds, responder } = cmd else {
+                    panic!("unexpected command");
                 };
+                if let Some(responder) = responder {
+                    let _ = responder.send(Ok(()));
+                }
 
-                match owned_storage_lock.upgrade() {
-                    None => {
-                        // The weak pointer was dropped, need to make another storage lock.
-
-                        // Check if the lock is owned by another client
-                        if self.storage_locks.contains(&store_id) {
-                            warn!("storage {} is locked by another client", store_id);
-                            // let _ = responder.send(Err(Error::StorageLocked));
-                            // return Ok(());
-                        }
-
-                        let Some(cmd_tx) = self.cmd_tx_weak.upgrade() else {
-                            error!("could not upgrade cmd_tx");
-                            let _ = responder.send(Err(Error::Internal));
-                            return Err(Error::Internal);
-                        };
+                let Payload::SetProgramInput(cpgi) = &cmds[0].payload else {
+                    panic!("unexpected command: {cmds:?}");
+                };
+                if cpgi.video_source == VideoSource::Input2 {
+                    push_atoms(
+                        &state,
+                        &state_tx,
+                        vec![Atom::new(ProgramInput {
+                            me: cpgi.me,
+                            video_source: cpgi.video_source,
+                        })],
+                    )
+                    .await
+                    .unwrap();
+                }
+            }
+        });
 
-                        // Create a lock entity to fill later
-                        let storage_lock = Arc::new(StorageLock::new(store_id, cmd_tx));
-                        *owned_storage_lock = Arc::downgrade(&storage_lock);
+        // The echo never arrives
+        assert!(matches!(
+            controller
+                .set_program_input_and_confirm(0, VideoSource::Input1, Duration::from_millis(50))
+                .await,
+            Err(Error::CommandIgnored)
+        ));
+        assert_ne!(
+            Some(VideoSource::Input1),
+            controller.get_state().await.get_program_source(0)
+        );
 
-                        // Now try to lock it
-                        let cmd = Atom::new(MediaPoolLock::lock(store_id));
+        // The switcher applies the change
+        controller
+            .set_program_input_and_confirm(0, VideoSource::Input2, Duration::from_secs(1))
+            .await?;
+        assert_eq!(
+            Some(VideoSource::Input2),
+            controller.get_state().await.get_program_source(0)
+        );
+        Ok(())
+    }
 
-                        let _ = responder.send(Ok(storage_lock));
-                        (vec![cmd], None)
-                    }
+    #[tokio::test]
+    async fn set_input_label() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // Input 3, "Camera 3" / "CAM3"
+        let inpr = camera3_inpr(0x1101)?;
+        push_atoms(&state, &state_tx, vec![inpr]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        for (source, long, short) in [
+            (VideoSource::Input4, "Camera 4", "CAM4"),
+            (VideoSource::Input3, "Camera 3 with a long name", "CAM3"),
+            (VideoSource::Input3, "Camera 3", "CAMERA"),
+        ] {
+            assert!(matches!(
+                controller
+                    .set_input_label(source, long.to_string(), short.to_string())
+                    .await,
+                Err(Error::ParameterOutOfRange)
+            ));
+        }
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller
+            .set_input_label(
+                VideoSource::Input3,
+                "Laptop HDMI".to_string(),
+                "LAP".to_string(),
+            )
+            .await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetInputProperties {
+                long_name: Some("Laptop HDMI".to_string()),
+                short_name: Some("LAP".to_string()),
+                ..SetInputProperties::new(VideoSource::Input3)
+            })],
+            cmds
+        );
 
-                    Some(storage_lock) => {
-                        // We already have a storage lock, so we can just pass that
-                        // back to the client.
-                        let _ = responder.send(Ok(storage_lock.clone()));
+        // Switcher reports the new names
+        let inpr = Atom::read(&mut Cursor::new(hex::decode(
+            "002c0000496e507200034c6170746f702048444d490000000000000000004c41500000000002000200001101",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![inpr]).await?;
+        let state = controller.get_state().await;
+        let inpr = &state.input_properties[&VideoSource::Input3];
+        assert_eq!("Laptop HDMI", inpr.get_long_name()?);
+        assert_eq!("LAP", inpr.get_short_name()?);
+        assert!(!inpr.input_names_are_default);
+        Ok(())
+    }
 
-                        return Ok(());
-                    }
-                }
-            }
-        };
+    #[tokio::test]
+    async fn set_program_input_number() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let topology = atem_mini_topology()?;
+        // Input 3, available on ME 1
+        let inpr = camera3_inpr(0x1101)?;
+        push_atoms(&state, &state_tx, vec![topology, inpr]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        for (me, n) in [(0, 99), (0, 0), (0, 4), (1, 3)] {
+            assert!(
+                matches!(
+                    controller.set_program_input_number(me, n).await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "ME {me}, input {n}"
+            );
+        }
+        assert!(matches!(
+            controller.set_program_input(0, VideoSource::Input4).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller.set_preview_input(1, VideoSource::Input3).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_program_input_number(0, 3).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetProgramInput {
+                me: 0,
+                video_source: VideoSource::Input3,
+            })],
+            cmds
+        );
+        Ok(())
+    }
 
-        let r = async {
-            let sender_packet_id = self.sender_packet_id.fetch_add(1, Ordering::SeqCst);
-            trace!(">>> 0x{sender_packet_id:04X}: {:?}", cmds);
-            let pkt = AtemPacket::new_atoms(
-                AtemPacketFlags::new().with_ack(true),
-                self.session_id,
-                0,
-                0,
-                sender_packet_id,
-                cmds,
+    #[tokio::test]
+    async fn set_transition_style() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // ATEM Mini topology, with 1 ME, no stingers, and DVE capabilities not
+        // yet known
+        let topology = atem_mini_topology()?;
+        push_atoms(&state, &state_tx, vec![topology]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        assert!(matches!(
+            controller
+                .set_transition_style(1, TransitionStyle::Mix)
+                .await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        for style in [TransitionStyle::DVE, TransitionStyle::Stinger] {
+            assert!(
+                matches!(
+                    controller.set_transition_style(0, style).await,
+                    Err(Error::FeatureUnavailable)
+                ),
+                "{style:?}"
             );
+        }
+        assert!(cmd_rx.try_recv().is_err());
+
+        // ATEM Mini DVE capabilities
+        let dve = Atom::read(&mut Cursor::new(hex::decode(
+            "002000005f44564500010011101112131415161718191a1b1c1d1e1f22000000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![dve]).await?;
+        assert!(matches!(
+            controller
+                .set_transition_style(0, TransitionStyle::Stinger)
+                .await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller
+            .set_transition_style(0, TransitionStyle::DVE)
+            .await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetTransitionSettings {
+                me: 0,
+                style: Some(TransitionStyle::DVE),
+                selection: None,
+            })],
+            cmds
+        );
+        Ok(())
+    }
 
-            self.channel.send(&pkt).await?;
+    #[tokio::test]
+    async fn set_transition_preview() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // ATEM Mini topology, with 1 ME
+        let topology = atem_mini_topology()?;
+        push_atoms(&state, &state_tx, vec![topology]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        assert!(matches!(
+            controller.set_transition_preview(1, true).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_transition_preview(0, true).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetTransitionPreview {
+                me: 0,
+                enabled: true,
+            })],
+            cmds
+        );
+        Ok(())
+    }
 
-            Ok(pkt)
-        }
-        .await;
+    #[tokio::test]
+    async fn recording() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // ATEM Mini: no recording
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(ProductName::new("ATEM Mini".to_string(), 0xd)?)],
+        )
+        .await?;
+        assert!(matches!(
+            controller.start_recording().await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(matches!(
+            controller.stop_recording().await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(matches!(
+            controller.switch_recording_disk().await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        // ATEM Mini Pro: can record
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(ProductName::new(
+                "ATEM Mini Pro".to_string(),
+                0xe,
+            )?)],
+        )
+        .await?;
+        let switcher = ack_commands(cmd_rx, 3);
+        controller.start_recording().await?;
+        controller.switch_recording_disk().await?;
+        controller.stop_recording().await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![
+                Atom::new(RecordToMedia { start: true }),
+                Atom::new(RECORD_TO_MEDIA_SWITCH_DISK),
+                Atom::new(RecordToMedia { start: false }),
+            ],
+            cmds
+        );
+        Ok(())
+    }
 
-        let mut pkt = match r {
-            Err(e) => {
-                if let Some(responder) = responder {
-                    if let Err(e) = responder.send(Err(e)) {
-                        error!("error sending error response to responder: {e:?}");
-                    }
-                }
+    #[tokio::test]
+    async fn streaming() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // ATEM Mini: no streaming
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(ProductName::new("ATEM Mini".to_string(), 0xd)?)],
+        )
+        .await?;
+        assert!(matches!(
+            controller.start_streaming().await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(matches!(
+            controller.stop_streaming().await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        // ATEM Mini Pro: can stream
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(ProductName::new(
+                "ATEM Mini Pro".to_string(),
+                0xe,
+            )?)],
+        )
+        .await?;
+        let switcher = ack_commands(cmd_rx, 2);
+        controller.start_streaming().await?;
+        controller.stop_streaming().await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![
+                Atom::new(StreamRtmp { start: true }),
+                Atom::new(StreamRtmp { start: false }),
+            ],
+            cmds
+        );
+        Ok(())
+    }
 
-                return Err(Error::Internal);
-            }
+    #[tokio::test]
+    async fn set_talkback_channel() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // No talkback
+        assert!(matches!(
+            controller.set_talkback_channel(0, true).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(AudioTalkbackMixerProperties {
+                channel: 0,
+                mute_sdi: true,
+            })],
+        )
+        .await?;
+        assert!(matches!(
+            controller.set_talkback_channel(1, true).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 2);
+        controller.set_talkback_channel(0, true).await?;
+        controller.set_talkback_channel(0, false).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![
+                Atom::new(SetAudioTalkbackMixerProperties {
+                    channel: 0,
+                    mute_sdi: false,
+                }),
+                Atom::new(SetAudioTalkbackMixerProperties {
+                    channel: 0,
+                    mute_sdi: true,
+                }),
+            ],
+            cmds
+        );
+        Ok(())
+    }
 
-            Ok(p) => p,
-        };
+    #[tokio::test]
+    async fn reset_all_media_player_sources() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // No media players
+        assert!(matches!(
+            controller.reset_all_media_player_sources().await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let mut topology = Topology::default();
+        topology.mes = 1;
+        topology.media_players = 3;
+        push_atoms(&state, &state_tx, vec![Atom::new(topology)]).await?;
+
+        // No still support
+        assert!(matches!(
+            controller.reset_all_media_player_sources().await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(MediaPlayerCapabilities {
+                still_count: 2,
+                ..Default::default()
+            })],
+        )
+        .await?;
 
-        // Add an entry to the ack_queue for this packet. Mark it as a
-        // retransmission for future sends.
-        pkt.flags.set_retransmission(true);
-        let waiting = PacketWaitingForResponse {
-            pkt,
-            responder,
-            retry_limit: Self::RETRANSMIT_LIMIT,
-            last_attempt: Instant::now(),
-        };
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.reset_all_media_player_sources().await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            (0..3)
+                .map(|id| Atom::new(SetMediaPlayerSource {
+                    enable: true,
+                    id,
+                    source: MediaPlayerSourceID::Still(0),
+                }))
+                .collect::<Vec<_>>(),
+            cmds
+        );
+        Ok(())
+    }
 
-        if self.ack_queue.len() >= Self::MAX_ACK_QUEUE_LENGTH {
-            warn!(
-                "ack_queue is long ({} entries), has the switcher stalled?",
-                self.ack_queue.len()
-            );
-            self.ack_queue.clear();
+    #[tokio::test]
+    async fn set_media_player_source() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // ATEM Mini topology, with 1 media player
+        let topology = atem_mini_topology()?;
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![
+                topology,
+                Atom::new(MediaPlayerCapabilities {
+                    still_count: 2,
+                    ..Default::default()
+                }),
+                Atom::new(MediaPlayerFrameDescription {
+                    index: 1,
+                    is_valid: true,
+                    ..Default::default()
+                }),
+            ],
+        )
+        .await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        assert!(matches!(
+            controller
+                .set_media_player_source(1, MediaPlayerSourceID::Still(1))
+                .await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 2);
+        controller
+            .set_media_player_source(0, MediaPlayerSourceID::Still(1))
+            .await?;
+        controller
+            .set_media_player_source_index(0, MediaPlayerSourceID::Still(1))
+            .await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(2, cmds.len());
+
+        // Setting mask is the first byte after the header; the enable bit
+        // should only be set on the first command.
+        for (cmd, mask) in cmds.iter().zip([0x03, 0x02]) {
+            let mut out = Cursor::new(Vec::new());
+            cmd.write(&mut out)?;
+            assert_eq!(mask, out.into_inner()[8]);
         }
+        Ok(())
+    }
 
-        let sender_packet_id = waiting.pkt.sender_packet_id;
-        let idx = self
-            .ack_queue
-            .partition_point(|p| p.pkt.sender_packet_id < waiting.pkt.sender_packet_id);
-        self.ack_queue.insert(idx, waiting);
-        trace!(
-            ack_queue_len = self.ack_queue.len(),
-            "sent command: 0x{sender_packet_id:04X}",
-        );
+    #[tokio::test]
+    async fn set_media_player_audio_name() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // No clip support
+        assert!(matches!(
+            controller.set_media_player_audio_name(0, "Intro").await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(MediaPlayerCapabilities {
+                still_count: 20,
+                clip_count: 2,
+                ..Default::default()
+            })],
+        )
+        .await?;
 
+        assert!(matches!(
+            controller.set_media_player_audio_name(2, "Intro").await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller
+                .set_media_player_audio_name(1, &"x".repeat(65))
+                .await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_media_player_audio_name(1, "Intro").await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetMediaPlayerAudio {
+                index: 1,
+                name: "Intro".to_string(),
+            })],
+            cmds
+        );
         Ok(())
     }
 
-    const RX_QUEUE_OVERFLOW_MARGIN: u16 =
-        AtemPacket::MAX_PACKET_ID - (Self::MAX_RX_QUEUE_LENGTH as u16);
-    const MAX_RX_QUEUE_LENGTH: usize = 64;
-    const MAX_RX_QUEUE_TIME: Duration = Duration::from_secs(2);
+    #[tokio::test]
+    async fn send_raw() -> Result {
+        let (state, _state_tx, state_rx) = state_channel();
+        let (controller, mut cmd_rx) = mock_controller(Arc::new(state), state_rx);
+
+        assert!(matches!(
+            controller
+                .send_raw(*b"Test", vec![0; usize::from(Atom::MAX_PAYLOAD_LENGTH) + 1])
+                .await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller
+            .send_raw(*b"Test", vec![0x01, 0x02, 0x03, 0x04])
+            .await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(1, cmds.len());
 
-    /// Handles an incoming packet from the device.
-    ///
-    /// If the packet is an acknowledgement of a previously-sent command, this
-    /// will [notify any waiting tasks][Self::handle_ack]. This handles when
-    /// the switcher batch-acknowledges packets.
-    ///
-    /// If the packet has a `sender_packet_id`, then it contains an event. This
-    /// is pushed (in order) into the receiver queue, and when we have the next
-    /// expected `sender_packet_id`, any outstanding packets are
-    /// [forwarded to listeners][Self::forward_rx_queue].
-    ///
-    /// This also [checks the receiver queue][Self::limit_rx_queue].
-    async fn handle_incoming_packet(&mut self, resp: AtemPacket) -> Result<(), Error> {
-        // Check that the incoming packet is for our session ID
-        assert_ne!(
-            0,
-            self.session_id & 0x8000,
-            "handling an incoming packet before establishing a session ID!"
-        );
-        if self.session_id != resp.session_id {
-            warn!(
-                "unexpected session ID: {:#X} != {:#X}",
-                resp.session_id, self.session_id,
-            );
-            return Ok(());
-        }
+        let mut out = Cursor::new(Vec::new());
+        cmds[0].write(&mut out)?;
+        assert_eq!(hex::decode("000c00005465737401020304")?, out.into_inner());
+        Ok(())
+    }
 
-        self.limit_rx_queue().await?;
-        trace!("<<< 0x{:04X}: {resp:X?}", resp.sender_packet_id);
+    #[tokio::test]
+    async fn wait_for_initialisation() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let controller = Arc::new(mock_controller(state.clone(), state_rx).0);
+        assert!(!controller.is_initialised());
 
-        if let Some(ctrl) = resp.control() {
-            match ctrl {
-                AtemControl::Disconnect => {
-                    error!("switcher disconnected us!");
-                    self.disconnected.store(true, Ordering::SeqCst);
+        let waiter = tokio::spawn({
+            let controller = controller.clone();
+            async move { controller.wait_for_initialisation().await }
+        });
+        tokio::task::yield_now().await;
+        assert!(!waiter.is_finished());
+
+        // The state task sets the flag before publishing the update
+        controller.initialised.store(true, Ordering::Relaxed);
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(InitialisationComplete {
+                unknown1: 0,
+                unknown2: 0,
+            })],
+        )
+        .await?;
+        tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .expect("timed out waiting for initialisation")
+            .expect("waiter task failed")?;
+        assert!(controller.is_initialised());
 
-                    // Acknowledge the disconnect immediately, without queueing.
-                    let pkt = AtemPacket::new_control(
-                        AtemPacketFlags::new().with_control(true),
-                        self.session_id,
-                        0,
-                        0,
-                        0,
-                        AtemControl::DisconnectAck,
-                    );
-                    self.channel.send(&pkt).await?;
-                    return Err(Error::Disconnected);
-                }
+        // Already initialised
+        tokio::time::timeout(Duration::from_secs(1), controller.wait_for_initialisation())
+            .await
+            .expect("timed out waiting for initialisation")?;
+        Ok(())
+    }
 
-                ctrl => {
-                    error!("unexpected control packet: {ctrl:?}");
-                    return Err(Error::UnexpectedState);
-                }
+    #[tokio::test]
+    async fn await_state() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // ATEM Mini topology, with 1 ME
+        let topology = atem_mini_topology()?;
+        push_atoms(&state, &state_tx, vec![topology]).await?;
+        let controller = Arc::new(mock_controller(state.clone(), state_rx).0);
+
+        // Predicate is already true
+        controller
+            .await_state(|state| state.topology.mes == 1, Duration::ZERO)
+            .await?;
+
+        // Predicate never becomes true
+        let is_input2 =
+            |state: &AtemState| state.get_program_source(0) == Some(VideoSource::Input2);
+        assert!(matches!(
+            controller
+                .await_state(is_input2, Duration::from_millis(50))
+                .await,
+            Err(Error::Timeout)
+        ));
+
+        // Predicate becomes true after an update
+        let waiter = tokio::spawn({
+            let controller = controller.clone();
+            async move {
+                controller
+                    .await_state(is_input2, Duration::from_secs(1))
+                    .await
             }
+        });
+        tokio::task::yield_now().await;
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(ProgramInput {
+                me: 0,
+                video_source: VideoSource::Input2,
+            })],
+        )
+        .await?;
+        waiter.await.expect("waiter task failed")?;
+        Ok(())
+    }
+
+    /// Creates a controller with a [VideoModeHold], for a switcher in 1080p25
+    /// which also supports 1080p50.
+    async fn video_mode_hold_controller() -> (
+        AtemController,
+        mpsc::Receiver<AsyncCommand>,
+        Arc<CowCell<AtemState>>,
+        broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
+        SharedVideoModeHold,
+    ) {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        {
+            let mut w = state.write().await;
+            w.supported_video_modes = vec![VideoMode::Fhd1080p25, VideoMode::Fhd1080p50];
+            w.video_mode = VideoMode::Fhd1080p25;
+            w.commit().await;
         }
+        let (mut controller, cmd_rx) = mock_controller(state.clone(), state_rx);
+        let hold = SharedVideoModeHold::default();
+        controller.video_mode_hold = Some(hold.clone());
+        controller.state_tx = state_tx.clone();
+        (controller, cmd_rx, state, state_tx, hold)
+    }
 
-        if let Some(atoms) = resp.atoms() {
-            if atoms
-                .iter()
-                .any(|c| matches!(c.payload, Payload::InitialisationComplete(_)))
-            {
-                self.initialisation_complete = true;
+    /// Acknowledges a [SetVideoMode] command, then pushes each of `packets`
+    /// through `hold`.
+    fn reinitialising_switcher(
+        mut cmd_rx: mpsc::Receiver<AsyncCommand>,
+        state: Arc<CowCell<AtemState>>,
+        state_tx: broadcast::Sender<(CowCellReadTxn<AtemState>, StateUpdate)>,
+        hold: SharedVideoModeHold,
+        packets: Vec<Vec<Atom>>,
+    ) -> JoinHandle<Result<Vec<Atom>>> {
+        tokio::spawn(async move {
+            let Some(AsyncCommand::Commands {
+                cmds,
+                responder: Some(responder),
+            }) = cmd_rx.recv().await
+            else {
+                panic!("expected commands");
+            };
+            let _ = responder.send(Ok(()));
+
+            for atoms in packets {
+                push_atoms_with_hold(&state, &state_tx, &hold, atoms).await?;
             }
-        }
+            Ok(cmds)
+        })
+    }
 
-        // Check if this is a response to something we sent earlier.
-        if resp.acked_packet_id != 0 && resp.flags.response() {
-            self.handle_ack(resp.acked_packet_id);
-        }
+    #[tokio::test]
+    async fn set_video_mode() -> Result {
+        let (controller, cmd_rx, state, state_tx, hold) = video_mode_hold_controller().await;
+        let mut updates = controller.state_update_events();
 
-        if resp.sender_packet_id == 0 && !resp.has_atoms() {
-            // Message is likely an acknowledgement of something we sent
-            // earlier; but it was something already in process (eg: pressing
-            // "Auto" when a transition is already in progress).
-            //
-            // Don't do anything with the rx_queue.
-            //
-            // On overflow, the switcher will send real messages with this
-            // packet ID. BMDSwitcherAPI seems to batch-ack this packet when
-            // sender_packet_id = 1 arrives; but this could mean we lost some
-            // data.
-            // debug!("got zero sender_packet_id: {resp:#?}");
-            return Ok(());
-        }
+        // Unsupported mode
+        assert!(matches!(
+            controller.set_video_mode(VideoMode::Hd720p50).await,
+            Err(Error::ParameterOutOfRange)
+        ));
 
-        if resp.sender_packet_id < self.next_pkt_forward {
-            // Switcher is sending us an old packet again, but we're past that
-            // point. This tends to happen a lot on WiFi.
-            trace!(
-                "switcher sent us old packet {:#04X}, we're up to {:#04X}",
-                resp.sender_packet_id,
-                self.next_pkt_forward
-            );
+        // Switcher accepts the command, then re-initialises over several
+        // packets.
+        let switcher = reinitialising_switcher(
+            cmd_rx,
+            state,
+            state_tx,
+            hold,
+            vec![
+                vec![atem_mini_topology()?],
+                vec![Atom::new(CoreVideoMode(VideoMode::Fhd1080p50))],
+                vec![camera3_inpr(0x1101)?],
+            ],
+        );
 
-            // forward_rx_queue should have acked this packet already, but maybe
-            // it was lost? But shouldn't matter, as when everything catches up
-            // again, we'll effectively "batch-ack" the messages.
-            // if let Some(mut ack) = resp.make_ack() {
-            //     trace!("re-acking packet: 0x{:04X}", ack.acked_packet_id);
-            //     if ack.has_atoms() && ack.sender_packet_id == 0 {
-            //         // Contains transfer acks, but no sender_packet_id has been
-            //         // allocated.
-            //         ack.sender_packet_id = self.sender_packet_id.fetch_add(1, Ordering::SeqCst);
-            //         trace!("includes transfer acks, sending with sender_packet_id={:#04X}", ack.sender_packet_id);
-            //     }
-            //     self.channel.send(&ack).await?;
-            // }
-            return Ok(());
-        }
+        controller.set_video_mode(VideoMode::Fhd1080p50).await?;
+        let cmds = switcher.await.expect("switcher task failed")?;
+        assert_eq!(cmds, vec![Atom::new(SetVideoMode(VideoMode::Fhd1080p50))]);
+        assert_eq!(
+            VideoMode::Fhd1080p50,
+            controller.get_state().await.video_mode
+        );
 
-        // Put the received packet into the queue
-        let idx = self
-            .rx_queue
-            .partition_point(|p| p.sender_packet_id < resp.sender_packet_id);
+        // Updates are held until the switcher goes quiet, and then one,
+        // coalesced update is broadcast.
+        assert!(updates.try_recv().is_err());
+        let (s, updated_fields) = tokio::time::timeout(Duration::from_secs(2), updates.recv())
+            .await
+            .expect("held updates not released")
+            .expect("no state update");
+        assert_eq!(VideoMode::Fhd1080p50, s.video_mode);
+        assert_eq!(
+            StateUpdate::TOPOLOGY | StateUpdate::VIDEO_MODE | StateUpdate::INPUT_PROPERTIES,
+            updated_fields
+        );
+        assert!(updates.try_recv().is_err());
 
-        if self
-            .rx_queue
-            .get(idx)
-            .is_some_and(|p| p.sender_packet_id == resp.sender_packet_id)
-        {
-            // Retransmission of a packet we already received
-            // TODO: maybe should ack this again
-            debug!(
-                "repeat incoming existing packet in queue: 0x{:04X}, next_pkt_forward=0x{:04x}",
-                resp.sender_packet_id, self.next_pkt_forward
-            );
-        } else {
-            if idx > 0 {
-                // We _always_ queue packets, but this means we only log
-                // when we're behind (due to packet loss or out-of-order
-                // delivery).
-                trace!(
-                    "queuing packet, next_pkt_forward=0x{:04X}",
-                    self.next_pkt_forward
-                );
-            }
-            self.rx_queue.insert(idx, resp);
-        }
+        // Already in the requested mode
+        controller.set_video_mode(VideoMode::Fhd1080p50).await?;
+        Ok(())
+    }
 
-        // Forward any packets in the queue which are near the next expected value.
-        //
-        // FIXME: this should actually push _everything_ in the queue which is
-        // sequentially after a next packet to forward. At the moment it waits
-        // for `limit_rx_queue()`, which could end up sending some out-of-order
-        // packets.
-        let s = if self.next_pkt_forward >= Self::RX_QUEUE_OVERFLOW_MARGIN {
-            // We're close to the overflow point, so do a lower bounds check to
-            // ensure only grab what's "high"
-            self.rx_queue
-                .partition_point(|p| Self::RX_QUEUE_OVERFLOW_MARGIN < p.sender_packet_id)
-        } else {
-            0
-        };
+    #[tokio::test]
+    async fn set_video_mode_without_inpr() -> Result {
+        let (controller, cmd_rx, state, state_tx, hold) = video_mode_hold_controller().await;
+        let mut updates = controller.state_update_events();
+
+        // Switcher doesn't re-send any input properties
+        let switcher = reinitialising_switcher(
+            cmd_rx,
+            state,
+            state_tx,
+            hold,
+            vec![
+                vec![atem_mini_topology()?],
+                vec![Atom::new(CoreVideoMode(VideoMode::Fhd1080p50))],
+            ],
+        );
 
-        let idx = self
-            .rx_queue
-            .partition_point(|p| p.sender_packet_id <= self.next_pkt_forward);
-        if idx > 0 {
-            self.forward_rx_queue(s..idx).await?;
-        }
+        controller.set_video_mode(VideoMode::Fhd1080p50).await?;
+        switcher.await.expect("switcher task failed")?;
 
+        let (s, updated_fields) = tokio::time::timeout(Duration::from_secs(2), updates.recv())
+            .await
+            .expect("held updates not released")
+            .expect("no state update");
+        assert_eq!(VideoMode::Fhd1080p50, s.video_mode);
+        assert_eq!(
+            StateUpdate::TOPOLOGY | StateUpdate::VIDEO_MODE,
+            updated_fields
+        );
+        assert!(updates.try_recv().is_err());
         Ok(())
     }
 
-    /// Forwards the contents of the reciever queue to subscribers, and
-    /// acknowledges the packet(s).
-    ///
-    /// Unlike the BM SDK, this acknowledges *every* packet explicitly, and
-    /// doesn't batch acks.
-    async fn forward_rx_queue(
-        &mut self,
-        range: impl std::ops::RangeBounds<usize>,
-    ) -> Result<(), Error> {
-        let packets: Vec<AtemPacket> = self.rx_queue.drain(range).collect();
-        for mut p in packets {
-            // There are two cases to handle in this function:
-            // - we have a total drain of all entries (timeout or size limit)
-            //   (not anymore, we'll just disconnect)
-            // - we have a partial drain of "what should happen next" (normal)
-            //
-            // rx_queue is always sorted by ID. On overflow, a normal partial
-            // drain will skip over the "low" values, and come back for them
-            // later.
-            //
-            // TODO: On total drains then there can be a big gap and everything
-            // is whacky.
-            if p.sender_packet_id == 0x7fff {
-                // Next packet after 0x7fff is 0x0
-                self.next_pkt_forward = 0;
-            } else if self.next_pkt_forward == p.sender_packet_id {
-                // This can go backwards if the switcher retransmits an
-                // older packet after sending a newer packet; particularly
-                // an issue over WiFi.
-                //
-                // This forces us to process every packet in order.
-                self.next_pkt_forward = p.sender_packet_id + 1;
-            }
+    #[tokio::test]
+    async fn set_video_mode_cancelled() -> Result {
+        let (controller, mut cmd_rx, state, state_tx, hold) = video_mode_hold_controller().await;
+        let mut updates = controller.state_update_events();
+
+        // Switcher accepts the command, but only re-initialises after the
+        // caller has given up waiting.
+        let (go_tx, go_rx) = oneshot::channel::<()>();
+        let switcher = tokio::spawn(async move {
+            let Some(AsyncCommand::Commands {
+                responder: Some(responder),
+                ..
+            }) = cmd_rx.recv().await
+            else {
+                panic!("expected commands");
+            };
+            let _ = responder.send(Ok(()));
+            go_rx.await.expect("test dropped go_tx");
+            push_atoms_with_hold(&state, &state_tx, &hold, vec![atem_mini_topology()?]).await?;
+            push_atoms_with_hold(
+                &state,
+                &state_tx,
+                &hold,
+                vec![Atom::new(CoreVideoMode(VideoMode::Fhd1080p50))],
+            )
+            .await?;
+            Ok::<_, crate::Error>(())
+        });
+
+        assert!(tokio::time::timeout(
+            Duration::from_millis(50),
+            controller.set_video_mode(VideoMode::Fhd1080p50)
+        )
+        .await
+        .is_err());
+        go_tx.send(()).expect("switcher task stopped");
+        switcher.await.expect("switcher task failed")?;
+
+        // The switcher goes quiet, so the held updates are still released.
+        let (s, updated_fields) = tokio::time::timeout(Duration::from_secs(2), updates.recv())
+            .await
+            .expect("held updates not released")
+            .expect("no state update");
+        assert_eq!(VideoMode::Fhd1080p50, s.video_mode);
+        assert_eq!(
+            StateUpdate::TOPOLOGY | StateUpdate::VIDEO_MODE,
+            updated_fields
+        );
+        assert!(updates.try_recv().is_err());
+        Ok(())
+    }
 
-            let ack: Option<AtemPacket> = p.make_ack();
+    #[tokio::test]
+    async fn set_video_mode_rejected() -> Result {
+        let (controller, mut cmd_rx, state, state_tx, hold) = video_mode_hold_controller().await;
+        let mut updates = controller.state_update_events();
+
+        // Switcher sends an update, then rejects the command
+        let switcher = tokio::spawn(async move {
+            let Some(AsyncCommand::Commands {
+                responder: Some(responder),
+                ..
+            }) = cmd_rx.recv().await
+            else {
+                panic!("expected commands");
+            };
+            push_atoms_with_hold(&state, &state_tx, &hold, vec![atem_mini_topology()?]).await?;
+            let _ = responder.send(Err(Error::ParameterOutOfRange));
+            Ok::<_, crate::Error>(())
+        });
 
-            if let Some(cmds) = p.atoms_mut() {
-                // Special-case some commands.
-                // TODO: migrate to extract_if when stable
-                let mut i = 0;
-                while i < cmds.len() {
-                    match cmds[i].payload {
-                        Payload::FileTransferChunkParams(_) => {
-                            // Uploads
-                            let ftcp = cmds.remove(i);
-                            self.enqueue_file_transfer_chunk_params(ftcp).await?;
-                        }
+        assert!(matches!(
+            controller.set_video_mode(VideoMode::Fhd1080p50).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        switcher.await.expect("switcher task failed")?;
 
-                        Payload::TransferCompleted(_) => {
-                            // Used by uploads and downloads
-                            let ftdc = cmds.remove(i);
-                            self.handle_transfer_completed(ftdc).await?;
-                        }
+        // The held update is released immediately.
+        let (_, updated_fields) = updates.try_recv().expect("no state update");
+        assert_eq!(StateUpdate::TOPOLOGY, updated_fields);
+        assert!(updates.try_recv().is_err());
+        Ok(())
+    }
 
-                        Payload::FileTransferError(_) => {
-                            // Used by uploads and downloads
-                            let ftde = cmds.remove(i);
-                            self.handle_transfer_error(ftde).await?;
-                        }
+    #[tokio::test]
+    async fn set_colorimetry() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // Colorimetry mode not reported
+        assert!(matches!(
+            controller.set_colorimetry(ColorimetryMode::Rec2020).await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let clrm = Atom::read(&mut Cursor::new(hex::decode("000c0000436c724d01000000")?))?;
+        push_atoms(&state, &state_tx, vec![clrm]).await?;
+        assert_eq!(
+            Some(ColorimetryMode::Rec709),
+            controller.get_state().await.colorimetry
+        );
 
-                        Payload::TransferChunk(_) => {
-                            // Downloads
-                            let chunk = cmds.remove(i);
-                            self.handle_download_chunk(chunk).await?;
-                        }
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_colorimetry(ColorimetryMode::Rec2020).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(vec![Atom::new(Colorimetry(ColorimetryMode::Rec2020))], cmds);
+        Ok(())
+    }
 
-                        Payload::LockObtained(_) => {
-                            let lkob = cmds.remove(i);
-                            self.handle_lock_obtained(lkob).await?;
-                        }
+    #[tokio::test]
+    async fn set_sdi_3g_level() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // 3G-SDI output level not reported
+        assert!(matches!(
+            controller.set_sdi_3g_level(Sdi3gLevel::LevelA).await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let v3sl = Atom::read(&mut Cursor::new(hex::decode("000c00005633736c00000000")?))?;
+        push_atoms(&state, &state_tx, vec![v3sl]).await?;
+        assert_eq!(
+            Some(Sdi3gLevel::LevelB),
+            controller.get_state().await.sdi_3g_level
+        );
 
-                        Payload::MediaPoolLockStatus(_) => {
-                            let lkst = cmds.remove(i);
-                            self.handle_lock_state_changed(lkst).await?;
-                        }
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_sdi_3g_level(Sdi3gLevel::LevelA).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetSdi3gOutputLevel(Sdi3gLevel::LevelA))],
+            cmds
+        );
+        Ok(())
+    }
 
-                        // Not a file transfer command, ignore it.
-                        _ => i += 1,
-                    }
-                }
-            }
+    #[tokio::test]
+    async fn get_supported_video_modes() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, _cmd_rx) = mock_controller(state.clone(), state_rx);
+        assert!(controller.get_supported_video_modes().await.is_empty());
+
+        // Synthetic example, not captured from hardware
+        let vmc = Atom::read(&mut Cursor::new(hex::decode(concat!(
+            "00260000",
+            "5f564d43",
+            "00020000",
+            "0a000004000000000a00000000",
+            "10000104000000040001000000",
+        ))?))?;
+        push_atoms(&state, &state_tx, vec![vmc]).await?;
+
+        let modes = controller.get_supported_video_modes().await;
+        assert_eq!(
+            vec![
+                SupportedVideoMode {
+                    mode: VideoMode::Fhd1080p25,
+                    multiview_modes: vec![VideoMode::Fhd1080p25],
+                    down_convert_modes: vec![VideoMode::Pal625i50, VideoMode::PalAnamorphic625i50],
+                    requires_reconfiguration: false,
+                },
+                SupportedVideoMode {
+                    mode: VideoMode::Uhd4Kp25,
+                    multiview_modes: vec![VideoMode::Fhd1080p25, VideoMode::Uhd4Kp25],
+                    down_convert_modes: vec![VideoMode::Fhd1080p25],
+                    requires_reconfiguration: true,
+                },
+            ],
+            modes
+        );
 
-            let Some(tx) = self.tx.as_ref() else {
-                error!("state channel missing!");
-                return Err(Error::Internal);
-            };
+        // Plain video mode list is still populated
+        assert_eq!(
+            vec![VideoMode::Fhd1080p25, VideoMode::Uhd4Kp25],
+            controller.get_state().await.supported_video_modes
+        );
+        Ok(())
+    }
 
-            if tx.send(p).await.is_err() {
-                error!("state channel gone!");
-                return Err(Error::Internal);
-            }
+    #[tokio::test]
+    async fn subscribe_filtered() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // ATEM Mini topology, with 1 ME
+        let topology = atem_mini_topology()?;
+        push_atoms(&state, &state_tx, vec![topology]).await?;
+        let (controller, _cmd_rx) = mock_controller(state.clone(), state_rx);
+        let events = controller.subscribe_filtered(StateUpdate::PROGRAM_SOURCE);
+        pin_mut!(events);
+
+        // Aux changes should be skipped
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(AuxSource {
+                aux: 0,
+                video_source: VideoSource::Input1,
+            })],
+        )
+        .await?;
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![Atom::new(ProgramInput {
+                me: 0,
+                video_source: VideoSource::Input2,
+            })],
+        )
+        .await?;
 
-            if let Some(mut ack) = ack {
-                trace!("acking packet: 0x{:04X}", ack.acked_packet_id);
-                if ack.has_atoms() && ack.sender_packet_id == 0 {
-                    // Contains transfer acks, but no sender_packet_id has been
-                    // allocated.
-                    ack.sender_packet_id = self.sender_packet_id.fetch_add(1, Ordering::SeqCst);
-                    trace!(
-                        "includes transfer acks, sending with sender_packet_id={:#04X}",
-                        ack.sender_packet_id
-                    );
-                }
-                self.channel.send(&ack).await?;
-            }
-            self.last_rx_time = Instant::now();
+        let (s, updated) = tokio::time::timeout(Duration::from_secs(1), events.next())
+            .await
+            .expect("timed out waiting for event")
+            .expect("stream ended");
+        assert!(updated.contains(StateUpdate::PROGRAM_SOURCE));
+        assert_eq!(Some(VideoSource::Input2), s.get_program_source(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn camera_events() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // Input 1 white balance: 5600K
+        let wb5600 = Atom::read(&mut Cursor::new(hex::decode(
+            "00200000434364500101020200000002000000000000000015e0000000000000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![wb5600.clone()]).await?;
+        let (controller, _cmd_rx) = mock_controller(state.clone(), state_rx);
+        let events = controller.camera_events().await;
+        pin_mut!(events);
+
+        // Input 1 auto focus
+        let af = Atom::read(&mut Cursor::new(hex::decode(
+            "001800004343645001000100000000000000000000000000",
+        )?))?;
+        // White balance is unchanged, so only auto focus is reported.
+        push_atoms(&state, &state_tx, vec![wb5600, af]).await?;
+        // Input 1 white balance: 3200K
+        let wb3200 = Atom::read(&mut Cursor::new(hex::decode(
+            "0020000043436450010102020000000200000000000000000c80000000000000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![wb3200]).await?;
+
+        for expected in [
+            (
+                1,
+                CameraParameterID::Lens(LensParam::AutoFocus),
+                CameraParameterValue::Bool(vec![]),
+            ),
+            (
+                1,
+                CameraParameterID::Video(VideoParam::ManualWhiteBalance),
+                CameraParameterValue::I16(vec![3200, 0]),
+            ),
+        ] {
+            let event = tokio::time::timeout(Duration::from_secs(1), events.next())
+                .await
+                .expect("timed out waiting for event")
+                .expect("stream ended");
+            assert_eq!(expected, event);
         }
 
+        // Re-reporting the same value doesn't emit anything
+        let wb3200 = Atom::read(&mut Cursor::new(hex::decode(
+            "0020000043436450010102020000000200000000000000000c80000000000000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![wb3200]).await?;
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), events.next())
+                .await
+                .is_err()
+        );
         Ok(())
     }
 
-    /// Limit the `rx_queue` to a maximum age and size
-    async fn limit_rx_queue(&mut self) -> Result<(), Error> {
-        let rx_duration: Duration = self.last_rx_time.elapsed();
-        if rx_duration >= Self::MAX_RX_QUEUE_TIME
-            || self.rx_queue.len() >= Self::MAX_RX_QUEUE_LENGTH
-        {
-            // Too long since the last message was forwarded; dump the
-            // buffer and move ahead.
-            warn!(
-                "packet buffer stalled for too long ({} ms) or too large ({}), disconnecting",
-                rx_duration.as_millis(),
-                self.rx_queue.len()
+    #[tokio::test]
+    async fn tally_changes() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // Input1 on program, Input2 on preview, Input3 and Input4 off
+        let tlsr1 = Atom::read(&mut Cursor::new(hex::decode(
+            "00160000546c5372000400010100020200030000040000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![tlsr1.clone()]).await?;
+        let (controller, _cmd_rx) = mock_controller(state.clone(), state_rx);
+        let events = controller.tally_changes().await;
+        pin_mut!(events);
+
+        // Re-reporting the same tally doesn't emit anything
+        push_atoms(&state, &state_tx, vec![tlsr1]).await?;
+
+        // Input2 moves to program, Input3 to preview
+        let tlsr2 = Atom::read(&mut Cursor::new(hex::decode(
+            "00160000546c5372000400010100020100030200040000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![tlsr2]).await?;
+
+        // Input2 is no longer reported
+        let tlsr3 = Atom::read(&mut Cursor::new(hex::decode(
+            "00130000546c5372000300010100030200040000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![tlsr3]).await?;
+
+        for expected in [
+            (VideoSource::Input2, TallyFlags::new().with_program(true)),
+            (VideoSource::Input3, TallyFlags::new().with_preview(true)),
+            (VideoSource::Input2, TallyFlags::new()),
+        ] {
+            let event = tokio::time::timeout(Duration::from_secs(1), events.next())
+                .await
+                .expect("timed out waiting for event")
+                .expect("stream ended");
+            assert_eq!(expected, event);
+        }
+
+        assert!(
+            tokio::time::timeout(Duration::from_millis(50), events.next())
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_aux_source() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // ATEM Mini topology, with 1 aux
+        let topology = atem_mini_topology()?;
+        // Input 3, available on aux outputs
+        let inpr3 = camera3_inpr(0x1101)?;
+        push_atoms(&state, &state_tx, vec![topology, inpr3]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // Aux doesn't exist, or the switcher hasn't reported the source
+        for (aux, video_source) in [(1, VideoSource::Input3), (0, VideoSource::ME1Prog)] {
+            assert!(
+                matches!(
+                    controller.set_aux_source(aux, video_source).await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "aux {aux}, {video_source:?}"
+            );
+        }
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_aux_source(0, VideoSource::Input3).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetAuxSource {
+                aux: 0,
+                video_source: VideoSource::Input3,
+            })],
+            cmds
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_aux_source_number() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // ATEM Mini topology, with 2 auxes
+        let topology = Atom::read(&mut Cursor::new(hex::decode(
+            "002400005f746f70010e0102000100000401000000000001000001000000010101000000",
+        )?))?;
+        // Input 2, available on aux outputs
+        let inpr2 = Atom::read(&mut Cursor::new(hex::decode(
+            "002c0000496e5072000243616d657261203200000000000000000000000043414d3201000002000200001101",
+        )?))?;
+        // Input 3, not available on aux outputs
+        let inpr3 = camera3_inpr(0x1001)?;
+        push_atoms(&state, &state_tx, vec![topology, inpr2, inpr3]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+        assert_eq!(
+            vec![VideoSource::Unknown, VideoSource::Unknown],
+            controller.aux_routing().await
+        );
+
+        for (aux, n) in [(2, 2), (1, 0), (1, 3), (1, 99)] {
+            assert!(
+                matches!(
+                    controller.set_aux_source_number(aux, n).await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "aux {aux}, input {n}"
             );
-            // self.forward_rx_queue(..).await?;
-            return Err(Error::Timeout);
         }
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_aux_source_number(1, 2).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetAuxSource {
+                aux: 1,
+                video_source: VideoSource::Input2,
+            })],
+            cmds
+        );
 
+        // Switcher confirms the new routing
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![
+                Atom::new(AuxSource {
+                    aux: 0,
+                    video_source: VideoSource::ME1Prog,
+                }),
+                Atom::new(AuxSource {
+                    aux: 1,
+                    video_source: VideoSource::Input2,
+                }),
+            ],
+        )
+        .await?;
+        assert_eq!(
+            vec![VideoSource::ME1Prog, VideoSource::Input2],
+            controller.aux_routing().await
+        );
         Ok(())
     }
 
-    /// Handle acknowledgements of previously sent packets.
-    fn handle_ack(&mut self, acked_packet_id: u16) {
-        trace!("got ack for command: 0x{acked_packet_id:04X}");
-        if self.ack_queue.is_empty() {
-            // Nothing outstanding to ack
-            return;
+    #[cfg(feature = "palette")]
+    #[tokio::test]
+    async fn set_colour_generator_hsl() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        // 2 colour generators
+        push_atoms(
+            &state,
+            &state_tx,
+            (0..2)
+                .map(|id| {
+                    Atom::new(crate::protocol::atom::ColourGeneratorParams {
+                        id,
+                        ..Default::default()
+                    })
+                })
+                .collect(),
+        )
+        .await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        for id in [2, 8] {
+            assert!(
+                matches!(
+                    controller
+                        .set_colour_generator_hsl(id, palette::Hsl::new_srgb(120., 0.5, 0.5))
+                        .await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "generator {id}"
+            );
         }
+        assert!(cmd_rx.try_recv().is_err());
 
-        // ack_queue is always sorted by sender_packet_id, but this is only a
-        // u16, so it could wrap around.
-        let min_pos = if acked_packet_id >= Self::OVERFLOW_MARGIN {
-            // The acked packet is close to the overflow point, so set a minimum
-            // sender_packet_id that we want to check
-            let min_acked_packet_id =
-                acked_packet_id.saturating_sub(Self::MAX_ACK_QUEUE_LENGTH as u16);
-            self.ack_queue
-                .partition_point(|p| p.pkt.sender_packet_id < min_acked_packet_id)
-        } else {
-            0
+        let switcher = ack_commands(cmd_rx, 1);
+        // Out of range saturation and lightness are clamped, hue wraps around
+        controller
+            .set_colour_generator_hsl(1, palette::Hsl::new_srgb(-90., 1.5, -0.25))
+            .await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetColourGeneratorParams {
+                hue: Some(2700),
+                saturation: Some(1000),
+                luminance: Some(0),
+                ..SetColourGeneratorParams::new(1)
+            })],
+            cmds
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_supersource_box() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+        let params = SuperSourceBoxParams {
+            enabled: true,
+            video_source: VideoSource::Input3,
+            x: -2400,
+            y: 1350,
+            size: 500,
+            ..Default::default()
         };
 
-        let pos = self
-            .ack_queue
-            .partition_point(|p| p.pkt.sender_packet_id <= acked_packet_id);
-        if pos == 0 {
-            // All packets waiting for ack are newer than this
-            return;
+        // No SuperSource
+        assert!(matches!(
+            controller.set_supersource_box(0, params).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // SuperSource, and input 3 which can be used in SuperSource boxes
+        let ssrc = Atom::read(&mut Cursor::new(hex::decode(concat!(
+            "002c0000496e50721770",
+            "0000000000000000000000000000000000000000",
+            "00000000010000000000060000000000",
+        ))?))?;
+        let inpr = camera3_inpr(0x1901)?;
+        push_atoms(&state, &state_tx, vec![ssrc, inpr]).await?;
+
+        for (box_id, params) in [
+            (4, params),
+            (
+                0,
+                SuperSourceBoxParams {
+                    video_source: VideoSource::Input4,
+                    ..params
+                },
+            ),
+            (0, SuperSourceBoxParams { x: 4801, ..params }),
+            (0, SuperSourceBoxParams { y: -2701, ..params }),
+            (0, SuperSourceBoxParams { size: 69, ..params }),
+            (
+                0,
+                SuperSourceBoxParams {
+                    crop_top: 18001,
+                    ..params
+                },
+            ),
+        ] {
+            assert!(
+                matches!(
+                    controller.set_supersource_box(box_id, params).await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "box {box_id}, {params:?}"
+            );
         }
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_supersource_box(3, params).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetSuperSourceBoxProperties::from_params(
+                0, 3, &params
+            ))],
+            cmds
+        );
+        Ok(())
+    }
 
-        for pending in self.ack_queue.drain(min_pos..pos) {
-            let Some(responder) = pending.responder else {
-                continue;
-            };
+    #[tokio::test]
+    async fn set_wipe_params() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+        let params = WipeTransitionParams {
+            rate: 25,
+            pattern: WipePattern::CircleIris,
+            border_width: 250,
+            border_input: VideoSource::Input3,
+            x: 5000,
+            y: 5000,
+            ..Default::default()
+        };
 
-            if responder.send(Ok(())).is_err() {
-                error!(
-                    "responder remote side gone ({})",
-                    pending.pkt.sender_packet_id
-                );
-                continue;
-            }
+        // No MEs
+        assert!(matches!(
+            controller.set_wipe_params(0, params).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+
+        // ATEM Mini, and input 3 which can be used on ME 1
+        let top = atem_mini_topology()?;
+        let inpr = camera3_inpr(0x1101)?;
+        push_atoms(&state, &state_tx, vec![top, inpr]).await?;
+
+        for (me, params) in [
+            (1, params),
+            (
+                0,
+                WipeTransitionParams {
+                    border_input: VideoSource::Input4,
+                    ..params
+                },
+            ),
+            (
+                0,
+                WipeTransitionParams {
+                    border_width: 10001,
+                    ..params
+                },
+            ),
+            (
+                0,
+                WipeTransitionParams {
+                    symmetry: 10001,
+                    ..params
+                },
+            ),
+            (0, WipeTransitionParams { x: 10001, ..params }),
+        ] {
+            assert!(
+                matches!(
+                    controller.set_wipe_params(me, params).await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "ME {me}, {params:?}"
+            );
         }
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        let params = WipeTransitionParams {
+            border_softness: 10000,
+            y: 0,
+            ..params
+        };
+        controller.set_wipe_params(0, params).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetTransitionWipeProperties::from_params(
+                0, &params
+            ))],
+            cmds
+        );
+        Ok(())
     }
 
-    /// Work through the [Self::ack_queue] and retransmit any outstanding
-    /// packets beyond the deadline.
-    async fn do_retransmits(&mut self) -> Result<(), Error> {
-        let mut errors = Vec::new();
+    #[tokio::test]
+    async fn set_dve_params() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+        let params = DVETransitionParams {
+            rate: 30,
+            style: DVETransitionStyle::PushLeft,
+            fill_source: VideoSource::Input3,
+            key_source: VideoSource::Input3,
+            clip: 500,
+            gain: 700,
+            ..Default::default()
+        };
 
-        for (i, pending) in self.ack_queue.iter_mut().enumerate() {
-            if pending.last_attempt.elapsed() < Self::RETRANSMIT_TIME {
-                // too soon to retransmit
-                continue;
-            }
+        // ATEM Mini, with DVE capabilities not yet known
+        let top = atem_mini_topology()?;
+        push_atoms(&state, &state_tx, vec![top]).await?;
+        assert!(matches!(
+            controller.set_dve_params(0, params).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // ATEM Mini DVE capabilities, and input 3 which can't be used as a
+        // key source
+        let dve = Atom::read(&mut Cursor::new(hex::decode(
+            "002000005f44564500010011101112131415161718191a1b1c1d1e1f22000000",
+        )?))?;
+        let inpr = camera3_inpr(0x0101)?;
+        push_atoms(&state, &state_tx, vec![dve, inpr]).await?;
+
+        for (me, params) in [
+            (1, params),
+            (
+                0,
+                DVETransitionParams {
+                    style: DVETransitionStyle::SwooshTopLeft,
+                    ..params
+                },
+            ),
+            (
+                0,
+                DVETransitionParams {
+                    key_enabled: true,
+                    ..params
+                },
+            ),
+            (
+                0,
+                DVETransitionParams {
+                    clip: 1001,
+                    ..params
+                },
+            ),
+            (
+                0,
+                DVETransitionParams {
+                    gain: 1001,
+                    ..params
+                },
+            ),
+        ] {
+            assert!(
+                matches!(
+                    controller.set_dve_params(me, params).await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "ME {me}, {params:?}"
+            );
+        }
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.set_dve_params(0, params).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetTransitionDVEProperties::from_params(
+                0, &params
+            ))],
+            cmds
+        );
+        Ok(())
+    }
 
-            if pending.retry_limit == 0 {
-                // No retransmits available, send timeout
-                error!("packet timeout: 0x{:04X}", pending.pkt.sender_packet_id);
-                errors.insert(0, (i, Error::Timeout));
-                continue;
-            }
+    #[tokio::test]
+    async fn hyperdeck_transport() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // No HyperDecks
+        assert!(matches!(
+            controller.hyperdeck_play(0).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+
+        // ATEM Mini: 4 HyperDecks
+        let top = atem_mini_topology()?;
+        push_atoms(&state, &state_tx, vec![top]).await?;
+        assert!(matches!(
+            controller.hyperdeck_record(4).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 3);
+        controller.hyperdeck_play(0).await?;
+        controller.hyperdeck_record(3).await?;
+        controller.hyperdeck_stop(3).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![
+                Atom::new(SetHyperDeckClipPlayStatus::transport(
+                    0,
+                    HyperDeckTransportState::Play
+                )),
+                Atom::new(SetHyperDeckClipPlayStatus::transport(
+                    3,
+                    HyperDeckTransportState::Record
+                )),
+                Atom::new(SetHyperDeckClipPlayStatus::transport(
+                    3,
+                    HyperDeckTransportState::Idle
+                )),
+            ],
+            cmds
+        );
+        Ok(())
+    }
 
-            pending.retry_limit -= 1;
-            pending.last_attempt = Instant::now();
-            trace!("retransmitting packet: {:?}", pending.pkt);
-            if let Err(e) = self.channel.send(&pending.pkt).await {
-                errors.insert(0, (i, e));
-                continue;
-            }
+    #[tokio::test]
+    async fn camera_lens() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // No camera control
+        assert!(matches!(
+            controller.camera_trigger_autofocus(3).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // ATEM Mini topology, and input 3
+        let topology = atem_mini_topology()?;
+        let inpr = camera3_inpr(0x1101)?;
+        push_atoms(&state, &state_tx, vec![topology, inpr]).await?;
+
+        for input in [0, 4, 99] {
+            assert!(
+                matches!(
+                    controller.camera_set_focus(input, I5F11::ZERO).await,
+                    Err(Error::ParameterOutOfRange)
+                ),
+                "input {input}"
+            );
         }
+        assert!(cmd_rx.try_recv().is_err());
 
-        // Collect any errored states
-        for (i, e) in errors {
-            let Some(pending) = self.ack_queue.remove(i) else {
-                error!(
-                    "tried to pop entry #{i} from {} entry ack_queue",
-                    self.ack_queue.len()
-                );
-                return Err(Error::Internal);
-            };
+        let switcher = ack_commands(cmd_rx, 3);
+        controller
+            .camera_set_focus(3, I5F11::from_num(0.25))
+            .await?;
+        controller.camera_set_iris(3, I5F11::from_num(0.5)).await?;
+        controller.camera_trigger_autofocus(3).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![
+                Atom::new(CameraCommand {
+                    input: 3,
+                    parameter: CameraParameterID::Lens(LensParam::Focus),
+                    relative: false,
+                    value: CameraParameterValue::I5F11(vec![I5F11::from_num(0.25)]),
+                }),
+                Atom::new(CameraCommand {
+                    input: 3,
+                    parameter: CameraParameterID::Lens(LensParam::ApertureNormalised),
+                    relative: false,
+                    value: CameraParameterValue::I5F11(vec![I5F11::from_num(0.5)]),
+                }),
+                Atom::new(CameraCommand {
+                    input: 3,
+                    parameter: CameraParameterID::Lens(LensParam::AutoFocus),
+                    relative: false,
+                    value: CameraParameterValue::Bool(vec![]),
+                }),
+            ],
+            cmds
+        );
 
-            if let Some(responder) = pending.responder {
-                if responder.send(Err(e)).is_err() {
-                    error!(
-                        "responder remote side gone ({})",
-                        pending.pkt.sender_packet_id
-                    );
-                    continue;
-                }
+        // The autofocus command should be a void value
+        let mut out = Cursor::new(Vec::new());
+        cmds[2].write(&mut out)?;
+        assert_eq!(
+            hex::decode("0020000043436d64030001000000000000000000000000000000000000000000")?,
+            out.into_inner()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn macros() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![
+                Atom::new(MacroProperties {
+                    index: 0,
+                    is_used: true,
+                    name: "Opening".to_string(),
+                    ..Default::default()
+                }),
+                Atom::new(MacroProperties {
+                    index: 1,
+                    ..Default::default()
+                }),
+            ],
+        )
+        .await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        assert!(matches!(
+            controller.run_macro(1).await,
+            Err(Error::NotFound)
+        ));
+        assert!(matches!(
+            controller.run_macro(2).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller.delete_macro(2).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller
+                .start_macro_recording(2, String::new(), String::new())
+                .await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = tokio::spawn(async move {
+            let mut sent = vec![];
+            while let Some(cmd) = cmd_rx.recv().await {
+                let AsyncCommand::Commands {
+                    mut cmds,
+                    responder: Some(responder),
+                } = cmd
+                else {
+                    panic!("unexpected command");
+                };
+                sent.append(&mut cmds);
+                let _ = responder.send(Ok(()));
             }
-        }
+            sent
+        });
 
+        controller.run_macro(0).await?;
+        controller.stop_macro().await?;
+        controller
+            .start_macro_recording(1, "New".to_string(), "Test".to_string())
+            .await?;
+        controller.stop_macro_recording().await?;
+        controller.delete_macro(0).await?;
+        drop(controller);
+
+        let sent = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![
+                Atom::new(MacroAction {
+                    index: 0,
+                    action: MacroActionType::Run,
+                }),
+                Atom::new(MacroAction {
+                    index: NO_MACRO,
+                    action: MacroActionType::Stop,
+                }),
+                Atom::new(MacroStartRecord {
+                    index: 1,
+                    name: "New".to_string(),
+                    description: "Test".to_string(),
+                }),
+                Atom::new(MacroAction {
+                    index: NO_MACRO,
+                    action: MacroActionType::StopRecording,
+                }),
+                Atom::new(MacroAction {
+                    index: 0,
+                    action: MacroActionType::Delete,
+                }),
+            ],
+            sent
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn set_audio_input_gain() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // No classic audio mixer
+        assert!(matches!(
+            controller.set_audio_input_gain(1, -6.).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // ATEM Mini topology, but with a classic audio mixer
+        let Payload::Topology(mut topology) = atem_mini_topology()?.payload else {
+            panic!("wrong command type");
+        };
+        topology.audio_mixer = true;
+        topology.farlight_audio_mixer = false;
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![
+                Atom::new(topology),
+                Atom::new(AudioMixerInputProperties {
+                    source: 1,
+                    ..Default::default()
+                }),
+            ],
+        )
+        .await?;
+
+        assert!(matches!(
+            controller.set_audio_input_gain(2, -6.).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller.set_audio_input_gain(1, 6.03).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller.set_audio_input_gain(1, f32::NAN).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        // Fake switcher, which applies the new gain
+        tokio::spawn(async move {
+            while let Some(cmd) = cmd_rx.recv().await {
+                let AsyncCommand::Commands {
+                    cmds,
+                    responder: Some(responder),
+                } = cmd
+                else {
+                    panic!("unexpected command");
+                };
+                let Payload::SetAudioMixerInputProperties(cami) = &cmds[0].payload else {
+                    panic!("unexpected command: {cmds:?}");
+                };
+                assert_eq!(1, cami.source);
+                assert_eq!(None, cami.state);
+                assert_eq!(None, cami.balance);
+                push_atoms(
+                    &state,
+                    &state_tx,
+                    vec![Atom::new(AudioMixerInputProperties {
+                        source: cami.source,
+                        gain: cami.gain.expect("gain should be set"),
+                        ..Default::default()
+                    })],
+                )
+                .await
+                .expect("could not update state");
+                let _ = responder.send(Ok(()));
+            }
+        });
+
+        controller.set_audio_input_gain(1, -6.).await?;
+        assert_eq!(
+            0x4027,
+            controller.get_state().await.audio_mixer_inputs[&1].gain
+        );
+        controller.set_audio_input_gain(1, 0.).await?;
+        assert_eq!(
+            0x8000,
+            controller.get_state().await.audio_mixer_inputs[&1].gain
+        );
         Ok(())
     }
 
-    /// Queue a request for the device's clock.
-    async fn request_clock(&mut self) -> Result<(), Error> {
-        let (responder, resp_rx) = oneshot::channel();
-        let async_cmd = AsyncCommand::Commands {
-            cmds: vec![TIME_REQUEST_COMMAND.clone()],
-            responder: Some(responder),
-        };
+    #[tokio::test]
+    async fn set_fairlight_input_state() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // No Fairlight audio mixer
+        assert!(matches!(
+            controller.mute_audio_input(1, true).await,
+            Err(Error::FeatureUnavailable)
+        ));
+        assert!(matches!(
+            controller.set_audio_follows_video(1, true).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // ATEM Mini topology, with input 1 muted
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![
+                atem_mini_topology()?,
+                Atom::read(&mut Cursor::new(hex::decode(
+                    "003c0000464153500001000000000001ffffffffffff010001000000000000000002000006010004000000000000000000000bc2000003e807010027",
+                )?))?,
+            ],
+        )
+        .await?;
 
-        self.handle_queued_command(async_cmd).await?;
+        assert!(matches!(
+            controller.mute_audio_input(2, true).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller.set_audio_follows_video(2, true).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        let sent = ack_commands(cmd_rx, 4);
+
+        controller.mute_audio_input(1, true).await?;
+        controller.mute_audio_input(1, false).await?;
+        controller.set_audio_follows_video(1, true).await?;
+        controller.set_audio_follows_video(1, false).await?;
+
+        let sent = sent.await.expect("switcher task failed");
+        assert_eq!(4, sent.len());
+        for (cmd, expected_state) in sent.iter().zip([0x01, 0x02, 0x04, 0x02]) {
+            let Payload::SetFairlightAudioMixerInputSourceProperties(cfsp) = &cmd.payload else {
+                panic!("unexpected command: {cmd:?}");
+            };
+            assert_eq!(1, cfsp.source_id);
+            assert_eq!(-0xff00, cfsp.channel_id);
+
+            let mut out = Cursor::new(Vec::new());
+            cmd.write(&mut out)?;
+            let out = out.into_inner();
+            assert_eq!(0x38, out.len());
+            // Mask only sets the source state
+            assert_eq!([0x01, 0x00], out[8..10]);
+            assert_eq!(expected_state, out[0x34]);
+        }
+        Ok(())
+    }
 
-        let clock_notifier = self.clock_notifier.clone();
-        tokio::task::spawn(async move {
-            // Wait for a result from the resp_rx; this has a RecvError layer
-            // then our Error type.
-            if !resp_rx.await.is_ok_and(|r| r.is_ok()) {
-                clock_notifier.notify_waiters();
+    #[tokio::test]
+    async fn set_fade_to_black_rate() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let topology = atem_mini_topology()?;
+        push_atoms(&state, &state_tx, vec![topology]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        assert!(matches!(
+            controller.set_fade_to_black_rate(1, 25).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        // Fake switcher, which applies the new rate
+        tokio::spawn(async move {
+            while let Some(cmd) = cmd_rx.recv().await {
+                let AsyncCommand::Commands {
+                    cmds,
+                    responder: Some(responder),
+                } = cmd
+                else {
+                    panic!("unexpected command");
+                };
+                let Payload::SetFadeToBlackParams(ftbc) = &cmds[0].payload else {
+                    panic!("unexpected command: {cmds:?}");
+                };
+                assert_eq!(0, ftbc.me);
+                let rate = ftbc.rate.expect("rate should be set");
+                push_atoms(
+                    &state,
+                    &state_tx,
+                    vec![Atom::new(FadeToBlackParams { me: ftbc.me, rate })],
+                )
+                .await
+                .expect("could not update state");
+                let _ = responder.send(Ok(()));
             }
         });
+
+        controller.set_fade_to_black_rate(0, 25).await?;
+        assert_eq!(
+            Some(25),
+            controller.get_state().await.get_fade_to_black_rate(0)
+        );
         Ok(())
     }
 
-    async fn handle_download_chunk(&mut self, cmd: Atom) -> Result<(), Error> {
-        let Payload::TransferChunk(chunk) = cmd.payload else {
-            return Err(Error::Internal);
+    #[tokio::test]
+    async fn set_key_dve() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let topology = atem_mini_topology()?;
+        let mec = Atom::new(MixEffectBlockCapabilities { me: 0, keyers: 1 });
+        push_atoms(&state, &state_tx, vec![topology, mec]).await?;
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+
+        // DVE capabilities not yet known
+        assert!(matches!(
+            controller.set_key_dve(0, 0, Some((0, 0)), None, None).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // ATEM Mini DVE capabilities: can't rotate, can scale up
+        let dve = Atom::read(&mut Cursor::new(hex::decode(
+            "002000005f44564500010011101112131415161718191a1b1c1d1e1f22000000",
+        )?))?;
+        push_atoms(&state, &state_tx, vec![dve]).await?;
+
+        assert!(matches!(
+            controller.set_key_dve(1, 0, Some((0, 0)), None, None).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller.set_key_dve(0, 1, Some((0, 0)), None, None).await,
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            controller.set_key_dve(0, 0, None, None, Some(900)).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // Nothing should have been sent to the switcher.
+        assert!(cmd_rx.try_recv().is_err());
+
+        let switcher = ack_commands(cmd_rx, 1);
+        controller
+            .set_key_dve(0, 0, Some((-8000, 4500)), Some((1500, 1500)), None)
+            .await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![Atom::new(SetKeyDVEProperties {
+                size_x: Some(1500),
+                size_y: Some(1500),
+                position_x: Some(-8000),
+                position_y: Some(4500),
+                ..SetKeyDVEProperties::new(0, 0)
+            })],
+            cmds
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn factory_reset() -> Result {
+        let (state, _state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx.resubscribe());
+        let (other_controller, _other_cmd_rx) = mock_controller(state, state_rx);
+
+        // Token from another controller
+        let confirm = other_controller.request_factory_reset()?;
+        assert!(matches!(
+            controller.factory_reset(confirm).await,
+            Err(Error::ConfirmationRequired)
+        ));
+
+        // Superseded token
+        let confirm = controller.request_factory_reset()?;
+        let _ = controller.request_factory_reset()?;
+        assert!(matches!(
+            controller.factory_reset(confirm).await,
+            Err(Error::ConfirmationRequired)
+        ));
+
+        // Expired token
+        let mut confirm = controller.request_factory_reset()?;
+        confirm.issued -= FactoryResetConfirm::VALIDITY * 2;
+        assert!(matches!(
+            controller.factory_reset(confirm).await,
+            Err(Error::ConfirmationRequired)
+        ));
+
+        // Nothing should have been sent to the switcher.
+        assert!(cmd_rx.try_recv().is_err());
+
+        // Valid token
+        let confirm = controller.request_factory_reset()?;
+        let switcher = ack_commands(cmd_rx, 1);
+        controller.factory_reset(confirm).await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(vec![Atom::new(CLEAR_STARTUP_SETTINGS)], cmds);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn upload_transfer_events() -> Result {
+        let (mut receiver, cmd_tx, _switcher) = fake_switcher().await?;
+        let mut events = receiver.transfer_tx.subscribe();
+
+        let (responder, mut resp_rx) = oneshot::channel();
+        let buffer = VecDeque::from([0x1122334455667788; 16]);
+        receiver
+            .handle_queued_command(AsyncCommand::FileUpload(AsyncFileUploadRequest {
+                store_id: 0,
+                index: 1,
+                buffer,
+                size: 128,
+                typ: FileType::StillFrame,
+                is_rle: false,
+                name: String::new(),
+                description: String::new(),
+                md5: [0; 16],
+                bytes_sent: 0,
+                chunk_size: 0,
+                chunks_remaining: 0,
+                responder: Some(responder),
+                semaphore: Arc::new(Semaphore::new(1)),
+                storage_lock: Some(Arc::new(StorageLock::new(0, cmd_tx.clone()))),
+            }))
+            .await?;
+
+        let TransferEvent::Started {
+            id,
+            direction: TransferDirection::Upload,
+            store_id: 0,
+            index: 1,
+        } = events.recv().await.expect("started event")
+        else {
+            panic!("expected upload started event");
         };
 
-        let Some(download) = self.downloads.get_mut(&chunk.id) else {
-            return Ok(());
+        receiver
+            .handle_file_transfer_chunk_params(FileTransferChunkParams {
+                id,
+                chunk_size: 1024,
+                chunk_count: 4,
+            })
+            .await?;
+        assert_eq!(
+            TransferEvent::Progress { id, bytes: 128 },
+            events.recv().await.expect("progress event")
+        );
+
+        let mut completed = TransferCompleted::default();
+        completed.id = id;
+        receiver
+            .handle_transfer_completed(Atom::new(completed))
+            .await?;
+        assert_eq!(
+            TransferEvent::Completed { id },
+            events.recv().await.expect("completed event")
+        );
+        assert!(matches!(resp_rx.try_recv(), Ok(Ok(()))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn drop_cancels_transfers() -> Result {
+        let (mut receiver, cmd_tx, switcher) = fake_switcher().await?;
+        receiver.session_id = 0x8001;
+        let mut events = receiver.transfer_tx.subscribe();
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let recv_timeout = Duration::from_secs(1);
+
+        // Upload a still to store 0, and download audio from store 1
+        let (responder, _resp_rx) = oneshot::channel();
+        receiver
+            .handle_queued_command(AsyncCommand::FileUpload(AsyncFileUploadRequest {
+                store_id: 0,
+                index: 1,
+                buffer: VecDeque::from([0x1122334455667788; 16]),
+                size: 128,
+                typ: FileType::StillFrame,
+                is_rle: false,
+                name: String::new(),
+                description: String::new(),
+                md5: [0; 16],
+                bytes_sent: 0,
+                chunk_size: 0,
+                chunks_remaining: 0,
+                responder: Some(responder),
+                semaphore: Arc::new(Semaphore::new(1)),
+                storage_lock: Some(Arc::new(StorageLock::new(0, cmd_tx.clone()))),
+            }))
+            .await?;
+        let TransferEvent::Started { id: upload_id, .. } =
+            events.recv().await.expect("started event")
+        else {
+            panic!("expected started event");
         };
 
-        let chunk_len = chunk.payload.len();
-        download.bytes_received += chunk_len;
-        download.bytes_since_last_ack += chunk_len;
+        let (tx, _rx) = mpsc::channel(1);
+        receiver
+            .handle_queued_command(AsyncCommand::FileDownload(AsyncFileDownloadRequest {
+                store_id: 1,
+                index: 0,
+                typ: FileType::Audio,
+                tx: Arc::new(tx),
+                bytes_received: 0,
+                bytes_since_last_ack: 0,
+                md5: None,
+                received_md5: DownloadMd5::new(FileType::Audio),
+                storage_lock: Some(Arc::new(StorageLock::new(1, cmd_tx.clone()))),
+            }))
+            .await?;
 
-        if download.tx.send(Ok(chunk.payload)).await.is_err() {
-            error!("download channel disconnected!");
-            self.downloads.remove(&chunk.id);
+        // Discard the transfer setup commands
+        for _ in 0..2 {
+            tokio::time::timeout(recv_timeout, switcher.recv(&mut b))
+                .await
+                .expect("timeout waiting for transfer setup")?;
         }
 
+        drop(receiver);
+
+        let l = tokio::time::timeout(recv_timeout, switcher.recv(&mut b))
+            .await
+            .expect("timeout waiting for transfer cancellation")?;
+        let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        assert_eq!(0x8001, pkt.session_id);
+        assert!(pkt.flags.ack());
+        assert_eq!(
+            Some(&vec![
+                Atom::new(CancelFileDownload { id: upload_id }),
+                Atom::new(MediaPoolLock::unlock(0)),
+                Atom::new(MediaPoolLock::unlock(1)),
+            ]),
+            pkt.atoms()
+        );
+
+        let l = tokio::time::timeout(recv_timeout, switcher.recv(&mut b))
+            .await
+            .expect("timeout waiting for disconnection")?;
+        let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        assert_eq!(Some(&AtemControl::Disconnect), pkt.control());
         Ok(())
     }
 
-    async fn handle_transfer_completed(&mut self, cmd: Atom) -> Result<(), Error> {
-        let Payload::TransferCompleted(completed) = cmd.payload else {
-            return Err(Error::Internal);
+    #[tokio::test]
+    async fn cancel_download() -> Result {
+        let (mut receiver, cmd_tx, switcher) = fake_switcher().await?;
+        receiver.session_id = 0x8001;
+        let mut events = receiver.transfer_tx.subscribe();
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let recv_timeout = Duration::from_secs(1);
+
+        let (tx, mut rx) = mpsc::channel(1);
+        receiver
+            .handle_queued_command(AsyncCommand::FileDownload(AsyncFileDownloadRequest {
+                store_id: 1,
+                index: 0,
+                typ: FileType::Audio,
+                tx: Arc::new(tx),
+                bytes_received: 0,
+                bytes_since_last_ack: 0,
+                md5: None,
+                received_md5: DownloadMd5::new(FileType::Audio),
+                storage_lock: Some(Arc::new(StorageLock::new(1, cmd_tx.clone()))),
+            }))
+            .await?;
+        let TransferEvent::Started { id, .. } = events.recv().await.expect("started event") else {
+            panic!("expected started event");
         };
 
-        if let Some(download) = self.downloads.remove(&completed.id) {
-            debug!("finished download: {:#04x}", completed.id);
-            drop(download.storage_lock);
-        } else if let Some((responder, storage_lock)) = self.finished_uploads.remove(&completed.id)
-        {
-            debug!("finished upload: {:#04x}", completed.id);
-            if let Some(responder) = responder {
-                if responder.send(Ok(())).is_err() {
-                    error!("error notifying upload responder");
-                }
-            }
-            drop(storage_lock);
-        }
+        // Discard the transfer setup command
+        tokio::time::timeout(recv_timeout, switcher.recv(&mut b))
+            .await
+            .expect("timeout waiting for transfer setup")?;
+
+        // Nothing to cancel while the receiver is open
+        receiver.cancel_closed_downloads();
+        assert!(receiver.downloads.contains_key(&id));
+
+        rx.close();
+        receiver.cancel_closed_downloads();
+        assert!(receiver.downloads.is_empty());
+        assert_eq!(
+            TransferEvent::Cancelled { id },
+            events.recv().await.expect("cancelled event")
+        );
 
+        // Cancellation isn't sent to the switcher
+        assert!(
+            tokio::time::timeout(Duration::from_millis(100), switcher.recv(&mut b))
+                .await
+                .is_err()
+        );
+
+        // Further chunks for the transfer are ignored
+        receiver
+            .handle_download_chunk(Atom::new(TransferChunk {
+                id,
+                payload: vec![0; 4],
+            }))
+            .await?;
+        assert!(receiver.downloads.is_empty());
+
+        // Dropping the storage lock queues an unlock request
+        let Some(AsyncCommand::Commands { cmds, .. }) =
+            tokio::time::timeout(recv_timeout, receiver.cmd_rx.recv())
+                .await
+                .expect("timeout waiting for unlock")
+        else {
+            panic!("expected commands");
+        };
+        assert_eq!(vec![Atom::new(MediaPoolLock::unlock(1))], cmds);
+
+        // Receiving no more data
+        assert!(rx.recv().await.is_none());
         Ok(())
     }
 
-    /// Handle an incoming [FileTransferError][crate::protocol::atom::FileTransferError].
-    async fn handle_transfer_error(&mut self, cmd: Atom) -> Result<(), Error> {
-        let Payload::FileTransferError(error) = cmd.payload else {
-            return Err(Error::Internal);
-        };
+    #[tokio::test]
+    async fn download_verifies_md5() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher().await?;
+        // RLE-compressed black 1080p frame, split over two chunks
+        let black = hex::decode("fefefefefefefefe00000000000fd2003ac800403ac80040")?;
+        let black_md5 = [
+            0x52, 0x99, 0x71, 0x75, 0x9f, 0xd7, 0x8a, 0x24, 0xbd, 0x0e, 0x24, 0x82, 0x69, 0xe9,
+            0xe5, 0xe5,
+        ];
+
+        for (md5, ok) in [(black_md5, true), ([0; 16], false)] {
+            let mut events = receiver.transfer_tx.subscribe();
+            let (tx, mut rx) = mpsc::channel(4);
+            receiver
+                .handle_queued_command(AsyncCommand::FileDownload(AsyncFileDownloadRequest {
+                    store_id: 0,
+                    index: 0,
+                    typ: FileType::StillFrame,
+                    tx: Arc::new(tx),
+                    bytes_received: 0,
+                    bytes_since_last_ack: 0,
+                    md5: Some(md5),
+                    received_md5: DownloadMd5::new(FileType::StillFrame),
+                    storage_lock: None,
+                }))
+                .await?;
+            let TransferEvent::Started { id, .. } = events.recv().await.expect("started event")
+            else {
+                panic!("expected started event");
+            };
 
-        if let Some(download) = self.downloads.remove(&error.id) {
-            error!(
-                "error during download {:#04x}: {:#02x}",
-                error.id, error.code
-            );
-            let tx = download.tx;
-            tokio::task::spawn(async move {
-                if tx
-                    .send(Err(Error::SwitcherTransferError(error.code)))
-                    .await
-                    .is_err()
-                {
-                    error!("error notifying download channel");
-                }
-            });
-            drop(download.storage_lock);
-        } else if let Some(upload) = self.uploads.remove(&error.id) {
-            error!("error during upload {:#04x}: {:#02x}", error.id, error.code);
-            if let Some(responder) = upload.responder {
-                if responder
-                    .send(Err(Error::SwitcherTransferError(error.code)))
-                    .is_err()
-                {
-                    error!("error notifying upload responder");
-                }
+            for c in black.chunks(16) {
+                let mut chunk = TransferChunk::new(id, c.len());
+                chunk.payload.extend_from_slice(c);
+                receiver.handle_download_chunk(Atom::new(chunk)).await?;
             }
-            drop(upload.storage_lock);
-        } else if let Some((responder, storage_lock)) = self.finished_uploads.remove(&error.id) {
-            error!(
-                "error finishing upload {:#04x}: {:#02x}",
-                error.id, error.code
-            );
-            if let Some(responder) = responder {
-                if responder
-                    .send(Err(Error::SwitcherTransferError(error.code)))
-                    .is_err()
-                {
-                    error!("error notifying upload responder");
+            let mut completed = TransferCompleted::default();
+            completed.id = id;
+            receiver
+                .handle_transfer_completed(Atom::new(completed))
+                .await?;
+
+            let mut o = Vec::new();
+            let mut last_error = None;
+            while let Some(chunk) = rx.recv().await {
+                match chunk {
+                    Ok(chunk) => o.extend_from_slice(&chunk),
+                    Err(e) => last_error = Some(e),
                 }
             }
-            drop(storage_lock);
+            assert_eq!(black, o);
+            if ok {
+                assert!(last_error.is_none());
+            } else {
+                assert!(matches!(last_error, Some(Error::ChecksumMismatch)));
+            }
         }
-
         Ok(())
     }
 
-    /// Enqueues a [FileTransferChunkParams] for later processing in
-    /// [`upload_chunk_params_rx`][Self::upload_chunk_params_rx].
-    ///
-    /// This ensures we ack the [FileTransferChunkParams] before we start
-    /// sending a bunch of [TransferChunk]s.
-    async fn enqueue_file_transfer_chunk_params(&mut self, cmd: Atom) -> Result<(), Error> {
-        let Payload::FileTransferChunkParams(params) = cmd.payload else {
-            return Err(Error::Internal);
+    #[tokio::test]
+    async fn upload_multiview_label() -> Result {
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
+        let label = vec![0xff; 320 * 90 * 4];
+
+        // No MultiView
+        assert!(matches!(
+            controller.upload_multiview_label(1, &label, 320, 90).await,
+            Err(Error::FeatureUnavailable)
+        ));
+
+        // ATEM Mini topology, but with a MultiView
+        let Payload::Topology(mut topology) = atem_mini_topology()?.payload else {
+            panic!("wrong command type");
         };
+        topology.multiviewers = 1;
+        push_atoms(&state, &state_tx, vec![Atom::new(topology)]).await?;
+
+        assert!(matches!(
+            controller.upload_multiview_label(1, &label, 320, 80).await,
+            Err(Error::InvalidLength)
+        ));
+        assert!(cmd_rx.try_recv().is_err());
+
+        // Labels don't need a storage lock, so the upload is the first thing
+        // the receiver sees.
+        let switcher = tokio::spawn(async move {
+            let Some(AsyncCommand::FileUpload(req)) = cmd_rx.recv().await else {
+                panic!("expected upload");
+            };
+            assert_eq!(0xffff, req.store_id);
+            assert_eq!(1, req.index);
+            assert_eq!(FileType::MultiViewLabel, req.typ);
+            assert!(req.is_rle);
+            assert_eq!(320 * 90 * 4, req.size);
+            assert_eq!(
+                320 * 90 / 2,
+                RleDecompressor::new(req.buffer.iter().copied()).count()
+            );
+            assert!(req.storage_lock.is_none());
+            let _ = req.responder.expect("responder").send(Ok(()));
+            cmd_rx
+        });
 
-        self.upload_chunk_params_tx
-            .send(params)
-            .await
-            .map_err(|_| Error::ChannelUnavailable)
+        controller
+            .upload_multiview_label(1, &label, 320, 90)
+            .await?;
+        let mut cmd_rx = switcher.await.expect("switcher task failed");
+
+        // Odd number of pixels is padded with a transparent pixel
+        let switcher = tokio::spawn(async move {
+            let Some(AsyncCommand::FileUpload(req)) = cmd_rx.recv().await else {
+                panic!("expected upload");
+            };
+            assert_eq!(8, req.size);
+            assert_eq!(
+                vec![0xffffffff00000000],
+                RleDecompressor::new(req.buffer.iter().copied()).collect::<Vec<_>>()
+            );
+            let _ = req.responder.expect("responder").send(Ok(()));
+        });
+        controller
+            .upload_multiview_label(2, &[0xff; 4], 1, 1)
+            .await?;
+        switcher.await.expect("switcher task failed");
+        Ok(())
     }
 
-    /// Handles a [FileTransferChunkParams] message from
-    /// [`upload_chunk_params_rx`][Self::upload_chunk_params_rx], and pushes
-    async fn handle_file_transfer_chunk_params(
-        &mut self,
-        params: FileTransferChunkParams,
-    ) -> Result<(), Error> {
-        let Some(cmd_tx) = self.cmd_tx_weak.upgrade() else {
-            error!("unable to upgrade cmd_tx_weak; AtemController dropped it?");
-            return Err(Error::UnexpectedState);
+    #[tokio::test]
+    async fn set_multiview_overlays() -> Result {
+        use crate::protocol::atom::{
+            MultiViewCapabilities, MultiViewSafeArea, SetMultiViewVuMeterEnabled,
         };
+        let (state, state_tx, state_rx) = state_channel();
+        let state = Arc::new(state);
+        let (controller, mut cmd_rx) = mock_controller(state.clone(), state_rx);
 
-        let Some(upload) = self.uploads.get_mut(&params.id) else {
-            // The switcher could have given us a FTCD when we've already queued
-            // up the last chunk. So just ignore this.
-            return Ok(());
+        // ATEM Mini topology, but with a MultiView of 10 windows
+        let Payload::Topology(mut topology) = atem_mini_topology()?.payload else {
+            panic!("wrong command type");
         };
+        topology.multiviewers = 1;
+        push_atoms(
+            &state,
+            &state_tx,
+            vec![
+                Atom::new(topology),
+                Atom::new(MultiViewCapabilities {
+                    multiviews: 1,
+                    windows: 10,
+                }),
+            ],
+        )
+        .await?;
 
-        upload.chunk_size = params.chunk_size;
-        upload.chunks_remaining = params.chunk_count;
-        let mtu = (TransferChunk::MAX_PAYLOAD_LENGTH.min(upload.chunk_size) & !0x7) as usize;
-        if mtu <= 24 {
-            error!(
-                "swicher's MTU was {}, protocol MTU is {}, need at least 24 bytes for RLE",
-                upload.chunk_size,
-                TransferChunk::MAX_PAYLOAD_LENGTH,
-            );
-            return Err(Error::ParameterOutOfRange);
+        for (multiview, window) in [(1, 0), (0, 10)] {
+            assert!(matches!(
+                controller
+                    .set_multiview_overlays(multiview, window, Some(true), None)
+                    .await,
+                Err(Error::ParameterOutOfRange)
+            ));
         }
+        assert!(cmd_rx.try_recv().is_err());
 
-        // The switcher has given us permission to send
-        // `chunk_count` chunks of data. Now we need to fiddle our
-        // data queue and figure out what we can send.
-        let mut chunks = Vec::with_capacity(upload.chunks_remaining.into());
-        while upload.chunks_remaining > 0 {
-            let mut chunk = TransferChunk::new(params.id, mtu);
+        let switcher = ack_commands(cmd_rx, 1);
+        controller
+            .set_multiview_overlays(0, 9, Some(true), Some(false))
+            .await?;
+        let cmds = switcher.await.expect("switcher task failed");
+        assert_eq!(
+            vec![
+                Atom::new(MultiViewSafeArea {
+                    multiview: 0,
+                    window: 9,
+                    enabled: true,
+                }),
+                Atom::new(SetMultiViewVuMeterEnabled {
+                    multiview: 0,
+                    window: 9,
+                    enabled: false,
+                }),
+            ],
+            cmds
+        );
+        Ok(())
+    }
 
-            while let Some(b) = upload.buffer.pop_front() {
-                if b == RLE_MARKER && (chunk.payload.len() + 24) > mtu {
-                    // We need to be able to push the entire RLE
-                    // sequence (u64 * 3) in the same command, but
-                    // there's not enough space.
-                    // Put it back and save it for later.
-                    upload.buffer.push_front(b);
-                    break;
-                }
+    #[tokio::test]
+    async fn upload_paused() -> Result {
+        let (mut receiver, cmd_tx, _switcher) = fake_switcher().await?;
+        let mut events = receiver.transfer_tx.subscribe();
+
+        let (responder, mut resp_rx) = oneshot::channel();
+        let buffer = VecDeque::from([0x1122334455667788; 16]);
+        receiver
+            .handle_queued_command(AsyncCommand::FileUpload(AsyncFileUploadRequest {
+                store_id: 0,
+                index: 1,
+                buffer,
+                size: 128,
+                typ: FileType::StillFrame,
+                is_rle: false,
+                name: String::new(),
+                description: String::new(),
+                md5: [0; 16],
+                bytes_sent: 0,
+                chunk_size: 0,
+                chunks_remaining: 0,
+                responder: Some(responder),
+                semaphore: Arc::new(Semaphore::new(1)),
+                storage_lock: Some(Arc::new(StorageLock::new(0, cmd_tx.clone()))),
+            }))
+            .await?;
 
-                chunk.payload.extend_from_slice(&b.to_be_bytes());
-                assert!(chunk.payload.len() <= mtu);
-                if chunk.payload.len() >= mtu {
-                    // Chunk is now full
-                    break;
-                }
-            }
+        let TransferEvent::Started { id, .. } = events.recv().await.expect("started event") else {
+            panic!("expected upload started event");
+        };
 
-            if chunk.payload.is_empty() {
-                // We've run out of data to send.
-                break;
-            }
+        // Switcher asks us to pause
+        receiver
+            .handle_file_transfer_chunk_params(FileTransferChunkParams {
+                id,
+                chunk_size: 0,
+                chunk_count: 0,
+            })
+            .await?;
+        assert!(events.try_recv().is_err());
+        assert!(receiver.uploads.contains_key(&id));
+        assert!(!receiver.finished_uploads.contains_key(&id));
+
+        // Switcher resumes the upload
+        receiver
+            .handle_file_transfer_chunk_params(FileTransferChunkParams {
+                id,
+                chunk_size: 1024,
+                chunk_count: 4,
+            })
+            .await?;
+        assert_eq!(
+            TransferEvent::Progress { id, bytes: 128 },
+            events.recv().await.expect("progress event")
+        );
+        assert!(!receiver.uploads.contains_key(&id));
 
-            // We've got a full (enough) packet, now pop it on our
-            // outbound packet queue
-            // TODO: this stuff could propagate the error back to the uploader
-            // properly
-            chunks.push(Atom::new(chunk));
-            upload.chunks_remaining -= 1;
+        let mut completed = TransferCompleted::default();
+        completed.id = id;
+        receiver
+            .handle_transfer_completed(Atom::new(completed))
+            .await?;
+        assert_eq!(
+            TransferEvent::Completed { id },
+            events.recv().await.expect("completed event")
+        );
+        assert!(matches!(resp_rx.try_recv(), Ok(Ok(()))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn heartbeat_interval() -> Result {
+        let (mut receiver, _cmd_tx, switcher) = fake_switcher_with_options(ConnectionOptions {
+            retransmit_interval: Duration::from_secs(3600),
+            heartbeat_interval: Duration::from_millis(20),
+            ..Default::default()
+        })
+        .await?;
+        receiver.initialisation_complete = true;
+        let main_loop = tokio::spawn(async move { receiver.main_loop().await });
+
+        // The switcher never acknowledges anything, so the only way to get
+        // more than one clock request is from the heartbeat.
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        for _ in 0..5 {
+            let l = tokio::time::timeout(Duration::from_secs(1), switcher.recv(&mut b))
+                .await
+                .expect("timeout waiting for clock request")?;
+            let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+            assert!(!pkt.flags.retransmission());
+            assert_eq!(Some(&vec![TIME_REQUEST_COMMAND.clone()]), pkt.atoms());
         }
 
-        let permit = upload.semaphore.clone().acquire_owned();
-        if upload.buffer.is_empty() {
-            // There's nothing more to upload, finish it.
-            let Some(upload) = self.uploads.remove(&params.id) else {
-                error!("upload ID {:#04x} disappeared?", params.id);
-                return Err(Error::UnexpectedState);
-            };
+        main_loop.abort();
+        Ok(())
+    }
 
-            chunks.push(Atom::new(FinishFileDownload {
-                id: params.id,
-                name: upload.name,
-                description: upload.description,
-                md5: upload.md5,
-            }));
-            if self
-                .finished_uploads
-                .insert(params.id, (upload.responder, upload.storage_lock))
-                .is_some()
-            {
-                error!("upload {:#04x} already in the finished list?", params.id);
-                return Err(Error::UnexpectedState);
-            } else {
-                info!("last chunk for upload {:#04x} in queue", params.id);
-            };
-        }
+    #[test]
+    fn round_trip_time() {
+        let mut rtt = RoundTripTime::default();
+        rtt.record(Duration::from_millis(16));
+        assert_eq!(Some(Duration::from_millis(16)), rtt.last);
+        assert_eq!(Some(Duration::from_millis(16)), rtt.average);
 
-        // Make a worker which will do the actual sending
-        // TODO: should signal errors back...
-        tokio::task::spawn(async move {
-            let Ok(permit) = permit.await else {
-                error!("permit error?");
-                return;
-            };
-            let mut burst = Vec::with_capacity(Self::UPLOAD_BURST_SIZE);
-            for chunk in chunks {
-                let (responder, resp_rx) = oneshot::channel();
-                if cmd_tx
-                    .send(AsyncCommand::Commands {
-                        cmds: vec![chunk],
-                        responder: Some(responder),
-                    })
-                    .await
-                    .is_err()
-                {
-                    error!("failed to transmit chunk to worker");
-                    return;
-                }
+        rtt.record(Duration::from_millis(80));
+        assert_eq!(Some(Duration::from_millis(80)), rtt.last);
+        assert_eq!(Some(Duration::from_millis(24)), rtt.average);
+    }
 
-                burst.push(resp_rx);
-                if burst.len() >= Self::UPLOAD_BURST_SIZE {
-                    // We've hit the limit for the number of packets we can send
-                    // in a burst. Wait for the acks to come back before
-                    // continuing.
-                    if wait_for_acks(&mut burst).await.is_err() {
-                        break;
-                    };
+    #[tokio::test]
+    async fn clock_request_rtt() -> Result {
+        let (mut receiver, _cmd_tx, switcher) = fake_switcher_with_options(ConnectionOptions {
+            retransmit_interval: Duration::from_secs(3600),
+            heartbeat_interval: Duration::from_millis(20),
+            ..Default::default()
+        })
+        .await?;
+        receiver.session_id = 0x8001;
+        receiver.initialisation_complete = true;
+        let rtt = receiver.rtt.clone();
+        let main_loop = tokio::spawn(async move { receiver.main_loop().await });
+        assert_eq!(None, rtt.lock().expect("rtt lock poisoned").last);
+
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let (l, from) = tokio::time::timeout(Duration::from_secs(1), switcher.recv_from(&mut b))
+            .await
+            .expect("timeout waiting for clock request")?;
+        let SocketAddr::V4(from) = from else {
+            unreachable!();
+        };
+        let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        assert_eq!(Some(&vec![TIME_REQUEST_COMMAND.clone()]), pkt.atoms());
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let ack = AtemPacket::builder()
+            .flags(AtemPacketFlags::new().with_response(true))
+            .session(0x8001)
+            .acked_packet_id(pkt.sender_packet_id)
+            .build();
+        send_packet_to(&switcher, from, &ack).await?;
+
+        let last = tokio::time::timeout(Duration::from_secs(1), async {
+            loop {
+                if let Some(last) = rtt.lock().expect("rtt lock poisoned").last {
+                    return last;
                 }
+                tokio::time::sleep(Duration::from_millis(5)).await;
             }
-            // Wait for any outstanding acks.
-            let _ = wait_for_acks(&mut burst).await;
-            drop(permit);
-        });
+        })
+        .await
+        .expect("timeout waiting for round-trip time");
+        assert!(last >= Duration::from_millis(50), "{last:?}");
 
+        main_loop.abort();
         Ok(())
     }
 
-    async fn handle_lock_obtained(&mut self, cmd: Atom) -> Result<(), Error> {
-        let Payload::LockObtained(lkob) = cmd.payload else {
-            return Err(Error::Internal);
+    #[tokio::test]
+    async fn initialise_resets_rtt() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher_with_options(ConnectionOptions {
+            init_timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .await?;
+        receiver
+            .rtt
+            .lock()
+            .expect("rtt lock poisoned")
+            .record(Duration::from_millis(16));
+
+        // The switcher never responds, but the previous session's round-trip
+        // times are gone.
+        assert!(matches!(receiver.initialise().await, Err(Error::Timeout)));
+        let rtt = receiver.rtt.lock().expect("rtt lock poisoned");
+        assert_eq!(None, rtt.last);
+        assert_eq!(None, rtt.average);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn connection_lost_and_restored() -> Result {
+        let (mut receiver, _cmd_tx, switcher) = fake_switcher_with_options(ConnectionOptions {
+            reconnect: true,
+            retransmit_interval: Duration::from_secs(3600),
+            heartbeat_interval: Duration::from_millis(20),
+            ..Default::default()
+        })
+        .await?;
+        receiver.initialisation_complete = true;
+        receiver.initialised.store(true, Ordering::Relaxed);
+        receiver.session_id = 0x8001;
+        let initialised = receiver.initialised.clone();
+        let mut state_rx = receiver.state_tx.subscribe();
+        let run = tokio::spawn(receiver.run());
+
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let recv_timeout = Duration::from_secs(5);
+        let (_, from) = tokio::time::timeout(recv_timeout, switcher.recv_from(&mut b))
+            .await
+            .expect("timeout waiting for clock request")?;
+        let SocketAddr::V4(from) = from else {
+            unreachable!();
         };
 
-        let store_id = lkob.store_id;
-        if store_id >= 64 {
-            error!("unable to lock storage ID {store_id}, too large");
-            return Err(Error::ParameterOutOfRange);
-        }
+        // Switcher disconnects us
+        send_packet_to(&switcher, from, &disconnect_packet(0x8001)).await?;
+        let (_, updated) = tokio::time::timeout(recv_timeout, state_rx.recv())
+            .await
+            .expect("timeout waiting for connection lost")
+            .expect("state channel closed");
+        assert_eq!(StateUpdate::CONNECTION_LOST, updated);
+        assert!(!initialised.load(Ordering::Relaxed));
 
-        let Some(owned_storage_lock) = self.owned_storage_locks.get(&store_id) else {
-            error!("No matching storage lock requests for storage ID {store_id}");
-            return Err(Error::UnexpectedState);
-        };
+        // Discard anything sent before the disconnection
+        while switcher.try_recv(&mut b).is_ok() {}
 
-        let Some(storage_lock) = owned_storage_lock.upgrade() else {
-            // TODO: we should probably unlock things if we didn't already?
-            error!("unable to upgrade storage lock {store_id}, no notifier?");
-            return Err(Error::Internal);
+        // Accept the reconnection
+        let (l, from) = tokio::time::timeout(recv_timeout, switcher.recv_from(&mut b))
+            .await
+            .expect("timeout waiting for reconnection")?;
+        let SocketAddr::V4(from) = from else {
+            unreachable!();
         };
+        let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        assert_eq!(Some(&AtemControl::Connect), pkt.control());
+        let ack = AtemPacket::new_control(
+            AtemPacketFlags::new().with_control(true),
+            pkt.session_id,
+            0,
+            0,
+            0,
+            AtemControl::ConnectAck { session_id: 0x1234 },
+        );
+        send_packet_to(&switcher, from, &ack).await?;
+        tokio::time::timeout(recv_timeout, switcher.recv(&mut b))
+            .await
+            .expect("timeout waiting for state request")?;
+        assert!(state_rx.try_recv().is_err());
+
+        // Switcher finishes sending its initial state
+        let incm = AtemPacket::builder()
+            .session(0x9234)
+            .sender_packet_id(1)
+            .atoms(vec![Atom::new(InitialisationComplete {
+                unknown1: 0,
+                unknown2: 0,
+            })])
+            .build();
+        send_packet_to(&switcher, from, &incm).await?;
+
+        let (_, updated) = tokio::time::timeout(recv_timeout, state_rx.recv())
+            .await
+            .expect("timeout waiting for initialisation")
+            .expect("state channel closed");
+        assert!(updated.contains(StateUpdate::INITIALISATION_COMPLETE));
+        assert!(initialised.load(Ordering::Relaxed));
+        let (_, updated) = tokio::time::timeout(recv_timeout, state_rx.recv())
+            .await
+            .expect("timeout waiting for connection restored")
+            .expect("state channel closed");
+        assert_eq!(StateUpdate::CONNECTION_RESTORED, updated);
 
-        storage_lock.make_available();
+        run.abort();
         Ok(())
     }
 
-    async fn handle_lock_state_changed(&mut self, cmd: Atom) -> Result<(), Error> {
-        let Payload::MediaPoolLockStatus(lkst) = cmd.payload else {
-            return Err(Error::Internal);
+    #[tokio::test]
+    async fn replay_on_reconnect() -> Result {
+        let (mut receiver, cmd_tx, switcher) = fake_switcher_with_options(ConnectionOptions {
+            reconnect: true,
+            retransmit_interval: Duration::from_secs(3600),
+            heartbeat_interval: Duration::from_millis(20),
+            replay_on_reconnect: true,
+            ..Default::default()
+        })
+        .await?;
+        receiver.initialisation_complete = true;
+        receiver.session_id = 0x8001;
+        let run = tokio::spawn(receiver.run());
+
+        let cpgi = Atom::new(SetProgramInput {
+            me: 0,
+            video_source: VideoSource::Input2,
+        });
+        let (responder, mut resp_rx) = oneshot::channel();
+        cmd_tx
+            .send(AsyncCommand::Commands {
+                cmds: vec![cpgi.clone()],
+                responder: Some(responder),
+            })
+            .await
+            .expect("command channel closed");
+
+        // Switcher receives the command, but never acknowledges it.
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let recv_timeout = Duration::from_secs(5);
+        let from = loop {
+            let (l, from) = tokio::time::timeout(recv_timeout, switcher.recv_from(&mut b))
+                .await
+                .expect("timeout waiting for command")?;
+            let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+            if pkt.atoms().is_some_and(|atoms| atoms.contains(&cpgi)) {
+                break from;
+            }
+        };
+        let SocketAddr::V4(from) = from else {
+            unreachable!();
         };
 
-        if lkst.locked {
-            self.storage_locks.insert(lkst.store_id);
-        } else {
-            self.storage_locks.remove(&lkst.store_id);
-            if let Some(storage_lock) = self
-                .owned_storage_locks
-                .remove(&lkst.store_id)
-                .and_then(|l| l.upgrade())
-            {
-                storage_lock.make_unavailable();
+        // Switcher disconnects us
+        send_packet_to(&switcher, from, &disconnect_packet(0x8001)).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        while switcher.try_recv(&mut b).is_ok() {}
+        assert!(
+            resp_rx.try_recv().is_err(),
+            "command should still be pending"
+        );
+
+        // Accept the reconnection
+        let (l, from) = tokio::time::timeout(recv_timeout, switcher.recv_from(&mut b))
+            .await
+            .expect("timeout waiting for reconnection")?;
+        let SocketAddr::V4(from) = from else {
+            unreachable!();
+        };
+        let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        assert_eq!(Some(&AtemControl::Connect), pkt.control());
+        let ack = AtemPacket::builder()
+            .flags(AtemPacketFlags::new())
+            .session(pkt.session_id)
+            .control(AtemControl::ConnectAck { session_id: 0x1234 })
+            .build();
+        send_packet_to(&switcher, from, &ack).await?;
+        tokio::time::timeout(recv_timeout, switcher.recv(&mut b))
+            .await
+            .expect("timeout waiting for state request")?;
+
+        // Switcher finishes sending its initial state, and then the command
+        // should be sent again.
+        let incm = AtemPacket::builder()
+            .session(0x9234)
+            .sender_packet_id(1)
+            .atoms(vec![Atom::new(InitialisationComplete {
+                unknown1: 0,
+                unknown2: 0,
+            })])
+            .build();
+        send_packet_to(&switcher, from, &incm).await?;
+        let replayed = loop {
+            let l = tokio::time::timeout(recv_timeout, switcher.recv(&mut b))
+                .await
+                .expect("timeout waiting for replayed command")?;
+            let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+            if pkt.atoms().is_some_and(|atoms| atoms.contains(&cpgi)) {
+                break pkt;
             }
-        }
+        };
+        assert_eq!(0x9234, replayed.session_id);
+        assert_eq!(Some(&vec![cpgi]), replayed.atoms());
+
+        // Acknowledging the replay completes the original command.
+        let ack = AtemPacket::builder()
+            .flags(AtemPacketFlags::new().with_response(true))
+            .session(0x9234)
+            .acked_packet_id(replayed.sender_packet_id)
+            .build();
+        send_packet_to(&switcher, from, &ack).await?;
+        tokio::time::timeout(recv_timeout, resp_rx)
+            .await
+            .expect("timeout waiting for response")
+            .expect("responder dropped")?;
 
+        run.abort();
         Ok(())
     }
-}
 
-/// Wait for acks to a collection of sent packets, draining the `Vec`.
-///
-/// On success, returns `Ok(())`, and `burst` will be empty.
-///
-/// On failure, returns the first [Error]. `burst` will still contain subsequent
-/// `oneshot::Receiver`s after the [Error].
-async fn wait_for_acks(burst: &mut Vec<oneshot::Receiver<Result<(), Error>>>) -> Result<(), Error> {
-    for recv_rx in burst.drain(..) {
-        match recv_rx.await.map_err(|_| Error::ChannelUnavailable) {
-            Ok(Err(e)) | Err(e) => {
-                error!("responder returned error: {e:?}");
-                return Err(e);
+    #[tokio::test]
+    async fn observed_atoms() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher_with_options(ConnectionOptions {
+            record_observed_atoms: true,
+            ..Default::default()
+        })
+        .await?;
+        let (pkt_tx, pkt_rx) = mpsc::channel(1);
+        let _initialised_rx = receiver.spawn_state_task(pkt_rx).await?;
+
+        let (state, _state_tx, state_rx) = state_channel();
+        let (mut controller, _cmd_rx) = mock_controller(Arc::new(state), state_rx);
+        assert!(controller.observed_atoms().is_empty());
+        controller.observed_atoms = receiver.observed_atoms.clone();
+
+        // Mix of implemented and unimplemented atoms
+        let pkt = AtemPacket::new_atoms(
+            AtemPacketFlags::new().with_ack(true),
+            0x8001,
+            0,
+            0,
+            1,
+            vec![
+                Atom::new(ProgramInput {
+                    me: 0,
+                    video_source: VideoSource::Input1,
+                }),
+                Atom::new(Payload::Unknown(*b"KeOn", vec![0, 0, 0, 0])),
+                Atom::new(ProgramInput {
+                    me: 0,
+                    video_source: VideoSource::Input2,
+                }),
+                Atom::new(Payload::Unknown(*b"KeOn", vec![0, 1, 0, 0])),
+                Atom::new(Payload::Unknown(*b"KeOn", vec![0, 2, 0, 0])),
+                Atom::new(Cut { me: 0 }),
+            ],
+        );
+        pkt_tx.send(pkt).await.expect("state task stopped");
+        drop(pkt_tx);
+        receiver
+            .state_task
+            .take()
+            .expect("state task not running")
+            .await
+            .expect("state task failed")?;
+
+        assert_eq!(
+            BTreeMap::from([(*b"DCut", 1), (*b"KeOn", 3), (*b"PrgI", 2)]),
+            controller.observed_atoms()
+        );
+        Ok(())
+    }
+
+    /// Fake switcher which accepts a connection request.
+    ///
+    /// Returns the connection request, and the client's request for the
+    /// initial state.
+    async fn accept_handshake(switcher: UdpSocket) -> Result<(AtemPacket, AtemPacket)> {
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let (l, from) = switcher.recv_from(&mut b).await?;
+        let connect = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        assert_eq!(Some(&AtemControl::Connect), connect.control());
+
+        let ack = AtemPacket::new_control(
+            AtemPacketFlags::new().with_control(true),
+            connect.session_id,
+            0,
+            0,
+            0,
+            AtemControl::ConnectAck { session_id: 0x1234 },
+        );
+        let mut out = Cursor::new(Vec::new());
+        ack.write(&mut out)?;
+        switcher.send_to(&out.into_inner(), from).await?;
+
+        let l = switcher.recv(&mut b).await?;
+        let request_state = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        Ok((connect, request_state))
+    }
+
+    #[tokio::test]
+    async fn discover() -> Result {
+        for report_name in [true, false] {
+            let switcher = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+            let SocketAddr::V4(switcher_addr) = switcher.local_addr()? else {
+                unreachable!();
+            };
+            let product_name = ProductName::new("ATEM Mini".to_string(), 0xd)?;
+
+            let expected_name = product_name.clone();
+            let switcher_task = tokio::spawn(async move {
+                let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+                let (l, from) = switcher.recv_from(&mut b).await?;
+                let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+                assert_eq!(Some(&AtemControl::Connect), pkt.control());
+                let initial_session_id = pkt.session_id;
+
+                // Respond twice, as if the broadcast arrived on two interfaces
+                let ack = AtemPacket::new_control(
+                    AtemPacketFlags::new().with_control(true),
+                    initial_session_id,
+                    0,
+                    0,
+                    0,
+                    AtemControl::ConnectAck { session_id: 0x1234 },
+                );
+                let mut out = Cursor::new(Vec::new());
+                ack.write(&mut out)?;
+                let out = out.into_inner();
+                switcher.send_to(&out, from).await?;
+                switcher.send_to(&out, from).await?;
+
+                // State request
+                let l = switcher.recv(&mut b).await?;
+                let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+                assert!(pkt.flags.response());
+                assert_eq!(initial_session_id, pkt.session_id);
+
+                if report_name {
+                    let pkt = AtemPacket::new_atoms(
+                        AtemPacketFlags::new().with_ack(true),
+                        0x9234,
+                        0,
+                        0,
+                        1,
+                        vec![Atom::new(expected_name)],
+                    );
+                    let mut out = Cursor::new(Vec::new());
+                    pkt.write(&mut out)?;
+                    switcher.send_to(&out.into_inner(), from).await?;
+                }
+
+                // Wait for the session to be closed
+                let mut requests = 1;
+                loop {
+                    let l = switcher.recv(&mut b).await?;
+                    let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+                    if let Some(control) = pkt.control() {
+                        assert_eq!(&AtemControl::Disconnect, control);
+                        assert_eq!(0x9234, pkt.session_id);
+                        break;
+                    }
+                    if pkt.session_id == initial_session_id {
+                        requests += 1;
+                    }
+                }
+                Ok::<_, crate::Error>(requests)
+            });
+
+            let found =
+                AtemController::discover_at(switcher_addr, Duration::from_millis(200)).await?;
+            let requests = switcher_task.await.expect("switcher task failed")?;
+            // The duplicate ConnectAck was ignored
+            assert_eq!(1, requests);
+
+            if report_name {
+                assert_eq!(vec![(switcher_addr, product_name)], found);
+            } else {
+                assert!(found.is_empty());
             }
-            Ok(Ok(())) => {}
         }
+        Ok(())
     }
 
-    Ok(())
-}
+    #[tokio::test]
+    async fn connection_options() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher_with_options(ConnectionOptions {
+            retransmit_limit: 7,
+            init_timeout: Duration::from_millis(50),
+            ..Default::default()
+        })
+        .await?;
 
-impl Drop for AtemReceiver {
-    fn drop(&mut self) {
-        if let Err(e) = self.disconnect() {
-            error!("disconnection error: {e:?}");
-        }
+        receiver
+            .handle_queued_command(AsyncCommand::Commands {
+                cmds: vec![Atom::new(Cut { me: 0 })],
+                responder: None,
+            })
+            .await?;
+        assert_eq!(7, receiver.ack_queue[0].retry_limit);
+
+        // The switcher never responds, so this should give up well before the
+        // default timeout.
+        let start = Instant::now();
+        assert!(matches!(receiver.initialise().await, Err(Error::Timeout)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        Ok(())
     }
-}
 
-/// A storage lock.
-///
-/// This keeps a storage lock alive using [Drop] semantics.
-struct StorageLock {
-    store_id: u16,
-    waiters: Semaphore,
-    cmd_tx: Sender<AsyncCommand>,
-}
+    #[tokio::test]
+    async fn handshake_client_packet_ids() -> Result {
+        let (mut receiver, _cmd_tx, switcher) = fake_switcher().await?;
+        let switcher = tokio::spawn(accept_handshake(switcher));
+        let _initialised_rx = receiver.initialise().await?;
+        let (connect, request_state) = switcher.await.expect("switcher task failed")?;
+
+        assert_eq!(0xb1, connect.client_packet_id);
+        assert!(request_state.flags.response());
+        assert_eq!(0xd4, request_state.client_packet_id);
+        assert_eq!(0x9234, receiver.session_id);
+        Ok(())
+    }
 
-impl StorageLock {
-    fn new(store_id: u16, cmd_tx: Sender<AsyncCommand>) -> Self {
-        Self {
-            store_id,
-            waiters: Semaphore::new(0),
-            cmd_tx,
+    #[tokio::test]
+    async fn ack_client_packet_id() -> Result {
+        let (mut receiver, _cmd_tx, switcher) = fake_switcher().await?;
+        let (tx, mut rx) = mpsc::channel(16);
+        receiver.tx = Some(tx);
+        receiver.session_id = 0x8001;
+
+        receiver
+            .handle_incoming_packet(
+                AtemPacket::builder()
+                    .flags(AtemPacketFlags::new().with_ack(true))
+                    .session(0x8001)
+                    .sender_packet_id(1)
+                    .atoms(vec![Atom::new(Cut { me: 0 })])
+                    .build(),
+            )
+            .await?;
+        assert_eq!(
+            1,
+            rx.try_recv()
+                .expect("expected forwarded packet")
+                .sender_packet_id
+        );
+
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let l = tokio::time::timeout(Duration::from_secs(1), switcher.recv(&mut b))
+            .await
+            .expect("timeout waiting for ack")?;
+        let ack = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+        assert!(ack.flags.response());
+        assert_eq!(1, ack.acked_packet_id);
+        assert_eq!(0xd2, ack.client_packet_id);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn command_client_packet_id() -> Result {
+        let (mut receiver, _cmd_tx, switcher) = fake_switcher().await?;
+
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        for me in 0..4 {
+            receiver
+                .handle_queued_command(AsyncCommand::Commands {
+                    cmds: vec![Atom::new(Cut { me })],
+                    responder: None,
+                })
+                .await?;
+
+            let l = tokio::time::timeout(Duration::from_secs(1), switcher.recv(&mut b))
+                .await
+                .expect("timeout waiting for command")?;
+            let pkt = AtemPacket::read(&mut Cursor::new(&b[..l]))?;
+            assert_eq!(Some(&vec![Atom::new(Cut { me })]), pkt.atoms());
+            assert_eq!(u16::from(me) + 1, pkt.sender_packet_id);
+            assert_eq!(0, pkt.client_packet_id);
         }
+        Ok(())
     }
 
-    fn make_available(&self) {
-        self.waiters.add_permits(Semaphore::MAX_PERMITS);
+    #[tokio::test]
+    async fn rx_queue_resync() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher_with_options(ConnectionOptions {
+            rx_queue_policy: RxQueuePolicy::FlushAndContinue,
+            ..Default::default()
+        })
+        .await?;
+        let (tx, mut rx) = mpsc::channel(16);
+        receiver.tx = Some(tx);
+        receiver.session_id = 0x8001;
+
+        let pkt = |sender_packet_id| {
+            AtemPacket::builder()
+                .session(0x8001)
+                .sender_packet_id(sender_packet_id)
+                .atoms(vec![Atom::new(Cut { me: 0 })])
+                .build()
+        };
+
+        // Packets 1 - 4 were lost, so the rest should be queued.
+        for id in 5..=7 {
+            receiver.handle_incoming_packet(pkt(id)).await?;
+        }
+        assert_eq!(3, receiver.rx_queue.len());
+        assert!(rx.try_recv().is_err());
+
+        // Still waiting for the missing packets.
+        receiver.limit_rx_queue().await?;
+        assert_eq!(3, receiver.rx_queue.len());
+
+        // Switcher never retransmitted the missing packets.
+        receiver.last_rx_time -= receiver.options.max_rx_gap_time;
+        receiver.limit_rx_queue().await?;
+        assert!(receiver.rx_queue.is_empty());
+        assert_eq!(8, receiver.next_pkt_forward);
+
+        // Everything after the gap should be forwarded immediately.
+        receiver.handle_incoming_packet(pkt(8)).await?;
+        assert!(receiver.rx_queue.is_empty());
+
+        for id in 5..=8 {
+            let p = rx.try_recv().expect("expected forwarded packet");
+            assert_eq!(id, p.sender_packet_id);
+        }
+        assert!(rx.try_recv().is_err());
+
+        // Nothing from the switcher at all.
+        receiver.last_rx_time -= receiver.options.max_rx_queue_time;
+        assert!(matches!(
+            receiver.limit_rx_queue().await,
+            Err(Error::Timeout)
+        ));
+        Ok(())
     }
 
-    fn make_unavailable(&self) {
-        self.waiters.close();
+    #[tokio::test]
+    async fn rx_queue_policy() -> Result {
+        let pkt = |sender_packet_id| {
+            AtemPacket::builder()
+                .session(0x8001)
+                .sender_packet_id(sender_packet_id)
+                .atoms(vec![Atom::new(Cut { me: 0 })])
+                .build()
+        };
+
+        for policy in [RxQueuePolicy::Disconnect, RxQueuePolicy::FlushAndContinue] {
+            let (mut receiver, _cmd_tx, _switcher) =
+                fake_switcher_with_options(ConnectionOptions {
+                    rx_queue_policy: policy,
+                    ..Default::default()
+                })
+                .await?;
+            let (tx, mut rx) = mpsc::channel(16);
+            receiver.tx = Some(tx);
+            receiver.session_id = 0x8001;
+
+            // Packets 1 - 2 were lost, and never retransmitted.
+            for id in 3..=5 {
+                receiver.handle_incoming_packet(pkt(id)).await?;
+            }
+            receiver.last_rx_time -= receiver.options.max_rx_gap_time;
+            let r = receiver.limit_rx_queue().await;
+
+            match policy {
+                RxQueuePolicy::Disconnect => {
+                    assert!(matches!(r, Err(Error::Timeout)));
+                    assert_eq!(3, receiver.rx_queue.len());
+                    assert_eq!(1, receiver.next_pkt_forward);
+                    assert!(rx.try_recv().is_err());
+                }
+
+                RxQueuePolicy::FlushAndContinue => {
+                    r?;
+                    assert!(receiver.rx_queue.is_empty());
+                    assert_eq!(6, receiver.next_pkt_forward);
+
+                    // The session continues after the flush.
+                    receiver.handle_incoming_packet(pkt(6)).await?;
+                    for id in 3..=6 {
+                        let p = rx.try_recv().expect("expected forwarded packet");
+                        assert_eq!(id, p.sender_packet_id);
+                    }
+                    assert!(rx.try_recv().is_err());
+                }
+            }
+        }
+        Ok(())
     }
 
-    async fn await_availability(&self) -> Result<(), Error> {
-        // We don't actually care about the permit itself, we would just drop it
-        // immediately and that's OK.
-        match self.waiters.acquire().await {
-            Ok(_) => Ok(()),
-            Err(_) => Err(Error::ChannelUnavailable),
+    #[tokio::test]
+    async fn rx_queue_options() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher_with_options(ConnectionOptions {
+            max_rx_queue_length: 128,
+            max_rx_queue_time: Duration::from_secs(10),
+            max_rx_gap_time: Duration::from_secs(5),
+            ..Default::default()
+        })
+        .await?;
+        let (tx, mut rx) = mpsc::channel(256);
+        receiver.tx = Some(tx);
+        receiver.session_id = 0x8001;
+
+        // Packet 1 was lost; more packets are queued than the default limit.
+        for id in 2..=100 {
+            receiver
+                .handle_incoming_packet(
+                    AtemPacket::builder()
+                        .session(0x8001)
+                        .sender_packet_id(id)
+                        .atoms(vec![Atom::new(Cut { me: 0 })])
+                        .build(),
+                )
+                .await?;
         }
+        receiver.limit_rx_queue().await?;
+        assert_eq!(99, receiver.rx_queue.len());
+        assert!(rx.try_recv().is_err());
+
+        // Waiting for packet 1 for longer than the default gap time.
+        receiver.last_rx_time -= Duration::from_secs(2);
+        receiver.limit_rx_queue().await?;
+        assert_eq!(99, receiver.rx_queue.len());
+
+        // Nothing from the switcher for longer than the default time.
+        receiver.rx_queue.clear();
+        receiver.last_rx_time -= Duration::from_secs(1);
+        receiver.limit_rx_queue().await?;
+
+        receiver.last_rx_time -= Duration::from_secs(7);
+        assert!(matches!(
+            receiver.limit_rx_queue().await,
+            Err(Error::Timeout)
+        ));
+        Ok(())
     }
-}
 
-impl Drop for StorageLock {
-    fn drop(&mut self) {
-        self.make_unavailable();
-        let cmd = Atom::new(MediaPoolLock::unlock(self.store_id));
+    #[tokio::test]
+    async fn unacknowledged_commands() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher().await?;
+
+        // Command which is never acknowledged, and runs out of retries.
+        let (responder, mut timeout_rx) = oneshot::channel();
+        receiver
+            .handle_queued_command(AsyncCommand::Commands {
+                cmds: vec![Atom::new(Cut { me: 0 })],
+                responder: Some(responder),
+            })
+            .await?;
+        receiver.ack_queue[0].retry_limit = 0;
+        receiver.ack_queue[0].last_attempt -= receiver.options.retransmit_interval;
+        receiver.do_retransmits().await?;
+        assert!(matches!(timeout_rx.try_recv(), Ok(Err(Error::Timeout))));
+        assert!(receiver.ack_queue.is_empty());
+
+        // Command which is abandoned before it was acknowledged.
+        let (responder, mut abandoned_rx) = oneshot::channel();
+        receiver
+            .handle_queued_command(AsyncCommand::Commands {
+                cmds: vec![Atom::new(Cut { me: 0 })],
+                responder: Some(responder),
+            })
+            .await?;
+        assert!(abandoned_rx.try_recv().is_err());
+        receiver.abandon_ack_queue();
+        assert!(matches!(
+            abandoned_rx.try_recv(),
+            Ok(Err(Error::NotAcknowledged))
+        ));
+
+        // Command which was acknowledged.
+        let (responder, mut acked_rx) = oneshot::channel();
+        receiver
+            .handle_queued_command(AsyncCommand::Commands {
+                cmds: vec![Atom::new(Cut { me: 0 })],
+                responder: Some(responder),
+            })
+            .await?;
+        let sender_packet_id = receiver.ack_queue[0].pkt.sender_packet_id;
+        receiver.handle_ack(sender_packet_id);
+        assert!(matches!(acked_rx.try_recv(), Ok(Ok(()))));
+        Ok(())
+    }
 
-        let cmd_tx = self.cmd_tx.clone();
-        tokio::task::spawn(async move {
-            cmd_tx
-                .send(AsyncCommand::Commands {
-                    cmds: vec![cmd],
+    #[tokio::test]
+    async fn outstanding_commands() -> Result {
+        let (mut receiver, _cmd_tx, _switcher) = fake_switcher().await?;
+        let outstanding = receiver.outstanding_commands.clone();
+        assert_eq!(0, outstanding.load(Ordering::Relaxed));
+
+        let (responder, mut stalled_rx) = oneshot::channel();
+        receiver
+            .handle_queued_command(AsyncCommand::Commands {
+                cmds: vec![Atom::new(Cut { me: 0 })],
+                responder: Some(responder),
+            })
+            .await?;
+        assert_eq!(1, outstanding.load(Ordering::Relaxed));
+
+        // Fill up the ack_queue, as if the switcher had stalled.
+        for _ in 1..AtemReceiver::MAX_ACK_QUEUE_LENGTH {
+            receiver
+                .handle_queued_command(AsyncCommand::Commands {
+                    cmds: vec![Atom::new(Cut { me: 0 })],
                     responder: None,
                 })
-                .await
-                .unwrap();
-        });
+                .await?;
+        }
+        assert_eq!(
+            AtemReceiver::MAX_ACK_QUEUE_LENGTH,
+            outstanding.load(Ordering::Relaxed)
+        );
+        assert!(stalled_rx.try_recv().is_err());
+
+        // The next command clears the queue, and fails the earlier commands.
+        receiver
+            .handle_queued_command(AsyncCommand::Commands {
+                cmds: vec![Atom::new(Cut { me: 0 })],
+                responder: None,
+            })
+            .await?;
+        assert_eq!(1, outstanding.load(Ordering::Relaxed));
+        assert!(matches!(
+            stalled_rx.try_recv(),
+            Ok(Err(Error::ChannelUnavailable))
+        ));
+
+        let sender_packet_id = receiver.ack_queue[0].pkt.sender_packet_id;
+        receiver.handle_ack(sender_packet_id);
+        assert_eq!(0, outstanding.load(Ordering::Relaxed));
+        Ok(())
     }
 }
-
-lazy_static! {
-    static ref TIME_REQUEST_COMMAND: Atom = Atom::new(Payload::TimecodeRequest(TimecodeRequest {}));
-}
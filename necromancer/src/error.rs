@@ -26,6 +26,15 @@ pub enum Error {
     #[error("timeout waiting for response")]
     Timeout,
 
+    #[error("command was sent, but abandoned before the switcher acknowledged it")]
+    NotAcknowledged,
+
+    #[error("command was acknowledged, but the switcher did not apply it")]
+    CommandIgnored,
+
+    #[error("missing or invalid confirmation for a destructive operation")]
+    ConfirmationRequired,
+
     #[error("unknown parameter")]
     UnknownParameter,
 
@@ -52,4 +61,7 @@ pub enum Error {
 
     #[error("switcher reported transfer error: {0}")]
     SwitcherTransferError(u8),
+
+    #[error("downloaded data does not match the expected checksum")]
+    ChecksumMismatch,
 }
@@ -10,14 +10,19 @@ extern crate tracing;
 mod controller;
 mod error;
 mod rle;
+#[cfg(any(test, feature = "sim"))]
+pub mod sim;
 mod state;
 mod udp;
 
 pub use {
     crate::{
-        controller::AtemController,
+        controller::{
+            AtemController, ConnectionOptions, FactoryResetConfirm, FileDownload, RxQueuePolicy,
+            TransferDirection, TransferEvent,
+        },
         error::Error,
-        state::{AtemState, StateUpdate},
+        state::{AtemState, Capability, MultiViewOverlays, SourceBus, StateUpdate},
         udp::AtemUdpChannel,
     },
     necromancer_protocol as protocol,
@@ -1,4 +1,4 @@
-use crate::protocol::rle::RleDecompressor;
+use crate::protocol::rle::{RleDecompressor, MAX_REPEATS, RLE_MARKER};
 
 /// Computes the MD5 of the uncompressed frame, and its size (in bytes).
 ///
@@ -16,6 +16,95 @@ pub fn rle_md5_size(i: impl Iterator<Item = u64>) -> ([u8; 16], u64) {
     (md5.finalize().into(), s)
 }
 
+/// Computes the MD5 of the uncompressed frame, and its size (in bytes), like
+/// [rle_md5_size], but from chunks of the compressed frame as they arrive.
+///
+/// Chunks may be split at any byte: incomplete words and RLE sequences are
+/// carried over to the next chunk.
+#[derive(Clone, Default)]
+pub struct RleMd5 {
+    md5: md5::Context,
+    size: u64,
+    /// Bytes of an incomplete word at the end of the last chunk.
+    partial: Vec<u8>,
+    /// Progress through an incomplete RLE sequence.
+    sequence: RleSequence,
+}
+
+/// Progress through an RLE sequence: [RLE_MARKER], count, then the repeated
+/// word.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+enum RleSequence {
+    /// Not in an RLE sequence.
+    #[default]
+    None,
+    /// Read [RLE_MARKER], expecting a count.
+    Marker,
+    /// Read [RLE_MARKER] and a count, expecting the word to repeat.
+    Count(u64),
+    /// Read an invalid count; ignore the rest of the file.
+    Invalid,
+}
+
+impl RleMd5 {
+    pub fn consume(&mut self, mut data: &[u8]) {
+        if !self.partial.is_empty() {
+            let l = (8 - self.partial.len()).min(data.len());
+            self.partial.extend_from_slice(&data[..l]);
+            data = &data[l..];
+            if self.partial.len() < 8 {
+                return;
+            }
+
+            let w = u64::from_be_bytes(self.partial[..].try_into().expect("8 byte word"));
+            self.partial.clear();
+            self.consume_word(w);
+        }
+
+        let mut words = data.chunks_exact(8);
+        for w in &mut words {
+            self.consume_word(u64::from_be_bytes(w.try_into().expect("8 byte chunk")));
+        }
+        self.partial.extend_from_slice(words.remainder());
+    }
+
+    fn consume_word(&mut self, w: u64) {
+        self.sequence = match self.sequence {
+            RleSequence::None if w == RLE_MARKER => RleSequence::Marker,
+            RleSequence::None => {
+                self.md5.consume(w.to_be_bytes());
+                self.size += 8;
+                RleSequence::None
+            }
+            RleSequence::Marker if w > MAX_REPEATS => {
+                error!(
+                    "RLE repeat {w:#08x} > {MAX_REPEATS:#08x}, aborting - likely data corruption!"
+                );
+                RleSequence::Invalid
+            }
+            RleSequence::Marker => RleSequence::Count(w),
+            RleSequence::Count(c) => {
+                let b = w.to_be_bytes();
+                for _ in 0..c {
+                    self.md5.consume(b);
+                }
+                self.size += c * 8;
+                RleSequence::None
+            }
+            RleSequence::Invalid => RleSequence::Invalid,
+        };
+    }
+
+    pub fn finalize(self) -> ([u8; 16], u64) {
+        if !self.partial.is_empty()
+            || !matches!(self.sequence, RleSequence::None | RleSequence::Invalid)
+        {
+            warn!("ignoring incomplete RLE data at end of file");
+        }
+        (self.md5.finalize().into(), self.size)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -59,4 +148,33 @@ mod test {
         );
         Ok(())
     }
+
+    #[test]
+    fn rle_md5_chunks() -> Result {
+        // A literal word, an RLE sequence, then another literal word
+        let b = hex::decode(concat!(
+            "3ac800403ac80040",
+            "fefefefefefefefe0000000000000003",
+            "3ac668f93acefcf9",
+            "3ac800403ac80040",
+        ))?;
+        let expected = rle_md5_size_from_bytes(&b);
+        assert_eq!(5 * 8, expected.1);
+
+        // Split at every possible point, including within words
+        for i in 0..=b.len() {
+            let mut md5 = RleMd5::default();
+            md5.consume(&b[..i]);
+            md5.consume(&b[i..]);
+            assert_eq!(expected, md5.finalize(), "split at {i}");
+        }
+
+        // One byte at a time
+        let mut md5 = RleMd5::default();
+        for c in b.chunks(1) {
+            md5.consume(c);
+        }
+        assert_eq!(expected, md5.finalize());
+        Ok(())
+    }
 }
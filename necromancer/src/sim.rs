@@ -0,0 +1,383 @@
+//! In-process simulated switcher, for testing without hardware.
+//!
+//! [SimulatedSwitcher] listens on a UDP socket on `localhost`, and speaks
+//! just enough of the protocol for an [AtemController][crate::AtemController]
+//! to connect to it:
+//!
+//! * it accepts the handshake, and sends an initial state based on a
+//!   [Topology], with [input properties][InputProperties] for
+//!   [VideoSource::Black] and [`SimulatedSwitcher::INPUTS`] external inputs,
+//!   which are available on every mix effect block
+//! * it acknowledges every command
+//! * it handles [SetProgramInput], [SetPreviewInput] and [Cut], and reports
+//!   the new program and preview sources and [tally][TalliedSources] state
+//!
+//! Every other command is acknowledged, but ignored.
+//!
+//! ```rust,no_run
+//! use necromancer::{protocol::atom::Topology, sim::SimulatedSwitcher, AtemController};
+//!
+//! # async fn example() -> necromancer::Result {
+//! let mut topology = Topology::default();
+//! topology.mes = 1;
+//! let sim = SimulatedSwitcher::new(topology).await?;
+//! let controller = AtemController::connect_udp(sim.addr(), false).await?;
+//! controller.cut(0).await?;
+//! # Ok(())
+//! # }
+//! ```
+use crate::{
+    protocol::{
+        atom::{
+            Atom, InitialisationComplete, InputProperties, Payload, PreviewInput, ProgramInput,
+            TalliedSources, Topology,
+        },
+        structs::{ExternalPortType, PortType, SourceAvailability, TallyFlags, VideoSource},
+        AtemControl, AtemPacket, AtemPacketFlags,
+    },
+    Result,
+};
+use binrw::BinWrite;
+use std::{
+    io::Cursor,
+    net::{Ipv4Addr, SocketAddr},
+};
+use tokio::{net::UdpSocket, task::JoinHandle};
+
+/// A simulated switcher, which runs until it is dropped.
+///
+/// See the [module documentation][self] for what is simulated.
+pub struct SimulatedSwitcher {
+    addr: SocketAddr,
+    task: JoinHandle<Result>,
+}
+
+impl SimulatedSwitcher {
+    /// Number of external inputs on the simulated switcher.
+    pub const INPUTS: u8 = 4;
+
+    /// Starts a simulated switcher with a given [Topology], listening on a
+    /// random port on `localhost`.
+    ///
+    /// Every mix effect block starts with [VideoSource::Input1] on program,
+    /// and [VideoSource::Input2] on preview.
+    pub async fn new(topology: Topology) -> Result<Self> {
+        let sock = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = sock.local_addr()?;
+        let mes = usize::from(topology.mes);
+        let sim = Simulation {
+            sock,
+            topology,
+            peer: None,
+            sender_packet_id: 0,
+            program: vec![VideoSource::Input1; mes],
+            preview: vec![VideoSource::Input2; mes],
+            tallied: vec![],
+        };
+        let task = tokio::task::spawn(sim.run());
+        Ok(Self { addr, task })
+    }
+
+    /// The address which the simulated switcher is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for SimulatedSwitcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// State of a [SimulatedSwitcher], owned by its task.
+struct Simulation {
+    sock: UdpSocket,
+    topology: Topology,
+    /// Address of the connected client.
+    peer: Option<SocketAddr>,
+    /// `sender_packet_id` of the last packet sent to the client.
+    sender_packet_id: u16,
+    program: Vec<VideoSource>,
+    preview: Vec<VideoSource>,
+    /// Every source which has been on program or preview, so that it can be
+    /// cleared in later [TalliedSources] snapshots.
+    tallied: Vec<VideoSource>,
+}
+
+impl Simulation {
+    /// Session ID given to clients in [`AtemControl::ConnectAck`].
+    const SESSION_ID: u16 = 0x1234;
+
+    async fn run(mut self) -> Result {
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        loop {
+            let (l, from) = self.sock.recv_from(&mut b).await?;
+            let pkt = match AtemPacket::read_lenient(&mut Cursor::new(&b[..l]), false) {
+                Ok(pkt) => pkt,
+                Err(e) => {
+                    warn!("simulator dropping invalid packet: {e:?}");
+                    continue;
+                }
+            };
+            self.handle_packet(pkt, from).await?;
+        }
+    }
+
+    async fn handle_packet(&mut self, pkt: AtemPacket, from: SocketAddr) -> Result {
+        if let Some(control) = pkt.control() {
+            match control {
+                AtemControl::Connect => {
+                    debug!("simulator accepting connection from {from}");
+                    self.peer = Some(from);
+                    self.sender_packet_id = 0;
+                    let ack = AtemPacket::builder()
+                        .flags(AtemPacketFlags::new())
+                        .session(pkt.session_id)
+                        .control(AtemControl::ConnectAck {
+                            session_id: Self::SESSION_ID,
+                        })
+                        .build();
+                    self.send(&ack).await?;
+                }
+
+                AtemControl::Disconnect => {
+                    debug!("simulator disconnecting {from}");
+                    let ack = AtemPacket::builder()
+                        .flags(AtemPacketFlags::new())
+                        .session(pkt.session_id)
+                        .control(AtemControl::DisconnectAck)
+                        .build();
+                    self.send(&ack).await?;
+                    self.peer = None;
+                }
+
+                _ => (),
+            }
+            return Ok(());
+        }
+
+        if self.peer != Some(from) {
+            // Not connected
+            return Ok(());
+        }
+
+        if pkt.flags.response() && pkt.session_id & 0x8000 == 0 {
+            // Acknowledgement of the ConnectAck, requesting the initial state
+            let mut atoms = vec![Atom::new(self.topology.clone())];
+            atoms.extend(self.input_properties());
+            atoms.extend(self.sources());
+            atoms.push(Atom::new(InitialisationComplete {
+                unknown1: 0,
+                unknown2: 0,
+            }));
+            return self.send_atoms(atoms).await;
+        }
+
+        if let Some(ack) = pkt.make_ack() {
+            self.send(&ack).await?;
+        }
+
+        let mut changed = false;
+        for atom in pkt.atoms().into_iter().flatten() {
+            changed |= self.handle_atom(&atom.payload);
+        }
+        if changed {
+            let atoms = self.sources();
+            self.send_atoms(atoms).await?;
+        }
+        Ok(())
+    }
+
+    /// Applies a command to the simulated state, returning `true` if the
+    /// program or preview sources changed.
+    fn handle_atom(&mut self, payload: &Payload) -> bool {
+        match payload {
+            Payload::SetProgramInput(cpgi) => {
+                let Some(program) = self.program.get_mut(usize::from(cpgi.me)) else {
+                    return false;
+                };
+                *program = cpgi.video_source;
+            }
+
+            Payload::SetPreviewInput(cpvi) => {
+                let Some(preview) = self.preview.get_mut(usize::from(cpvi.me)) else {
+                    return false;
+                };
+                *preview = cpvi.video_source;
+            }
+
+            Payload::Cut(dcut) => {
+                let me = usize::from(dcut.me);
+                if me >= self.program.len() {
+                    return false;
+                }
+                std::mem::swap(&mut self.program[me], &mut self.preview[me]);
+            }
+
+            _ => return false,
+        }
+        true
+    }
+
+    /// Makes [InputProperties] atoms for every source on the switcher.
+    fn input_properties(&self) -> Vec<Atom> {
+        let mes = (1u16 << self.topology.mes.min(8)) - 1;
+        let availability = u16::from(u8::from(
+            SourceAvailability::new()
+                .with_auxiliary(true)
+                .with_multiview(true),
+        )) << 8;
+
+        let mut sources = vec![(VideoSource::Black, "Black".to_string(), "BLK".to_string())];
+        sources.extend((1..=SimulatedSwitcher::INPUTS).filter_map(|n| {
+            let video_source = VideoSource::from_input_number(n)?;
+            Some((video_source, format!("Camera {n}"), format!("CAM{n}")))
+        }));
+
+        sources
+            .into_iter()
+            .map(|(video_source, long_name, short_name)| {
+                let external = video_source != VideoSource::Black;
+                let port_types = ExternalPortType::new().with_hdmi(external);
+                let mut inpr = InputProperties {
+                    video_source,
+                    long_name: [0; 20],
+                    short_name: [0; 4],
+                    input_names_are_default: true,
+                    available_external_port_types: port_types,
+                    external_port_type: port_types,
+                    port_type: if external {
+                        PortType::External
+                    } else {
+                        PortType::Black
+                    },
+                    availability3: availability | mes,
+                };
+                inpr.long_name[..long_name.len()].copy_from_slice(long_name.as_bytes());
+                inpr.short_name[..short_name.len()].copy_from_slice(short_name.as_bytes());
+                Atom::new(inpr)
+            })
+            .collect()
+    }
+
+    /// Makes atoms describing the current program, preview and tally state.
+    fn sources(&mut self) -> Vec<Atom> {
+        let mut atoms = Vec::new();
+        for (me, (program, preview)) in self.program.iter().zip(&self.preview).enumerate() {
+            let me = me as u8;
+            atoms.push(Atom::new(ProgramInput {
+                me,
+                video_source: *program,
+            }));
+            atoms.push(Atom::new(PreviewInput {
+                me,
+                video_source: *preview,
+                preview_input_live: false,
+            }));
+        }
+
+        for source in self.program.iter().chain(&self.preview) {
+            if !self.tallied.contains(source) {
+                self.tallied.push(*source);
+            }
+        }
+        let tally: Vec<(VideoSource, TallyFlags)> = self
+            .tallied
+            .iter()
+            .map(|source| {
+                (
+                    *source,
+                    TallyFlags::new()
+                        .with_program(self.program.contains(source))
+                        .with_preview(self.preview.contains(source)),
+                )
+            })
+            .collect();
+        atoms.push(Atom::new(TalliedSources::from(tally)));
+        atoms
+    }
+
+    /// Sends `atoms` to the client as the next packet in the session.
+    async fn send_atoms(&mut self, atoms: Vec<Atom>) -> Result {
+        self.sender_packet_id += 1;
+        let pkt = AtemPacket::builder()
+            .session(Self::SESSION_ID | 0x8000)
+            .sender_packet_id(self.sender_packet_id)
+            .atoms(atoms)
+            .build();
+        self.send(&pkt).await
+    }
+
+    async fn send(&self, pkt: &AtemPacket) -> Result {
+        let Some(peer) = self.peer else {
+            return Ok(());
+        };
+        let mut out = Cursor::new(Vec::new());
+        pkt.write(&mut out)?;
+        self.sock.send_to(&out.into_inner(), peer).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{AtemController, StateUpdate};
+    use futures::{pin_mut, StreamExt};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn cut() -> Result {
+        let mut topology = Topology::default();
+        topology.mes = 1;
+        let sim = SimulatedSwitcher::new(topology).await?;
+        let controller = AtemController::connect_udp(sim.addr(), false).await?;
+
+        {
+            let state = controller.get_state().await;
+            assert_eq!(Some(VideoSource::Input1), state.get_program_source(0));
+            assert_eq!(Some(VideoSource::Input2), state.get_preview_source(0));
+            assert!(state.get_tally(VideoSource::Input1).program());
+            assert!(state.get_tally(VideoSource::Input2).preview());
+        }
+
+        assert_eq!(
+            vec![
+                VideoSource::Black,
+                VideoSource::Input1,
+                VideoSource::Input2,
+                VideoSource::Input3,
+                VideoSource::Input4,
+            ],
+            controller.get_state().await.routable_sources(0)
+        );
+
+        let events = controller.subscribe_filtered(StateUpdate::TALLY_BY_SOURCE);
+        pin_mut!(events);
+        let timeout = Duration::from_secs(5);
+        controller.set_preview_input(0, VideoSource::Input3).await?;
+        let (state, _) = tokio::time::timeout(timeout, events.next())
+            .await
+            .expect("timeout waiting for tally")
+            .expect("stream ended");
+        assert_eq!(Some(VideoSource::Input3), state.get_preview_source(0));
+        assert_eq!(TallyFlags::new(), state.get_tally(VideoSource::Input2));
+
+        controller.cut(0).await?;
+        let (state, _) = tokio::time::timeout(timeout, events.next())
+            .await
+            .expect("timeout waiting for tally")
+            .expect("stream ended");
+        assert_eq!(Some(VideoSource::Input3), state.get_program_source(0));
+        assert_eq!(
+            TallyFlags::new().with_program(true),
+            state.get_tally(VideoSource::Input3)
+        );
+        assert_eq!(
+            TallyFlags::new().with_preview(true),
+            state.get_tally(VideoSource::Input1)
+        );
+        Ok(())
+    }
+}
@@ -1,23 +1,36 @@
 use crate::{
     protocol::{
         atom::{
-            Atom, ColourGeneratorParams, FadeToBlackStatus,
-            FairlightAudioMixerInputSourceProperties, InputProperties, MediaPlayerCapabilities,
-            MediaPlayerFrameDescription, MediaPlayerSourceID, MixEffectBlockCapabilities, Payload,
-            ProductName, Topology, TransitionPosition, Version,
+            Atom, AudioMixerInputProperties, AudioMixerMasterOutProperties,
+            AudioTalkbackMixerProperties, CameraParameterID, CameraParameterValue, ColorimetryMode,
+            ColourGeneratorParams, DVETransitionParams, DownstreamKeyerProperties,
+            DownstreamKeyerStatus, FadeToBlackStatus, FairlightAudioMixerInputSourceProperties,
+            HyperDeckClipPlayStatus, HyperDeckConnectionState, HyperDeckStatus,
+            HyperDeckTransportState, InputProperties, KeyDVEProperties, MacroProperties,
+            MediaPlayerAudioEntry, MediaPlayerCapabilities, MediaPlayerFrameDescription,
+            MediaPlayerSourceID, MixEffectBlockCapabilities, MultiViewCapabilities, Payload,
+            ProductName, RecordToMediaStatus, Sdi3gLevel, StreamRtmpStatistics, StreamRtmpStatus,
+            SuperSourceBoxParams, SupportedVideoMode, Topology, TransitionPosition,
+            TransitionSettings, UnknownCCST, Version, WipeTransitionParams,
+        },
+        structs::{
+            DVETransitionStyle, EqualiserRange, PortType, TallyFlags, TransitionStyle, VideoMode,
+            VideoSource,
         },
-        structs::{DVETransitionStyle, EqualiserRange, TallyFlags, VideoMode, VideoSource},
     },
     Result,
 };
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::{
     collections::{BTreeMap, HashMap, HashSet},
     ops::RangeInclusive,
+    time::Duration,
 };
 
 bitflags! {
     #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
-    pub struct StateUpdate: u32 {
+    pub struct StateUpdate: u64 {
         /// The switcher reports that initialisation has completed, and state
         /// is complete.
         const INITIALISATION_COMPLETE        = 1;
@@ -42,14 +55,151 @@ bitflags! {
         const FAIRLIGHT_INPUT_SOURCE_PROPS   = 1 << 19;
         const FAIRLIGHT_FREQUENCY_RANGES     = 1 << 20;
         const DVE_CAPABILITIES               = 1 << 21;
+        const STILL_CAPTURE_AVAILABILITY     = 1 << 22;
+        const MULTIVIEW_OVERLAYS             = 1 << 23;
+        const UNKNOWN_CCST                   = 1 << 24;
+        const KEY_DVE_PROPERTIES             = 1 << 25;
+        const DSK_ON_AIR                     = 1 << 26;
+        const TRANSITION_SETTINGS            = 1 << 27;
+        const AUDIO_MIXER_INPUT              = 1 << 28;
+        const AUDIO_MIXER_MASTER_OUT         = 1 << 29;
+        const MACRO                          = 1 << 30;
 
         const PREVIEW_OR_PROGRAM_SOURCE = Self::PREVIEW_SOURCE.bits() | Self::PROGRAM_SOURCE.bits();
         const UNSUPPORTED_COMMAND            = 1 << 31;
+
+        /// The connection to the switcher was lost, or the switcher
+        /// disconnected us.
+        ///
+        /// State will not be updated again until the connection is restored.
+        const CONNECTION_LOST                = 1 << 32;
+
+        /// The connection to the switcher was re-established after
+        /// [`CONNECTION_LOST`][Self::CONNECTION_LOST], and the switcher has
+        /// finished sending its initial state.
+        const CONNECTION_RESTORED            = 1 << 33;
+
+        const DSK_PROPERTIES                 = 1 << 34;
+        const AUX_SOURCE                     = 1 << 35;
+        const SUPER_SOURCE                   = 1 << 36;
+        const HYPERDECK                      = 1 << 37;
+        const TRANSITION_PREVIEW             = 1 << 38;
+        const TRANSITION_WIPE                = 1 << 39;
+        const TRANSITION_DVE                 = 1 << 40;
+        const RECORDING                      = 1 << 41;
+        const STREAMING                      = 1 << 42;
+        const MEDIA_PLAYER_AUDIO             = 1 << 43;
+        const WARNING                        = 1 << 44;
+        const POWER_STATUS                   = 1 << 45;
+        const TALKBACK                       = 1 << 46;
+        const CAMERA_PARAMETERS              = 1 << 47;
+        const COLORIMETRY                    = 1 << 48;
+        const SDI_3G_LEVEL                   = 1 << 49;
+        const MULTIVIEW_CAPABILITIES         = 1 << 50;
+    }
+}
+
+/// Switcher features which can be checked with [`AtemState::supports()`].
+///
+/// These are derived from the switcher's capability atoms, product ID and
+/// [Topology], so are only accurate once initialisation has completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Capability {
+    /// Streaming over RTMP.
+    Streaming,
+    /// Recording to media (eg: USB disk).
+    Recording,
+    /// Simultaneous ISO recording of all inputs.
+    IsoRecording,
+    /// Fairlight audio mixer.
+    Fairlight,
+    /// Classic (non-Fairlight) audio mixer.
+    AudioMixer,
+    /// SuperSource compositor.
+    SuperSource,
+    /// More than one mix effect block (ME).
+    MultipleMEs,
+    /// Camera control.
+    CameraControl,
+    /// Capturing the program output as a still image.
+    StillCapture,
+    /// At least one media player.
+    MediaPlayers,
+    /// At least one MultiView output.
+    Multiview,
+    /// At least one auxiliary output.
+    AuxOutputs,
+    /// At least one downstream keyer.
+    DownstreamKeyers,
+    /// Remote sources.
+    RemoteSource,
+    /// Digital video effects (DVE) on upstream keyers.
+    Dve,
+    /// At least one talkback (intercom) channel.
+    Talkback,
+}
+
+/// A bus which a [VideoSource] can be routed to, for
+/// [`AtemState::validate_source()`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum SourceBus {
+    /// Program and preview bus of a mix effect block (ME).
+    Me(u8),
+    /// Auxiliary outputs.
+    Auxiliary,
+    /// MultiView windows.
+    Multiview,
+    /// SuperSource art fill / key.
+    SuperSourceArt,
+    /// SuperSource boxes.
+    SuperSourceBox,
+    /// Upstream and downstream key fill / key sources.
+    KeySource,
+}
+
+impl SourceBus {
+    /// Every bus which [InputProperties] reports availability for.
+    const ALL: [Self; 13] = [
+        Self::Me(0),
+        Self::Me(1),
+        Self::Me(2),
+        Self::Me(3),
+        Self::Me(4),
+        Self::Me(5),
+        Self::Me(6),
+        Self::Me(7),
+        Self::Auxiliary,
+        Self::Multiview,
+        Self::SuperSourceArt,
+        Self::SuperSourceBox,
+        Self::KeySource,
+    ];
+
+    /// Returns `true` if the input described by `inpr` can be routed to this
+    /// bus.
+    fn accepts(&self, inpr: &InputProperties) -> bool {
+        let availability = inpr.source_availability();
+        match *self {
+            Self::Me(me) => inpr.available_on_me(me),
+            Self::Auxiliary => availability.auxiliary(),
+            Self::Multiview => availability.multiview(),
+            Self::SuperSourceArt => availability.super_source_art(),
+            Self::SuperSourceBox => availability.super_source_box(),
+            Self::KeySource => availability.key_source(),
+        }
     }
 }
 
-/// Maximum number of supported MEs.
-const MAX_MES: usize = 8;
+/// Overlays shown on a MultiView window.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiViewOverlays {
+    /// Safe area markers are shown.
+    pub safe_area: bool,
+    /// Audio VU meters are shown.
+    pub vu_meter: bool,
+}
 
 /// Maximum number of supported colour generators.
 const MAX_COLOUR_GENERATORS: u8 = 8;
@@ -57,10 +207,46 @@ const MAX_COLOUR_GENERATORS: u8 = 8;
 /// Maximum number of supported media players.
 const MAX_MEDIA_PLAYERS: u8 = 8;
 
+/// Maximum number of supported upstream keyers on each ME.
+///
+/// [`TransitionSelection`][crate::protocol::structs::TransitionSelection]
+/// can only select 4 keyers.
+const MAX_UPSTREAM_KEYERS: u8 = 4;
+
+/// (De)serialises a map as a sequence of `(key, value)` pairs, for maps with
+/// keys that formats like JSON can't represent as strings.
+#[cfg(feature = "serde")]
+mod serde_pairs {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<'a, M, K, V, S>(map: &'a M, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        &'a M: IntoIterator<Item = (&'a K, &'a V)>,
+        K: Serialize + 'a,
+        V: Serialize + 'a,
+        S: Serializer,
+    {
+        serializer.collect_seq(map)
+    }
+
+    pub fn deserialize<'de, M, K, V, D>(deserializer: D) -> Result<M, D::Error>
+    where
+        M: FromIterator<(K, V)>,
+        K: Deserialize<'de>,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        Ok(Vec::<(K, V)>::deserialize(deserializer)?
+            .into_iter()
+            .collect())
+    }
+}
+
 /// [AtemState] stores all state from [AtemController] events.
 ///
 /// [AtemController]: crate::controller::AtemController
 #[derive(Default, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct AtemState {
     // TODO: replace Hashmaps in this structure with Vec or simple arrays.
     /// Switcher intialisation completed.
@@ -72,22 +258,43 @@ pub struct AtemState {
     /// The topology of the switcher.
     pub topology: Topology,
 
-    me_capabilities: [MixEffectBlockCapabilities; MAX_MES],
-    program_source: [VideoSource; MAX_MES],
-    preview_source: [VideoSource; MAX_MES],
+    // Per-ME state is sized from the topology.
+    me_capabilities: Vec<MixEffectBlockCapabilities>,
+    program_source: Vec<VideoSource>,
+    preview_source: Vec<VideoSource>,
     /// Transition position for each ME.
     pub transition_position: HashMap<u8, TransitionPosition>,
+    /// Transition style and next transition selection for each ME.
+    pub transition_settings: HashMap<u8, TransitionSettings>,
+    /// Whether transition preview ("PREV TRANS") is enabled for each ME.
+    pub transition_preview: HashMap<u8, bool>,
+    /// Wipe transition parameters for each ME.
+    pub transition_wipe_params: HashMap<u8, WipeTransitionParams>,
+    /// DVE transition parameters for each ME.
+    pub transition_dve_params: HashMap<u8, DVETransitionParams>,
     /// Current tally state for each source.
     pub tally_by_source: HashMap<VideoSource, TallyFlags>,
     /// List of all video modes supported by the switcher.
     pub supported_video_modes: Vec<VideoMode>,
+    /// List of all video modes supported by the switcher, with the MultiView
+    /// and down-conversion modes supported in each.
+    pub supported_video_mode_capabilities: Vec<SupportedVideoMode>,
     /// Current video mode.
     pub video_mode: VideoMode,
+    /// Current colorimetry mode, if supported by the switcher.
+    pub colorimetry: Option<ColorimetryMode>,
+    /// Current 3G-SDI output level, if supported by the switcher.
+    pub sdi_3g_level: Option<Sdi3gLevel>,
     /// Input properties.
     pub input_properties: HashMap<VideoSource, InputProperties>,
-    fade_to_black_status: [FadeToBlackStatus; MAX_MES],
-    fade_to_black_rates: [u8; MAX_MES],
+    /// Sources available on each bus, derived from [`Self::input_properties`].
+    #[cfg_attr(feature = "serde", serde(with = "serde_pairs"))]
+    source_availability: HashMap<SourceBus, HashSet<VideoSource>>,
+    fade_to_black_status: Vec<FadeToBlackStatus>,
+    fade_to_black_rates: Vec<u8>,
     pub media_player_capabilities: MediaPlayerCapabilities,
+    /// Still capture is currently available.
+    pub still_capture_available: bool,
 
     /// List of media player sources.
     ///
@@ -107,6 +314,10 @@ pub struct AtemState {
     /// [SetupFileDownload]: crate::protocol::atom::SetupFileDownload
     pub media_player_frame_descriptions: HashMap<u8, MediaPlayerFrameDescription>,
 
+    /// Media player clip audio descriptions. Slots are 0-indexed, and share
+    /// the same numbering as video clips.
+    pub media_player_audio: HashMap<u8, MediaPlayerAudioEntry>,
+
     /// Colour generator configurations.
     ///
     /// Entries are 0-indexed (ie: `colour_generator[0]` == [VideoSource::Colour1]).
@@ -133,6 +344,83 @@ pub struct AtemState {
     pub dve_can_rotate: bool,
     pub dve_can_scale_up: bool,
     pub dve_supported_transition_styles: HashSet<DVETransitionStyle>,
+
+    /// The last unidentified [`CCST`][UnknownCCST] atom sent by the switcher.
+    ///
+    /// This is sent after a storage lock request, and its purpose is unknown.
+    pub unknown_ccst: Option<UnknownCCST>,
+
+    /// MultiView capabilities of the switcher.
+    pub multiview_capabilities: MultiViewCapabilities,
+
+    /// Overlays shown on each MultiView window, keyed by
+    /// `(multiview, window)`.
+    #[cfg_attr(feature = "serde", serde(with = "serde_pairs"))]
+    pub multiview_overlays: BTreeMap<(u8, u8), MultiViewOverlays>,
+
+    /// DVE properties of each upstream keyer, keyed by `(me, keyer)`.
+    #[cfg_attr(feature = "serde", serde(with = "serde_pairs"))]
+    pub key_dve_properties: BTreeMap<(u8, u8), KeyDVEProperties>,
+
+    /// Status of each downstream keyer, keyed by DSK index.
+    pub dsk_status: BTreeMap<u8, DownstreamKeyerStatus>,
+
+    /// Properties of each downstream keyer, keyed by DSK index.
+    pub dsk_properties: BTreeMap<u8, DownstreamKeyerProperties>,
+
+    /// Source routed to each auxiliary output, keyed by aux index.
+    pub aux_sources: BTreeMap<u8, VideoSource>,
+
+    /// Parameters of each SuperSource box, keyed by `(super_source, box)`.
+    #[cfg_attr(feature = "serde", serde(with = "serde_pairs"))]
+    pub super_source_boxes: BTreeMap<(u8, u8), SuperSourceBoxParams>,
+
+    /// Status of each HyperDeck, keyed by HyperDeck ID.
+    pub hyperdeck_status: BTreeMap<u16, HyperDeckStatus>,
+
+    /// Clip play status of each HyperDeck, keyed by HyperDeck ID.
+    pub hyperdeck_play_status: BTreeMap<u16, HyperDeckClipPlayStatus>,
+
+    /// Properties for each classic audio mixer input, keyed by audio source
+    /// ID.
+    pub audio_mixer_inputs: BTreeMap<u16, AudioMixerInputProperties>,
+
+    /// Classic audio mixer master output properties.
+    pub audio_mixer_master_out: AudioMixerMasterOutProperties,
+
+    /// Properties of each macro slot, keyed by macro index.
+    pub macros: BTreeMap<u16, MacroProperties>,
+
+    /// The index of the macro currently being recorded, if any.
+    pub macro_recording: Option<u16>,
+
+    /// Status of recording to media (eg: USB disk), if reported by the
+    /// switcher.
+    pub recording_status: Option<RecordToMediaStatus>,
+
+    /// Status of RTMP streaming, if reported by the switcher.
+    pub streaming_status: Option<StreamRtmpStatus>,
+
+    /// RTMP streaming statistics (eg: bitrate), if reported by the switcher.
+    pub streaming_statistics: Option<StreamRtmpStatistics>,
+
+    /// The switcher's current warning message (eg: fan failure), or `None`
+    /// when there is no warning.
+    warning_message: Option<String>,
+
+    /// Power supply states, if reported by the switcher.
+    power_supplies: Vec<bool>,
+
+    /// Talkback mixer channel properties, keyed by channel.
+    ///
+    /// The switcher reports every talkback channel it supports during
+    /// initialisation.
+    pub talkback_channels: BTreeMap<u8, AudioTalkbackMixerProperties>,
+
+    /// Camera control parameters last reported by the switcher, keyed by
+    /// `(input, parameter)`.
+    #[cfg_attr(feature = "serde", serde(with = "serde_pairs"))]
+    pub camera_parameters: BTreeMap<(u8, CameraParameterID), CameraParameterValue>,
 }
 
 impl AtemState {
@@ -178,12 +466,12 @@ impl AtemState {
                 Payload::Topology(top) => {
                     self.topology = top.clone();
                     debug!(?self.topology, "updated");
-                    if self.topology.mes as usize > MAX_MES {
-                        warn!(
-                            "device reports {} MEs, but this library only supports {MAX_MES}",
-                            self.topology.mes
-                        );
-                    }
+                    let mes = usize::from(self.topology.mes);
+                    self.me_capabilities.resize(mes, Default::default());
+                    self.program_source.resize(mes, Default::default());
+                    self.preview_source.resize(mes, Default::default());
+                    self.fade_to_black_status.resize(mes, Default::default());
+                    self.fade_to_black_rates.resize(mes, Default::default());
                     if self.tally_by_source.is_empty() {
                         self.tally_by_source.reserve(self.topology.sources as usize);
                     }
@@ -236,15 +524,52 @@ impl AtemState {
                     updated_fields |= StateUpdate::TRANSITION_POSITION;
                 }
 
+                Payload::TransitionSettings(trss) => {
+                    self.transition_settings.insert(trss.me, *trss);
+                    debug!(?trss, "updated transition settings");
+                    updated_fields |= StateUpdate::TRANSITION_SETTINGS;
+                }
+
+                Payload::TransitionPreview(trpr) => {
+                    self.transition_preview.insert(trpr.me, trpr.enabled);
+                    debug!(?trpr, "updated transition preview");
+                    updated_fields |= StateUpdate::TRANSITION_PREVIEW;
+                }
+
+                Payload::TransitionWipeProperties(twpp) => {
+                    self.transition_wipe_params.insert(twpp.me, twpp.params);
+                    debug!(?twpp, "updated wipe transition parameters");
+                    updated_fields |= StateUpdate::TRANSITION_WIPE;
+                }
+
+                Payload::TransitionDVEProperties(tdvp) => {
+                    self.transition_dve_params.insert(tdvp.me, tdvp.params);
+                    debug!(?tdvp, "updated DVE transition parameters");
+                    updated_fields |= StateUpdate::TRANSITION_DVE;
+                }
+
                 Payload::TalliedSources(tally) => {
-                    self.tally_by_source = tally.clone().into();
+                    // Each TlSr is a complete snapshot: sources which aren't
+                    // in it are dropped, and if a source appears more than
+                    // once, the last entry wins.
+                    self.tally_by_source.clear();
+                    self.tally_by_source
+                        .extend(tally.iter().map(|e| (e.video_source(), e.tally_flags())));
+                    if self.tally_by_source.len() != tally.len() {
+                        warn!(
+                            "TlSr has {} entries for {} sources",
+                            tally.len(),
+                            self.tally_by_source.len()
+                        );
+                    }
                     debug!(?self.tally_by_source, "updated");
                     updated_fields |= StateUpdate::TALLY_BY_SOURCE;
                 }
 
                 Payload::SupportedVideoModes(vmc) => {
                     self.supported_video_modes = vmc.clone().into();
-                    debug!(?self.supported_video_modes, "updated");
+                    self.supported_video_mode_capabilities = vmc.clone().into();
+                    debug!(?self.supported_video_mode_capabilities, "updated");
                     updated_fields |= StateUpdate::SUPPORTED_VIDEO_MODES;
                 }
 
@@ -254,6 +579,18 @@ impl AtemState {
                     updated_fields |= StateUpdate::VIDEO_MODE;
                 }
 
+                Payload::Colorimetry(clrm) => {
+                    self.colorimetry = Some(**clrm);
+                    debug!(?self.colorimetry, "updated");
+                    updated_fields |= StateUpdate::COLORIMETRY;
+                }
+
+                Payload::Sdi3gOutputLevel(v3sl) => {
+                    self.sdi_3g_level = Some(**v3sl);
+                    debug!(?self.sdi_3g_level, "updated");
+                    updated_fields |= StateUpdate::SDI_3G_LEVEL;
+                }
+
                 Payload::InputProperties(inpr) => {
                     debug!(?inpr, "updated input property");
                     if let Some(colour_generator_id) = inpr.colour_generator_id() {
@@ -264,6 +601,15 @@ impl AtemState {
                         }
                     }
 
+                    for bus in SourceBus::ALL {
+                        let sources = self.source_availability.entry(bus).or_default();
+                        if bus.accepts(inpr) {
+                            sources.insert(inpr.video_source);
+                        } else {
+                            sources.remove(&inpr.video_source);
+                        }
+                    }
+
                     self.input_properties
                         .insert(inpr.video_source, inpr.clone());
                     updated_fields |= StateUpdate::INPUT_PROPERTIES;
@@ -295,6 +641,12 @@ impl AtemState {
                     updated_fields |= StateUpdate::MEDIA_PLAYER_CAPABILITIES;
                 }
 
+                Payload::StillCaptureAvailability(capa) => {
+                    self.still_capture_available = capa.available;
+                    debug!(?capa, "updated still capture availability");
+                    updated_fields |= StateUpdate::STILL_CAPTURE_AVAILABILITY;
+                }
+
                 Payload::MediaPlayerFrameDescription(mpfe) => {
                     debug!(?mpfe, "updated media player frame description");
                     if mpfe.store_id != 0 || mpfe.index > 0xff {
@@ -305,6 +657,15 @@ impl AtemState {
                     updated_fields |= StateUpdate::MEDIA_PLAYER_FRAME_DESCRIPTION;
                 }
 
+                Payload::MediaPlayerAudioEntry(mpas) => {
+                    debug!(?mpas, "updated media player audio");
+                    let Ok(index) = u8::try_from(mpas.index) else {
+                        continue;
+                    };
+                    self.media_player_audio.insert(index, mpas.clone());
+                    updated_fields |= StateUpdate::MEDIA_PLAYER_AUDIO;
+                }
+
                 Payload::MediaPlayerSource(mpce) => {
                     debug!(?mpce, "updated media player source");
                     if mpce.id >= MAX_MEDIA_PLAYERS || mpce.id >= self.topology.media_players {
@@ -366,6 +727,150 @@ impl AtemState {
                     updated_fields |= StateUpdate::DVE_CAPABILITIES;
                 }
 
+                Payload::UnknownCCST(ccst) => {
+                    debug!(?ccst, "updated CCST");
+                    self.unknown_ccst = Some(ccst.clone());
+                    updated_fields |= StateUpdate::UNKNOWN_CCST;
+                }
+
+                Payload::MultiViewCapabilities(mvc) => {
+                    debug!(?mvc, "updated multiview capabilities");
+                    self.multiview_capabilities = *mvc;
+                    updated_fields |= StateUpdate::MULTIVIEW_CAPABILITIES;
+                }
+
+                Payload::MultiViewSafeArea(samw) => {
+                    debug!(?samw, "updated multiview safe area");
+                    self.multiview_overlays
+                        .entry((samw.multiview, samw.window))
+                        .or_default()
+                        .safe_area = samw.enabled;
+                    updated_fields |= StateUpdate::MULTIVIEW_OVERLAYS;
+                }
+
+                Payload::MultiViewVuMeterEnabled(vumc) => {
+                    debug!(?vumc, "updated multiview VU meter");
+                    self.multiview_overlays
+                        .entry((vumc.multiview, vumc.window))
+                        .or_default()
+                        .vu_meter = vumc.enabled;
+                    updated_fields |= StateUpdate::MULTIVIEW_OVERLAYS;
+                }
+
+                Payload::KeyDVEProperties(kedv) => {
+                    debug!(?kedv, "updated key DVE properties");
+                    self.key_dve_properties.insert((kedv.me, kedv.keyer), *kedv);
+                    updated_fields |= StateUpdate::KEY_DVE_PROPERTIES;
+                }
+
+                Payload::DownstreamKeyerStatus(dsks) => {
+                    debug!(?dsks, "updated DSK status");
+                    self.dsk_status.insert(dsks.keyer, *dsks);
+                    updated_fields |= StateUpdate::DSK_ON_AIR;
+                }
+
+                Payload::DownstreamKeyerProperties(dskp) => {
+                    debug!(?dskp, "updated DSK properties");
+                    self.dsk_properties.insert(dskp.keyer, *dskp);
+                    updated_fields |= StateUpdate::DSK_PROPERTIES;
+                }
+
+                Payload::AuxSource(auxs) => {
+                    debug!(?auxs, "updated aux source");
+                    self.aux_sources.insert(auxs.aux, auxs.video_source);
+                    updated_fields |= StateUpdate::AUX_SOURCE;
+                }
+
+                Payload::SuperSourceBoxProperties(ssbp) => {
+                    debug!(?ssbp, "updated SuperSource box");
+                    self.super_source_boxes
+                        .insert((ssbp.super_source, ssbp.box_id), ssbp.params);
+                    updated_fields |= StateUpdate::SUPER_SOURCE;
+                }
+
+                Payload::HyperDeckStatus(rxss) => {
+                    debug!(?rxss, "updated HyperDeck status");
+                    self.hyperdeck_status.insert(rxss.hyperdeck_id, *rxss);
+                    updated_fields |= StateUpdate::HYPERDECK;
+                }
+
+                Payload::HyperDeckClipPlayStatus(rxcp) => {
+                    debug!(?rxcp, "updated HyperDeck clip play status");
+                    self.hyperdeck_play_status.insert(rxcp.hyperdeck_id, *rxcp);
+                    updated_fields |= StateUpdate::HYPERDECK;
+                }
+
+                Payload::AudioMixerInputProperties(amip) => {
+                    debug!(?amip, "updated audio mixer input properties");
+                    self.audio_mixer_inputs.insert(amip.source, *amip);
+                    updated_fields |= StateUpdate::AUDIO_MIXER_INPUT;
+                }
+
+                Payload::MacroProperties(mprp) => {
+                    debug!(?mprp, "updated macro properties");
+                    self.macros.insert(mprp.index, mprp.clone());
+                    updated_fields |= StateUpdate::MACRO;
+                }
+
+                Payload::MacroRecordStatus(mrcs) => {
+                    debug!(?mrcs, "updated macro record status");
+                    self.macro_recording = mrcs.is_recording.then_some(mrcs.index);
+                    updated_fields |= StateUpdate::MACRO;
+                }
+
+                Payload::RecordToMediaStatus(rtms) => {
+                    debug!(?rtms, "updated recording status");
+                    self.recording_status = Some(rtms.clone());
+                    updated_fields |= StateUpdate::RECORDING;
+                }
+
+                Payload::StreamRtmpStatus(strs) => {
+                    debug!(?strs, "updated streaming status");
+                    self.streaming_status = Some(*strs);
+                    updated_fields |= StateUpdate::STREAMING;
+                }
+
+                Payload::StreamRtmpStatistics(srss) => {
+                    debug!(?srss, "updated streaming statistics");
+                    self.streaming_statistics = Some(*srss);
+                    updated_fields |= StateUpdate::STREAMING;
+                }
+
+                Payload::WarningMessage(warn) => {
+                    debug!(?warn, "updated warning message");
+                    self.warning_message = (!warn.message.is_empty()).then(|| warn.message.clone());
+                    updated_fields |= StateUpdate::WARNING;
+                }
+
+                Payload::PowerStatus(powr) => {
+                    debug!(?powr, "updated power status");
+                    self.power_supplies = powr.supplies.to_array().to_vec();
+                    updated_fields |= StateUpdate::POWER_STATUS;
+                }
+
+                Payload::AudioTalkbackMixerProperties(atmp) => {
+                    debug!(?atmp, "updated talkback mixer properties");
+                    self.talkback_channels.insert(atmp.channel, *atmp);
+                    updated_fields |= StateUpdate::TALKBACK;
+                }
+
+                Payload::CameraControl(ccdp) => {
+                    let key = (ccdp.input, ccdp.parameter);
+                    if self.camera_parameters.get(&key) == Some(&ccdp.value) {
+                        trace!(?ccdp, "camera parameter unchanged");
+                        continue;
+                    }
+                    debug!(?ccdp, "updated camera parameter");
+                    self.camera_parameters.insert(key, ccdp.value.clone());
+                    updated_fields |= StateUpdate::CAMERA_PARAMETERS;
+                }
+
+                Payload::AudioMixerMasterOutProperties(ammo) => {
+                    self.audio_mixer_master_out = *ammo;
+                    debug!(?self.audio_mixer_master_out, "updated");
+                    updated_fields |= StateUpdate::AUDIO_MIXER_MASTER_OUT;
+                }
+
                 _ => (),
             }
         }
@@ -373,86 +878,387 @@ impl AtemState {
         Ok(updated_fields)
     }
 
-    /// Get the capabilities of a given ME.
-    pub const fn get_me_capabilities(&self, me: u8) -> Option<MixEffectBlockCapabilities> {
+    /// Returns `true` if the switcher supports a given [Capability].
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::Streaming => self.product_name.supports_rtmp_streaming(),
+            Capability::Recording => self.product_name.supports_recording(),
+            Capability::IsoRecording => self.product_name.supports_iso_recording_all_inputs(),
+            Capability::Fairlight => self.topology.farlight_audio_mixer,
+            Capability::AudioMixer => self.topology.audio_mixer,
+            Capability::SuperSource => self
+                .input_properties
+                .values()
+                .any(|inpr| inpr.port_type == PortType::SuperSource),
+            Capability::MultipleMEs => self.topology.mes > 1,
+            Capability::CameraControl => self.topology.camera_control,
+            Capability::StillCapture => self.media_player_capabilities.supports_still_capture,
+            Capability::MediaPlayers => self.topology.media_players > 0,
+            Capability::Multiview => self.topology.multiviewers > 0,
+            Capability::AuxOutputs => self.topology.auxs > 0,
+            Capability::DownstreamKeyers => self.topology.downstream_keys > 0,
+            Capability::RemoteSource => self.product_name.supports_remote_source(),
+            Capability::Dve => !self.dve_supported_transition_styles.is_empty(),
+            Capability::Talkback => !self.talkback_channels.is_empty(),
+        }
+    }
+
+    /// Suggests a default video mode for the switcher, based on
+    /// [its supported video modes][Self::supported_video_modes].
+    ///
+    /// This prefers HD modes with the same family of frame rates as the
+    /// switcher's [current video mode][Self::video_mode] (25/50 Hz,
+    /// 23.98/29.97/59.94 Hz, or 24/30/60 Hz), then progressive modes over
+    /// interlaced modes, then the highest resolution and frame rate.
+    ///
+    /// If the current video mode is not yet known, this has no regional
+    /// preference, and picks the highest frame rate the switcher supports
+    /// (ie: a 60 Hz mode on a switcher which supports every frame rate).
+    ///
+    /// Returns `None` if the switcher doesn't support any HD (720 or 1080 line)
+    /// video modes, or the supported video modes are not yet known.
+    pub fn suggested_default_video_mode(&self) -> Option<VideoMode> {
+        let family = |m: &VideoMode| {
+            let rate = m.rate_per_100sec();
+            (rate.is_multiple_of(25_00), rate.is_multiple_of(100))
+        };
+        let region = (self.video_mode != VideoMode::Unknown).then(|| family(&self.video_mode));
+        self.supported_video_modes
+            .iter()
+            .copied()
+            .filter(|m| matches!(m.lines(), 720 | 1080))
+            .max_by_key(|m| {
+                (
+                    region.is_none_or(|r| r == family(m)),
+                    !m.is_interlaced(),
+                    m.lines(),
+                    m.rate_per_100sec(),
+                )
+            })
+    }
+
+    /// Gets the MD5 hash of the still image frame in slot `index`, as reported
+    /// by the switcher.
+    ///
+    /// Returns `None` if the slot doesn't contain a valid frame, or its
+    /// description is not yet known.
+    pub fn still_md5(&self, index: u8) -> Option<[u8; 16]> {
+        self.media_player_frame_descriptions
+            .get(&index)
+            .filter(|frame_info| frame_info.is_valid)
+            .map(|frame_info| frame_info.md5)
+    }
+
+    /// Gets the switcher's current warning message (eg: fan failure).
+    ///
+    /// Returns `None` if the switcher hasn't reported a warning, or the last
+    /// warning has been cleared.
+    pub fn warning_message(&self) -> Option<&str> {
+        self.warning_message.as_deref()
+    }
+
+    /// Gets whether each power supply is present and supplying power, in
+    /// order.
+    ///
+    /// This is empty if the switcher hasn't reported its power status.
+    /// Otherwise, this always has two entries, because the switcher doesn't
+    /// report how many power supplies it has. On switchers with a single power
+    /// supply, the second entry is meaningless.
+    ///
+    /// The switcher does not distinguish between a missing and a failed power
+    /// supply.
+    pub fn power_supplies(&self) -> &[bool] {
+        &self.power_supplies
+    }
+
+    /// Get the tally state of a given source.
+    ///
+    /// Returns [`TallyFlags::default()`] (not on program or preview) if the
+    /// switcher hasn't reported a tally state for `source`.
+    pub fn get_tally(&self, source: VideoSource) -> TallyFlags {
+        self.tally_by_source
+            .get(&source)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Iterates over the tally state of every source which the switcher has
+    /// reported, in no particular order.
+    pub fn iter_tally(&self) -> impl Iterator<Item = (VideoSource, TallyFlags)> + '_ {
+        self.tally_by_source
+            .iter()
+            .map(|(source, tally)| (*source, *tally))
+    }
+
+    /// Iterates over the properties of every input which the switcher has
+    /// reported, in no particular order.
+    pub fn iter_inputs(&self) -> impl Iterator<Item = (VideoSource, &InputProperties)> + '_ {
+        self.input_properties
+            .iter()
+            .map(|(source, inpr)| (*source, inpr))
+    }
+
+    /// Returns `true` if `source` can be routed to `bus`, according to the
+    /// switcher's [input properties][Self::input_properties].
+    ///
+    /// Returns `false` if the switcher hasn't reported the properties of
+    /// `source`.
+    pub fn validate_source(&self, source: VideoSource, bus: SourceBus) -> bool {
+        self.source_availability
+            .get(&bus)
+            .is_some_and(|sources| sources.contains(&source))
+    }
+
+    /// Returns every source which can be used as a program or preview source
+    /// on `me`, according to the switcher's
+    /// [input properties][Self::input_properties], in [VideoSource] order.
+    ///
+    /// Returns an empty [Vec] if `me` doesn't exist, or the switcher hasn't
+    /// reported its input properties.
+    pub fn routable_sources(&self, me: u8) -> Vec<VideoSource> {
+        let mut sources: Vec<VideoSource> = self
+            .source_availability
+            .get(&SourceBus::Me(me))
+            .map(|sources| sources.iter().copied().collect())
+            .unwrap_or_default();
+        sources.sort_by_key(|source| *source as u16);
+        sources
+    }
+
+    /// Returns every ME program and preview output which the switcher reports
+    /// as an input (eg: [`VideoSource::ME1Prog`]), in [VideoSource] order.
+    ///
+    /// On switchers with multiple MEs, these can be used to route the output
+    /// of one ME into another. Check [`validate_source()`][Self::validate_source]
+    /// or [`routable_sources()`][Self::routable_sources] for where each can be
+    /// used.
+    ///
+    /// Returns an empty [Vec] if the switcher hasn't reported its input
+    /// properties.
+    pub fn me_output_sources(&self) -> Vec<VideoSource> {
+        let mut sources: Vec<VideoSource> = self
+            .input_properties
+            .values()
+            .filter(|inpr| inpr.port_type == PortType::MEOutput)
+            .map(|inpr| inpr.video_source)
+            .collect();
+        sources.sort_by_key(|source| *source as u16);
+        sources
+    }
+
+    /// Returns `true` if the downstream keyer is on air.
+    ///
+    /// Returns `None` if the switcher hasn't reported the status of `keyer`.
+    pub fn get_dsk_on_air(&self, keyer: u8) -> Option<bool> {
+        self.dsk_status.get(&keyer).map(|dsks| dsks.on_air)
+    }
+
+    /// Returns `true` if the downstream keyer is tied to the next transition.
+    ///
+    /// Returns `None` if the switcher hasn't reported the properties of
+    /// `keyer`.
+    pub fn get_dsk_tie(&self, keyer: u8) -> Option<bool> {
+        self.dsk_properties.get(&keyer).map(|dskp| dskp.tie)
+    }
+
+    /// Get the source routed to a given auxiliary output.
+    ///
+    /// Returns `None` if the switcher hasn't reported the source of `aux`.
+    pub fn get_aux_source(&self, aux: u8) -> Option<VideoSource> {
+        self.aux_sources.get(&aux).copied()
+    }
+
+    /// Get the source routed to every auxiliary output, indexed by aux.
+    ///
+    /// Outputs whose source hasn't been reported by the switcher are
+    /// [`VideoSource::Unknown`].
+    pub fn aux_routing(&self) -> Vec<VideoSource> {
+        (0..self.topology.auxs)
+            .map(|aux| self.get_aux_source(aux).unwrap_or_default())
+            .collect()
+    }
+
+    /// Get the parameters of a given SuperSource box.
+    ///
+    /// Returns `None` if the switcher hasn't reported the parameters of
+    /// `box_id`.
+    pub fn get_super_source_box(
+        &self,
+        super_source: u8,
+        box_id: u8,
+    ) -> Option<SuperSourceBoxParams> {
+        self.super_source_boxes
+            .get(&(super_source, box_id))
+            .copied()
+    }
+
+    /// Get the connection state of a given HyperDeck.
+    ///
+    /// Returns `None` if the switcher hasn't reported the status of
+    /// `hyperdeck_id`.
+    pub fn get_hyperdeck_connection_state(
+        &self,
+        hyperdeck_id: u16,
+    ) -> Option<HyperDeckConnectionState> {
+        self.hyperdeck_status
+            .get(&hyperdeck_id)
+            .map(|rxss| rxss.connection_state)
+    }
+
+    /// Get the transport mode of a given HyperDeck.
+    ///
+    /// Returns `None` if the switcher hasn't reported the clip play status of
+    /// `hyperdeck_id`.
+    pub fn get_hyperdeck_transport_state(
+        &self,
+        hyperdeck_id: u16,
+    ) -> Option<HyperDeckTransportState> {
+        self.hyperdeck_play_status
+            .get(&hyperdeck_id)
+            .map(|rxcp| rxcp.transport_state)
+    }
+
+    /// Get the ID of the current clip on a given HyperDeck.
+    ///
+    /// Returns `None` if the switcher hasn't reported the status of
+    /// `hyperdeck_id`.
+    pub fn get_hyperdeck_current_clip(&self, hyperdeck_id: u16) -> Option<u16> {
+        self.hyperdeck_status
+            .get(&hyperdeck_id)
+            .map(|rxss| rxss.current_clip_id)
+    }
+
+    /// Estimates the wall-clock time remaining for the transition in progress
+    /// on `me`.
+    ///
+    /// This uses the number of frames remaining reported by the switcher in
+    /// [`TransitionPosition`] (which it derives from the transition rate and
+    /// current position), and the duration of a frame in the current
+    /// [video mode][Self::video_mode].
+    ///
+    /// Returns `None` if there is no transition in progress on `me`, or the
+    /// video mode is unknown.
+    pub fn estimated_transition_remaining(&self, me: u8) -> Option<Duration> {
+        let pos = self
+            .transition_position
+            .get(&me)
+            .filter(|pos| pos.in_progress)?;
+        Some(self.video_mode.frame_duration()? * u32::from(pos.frames_remaining))
+    }
+
+    /// Returns `true` if the next transition on `me` is being shown on the
+    /// preview output ("PREV TRANS").
+    ///
+    /// This requires [transition preview][Self::transition_preview] to be
+    /// enabled, and the next transition to
+    /// [affect at least one layer][TransitionSettings::selection].
+    pub fn next_transition_preview_active(&self, me: u8) -> bool {
+        self.transition_preview
+            .get(&me)
+            .copied()
+            .unwrap_or_default()
+            && self
+                .transition_settings
+                .get(&me)
+                .is_some_and(|trss| u8::from(trss.selection) != 0)
+    }
+
+    /// Gets the transition styles which are available on `me`, in numeric
+    /// order.
+    ///
+    /// The switcher reports DVE and stinger support switcher-wide, so every ME
+    /// reports the same styles.
+    ///
+    /// Returns an empty list if `me` does not exist.
+    pub fn supported_transition_styles(&self, me: u8) -> Vec<TransitionStyle> {
         if me >= self.topology.mes {
-            return None;
+            return vec![];
         }
-        let me = me as usize;
-        if me >= MAX_MES {
-            return None;
+
+        let mut styles = vec![
+            TransitionStyle::Mix,
+            TransitionStyle::Dip,
+            TransitionStyle::Wipe,
+        ];
+        if self.supports(Capability::Dve) {
+            styles.push(TransitionStyle::DVE);
         }
-        Some(self.me_capabilities[me])
+        if self.topology.stingers > 0 {
+            styles.push(TransitionStyle::Stinger);
+        }
+        styles
+    }
+
+    /// Iterates over the IDs of every ME on the switcher.
+    pub fn mes(&self) -> impl Iterator<Item = u8> {
+        0..self.topology.mes
+    }
+
+    /// Iterates over the IDs of every auxiliary output on the switcher.
+    pub fn auxes(&self) -> impl Iterator<Item = u8> {
+        0..self.topology.auxs
+    }
+
+    /// Iterates over the IDs of every downstream keyer on the switcher.
+    pub fn dsks(&self) -> impl Iterator<Item = u8> {
+        0..self.topology.downstream_keys
+    }
+
+    /// Iterates over the IDs of every upstream keyer on `me`.
+    ///
+    /// This is limited to the 4 keyers per ME which this library supports, and
+    /// is empty if `me` does not exist or its
+    /// [capabilities][Self::get_me_capabilities] are not yet known.
+    pub fn upstream_keyers(&self, me: u8) -> impl Iterator<Item = u8> {
+        let keyers = self.get_me_capabilities(me).map_or(0, |mec| mec.keyers);
+        0..keyers.min(MAX_UPSTREAM_KEYERS)
+    }
+
+    /// Get the capabilities of a given ME.
+    pub fn get_me_capabilities(&self, me: u8) -> Option<MixEffectBlockCapabilities> {
+        self.me_capabilities.get(usize::from(me)).copied()
     }
 
     /// Get the current program source for the given ME.
     ///
     /// Returns `None` if the `me` is invalid for this switcher's topology.
-    pub const fn get_program_source(&self, me: u8) -> Option<VideoSource> {
-        if me >= self.topology.mes {
-            return None;
-        }
-        let me = me as usize;
-        if me >= MAX_MES {
-            return None;
-        }
-        Some(self.program_source[me])
+    pub fn get_program_source(&self, me: u8) -> Option<VideoSource> {
+        self.program_source.get(usize::from(me)).copied()
     }
 
     /// Get the program sources for all MEs.
     pub fn get_program_sources(&self) -> &[VideoSource] {
-        &self.program_source[0..self.topology.mes as usize]
+        &self.program_source
     }
 
     /// Get the current preview source for the given ME.
     ///
     /// Returns `None` if the `me` is invalid for this switcher's topology.
-    pub const fn get_preview_source(&self, me: u8) -> Option<VideoSource> {
-        if me >= self.topology.mes {
-            return None;
-        }
-        let me = me as usize;
-        if me >= MAX_MES {
-            return None;
-        }
-        Some(self.preview_source[me])
+    pub fn get_preview_source(&self, me: u8) -> Option<VideoSource> {
+        self.preview_source.get(usize::from(me)).copied()
     }
 
     /// Get the preview sources for all MEs.
     pub fn get_preview_sources(&self) -> &[VideoSource] {
-        &self.preview_source[0..self.topology.mes as usize]
+        &self.preview_source
     }
 
-    pub const fn get_fade_to_black_status(&self, me: u8) -> Option<FadeToBlackStatus> {
-        if me >= self.topology.mes {
-            return None;
-        }
-        let me = me as usize;
-        if me >= MAX_MES {
-            return None;
-        }
-        Some(self.fade_to_black_status[me])
+    pub fn get_fade_to_black_status(&self, me: u8) -> Option<FadeToBlackStatus> {
+        self.fade_to_black_status.get(usize::from(me)).copied()
     }
 
     /// Get the fade-to-black statuses for all MEs.
     pub fn get_fade_to_black_statuses(&self) -> &[FadeToBlackStatus] {
-        &self.fade_to_black_status[0..self.topology.mes as usize]
+        &self.fade_to_black_status
     }
 
-    pub const fn get_fade_to_black_rate(&self, me: u8) -> Option<u8> {
-        if me >= self.topology.mes {
-            return None;
-        }
-        let me = me as usize;
-        if me >= MAX_MES {
-            return None;
-        }
-        Some(self.fade_to_black_rates[me])
+    pub fn get_fade_to_black_rate(&self, me: u8) -> Option<u8> {
+        self.fade_to_black_rates.get(usize::from(me)).copied()
     }
 
     /// Get the fade-to-black rates for all MEs.
     pub fn get_fade_to_black_rates(&self) -> &[u8] {
-        &self.fade_to_black_rates[0..self.topology.mes as usize]
+        &self.fade_to_black_rates
     }
 
     pub fn get_colour_generator(&self, id: u8) -> Option<ColourGeneratorParams> {
@@ -488,36 +1294,34 @@ impl std::fmt::Debug for AtemState {
             .field("product_name", &self.product_name)
             .field("version", &self.version)
             .field("topology", &self.topology)
-            .field(
-                "me_capabilities",
-                &&self.me_capabilities[..MAX_MES.min(self.topology.mes as usize)],
-            )
-            .field(
-                "program_source",
-                &&self.program_source[..MAX_MES.min(self.topology.mes as usize)],
-            )
-            .field(
-                "preview_source",
-                &&self.preview_source[..MAX_MES.min(self.topology.mes as usize)],
-            )
+            .field("me_capabilities", &self.me_capabilities)
+            .field("program_source", &self.program_source)
+            .field("preview_source", &self.preview_source)
             .field("transition_position", &self.transition_position)
+            .field("transition_settings", &self.transition_settings)
+            .field("transition_preview", &self.transition_preview)
+            .field("transition_wipe_params", &self.transition_wipe_params)
+            .field("transition_dve_params", &self.transition_dve_params)
             .field("tally_by_source", &self.tally_by_source)
             .field("supported_video_modes", &self.supported_video_modes)
-            .field("input_properties", &self.input_properties)
-            .field("video_mode", &self.video_mode)
-            .field(
-                "fade_to_black_status",
-                &&self.fade_to_black_status[..MAX_MES.min(self.topology.mes as usize)],
-            )
             .field(
-                "fade_to_black_rates",
-                &&self.fade_to_black_rates[..MAX_MES.min(self.topology.mes as usize)],
+                "supported_video_mode_capabilities",
+                &self.supported_video_mode_capabilities,
             )
+            .field("input_properties", &self.input_properties)
+            .field("source_availability", &self.source_availability)
+            .field("video_mode", &self.video_mode)
+            .field("colorimetry", &self.colorimetry)
+            .field("sdi_3g_level", &self.sdi_3g_level)
+            .field("fade_to_black_status", &self.fade_to_black_status)
+            .field("fade_to_black_rates", &self.fade_to_black_rates)
             .field("media_player_capabilities", &self.media_player_capabilities)
+            .field("still_capture_available", &self.still_capture_available)
             .field(
                 "media_player_frame_descriptions",
                 &self.media_player_frame_descriptions,
             )
+            .field("media_player_audio", &self.media_player_audio)
             .field(
                 "media_player_sources",
                 &&self.media_player_sources
@@ -537,6 +1341,1096 @@ impl std::fmt::Debug for AtemState {
                 "fairlight_audio_mixer_input_props",
                 &self.fairlight_audio_mixer_input_props,
             )
+            .field("unknown_ccst", &self.unknown_ccst)
+            .field("multiview_capabilities", &self.multiview_capabilities)
+            .field("multiview_overlays", &self.multiview_overlays)
+            .field("key_dve_properties", &self.key_dve_properties)
+            .field("dsk_status", &self.dsk_status)
+            .field("dsk_properties", &self.dsk_properties)
+            .field("aux_sources", &self.aux_sources)
+            .field("super_source_boxes", &self.super_source_boxes)
+            .field("hyperdeck_status", &self.hyperdeck_status)
+            .field("hyperdeck_play_status", &self.hyperdeck_play_status)
+            .field("audio_mixer_inputs", &self.audio_mixer_inputs)
+            .field("audio_mixer_master_out", &self.audio_mixer_master_out)
+            .field("macros", &self.macros)
+            .field("macro_recording", &self.macro_recording)
+            .field("recording_status", &self.recording_status)
+            .field("streaming_status", &self.streaming_status)
+            .field("streaming_statistics", &self.streaming_statistics)
+            .field("warning_message", &self.warning_message)
+            .field("power_supplies", &self.power_supplies)
+            .field("talkback_channels", &self.talkback_channels)
+            .field("camera_parameters", &self.camera_parameters)
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::protocol::{
+        atom::{
+            AudioMixerInputState, AuxSource, MacroRecordStatus, MultiViewSafeArea,
+            MultiViewVuMeterEnabled, PowerStatus, PowerSupplies, PreviewInput, ProgramInput,
+            RecordStatus, StreamingStatus, SuperSourceBoxProperties, TransitionDVEProperties,
+            TransitionPreview, TransitionWipeProperties, WarningMessage, NO_MACRO,
+        },
+        structs::{TransitionSelection, WipePattern},
+    };
+    use binrw::BinRead;
+    use std::io::Cursor;
+
+    /// Builds the initial state of an ATEM Mini series switcher.
+    fn atem_mini(name: &str, id: u8) -> Result<AtemState> {
+        // ATEM Mini and ATEM Mini Pro share the same topology.
+        let topology = Atom::read(&mut Cursor::new(hex::decode(
+            "002400005f746f70010e0101000100000401000000000001000001000000010101000000",
+        )?))?;
+
+        let mut state = AtemState::default();
+        state.update_state(&[
+            Atom::new(ProductName::new(name.to_string(), id)?),
+            topology,
+            Atom::new(MediaPlayerCapabilities {
+                still_count: 20,
+                clip_count: 0,
+                supports_still_capture: true,
+            }),
+        ])?;
+        Ok(state)
+    }
+
+    #[test]
+    fn topology_iterators() -> Result {
+        let state = AtemState::default();
+        assert_eq!(0, state.mes().count());
+        assert_eq!(0, state.upstream_keyers(0).count());
+
+        let mut state = atem_mini("ATEM Mini", 0xd)?;
+        assert_eq!(vec![0], state.mes().collect::<Vec<_>>());
+        assert_eq!(vec![0], state.auxes().collect::<Vec<_>>());
+        assert_eq!(vec![0], state.dsks().collect::<Vec<_>>());
+        // ME capabilities not yet known
+        assert_eq!(0, state.upstream_keyers(0).count());
+
+        state.update_state(&[Atom::new(MixEffectBlockCapabilities { me: 0, keyers: 1 })])?;
+        assert_eq!(vec![0], state.upstream_keyers(0).collect::<Vec<_>>());
+        assert_eq!(0, state.upstream_keyers(1).count());
+
+        // 12 MEs, 2 DSKs, 3 auxes; keyer counts beyond what the library
+        // supports are capped
+        let topology = Atom::read(&mut Cursor::new(hex::decode(
+            "002400005f746f700c0e0203000100000401000000000001000001000000010101000000",
+        )?))?;
+        state.update_state(&[
+            topology,
+            Atom::new(MixEffectBlockCapabilities { me: 9, keyers: 4 }),
+            Atom::new(MixEffectBlockCapabilities { me: 1, keyers: 16 }),
+        ])?;
+        assert_eq!((0..12).collect::<Vec<_>>(), state.mes().collect::<Vec<_>>());
+        assert_eq!(3, state.auxes().count());
+        assert_eq!(2, state.dsks().count());
+        assert_eq!(4, state.upstream_keyers(9).count());
+        assert_eq!(4, state.upstream_keyers(1).count());
+        Ok(())
+    }
+
+    #[test]
+    fn supported_transition_styles() -> Result {
+        let mut state = atem_mini("ATEM Mini", 0xd)?;
+        assert_eq!(0, state.topology.stingers);
+
+        // DVE capabilities not yet known
+        assert_eq!(
+            vec![
+                TransitionStyle::Mix,
+                TransitionStyle::Dip,
+                TransitionStyle::Wipe
+            ],
+            state.supported_transition_styles(0)
+        );
+        assert!(state.supported_transition_styles(1).is_empty());
+
+        let dve = Atom::read(&mut Cursor::new(hex::decode(
+            "002000005f44564500010011101112131415161718191a1b1c1d1e1f22000000",
+        )?))?;
+        state.update_state(&[dve])?;
+        assert_eq!(
+            vec![
+                TransitionStyle::Mix,
+                TransitionStyle::Dip,
+                TransitionStyle::Wipe,
+                TransitionStyle::DVE
+            ],
+            state.supported_transition_styles(0)
+        );
+
+        // ATEM Mini topology, modified to have a stinger
+        let topology = Atom::read(&mut Cursor::new(hex::decode(
+            "002400005f746f70010e0101000100000401010000000001000001000000010101000000",
+        )?))?;
+        state.update_state(&[topology])?;
+        assert_eq!(1, state.topology.stingers);
+        assert_eq!(
+            Some(&TransitionStyle::Stinger),
+            state.supported_transition_styles(0).last()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn dsk_on_air() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.get_dsk_on_air(0));
+
+        let updated = state.update_state(&[
+            Atom::new(DownstreamKeyerStatus {
+                keyer: 0,
+                on_air: true,
+                ..Default::default()
+            }),
+            Atom::new(DownstreamKeyerStatus {
+                keyer: 1,
+                on_air: false,
+                ..Default::default()
+            }),
+        ])?;
+        assert!(updated.contains(StateUpdate::DSK_ON_AIR));
+        assert_eq!(Some(true), state.get_dsk_on_air(0));
+        assert_eq!(Some(false), state.get_dsk_on_air(1));
+        assert_eq!(None, state.get_dsk_on_air(2));
+        Ok(())
+    }
+
+    #[test]
+    fn aux_source() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.get_aux_source(0));
+
+        let updated = state.update_state(&[Atom::new(AuxSource {
+            aux: 0,
+            video_source: VideoSource::Input2,
+        })])?;
+        assert!(updated.contains(StateUpdate::AUX_SOURCE));
+        assert_eq!(Some(VideoSource::Input2), state.get_aux_source(0));
+        assert_eq!(None, state.get_aux_source(1));
+        Ok(())
+    }
+
+    #[test]
+    fn super_source_box() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.get_super_source_box(0, 0));
+
+        let params = SuperSourceBoxParams {
+            enabled: true,
+            video_source: VideoSource::Input4,
+            x: -2400,
+            y: 1350,
+            size: 500,
+            ..Default::default()
+        };
+        let updated = state.update_state(&[Atom::new(SuperSourceBoxProperties {
+            super_source: 0,
+            box_id: 2,
+            params,
+        })])?;
+        assert!(updated.contains(StateUpdate::SUPER_SOURCE));
+        assert_eq!(Some(params), state.get_super_source_box(0, 2));
+        assert_eq!(None, state.get_super_source_box(0, 1));
+        assert_eq!(None, state.get_super_source_box(1, 2));
+        Ok(())
+    }
+
+    #[test]
+    fn hyperdeck() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.get_hyperdeck_connection_state(1));
+        assert_eq!(None, state.get_hyperdeck_transport_state(1));
+        assert_eq!(None, state.get_hyperdeck_current_clip(1));
+
+        let updated = state.update_state(&[
+            Atom::new(HyperDeckStatus {
+                hyperdeck_id: 1,
+                connection_state: HyperDeckConnectionState::Connected,
+                current_clip_id: 7,
+                ..Default::default()
+            }),
+            Atom::new(HyperDeckClipPlayStatus {
+                hyperdeck_id: 1,
+                transport_state: HyperDeckTransportState::Record,
+                ..Default::default()
+            }),
+        ])?;
+        assert!(updated.contains(StateUpdate::HYPERDECK));
+        assert_eq!(
+            Some(HyperDeckConnectionState::Connected),
+            state.get_hyperdeck_connection_state(1)
+        );
+        assert_eq!(
+            Some(HyperDeckTransportState::Record),
+            state.get_hyperdeck_transport_state(1)
+        );
+        assert_eq!(Some(7), state.get_hyperdeck_current_clip(1));
+        assert_eq!(None, state.get_hyperdeck_connection_state(0));
+        Ok(())
+    }
+
+    #[test]
+    fn dsk_tie() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.get_dsk_tie(0));
+
+        let updated = state.update_state(&[Atom::new(DownstreamKeyerProperties {
+            keyer: 1,
+            tie: true,
+            ..Default::default()
+        })])?;
+        assert!(updated.contains(StateUpdate::DSK_PROPERTIES));
+        assert_eq!(None, state.get_dsk_tie(0));
+        assert_eq!(Some(true), state.get_dsk_tie(1));
+        Ok(())
+    }
+
+    #[test]
+    fn audio_mixer_input() -> Result {
+        let mut state = AtemState::default();
+        let amip = Atom::read(&mut Cursor::new(hex::decode(
+            "00180000414d4950000100000000000202004027ec780000",
+        )?))?;
+        let updated = state.update_state(&[amip])?;
+        assert!(updated.contains(StateUpdate::AUDIO_MIXER_INPUT));
+
+        let amip = state.audio_mixer_inputs[&1];
+        assert_eq!(AudioMixerInputState::AudioFollowsVideo, amip.state);
+        assert_eq!(0x4027, amip.gain);
+        assert_eq!(-5000, amip.balance);
+        assert!(!state.audio_mixer_inputs.contains_key(&2));
+        Ok(())
+    }
+
+    #[test]
+    fn macros() -> Result {
+        let mut state = AtemState::default();
+        let updated = state.update_state(&[
+            Atom::new(MacroProperties {
+                index: 0,
+                is_used: true,
+                name: "Opening".to_string(),
+                ..Default::default()
+            }),
+            Atom::new(MacroProperties {
+                index: 1,
+                ..Default::default()
+            }),
+            Atom::new(MacroRecordStatus {
+                is_recording: true,
+                index: 1,
+            }),
+        ])?;
+        assert!(updated.contains(StateUpdate::MACRO));
+        assert_eq!("Opening", state.macros[&0].name);
+        assert!(!state.macros[&1].is_used);
+        assert_eq!(Some(1), state.macro_recording);
+
+        state.update_state(&[Atom::new(MacroRecordStatus {
+            is_recording: false,
+            index: NO_MACRO,
+        })])?;
+        assert_eq!(None, state.macro_recording);
+        Ok(())
+    }
+
+    #[test]
+    fn recording_status() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.recording_status);
+
+        // Recording running
+        let rtms = Atom::read(&mut Cursor::new(hex::decode(
+            "0010000052544d5300030000000a1a5a",
+        )?))?;
+        let updated = state.update_state(&[rtms])?;
+        assert_eq!(StateUpdate::RECORDING, updated);
+        let status = state.recording_status.as_ref().expect("recording status");
+        assert!(status.status.recording());
+        assert!(!status.status.media_full());
+        assert_eq!(0x0a1a5a, status.total_recording_time_available);
+
+        // Disk full
+        state.update_state(&[Atom::new(RecordToMediaStatus {
+            status: RecordStatus::new()
+                .with_has_media(true)
+                .with_media_full(true),
+            total_recording_time_available: 0,
+        })])?;
+        let status = state.recording_status.as_ref().expect("recording status");
+        assert!(!status.status.recording());
+        assert!(status.status.media_full());
+        Ok(())
+    }
+
+    #[test]
+    fn streaming_status() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.streaming_status);
+        assert_eq!(None, state.streaming_statistics);
+
+        let updated = state.update_state(&[Atom::new(StreamRtmpStatus {
+            status: StreamingStatus::Connecting,
+            error: 0,
+        })])?;
+        assert_eq!(StateUpdate::STREAMING, updated);
+        assert_eq!(
+            Some(StreamingStatus::Connecting),
+            state.streaming_status.map(|s| s.status)
+        );
+
+        let updated = state.update_state(&[
+            Atom::new(StreamRtmpStatus {
+                status: StreamingStatus::Streaming,
+                error: 0,
+            }),
+            Atom::new(StreamRtmpStatistics {
+                encoding_bitrate: 5_000_000,
+                cache_used: 0,
+            }),
+        ])?;
+        assert_eq!(StateUpdate::STREAMING, updated);
+        assert_eq!(
+            Some(StreamingStatus::Streaming),
+            state.streaming_status.map(|s| s.status)
+        );
+        assert_eq!(
+            Some(5_000_000),
+            state.streaming_statistics.map(|s| s.encoding_bitrate)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn media_player_audio() -> Result {
+        let mut state = AtemState::default();
+        let updated = state.update_state(&[
+            Atom::new(MediaPlayerAudioEntry {
+                index: 1,
+                is_used: true,
+                md5: [0xaa; 16],
+                name: "Intro".to_string(),
+            }),
+            // Out of range for the state's index type
+            Atom::new(MediaPlayerAudioEntry {
+                index: 0x100,
+                ..Default::default()
+            }),
+        ])?;
+        assert_eq!(StateUpdate::MEDIA_PLAYER_AUDIO, updated);
+        assert_eq!(1, state.media_player_audio.len());
+        assert_eq!("Intro", state.media_player_audio[&1].name);
+        Ok(())
+    }
+
+    #[test]
+    fn warning_message() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.warning_message());
+
+        let updated = state.update_state(&[Atom::new(WarningMessage {
+            message: "Fan failure".to_string(),
+        })])?;
+        assert_eq!(StateUpdate::WARNING, updated);
+        assert_eq!(Some("Fan failure"), state.warning_message());
+
+        // An empty message clears the warning
+        let updated = state.update_state(&[Atom::new(WarningMessage::default())])?;
+        assert_eq!(StateUpdate::WARNING, updated);
+        assert_eq!(None, state.warning_message());
+        Ok(())
+    }
+
+    #[test]
+    fn power_status() -> Result {
+        let mut state = AtemState::default();
+        assert!(state.power_supplies().is_empty());
+
+        let updated = state.update_state(&[Atom::new(PowerStatus {
+            supplies: PowerSupplies::new().with_supply1(true),
+        })])?;
+        assert_eq!(StateUpdate::POWER_STATUS, updated);
+        assert_eq!(&[true, false], state.power_supplies());
+        Ok(())
+    }
+
+    #[test]
+    fn talkback() -> Result {
+        let mut state = AtemState::default();
+        assert!(!state.supports(Capability::Talkback));
+
+        let updated = state.update_state(&[
+            Atom::new(AudioTalkbackMixerProperties {
+                channel: 0,
+                mute_sdi: false,
+            }),
+            Atom::new(AudioTalkbackMixerProperties {
+                channel: 1,
+                mute_sdi: true,
+            }),
+        ])?;
+        assert_eq!(StateUpdate::TALKBACK, updated);
+        assert!(state.supports(Capability::Talkback));
+        assert_eq!(2, state.talkback_channels.len());
+        assert!(!state.talkback_channels[&0].mute_sdi);
+        assert!(state.talkback_channels[&1].mute_sdi);
+        Ok(())
+    }
+
+    #[test]
+    fn transition_settings() -> Result {
+        let mut state = AtemState::default();
+        let trss = Atom::read(&mut Cursor::new(hex::decode(
+            "00100000547253530102030003000000",
+        )?))?;
+        let updated = state.update_state(&[trss])?;
+        assert!(updated.contains(StateUpdate::TRANSITION_SETTINGS));
+
+        let trss = state.transition_settings[&1];
+        assert_eq!(TransitionStyle::Wipe, trss.style);
+        assert!(trss.selection.background());
+        assert!(trss.selection.key1());
+        assert!(!trss.selection.key2());
+        assert!(!state.transition_settings.contains_key(&0));
+        Ok(())
+    }
+
+    #[test]
+    fn estimated_transition_remaining() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.estimated_transition_remaining(0));
+
+        // 50 frame transition at 1080p50, half complete
+        state.video_mode = VideoMode::Fhd1080p50;
+        state.update_state(&[Atom::new(TransitionPosition {
+            me: 0,
+            in_progress: true,
+            frames_remaining: 25,
+            position: 5000,
+        })])?;
+        assert_eq!(
+            Some(Duration::from_millis(500)),
+            state.estimated_transition_remaining(0)
+        );
+        assert_eq!(None, state.estimated_transition_remaining(1));
+
+        // Same transition at 1080i50 has frames twice as long
+        state.video_mode = VideoMode::Fhd1080i50;
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            state.estimated_transition_remaining(0)
+        );
+
+        // Unknown video mode
+        state.video_mode = VideoMode::Unknown;
+        assert_eq!(None, state.estimated_transition_remaining(0));
+
+        // Transition complete
+        state.video_mode = VideoMode::Fhd1080p50;
+        state.update_state(&[Atom::new(TransitionPosition {
+            me: 0,
+            in_progress: false,
+            frames_remaining: 0,
+            position: 0,
+        })])?;
+        assert_eq!(None, state.estimated_transition_remaining(0));
+        Ok(())
+    }
+
+    #[test]
+    fn transition_preview() -> Result {
+        let mut state = AtemState::default();
+        let updated = state.update_state(&[Atom::new(TransitionPreview {
+            me: 0,
+            enabled: true,
+        })])?;
+        assert!(updated.contains(StateUpdate::TRANSITION_PREVIEW));
+        assert_eq!(Some(&true), state.transition_preview.get(&0));
+        assert_eq!(None, state.transition_preview.get(&1));
+
+        state.update_state(&[Atom::new(TransitionPreview {
+            me: 0,
+            enabled: false,
+        })])?;
+        assert_eq!(Some(&false), state.transition_preview.get(&0));
+        Ok(())
+    }
+
+    #[test]
+    fn next_transition_preview_active() -> Result {
+        let mut state = AtemState::default();
+        state.update_state(&[Atom::new(TransitionPreview {
+            me: 0,
+            enabled: true,
+        })])?;
+        // No transition settings reported yet
+        assert!(!state.next_transition_preview_active(0));
+
+        state.update_state(&[Atom::new(TransitionSettings {
+            me: 0,
+            selection: TransitionSelection::new().with_background(true),
+            ..Default::default()
+        })])?;
+        assert!(state.next_transition_preview_active(0));
+        assert!(!state.next_transition_preview_active(1));
+
+        // No layers selected
+        state.update_state(&[Atom::new(TransitionSettings {
+            me: 0,
+            ..Default::default()
+        })])?;
+        assert!(!state.next_transition_preview_active(0));
+
+        // Only a keyer selected, but preview disabled
+        state.update_state(&[
+            Atom::new(TransitionSettings {
+                me: 0,
+                selection: TransitionSelection::new().with_key1(true),
+                ..Default::default()
+            }),
+            Atom::new(TransitionPreview {
+                me: 0,
+                enabled: false,
+            }),
+        ])?;
+        assert!(!state.next_transition_preview_active(0));
+        Ok(())
+    }
+
+    #[test]
+    fn transition_params() -> Result {
+        let mut state = AtemState::default();
+        let wipe = WipeTransitionParams {
+            rate: 25,
+            pattern: WipePattern::DiamondIris,
+            border_width: 250,
+            x: 5000,
+            y: 5000,
+            ..Default::default()
+        };
+        let updated = state.update_state(&[Atom::new(TransitionWipeProperties {
+            me: 1,
+            params: wipe,
+        })])?;
+        assert_eq!(StateUpdate::TRANSITION_WIPE, updated);
+        assert_eq!(Some(&wipe), state.transition_wipe_params.get(&1));
+        assert_eq!(None, state.transition_wipe_params.get(&0));
+
+        let dve = DVETransitionParams {
+            rate: 30,
+            style: DVETransitionStyle::SqueezeLeft,
+            clip: 500,
+            gain: 700,
+            ..Default::default()
+        };
+        let updated =
+            state.update_state(&[Atom::new(TransitionDVEProperties { me: 0, params: dve })])?;
+        assert_eq!(StateUpdate::TRANSITION_DVE, updated);
+        assert_eq!(Some(&dve), state.transition_dve_params.get(&0));
+        assert_eq!(None, state.transition_dve_params.get(&1));
+        Ok(())
+    }
+
+    #[test]
+    fn get_tally() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(TallyFlags::default(), state.get_tally(VideoSource::Input1));
+
+        // Input1 on program, Input2 on preview
+        let tlsr = Atom::read(&mut Cursor::new(hex::decode(
+            "00100000546c53720002000101000202",
+        )?))?;
+        let updated = state.update_state(&[tlsr])?;
+        assert!(updated.contains(StateUpdate::TALLY_BY_SOURCE));
+
+        let tally = state.get_tally(VideoSource::Input1);
+        assert!(tally.program());
+        assert!(!tally.preview());
+
+        let tally = state.get_tally(VideoSource::Input2);
+        assert!(!tally.program());
+        assert!(tally.preview());
+
+        assert_eq!(TallyFlags::default(), state.get_tally(VideoSource::Input3));
+
+        let mut tally: Vec<_> = state
+            .iter_tally()
+            .map(|(source, tally)| (source, tally.program(), tally.preview()))
+            .collect();
+        tally.sort_by_key(|(source, _, _)| *source as u16);
+        assert_eq!(
+            vec![
+                (VideoSource::Input1, true, false),
+                (VideoSource::Input2, false, true)
+            ],
+            tally
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tally_source_count_mismatch() -> Result {
+        let mut state = AtemState::default();
+
+        // Input1 on program, Input2 on preview, Input3 and Input4 off
+        let tlsr = Atom::read(&mut Cursor::new(hex::decode(
+            "00160000546c5372000400010100020200030000040000",
+        )?))?;
+        state.update_state(&[tlsr])?;
+        assert_eq!(4, state.iter_tally().count());
+
+        // A later TlSr with fewer sources, and a duplicate entry for Input3
+        let tlsr = Atom::read(&mut Cursor::new(hex::decode(
+            "00130000546c537200030003020001000003010000",
+        )?))?;
+        let updated = state.update_state(&[tlsr])?;
+        assert!(updated.contains(StateUpdate::TALLY_BY_SOURCE));
+
+        let expected = HashMap::from([
+            (VideoSource::Input1, TallyFlags::new()),
+            (VideoSource::Input3, TallyFlags::new().with_program(true)),
+        ]);
+        assert_eq!(expected, state.tally_by_source);
+        assert_eq!(TallyFlags::default(), state.get_tally(VideoSource::Input2));
+        Ok(())
+    }
+
+    #[test]
+    fn iter_inputs() -> Result {
+        let mut state = AtemState::default();
+        assert_eq!(0, state.iter_inputs().count());
+
+        let inpr = Atom::read(&mut Cursor::new(hex::decode(
+            "002c0000496e5072000143616d657261203100000000000000000000000043414d3101000002000200001101",
+        )?))?;
+        state.update_state(&[inpr])?;
+
+        let inputs: Vec<_> = state.iter_inputs().collect();
+        assert_eq!(1, inputs.len());
+        let (source, inpr) = inputs[0];
+        assert_eq!(VideoSource::Input1, source);
+        assert_eq!("Camera 1", inpr.get_long_name()?);
+        assert_eq!("CAM1", inpr.get_short_name()?);
+        Ok(())
+    }
+
+    #[test]
+    fn validate_source() -> Result {
+        // Input1, which is only available on ME 1, auxiliary and key sources
+        let base = InputProperties::read_be(&mut Cursor::new(hex::decode(
+            "00014c6170746f702048444d490000000000000000004c41500000000002000200001101",
+        )?))?;
+
+        // Report every known video source, with varying availability.
+        let inputs: Vec<InputProperties> = (0..=u16::MAX)
+            .filter_map(|id| {
+                let mut inpr = base.clone();
+                inpr.video_source =
+                    VideoSource::read_be(&mut Cursor::new(id.to_be_bytes())).ok()?;
+                inpr.availability3 = id.wrapping_mul(0x2f1d) & 0x1fff;
+                Some(inpr)
+            })
+            .collect();
+        assert!(inputs.len() > 50);
+
+        let mut state = AtemState::default();
+        let updated =
+            state.update_state(&inputs.iter().cloned().map(Atom::new).collect::<Vec<_>>())?;
+        assert!(updated.contains(StateUpdate::INPUT_PROPERTIES));
+
+        // Compare against a linear scan of all inputs.
+        let check = |state: &AtemState| {
+            for inpr in &inputs {
+                for bus in SourceBus::ALL {
+                    let expected = state
+                        .input_properties
+                        .values()
+                        .any(|i| i.video_source == inpr.video_source && bus.accepts(i));
+                    assert_eq!(
+                        expected,
+                        state.validate_source(inpr.video_source, bus),
+                        "{:?} on {bus:?}",
+                        inpr.video_source,
+                    );
+                }
+            }
+        };
+        check(&state);
+
+        // Input1 is no longer available anywhere
+        let mut inpr = base.clone();
+        inpr.availability3 = 0;
+        state.update_state(&[Atom::new(inpr)])?;
+        for bus in SourceBus::ALL {
+            assert!(!state.validate_source(VideoSource::Input1, bus));
+        }
+        check(&state);
+
+        // Input1 is available on ME 2 only
+        let mut inpr = base;
+        inpr.availability3 = 0x0002;
+        state.update_state(&[Atom::new(inpr)])?;
+        assert!(!state.validate_source(VideoSource::Input1, SourceBus::Me(0)));
+        assert!(state.validate_source(VideoSource::Input1, SourceBus::Me(1)));
+        check(&state);
+        Ok(())
+    }
+
+    #[test]
+    fn routable_sources() -> Result {
+        let Payload::InputProperties(base) = Atom::read(&mut Cursor::new(hex::decode(
+            "002c0000496e5072000343616d657261203300000000000000000000000043414d3301000002000200001101",
+        )?))?
+        .payload
+        else {
+            panic!("wrong command type");
+        };
+        let mut state = AtemState::default();
+        assert!(state.routable_sources(0).is_empty());
+
+        let inputs: Vec<Atom> = [
+            (VideoSource::Input3, 0x1101),
+            (VideoSource::Input1, 0x1103),
+            (VideoSource::Black, 0x0002),
+        ]
+        .into_iter()
+        .map(|(video_source, availability3)| {
+            let mut inpr = base.clone();
+            inpr.video_source = video_source;
+            inpr.availability3 = availability3;
+            Atom::new(inpr)
+        })
+        .collect();
+        state.update_state(&inputs)?;
+
+        assert_eq!(
+            vec![VideoSource::Input1, VideoSource::Input3],
+            state.routable_sources(0)
+        );
+        assert_eq!(
+            vec![VideoSource::Black, VideoSource::Input1],
+            state.routable_sources(1)
+        );
+        assert!(state.routable_sources(2).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn me_output_sources() -> Result {
+        let Payload::InputProperties(base) = Atom::read(&mut Cursor::new(hex::decode(
+            "002c0000496e5072000343616d657261203300000000000000000000000043414d3301000002000200001101",
+        )?))?
+        .payload
+        else {
+            panic!("wrong command type");
+        };
+        let mut state = AtemState::default();
+        assert!(state.me_output_sources().is_empty());
+
+        // 2 ME switcher: each ME's outputs are available on the other ME
+        let inputs: Vec<Atom> = [
+            (VideoSource::Input1, PortType::External, 0x1103),
+            (VideoSource::ME2Prev, PortType::MEOutput, 0x0101),
+            (VideoSource::ME1Prog, PortType::MEOutput, 0x0102),
+            (VideoSource::ME2Prog, PortType::MEOutput, 0x0101),
+            (VideoSource::ME1Prev, PortType::MEOutput, 0x0102),
+        ]
+        .into_iter()
+        .map(|(video_source, port_type, availability3)| {
+            let mut inpr = base.clone();
+            inpr.video_source = video_source;
+            inpr.port_type = port_type;
+            inpr.availability3 = availability3;
+            Atom::new(inpr)
+        })
+        .collect();
+        state.update_state(&inputs)?;
+
+        assert_eq!(
+            vec![
+                VideoSource::ME1Prog,
+                VideoSource::ME1Prev,
+                VideoSource::ME2Prog,
+                VideoSource::ME2Prev,
+            ],
+            state.me_output_sources()
+        );
+        assert!(state.validate_source(VideoSource::ME2Prog, SourceBus::Me(0)));
+        assert!(state.validate_source(VideoSource::ME1Prog, SourceBus::Me(1)));
+        assert!(!state.validate_source(VideoSource::ME1Prog, SourceBus::Me(0)));
+        Ok(())
+    }
+
+    #[test]
+    fn me_capabilities_atem_mini() -> Result {
+        let mut state = atem_mini("ATEM Mini", 0xd)?;
+        assert_eq!(
+            Some(MixEffectBlockCapabilities::default()),
+            state.get_me_capabilities(0)
+        );
+
+        let mec = Atom::read(&mut Cursor::new(hex::decode("000c00005f4d654300010000")?))?;
+        let updated = state.update_state(&[mec])?;
+        assert!(updated.contains(StateUpdate::MIX_EFFECT_BLOCK_CAPABILITIES));
+
+        // 1 ME, with 1 upstream keyer
+        assert_eq!(1, state.topology.mes);
+        assert_eq!(Some(1), state.get_me_capabilities(0).map(|mec| mec.keyers));
+        assert_eq!(None, state.get_me_capabilities(1));
+        Ok(())
+    }
+
+    #[test]
+    fn capabilities_atem_mini() -> Result {
+        let state = atem_mini("ATEM Mini", 0xd)?;
+
+        assert!(!state.supports(Capability::Streaming));
+        assert!(!state.supports(Capability::Recording));
+        assert!(!state.supports(Capability::IsoRecording));
+        assert!(state.supports(Capability::Fairlight));
+        assert!(!state.supports(Capability::AudioMixer));
+        assert!(!state.supports(Capability::SuperSource));
+        assert!(!state.supports(Capability::MultipleMEs));
+        assert!(state.supports(Capability::CameraControl));
+        assert!(state.supports(Capability::StillCapture));
+        assert!(state.supports(Capability::MediaPlayers));
+        assert!(!state.supports(Capability::Multiview));
+        assert!(state.supports(Capability::AuxOutputs));
+        assert!(state.supports(Capability::DownstreamKeyers));
+        assert!(!state.supports(Capability::RemoteSource));
+        Ok(())
+    }
+
+    #[test]
+    fn capabilities_atem_mini_pro() -> Result {
+        let state = atem_mini("ATEM Mini Pro", 0xe)?;
+
+        assert!(state.supports(Capability::Streaming));
+        assert!(state.supports(Capability::Recording));
+        assert!(!state.supports(Capability::IsoRecording));
+        assert!(state.supports(Capability::Fairlight));
+        assert!(!state.supports(Capability::AudioMixer));
+        assert!(!state.supports(Capability::SuperSource));
+        assert!(!state.supports(Capability::MultipleMEs));
+        assert!(state.supports(Capability::CameraControl));
+        assert!(state.supports(Capability::StillCapture));
+        assert!(!state.supports(Capability::RemoteSource));
+        Ok(())
+    }
+
+    /// Atoms sent by the switcher in response to a storage lock request
+    /// should be handled without warnings.
+    #[test]
+    fn lock_sequence() -> Result {
+        let mut state = AtemState::default();
+        let atoms = [
+            "000c00004c4b535400000100",
+            "000c00004361704101000000",
+            "000c00004343535400010000",
+        ]
+        .into_iter()
+        .map(|cmd| Ok(Atom::read(&mut Cursor::new(hex::decode(cmd)?))?))
+        .collect::<Result<Vec<_>>>()?;
+
+        let updated = state.update_state(&atoms)?;
+        assert!(!updated.intersects(StateUpdate::UNSUPPORTED_COMMAND));
+        assert!(updated.intersects(StateUpdate::STILL_CAPTURE_AVAILABILITY));
+        assert!(updated.intersects(StateUpdate::UNKNOWN_CCST));
+        assert_eq!(0, state.unsupported_command_count);
+        assert!(state.still_capture_available);
+        assert_eq!(
+            Some(UnknownCCST {
+                payload: vec![0, 1, 0, 0]
+            }),
+            state.unknown_ccst
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn suggested_default_video_mode() {
+        let mut state = AtemState::default();
+        assert_eq!(None, state.suggested_default_video_mode());
+
+        state.supported_video_modes = vec![
+            VideoMode::Pal625i50,
+            VideoMode::PalAnamorphic625i50,
+            VideoMode::Hd720p50,
+            VideoMode::Fhd1080i50,
+            VideoMode::Fhd1080p25,
+            VideoMode::Fhd1080p50,
+            VideoMode::Uhd4Kp25,
+        ];
+        assert_eq!(
+            Some(VideoMode::Fhd1080p50),
+            state.suggested_default_video_mode()
+        );
+
+        state.supported_video_modes = vec![
+            VideoMode::Ntsc525i59_94,
+            VideoMode::Hd720p59_94,
+            VideoMode::Fhd1080i59_94,
+        ];
+        assert_eq!(
+            Some(VideoMode::Hd720p59_94),
+            state.suggested_default_video_mode()
+        );
+
+        // Every frame rate is supported
+        state.supported_video_modes = vec![
+            VideoMode::Pal625i50,
+            VideoMode::Ntsc525i59_94,
+            VideoMode::Hd720p50,
+            VideoMode::Hd720p59_94,
+            VideoMode::Fhd1080i50,
+            VideoMode::Fhd1080i59_94,
+            VideoMode::Fhd1080p23_98,
+            VideoMode::Fhd1080p24,
+            VideoMode::Fhd1080p25,
+            VideoMode::Fhd1080p29_97,
+            VideoMode::Fhd1080p30,
+            VideoMode::Fhd1080p50,
+            VideoMode::Fhd1080p59_94,
+            VideoMode::Fhd1080p60,
+        ];
+        assert_eq!(
+            Some(VideoMode::Fhd1080p60),
+            state.suggested_default_video_mode()
+        );
+
+        // ...but the switcher is in a 50 Hz mode
+        state.video_mode = VideoMode::Pal625i50;
+        assert_eq!(
+            Some(VideoMode::Fhd1080p50),
+            state.suggested_default_video_mode()
+        );
+
+        // ...or a 59.94 Hz mode
+        state.video_mode = VideoMode::Fhd1080i59_94;
+        assert_eq!(
+            Some(VideoMode::Fhd1080p59_94),
+            state.suggested_default_video_mode()
+        );
+    }
+
+    #[test]
+    fn still_md5() -> Result {
+        let mut state = AtemState::default();
+        let mpfe = Atom::read(&mut Cursor::new(hex::decode(
+            "003000004d5066650000000501b1a6194d4f52b449fd519870a63cb3c200000e7472616d2d31303830702e726c650000",
+        )?))?;
+        let empty = Atom::read(&mut Cursor::new(hex::decode(
+            "002000004d506665000000020000000000000000000000000000000000000000",
+        )?))?;
+        assert_eq!(None, state.still_md5(5));
+
+        state.update_state(&[mpfe, empty])?;
+        assert_eq!(
+            Some(hex::decode("b1a6194d4f52b449fd519870a63cb3c2")?),
+            state.still_md5(5).map(Vec::from)
+        );
+
+        // Empty and unknown slots
+        assert_eq!(None, state.still_md5(2));
+        assert_eq!(None, state.still_md5(3));
+        Ok(())
+    }
+
+    #[test]
+    fn many_mes() -> Result {
+        // More MEs than any current hardware
+        const MES: u8 = 9;
+        let mut state = AtemState::default();
+        assert_eq!(None, state.get_program_source(0));
+        assert!(state.get_program_sources().is_empty());
+
+        // ATEM Mini topology, with the ME count changed
+        let topology = Atom::read(&mut Cursor::new(hex::decode(
+            "002400005f746f70090e0101000100000401000000000001000001000000010101000000",
+        )?))?;
+        state.update_state(&[topology])?;
+        assert_eq!(MES, state.topology.mes);
+
+        let sources = [
+            VideoSource::Input1,
+            VideoSource::Input2,
+            VideoSource::Input3,
+            VideoSource::Input4,
+            VideoSource::Input5,
+            VideoSource::Input6,
+            VideoSource::Input7,
+            VideoSource::Input8,
+            VideoSource::Input9,
+        ];
+        let mut atoms = Vec::new();
+        for (me, video_source) in (0..MES).zip(sources) {
+            atoms.push(Atom::new(ProgramInput { me, video_source }));
+            atoms.push(Atom::new(PreviewInput {
+                me,
+                video_source: VideoSource::Black,
+                preview_input_live: false,
+            }));
+        }
+        let updated = state.update_state(&atoms)?;
+        assert!(updated.contains(StateUpdate::PROGRAM_SOURCE | StateUpdate::PREVIEW_SOURCE));
+
+        assert_eq!(Some(VideoSource::Input9), state.get_program_source(MES - 1));
+        assert_eq!(Some(VideoSource::Black), state.get_preview_source(MES - 1));
+        assert_eq!(None, state.get_program_source(MES));
+        assert_eq!(usize::from(MES), state.get_program_sources().len());
+        assert_eq!(usize::from(MES), state.get_preview_sources().len());
+        assert_eq!(usize::from(MES), state.get_fade_to_black_rates().len());
+        assert_eq!(usize::from(MES), state.get_fade_to_black_statuses().len());
+        assert!(state.get_me_capabilities(MES - 1).is_some());
+        assert!(format!("{state:?}").contains("Input9"));
+
+        // Events for MEs outside of the topology are ignored
+        let updated = state.update_state(&[Atom::new(ProgramInput {
+            me: MES,
+            video_source: VideoSource::Black,
+        })])?;
+        assert!(updated.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn multiview_overlays() -> Result {
+        let mut state = AtemState::default();
+        let updated = state.update_state(&[
+            Atom::new(MultiViewSafeArea {
+                multiview: 0,
+                window: 1,
+                enabled: true,
+            }),
+            Atom::new(MultiViewVuMeterEnabled {
+                multiview: 0,
+                window: 2,
+                enabled: true,
+            }),
+        ])?;
+        assert!(updated.contains(StateUpdate::MULTIVIEW_OVERLAYS));
+        assert_eq!(
+            Some(&MultiViewOverlays {
+                safe_area: true,
+                vu_meter: false,
+            }),
+            state.multiview_overlays.get(&(0, 1))
+        );
+        assert_eq!(
+            Some(&MultiViewOverlays {
+                safe_area: false,
+                vu_meter: true,
+            }),
+            state.multiview_overlays.get(&(0, 2))
+        );
+        Ok(())
+    }
+}
@@ -9,24 +9,49 @@
 //! * `_switcher_ctrl._udp`: UDP BURP protocol
 //! * `_blackmagic._tcp`: TCP config protocol
 use crate::{protocol::AtemPacket, Error, Result};
-use binrw::{BinRead, BinWrite};
+use binrw::BinWrite;
 use std::{
     io::Cursor,
-    net::{Ipv4Addr, SocketAddrV4},
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
 };
-use tokio::net::{ToSocketAddrs, UdpSocket};
+use tokio::net::{lookup_host, ToSocketAddrs, UdpSocket};
 
 pub struct AtemUdpChannel {
     sock: Option<UdpSocket>,
+    lossy_utf8: bool,
 }
 
 impl AtemUdpChannel {
+    /// The UDP port switchers listen on.
+    pub const DEFAULT_PORT: u16 = 9910;
+
     pub fn new() -> Self {
-        Self { sock: None }
+        Self {
+            sock: None,
+            lossy_utf8: false,
+        }
+    }
+
+    /// Sets whether names in received atoms are
+    /// [decoded lossily][crate::protocol::atom::Atom#lossy-utf-8-decoding].
+    pub fn set_lossy_utf8(&mut self, lossy_utf8: bool) {
+        self.lossy_utf8 = lossy_utf8;
     }
 
+    /// Connects to a switcher at `addr`.
+    ///
+    /// The local socket is bound to the same address family (IPv4 or IPv6) as
+    /// the first address `addr` resolves to.
     pub async fn connect<A: ToSocketAddrs>(&mut self, addr: A) -> Result {
-        let sock = UdpSocket::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0)).await?;
+        let addr = lookup_host(addr)
+            .await?
+            .next()
+            .ok_or(Error::UnknownParameter)?;
+        let bind_addr = match addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let sock = UdpSocket::bind(bind_addr).await?;
         sock.connect(addr).await?;
         self.sock = Some(sock);
         Ok(())
@@ -52,12 +77,47 @@ impl AtemUdpChannel {
         Ok(sock.into_std()?)
     }
 
+    /// Receives a packet from the switcher.
+    ///
+    /// Atoms which can't be parsed are [recorded as unknown][AtemPacket::read_lenient],
+    /// so that they don't cause the whole packet to be dropped.
     pub async fn recv(&self) -> Result<AtemPacket> {
         let sock = self.sock.as_ref().ok_or(Error::ChannelUnavailable)?;
         let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
         let l = sock.recv(&mut b).await?;
         let b = &b[..l];
 
-        Ok(AtemPacket::read(&mut Cursor::new(b))?)
+        Ok(AtemPacket::read_lenient(
+            &mut Cursor::new(b),
+            self.lossy_utf8,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::protocol::AtemPacketFlags;
+    use binrw::BinRead;
+
+    #[tokio::test]
+    async fn ipv6() -> Result {
+        let switcher = UdpSocket::bind((Ipv6Addr::LOCALHOST, 0)).await?;
+        let addr = switcher.local_addr()?;
+
+        let mut channel = AtemUdpChannel::new();
+        channel.connect(addr).await?;
+        let sock = channel.sock.as_ref().expect("socket should be connected");
+        assert!(sock.local_addr()?.is_ipv6());
+        assert_eq!(addr, sock.peer_addr()?);
+
+        // Packets should make it to the switcher
+        let pkt = AtemPacket::new(AtemPacketFlags::new().with_ack(true), 0x1234, 0, 0, 1);
+        channel.send(&pkt).await?;
+        let mut b = [0u8; AtemPacket::MAX_PACKET_LENGTH as usize];
+        let (l, from) = switcher.recv_from(&mut b).await?;
+        assert_eq!(sock.local_addr()?.port(), from.port());
+        assert_eq!(pkt, AtemPacket::read(&mut Cursor::new(&b[..l]))?);
+        Ok(())
     }
 }
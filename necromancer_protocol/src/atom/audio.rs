@@ -1,14 +1,20 @@
-//! # Audio (non-Fairlight); 0/23 atoms
+//! # Audio (non-Fairlight); 3/23 atoms
 //!
-//! ## Unimplemented atoms (23)
+//! ## Implemented atoms
+//!
+//! FourCC | Atom name | Length
+//! ------ | --------- | ------
+//! `AMIP` | [`AudioMixerInputProperties`] | 0x18
+//! `AMMO` | [`AudioMixerMasterOutProperties`] | 0x10
+//! `CAMI` | [`SetAudioMixerInputProperties`] | 0x14
+//!
+//! ## Unimplemented atoms (20)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
 //! `_AMC` | `CapabilitiesAudioMixer` | 0xc
 //! `AMHP` | `AudioMixerHeadphoneOutProperties` | 0x10
-//! `AMIP` | `AudioMixerInputProperties` | 0x18
 //! `AMLv` | `AudioMixerLevels` | (variable)
-//! `AMMO` | `AudioMixerMasterOutProperties` | 0x10
 //! `AMmO` | `AudioMixerMonitorOutProperties` | 0x14
 //! `AMPP` | `AudioMixerProperties` | 0xc
 //! `AMTl` | `AudioMixerTally` | (0xa + (audio_tally_len * 3) bytes)
@@ -19,7 +25,6 @@
 //! `ARSP` | `AudioRoutingSourceProperties` | 0x54
 //! `ARSP` | `AudioRoutingSourceProperties` | 0x58
 //! `CAMH` | `ChangeAudioMixerHeadphoneOutProperties` | 0x14
-//! `CAMI` | `ChangeAudioMixerInputProperties` | 0x14
 //! `CAMM` | `ChangeAudioMixerMasterOutProperties` | 0x10
 //! `CAMm` | `ChangeAudioMixerMonitorOutProperties` | 0x14
 //! `CAMP` | `ChangeAudioMixerProperties` | 0xc
@@ -27,3 +32,297 @@
 //! `MMOP` | `MixMinusOutProperties` | 0x14
 //! `SALN` | `SetAudioMixerLevelsNotification` | 0xc
 //! `RAMP` | `ResetAudioMixerPeakLevels` | 0x10
+
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{bitfield, specifiers::B4};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Classic audio mixer gain value for 0 dB (unity gain).
+///
+/// Classic audio mixer gain fields are linear amplitudes, where `0` is silence
+/// (-&infin; dB), and `65535` is the maximum gain (about +6.02 dB).
+pub const AUDIO_GAIN_UNITY: u16 = 0x8000;
+
+/// Converts a linear classic audio mixer gain value to decibels.
+///
+/// `0` is converted to [`f32::NEG_INFINITY`].
+pub fn audio_gain_to_db(gain: u16) -> f32 {
+    20. * (f32::from(gain) / f32::from(AUDIO_GAIN_UNITY)).log10()
+}
+
+/// Converts decibels to a linear classic audio mixer gain value, rounded to
+/// the nearest step.
+///
+/// Returns `None` if `db` is NaN, or louder than the maximum gain (about
+/// +6.02 dB).
+pub fn audio_gain_from_db(db: f32) -> Option<u16> {
+    let gain = (10f32.powf(db / 20.) * f32::from(AUDIO_GAIN_UNITY)).round();
+    if gain.is_nan() || gain > f32::from(u16::MAX) {
+        return None;
+    }
+    Some(gain as u16)
+}
+
+/// Whether a classic audio mixer input is mixed into the program output.
+#[binrw]
+#[brw(repr = u8)]
+#[derive(Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum AudioMixerInputState {
+    /// Audio input is muted.
+    #[default]
+    Off = 0x00,
+    /// Audio input is always mixed in.
+    On = 0x01,
+    /// Audio input is mixed in when its video source is on program.
+    AudioFollowsVideo = 0x02,
+}
+
+/// `AMIP`: Audio mixer input properties (`AudioMixerInputProperties`)
+///
+/// ## Packet format
+///
+/// * `u16`: audio source ID
+/// * `u8`: source type
+/// * 3 bytes padding
+/// * `u16`: external port type
+/// * `u8`: [state][AudioMixerInputState]
+/// * 1 byte padding
+/// * `u16`: gain, linear ([`AUDIO_GAIN_UNITY`] = 0 dB)
+/// * `i16`: balance, `-10000` (left) to `10000` (right)
+/// * `bool`: supports RCA to XLR
+/// * `bool`: RCA to XLR enabled
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AudioMixerInputProperties {
+    pub source: u16,
+    #[brw(pad_after = 3)]
+    pub source_type: u8,
+    pub port_type: u16,
+    #[brw(pad_after = 1)]
+    pub state: AudioMixerInputState,
+    /// Gain, as a linear value where [`AUDIO_GAIN_UNITY`] is 0 dB.
+    ///
+    /// See [`audio_gain_to_db()`].
+    pub gain: u16,
+    /// Balance, from `-10000` (fully left, shown as `-50`) to `10000` (fully
+    /// right, shown as `50`).
+    pub balance: i16,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub supports_rca_to_xlr: bool,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub rca_to_xlr_enabled: bool,
+}
+
+#[bitfield(bits = 8)]
+#[repr(u8)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u8>::from)]
+#[bw(map = |&x| Into::<u8>::into(x))]
+struct SetAudioMixerInputMask {
+    pub state: bool,
+    pub gain: bool,
+    pub balance: bool,
+    pub rca_to_xlr_enabled: bool,
+    #[skip]
+    __: B4,
+}
+
+/// `CAMI`: Change audio mixer input properties (`ChangeAudioMixerInputProperties`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// ## Packet format
+///
+/// * `u8`: change mask
+/// * 1 byte padding
+/// * `u16`: audio source ID
+/// * `u8`: [state][AudioMixerInputState]
+/// * 1 byte padding
+/// * `u16`: gain, linear ([`AUDIO_GAIN_UNITY`] = 0 dB)
+/// * `i16`: balance, `-10000` (left) to `10000` (right)
+/// * `bool`: RCA to XLR enabled
+/// * 1 byte padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetAudioMixerInputProperties {
+    #[br(temp)]
+    #[bw(calc(
+        SetAudioMixerInputMask::new()
+            .with_state(self.state.is_some())
+            .with_gain(self.gain.is_some())
+            .with_balance(self.balance.is_some())
+            .with_rca_to_xlr_enabled(self.rca_to_xlr_enabled.is_some())
+    ))]
+    #[brw(pad_after = 1)]
+    mask: SetAudioMixerInputMask,
+
+    pub source: u16,
+
+    #[br(map(|v: AudioMixerInputState| mask.state().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    #[brw(pad_after = 1)]
+    pub state: Option<AudioMixerInputState>,
+
+    /// Gain, as a linear value where [`AUDIO_GAIN_UNITY`] is 0 dB.
+    ///
+    /// See [`audio_gain_from_db()`].
+    #[br(map(|v: u16| mask.gain().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub gain: Option<u16>,
+
+    /// Balance, from `-10000` (fully left, shown as `-50`) to `10000` (fully
+    /// right, shown as `50`).
+    #[br(map(|v: i16| mask.balance().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub balance: Option<i16>,
+
+    #[br(map(|v: u8| mask.rca_to_xlr_enabled().then_some(v != 0)))]
+    #[bw(map(|v| u8::from(v.unwrap_or_default())))]
+    #[brw(pad_after = 1)]
+    pub rca_to_xlr_enabled: Option<bool>,
+}
+
+impl SetAudioMixerInputProperties {
+    /// Creates a new command for audio `source`, which changes nothing.
+    pub fn new(source: u16) -> Self {
+        Self {
+            source,
+            ..Default::default()
+        }
+    }
+}
+
+/// `AMMO`: Audio mixer master out properties (`AudioMixerMasterOutProperties`)
+///
+/// ## Packet format
+///
+/// * `u16`: gain, linear ([`AUDIO_GAIN_UNITY`] = 0 dB)
+/// * `i16`: balance, `-10000` (left) to `10000` (right)
+/// * `bool`: follow fade to black
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AudioMixerMasterOutProperties {
+    /// Gain, as a linear value where [`AUDIO_GAIN_UNITY`] is 0 dB.
+    ///
+    /// See [`audio_gain_to_db()`].
+    pub gain: u16,
+    /// Balance, from `-10000` (fully left, shown as `-50`) to `10000` (fully
+    /// right, shown as `50`).
+    pub balance: i16,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    #[brw(pad_after = 3)]
+    pub follow_fade_to_black: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    #[test]
+    fn gain() {
+        assert_eq!(0., audio_gain_to_db(AUDIO_GAIN_UNITY));
+        assert_eq!(f32::NEG_INFINITY, audio_gain_to_db(0));
+        assert!((audio_gain_to_db(0x4027) + 6.).abs() < 0.001);
+        assert!((audio_gain_to_db(u16::MAX) - 6.02).abs() < 0.001);
+
+        assert_eq!(Some(AUDIO_GAIN_UNITY), audio_gain_from_db(0.));
+        assert_eq!(Some(0x4027), audio_gain_from_db(-6.));
+        assert_eq!(Some(0x287a), audio_gain_from_db(-10.));
+        assert_eq!(Some(0xff65), audio_gain_from_db(6.));
+        assert_eq!(Some(0), audio_gain_from_db(f32::NEG_INFINITY));
+        assert_eq!(None, audio_gain_from_db(6.03));
+        assert_eq!(None, audio_gain_from_db(f32::INFINITY));
+        assert_eq!(None, audio_gain_from_db(f32::NAN));
+    }
+
+    #[test]
+    fn audio_mixer_input_properties() -> Result {
+        // Synthetic example, not captured from hardware:
+        // Input 1 (HDMI), audio follows video, -6dB, balance 25 left
+        let cmd = hex::decode("00180000414d4950000100000000000202004027ec780000")?;
+        let atom = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::AudioMixerInputProperties(amip) = &atom.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = AudioMixerInputProperties {
+            source: 1,
+            source_type: 0,
+            port_type: 2,
+            state: AudioMixerInputState::AudioFollowsVideo,
+            gain: 0x4027,
+            balance: -5000,
+            supports_rca_to_xlr: false,
+            rca_to_xlr_enabled: false,
+        };
+        assert_eq!(&expected, amip);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        Atom::new(expected).write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_audio_mixer_input_gain() -> Result {
+        // Synthetic example, not captured from hardware: input 1, 0dB
+        let cmd = hex::decode("0014000043414d49020000010000800000000000")?;
+        let atom = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetAudioMixerInputProperties(cami) = &atom.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = SetAudioMixerInputProperties {
+            gain: Some(AUDIO_GAIN_UNITY),
+            ..SetAudioMixerInputProperties::new(1)
+        };
+        assert_eq!(&expected, cami);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        Atom::new(expected).write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn audio_mixer_master_out_properties() -> Result {
+        // Synthetic example, not captured from hardware: -10dB, balance 5
+        // right, follows fade to black
+        let cmd = hex::decode("00100000414d4d4f287a03e801000000")?;
+        let atom = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::AudioMixerMasterOutProperties(ammo) = &atom.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = AudioMixerMasterOutProperties {
+            gain: 0x287a,
+            balance: 1000,
+            follow_fade_to_black: true,
+        };
+        assert_eq!(&expected, ammo);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        Atom::new(expected).write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
@@ -1,8 +1,97 @@
-//! # Aux; 0/2 atoms
-//!
-//! ## Unimplemented atoms (2)
-//!
-//! FourCC | Atom name | Length
-//! ------ | --------- | ------
-//! `AuxS` | `AuxSource` | 0xc
-//! `CAuS` | `ChangeAuxSource` | 0xc
+//! # Aux; 2/2 atoms
+use crate::structs::VideoSource;
+use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// `AuxS`: auxiliary output source (`AuxSource`)
+///
+/// ## Packet format
+///
+/// * `u8`: auxiliary output
+/// * 1 byte padding
+/// * `u16`: video source
+#[binrw]
+#[brw(big)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AuxSource {
+    #[brw(pad_after = 1)]
+    pub aux: u8,
+    pub video_source: VideoSource,
+}
+
+/// `CAuS`: set auxiliary output source (`ChangeAuxSource`)
+///
+/// ## Packet format
+///
+/// * `u8`: set mask; always `0x01` (video source)
+/// * `u8`: auxiliary output
+/// * `u16`: video source
+#[binrw]
+#[brw(big, magic = 1u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetAuxSource {
+    pub aux: u8,
+    pub video_source: VideoSource,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, AtomPayload, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    #[test]
+    fn aux_source() -> Result<()> {
+        let expected = AuxSource {
+            aux: 0,
+            video_source: VideoSource::Input3,
+        };
+
+        let cmd = hex::decode("000c00004175785300000003")?;
+        let auxs = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::AuxSource(auxs) = auxs.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, auxs);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_aux_source() -> Result<()> {
+        let expected = SetAuxSource {
+            aux: 1,
+            video_source: VideoSource::ME1Prog,
+        };
+
+        let cmd = hex::decode("000c0000434175530101271a")?;
+        let caus = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::SetAuxSource(caus) = caus.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, caus);
+
+        // Payload only, without the atom headers
+        assert_eq!(cmd[8..], expected.to_bytes()?);
+        assert_eq!(expected, SetAuxSource::from_bytes(&cmd[8..])?);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
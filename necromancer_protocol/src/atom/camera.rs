@@ -36,6 +36,8 @@
 use crate::{Error, Result};
 use binrw::binrw;
 use fixed::types::I5F11;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::ops::{Deref, DerefMut};
 
 /// Container type for [CameraParameterValue].
@@ -82,6 +84,7 @@ struct CameraParameterValueContainer {
 /// mapped to [CameraParameterValueContainer].
 #[binrw]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[br(import { typ: u8, len8: u16, len16: u16, len32: u16, len64: u16 })]
 pub enum CameraParameterValue {
     /// Boolean or void type.
@@ -223,7 +226,8 @@ to_camera_parameter_value!(I5F11, I5F11);
 //
 // This is different to a lot of the reverse engineered docs
 #[binrw]
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[brw(big)]
 pub enum CameraParameterID {
     #[brw(magic = 0u8)]
@@ -253,7 +257,8 @@ pub enum CameraParameterID {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum LensParam {
     Focus = 0,
@@ -270,7 +275,8 @@ pub enum LensParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum VideoParam {
     VideoMode = 0,
@@ -292,7 +298,8 @@ pub enum VideoParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum AudioParam {
     MicLevel = 0,
@@ -306,7 +313,8 @@ pub enum AudioParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum OutputParam {
     OverlayEnables = 0,
@@ -317,7 +325,8 @@ pub enum OutputParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum DisplayParam {
     Brightness = 0,
@@ -330,7 +339,8 @@ pub enum DisplayParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 #[allow(clippy::enum_variant_names)]
 pub enum TallyParam {
@@ -341,7 +351,8 @@ pub enum TallyParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum ReferenceParam {
     Source = 0,
@@ -350,7 +361,8 @@ pub enum ReferenceParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum ConfigParam {
     RealTimeClock = 0,
@@ -361,7 +373,8 @@ pub enum ConfigParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum ColourCorrectionParam {
     LiftAdjust = 0,
@@ -376,7 +389,8 @@ pub enum ColourCorrectionParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum MediaParam {
     Codec = 0,
@@ -385,7 +399,8 @@ pub enum MediaParam {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum PtzControlParam {
     PanTiltVelocity = 0,
@@ -401,6 +416,7 @@ pub enum PtzControlParam {
 /// * parameter value
 #[binrw]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[brw(big)]
 pub struct CameraControl {
     pub input: u8,
@@ -421,6 +437,7 @@ pub struct CameraControl {
 /// * value
 #[binrw]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[brw(big)]
 pub struct CameraCommand {
     pub input: u8,
@@ -439,7 +456,7 @@ pub struct CameraCommand {
 mod test {
     use super::*;
     use crate::{
-        atom::{Atom, Payload},
+        atom::{Atom, AtomPayload, Payload},
         packet::AtemPacket,
         Result,
     };
@@ -480,6 +497,21 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn payload_bytes() -> Result<()> {
+        let expected = CameraControl {
+            input: 1,
+            parameter: CameraParameterID::Video(VideoParam::ManualWhiteBalance),
+            value: CameraParameterValue::I16(vec![5600, 0]),
+        };
+        let payload = hex::decode("0101020200000002000000000000000015e0000000000000")?;
+
+        assert_eq!(*b"CCdP", CameraControl::MAGIC);
+        assert_eq!(payload, expected.to_bytes()?);
+        assert_eq!(expected, CameraControl::from_bytes(&payload)?);
+        Ok(())
+    }
+
     #[test]
     fn auto_focus() -> Result<()> {
         let _ = tracing_subscriber::fmt().try_init();
@@ -697,4 +729,44 @@ mod test {
         assert_eq!(pkt, pkt2);
         Ok(())
     }
+
+    #[test]
+    fn pack_many_atoms() -> Result<()> {
+        let _ = tracing_subscriber::fmt().try_init();
+        let atoms: Vec<Atom> = (0..60)
+            .map(|i| {
+                Atom::new(CameraControl {
+                    input: i,
+                    parameter: CameraParameterID::Video(VideoParam::ManualWhiteBalance),
+                    value: CameraParameterValue::I64(vec![i.into(); 4]),
+                })
+            })
+            .collect();
+
+        let packets = AtemPacket::pack_atoms(atoms.clone())?;
+        // These are 56 bytes each, so need to be split over two packets.
+        assert_eq!(2, packets.len());
+
+        for pkt in &packets {
+            let mut out = Cursor::new(Vec::new());
+            pkt.write(&mut out)?;
+            let out = out.into_inner();
+            assert!(out.len() <= usize::from(AtemPacket::MAX_PACKET_LENGTH));
+
+            // Check the packet survives a round-trip
+            let pkt2 = AtemPacket::read(&mut Cursor::new(&out))?;
+            assert_eq!(pkt, &pkt2);
+        }
+
+        // All atoms should be present, in order
+        let packed: Vec<Atom> = packets
+            .iter()
+            .flat_map(|p| p.atoms().cloned().unwrap_or_default())
+            .collect();
+        assert_eq!(atoms, packed);
+
+        assert!(AtemPacket::pack_atoms([])?.is_empty());
+
+        Ok(())
+    }
 }
@@ -2,6 +2,8 @@
 use crate::{error::Error, structs::VideoSource, Result};
 use binrw::{binrw, BinRead, BinWrite};
 use modular_bitfield::{bitfield, specifiers::B5};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 const MAX_HUE: u16 = 3600;
 const MAX_SAT_LUM: u16 = 1000;
@@ -42,6 +44,34 @@ pub fn video_source_to_generator_id(src: VideoSource) -> Result<u8> {
     }
 }
 
+/// Converts an 8-bit-per-channel sRGB colour to the switcher's HSL
+/// representation: `(hue, saturation, luminance)`.
+fn rgb_to_hsl(r: u8, g: u8, b: u8) -> (u16, u16, u16) {
+    let (max, min) = (r.max(g).max(b), r.min(g).min(b));
+    let [r, g, b, max_f, min_f] = [r, g, b, max, min].map(|v| f32::from(v) / 255.);
+    let lum = (max_f + min_f) / 2.;
+    if max == min {
+        // Greyscale
+        return (0, 0, (lum * 1000.).round() as u16);
+    }
+
+    let delta = max_f - min_f;
+    let sat = delta / (1. - (2. * lum - 1.).abs());
+    let hue = if max_f == r {
+        ((g - b) / delta).rem_euclid(6.)
+    } else if max_f == g {
+        (b - r) / delta + 2.
+    } else {
+        (r - g) / delta + 4.
+    } * 60.;
+
+    (
+        ((hue * 10.).round() as u16) % MAX_HUE,
+        ((sat * 1000.).round() as u16).min(MAX_SAT_LUM),
+        (lum * 1000.).round() as u16,
+    )
+}
+
 #[cfg(feature = "palette")]
 fn from_palette_hsl(colour: palette::Hsl) -> Result<(u16, u16, u16)> {
     let hue = (colour.hue.into_positive_degrees() * 10.) as u16;
@@ -76,6 +106,7 @@ fn to_palette_hsl(hue: u16, sat: u16, lum: u16) -> palette::Hsl {
 #[binrw]
 #[brw(big)]
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ColourGeneratorParams {
     #[brw(pad_after = 1)]
     pub id: u8,
@@ -146,6 +177,7 @@ struct SetColourGeneratorMask {
 #[binrw]
 #[brw(big)]
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetColourGeneratorParams {
     #[br(temp)]
     #[bw(calc(
@@ -193,6 +225,20 @@ impl SetColourGeneratorParams {
         }
     }
 
+    /// Creates a new [SetColourGeneratorParams] which sets all components of
+    /// the colour from an sRGB colour.
+    ///
+    /// Components are rounded to the nearest value the switcher supports.
+    pub fn from_rgb(id: u8, r: u8, g: u8, b: u8) -> Self {
+        let (hue, saturation, luminance) = rgb_to_hsl(r, g, b);
+        Self {
+            id,
+            hue: Some(hue),
+            saturation: Some(saturation),
+            luminance: Some(luminance),
+        }
+    }
+
     /// Creates a new [SetColourGeneratorParams] for a given [VideoSource] ID.
     ///
     /// Returns [Error::ParameterOutOfRange] if `src` is not a colour generator.
@@ -409,4 +455,48 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn from_rgb() -> Result<()> {
+        let expected = SetColourGeneratorParams {
+            id: 1,
+            hue: Some(2100),
+            saturation: Some(504),
+            luminance: Some(502),
+        };
+        assert_eq!(
+            expected,
+            SetColourGeneratorParams::from_rgb(1, 64, 128, 192)
+        );
+
+        let o = Atom::new(SetColourGeneratorParams::from_rgb(1, 64, 128, 192));
+        let mut out = Cursor::new(Vec::new());
+        o.write(&mut out)?;
+        let cmd = hex::decode("0010000043436c560701083401f801f6")?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Primary and secondary colours
+        for ((r, g, b), hue) in [
+            ((255, 0, 0), 0),
+            ((255, 255, 0), 600),
+            ((0, 255, 0), 1200),
+            ((0, 255, 255), 1800),
+            ((0, 0, 255), 2400),
+            ((255, 0, 255), 3000),
+        ] {
+            let params = SetColourGeneratorParams::from_rgb(0, r, g, b);
+            assert_eq!(Some(hue), params.hue);
+            assert_eq!(Some(MAX_SAT_LUM), params.saturation);
+            assert_eq!(Some(500), params.luminance);
+        }
+
+        // Greys
+        for (v, lum) in [(0, 0), (128, 502), (255, 1000)] {
+            let params = SetColourGeneratorParams::from_rgb(0, v, v, v);
+            assert_eq!(Some(0), params.hue);
+            assert_eq!(Some(0), params.saturation);
+            assert_eq!(Some(lum), params.luminance);
+        }
+        Ok(())
+    }
 }
@@ -1,17 +1,238 @@
-//! # Dsk; 0/11 atoms
+//! # Dsk; 4/11 atoms
 //!
-//! ## Unimplemented atoms (11)
+//! ## Unimplemented atoms (7)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
 //! `CDsC` | `ChangeDskCut` | 0xc
 //! `CDsF` | `ChangeDskFill` | 0xc
 //! `CDsG` | `ChangeDskShapedClipGain` | 0x14
-//! `CDsL` | `ChangeDskLive` | 0xc
 //! `CDsM` | `ChangeDskMask` | 0x14
 //! `CDsR` | `ChangeDskRate` | 0xc
 //! `CDsT` | `ChangeDskTie` | 0xc
-//! `DDsA` | `DoDskAuto` | 0xc
 //! `DskB` | `DskInputSelection` | 0x10
-//! `DskP` | `DskConfigParameters` | 0x1c
-//! `DskS` | `DskCurrentState` | 0x10
+use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// `CDsL`: put a downstream keyer on or off air, without a transition
+/// (`ChangeDskLive`)
+///
+/// ## Packet format
+///
+/// * `u8`: downstream keyer
+/// * `bool`: on air
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetDownstreamKeyerOnAir {
+    pub keyer: u8,
+    #[brw(pad_after = 2)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub on_air: bool,
+}
+
+/// `DDsA`: put a downstream keyer on or off air with a transition
+/// (`DoDskAuto`)
+///
+/// ## Packet format
+///
+/// * `u8`: downstream keyer
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DownstreamKeyerAuto {
+    #[brw(pad_size_to = 4)]
+    pub keyer: u8,
+}
+
+/// `DskP`: downstream keyer properties (`DskConfigParameters`)
+///
+/// ## Packet format
+///
+/// * `u8`: downstream keyer
+/// * `bool`: tied to the next transition
+/// * `u8`: transition rate, in frames
+/// * `bool`: key is pre-multiplied
+/// * `u16`: clip
+/// * `u16`: gain
+/// * `bool`: invert key
+/// * `bool`: mask enabled
+/// * `i16`: mask top
+/// * `i16`: mask bottom
+/// * `i16`: mask left
+/// * `i16`: mask right
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DownstreamKeyerProperties {
+    pub keyer: u8,
+    /// If `true`, the keyer transitions on or off air along with the next
+    /// transition.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub tie: bool,
+    pub rate: u8,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub pre_multiplied: bool,
+    pub clip: u16,
+    pub gain: u16,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub invert_key: bool,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub masked: bool,
+    pub mask_top: i16,
+    pub mask_bottom: i16,
+    pub mask_left: i16,
+    #[brw(pad_after = 2)]
+    pub mask_right: i16,
+}
+
+/// `DskS`: downstream keyer status (`DskCurrentState`)
+///
+/// ## Packet format
+///
+/// * `u8`: downstream keyer
+/// * `bool`: on air
+/// * `bool`: in transition
+/// * `bool`: auto transition in progress
+/// * `u8`: frames remaining in transition
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DownstreamKeyerStatus {
+    pub keyer: u8,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub on_air: bool,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub in_transition: bool,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub auto_transitioning: bool,
+    #[brw(pad_after = 3)]
+    pub frames_remaining: u8,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    #[test]
+    fn set_on_air() -> Result<()> {
+        let expected = SetDownstreamKeyerOnAir {
+            keyer: 1,
+            on_air: true,
+        };
+
+        let cmd = hex::decode("000c00004344734c01010000")?;
+        let cdsl = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::SetDownstreamKeyerOnAir(cdsl) = cdsl.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, cdsl);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn status() -> Result<()> {
+        let expected = DownstreamKeyerStatus {
+            keyer: 0,
+            on_air: true,
+            in_transition: true,
+            auto_transitioning: true,
+            frames_remaining: 12,
+        };
+
+        // Synthetic example, not captured from hardware
+        let cmd = hex::decode("0010000044736b53000101010c000000")?;
+        let dsks = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::DownstreamKeyerStatus(dsks) = dsks.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, dsks);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn auto() -> Result<()> {
+        let expected = DownstreamKeyerAuto { keyer: 1 };
+
+        let cmd = hex::decode("000c00004444734101000000")?;
+        let ddsa = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::DownstreamKeyerAuto(ddsa) = ddsa.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, ddsa);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn properties() -> Result<()> {
+        let expected = DownstreamKeyerProperties {
+            keyer: 0,
+            tie: true,
+            rate: 25,
+            pre_multiplied: true,
+            clip: 500,
+            gain: 700,
+            invert_key: false,
+            masked: true,
+            mask_top: 9000,
+            mask_bottom: -9000,
+            mask_left: -15000,
+            mask_right: 15000,
+        };
+
+        let cmd = hex::decode("001c000044736b500001190101f402bc00012328dcd8c5683a980000")?;
+        let dskp = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::DownstreamKeyerProperties(dskp) = dskp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, dskp);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
@@ -1,6 +1,6 @@
-//! # Fairlight audio; 6/52 atoms
+//! # Fairlight audio; 7/52 atoms
 //!
-//! ## Unimplemented atoms (46)
+//! ## Unimplemented atoms (45)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
@@ -17,7 +17,6 @@
 //! `CFMH` | `ChangeFairlightAudioMixerHeadphoneOutProperties` | 0x2c
 //! `CFMP` | `ChangeFairlightAudioMixerMasterOutProperties` | 0x1c
 //! `CFMS` | `ChangeFairlightAudioMixerSolo` | 0x20
-//! `CFSP` | `ChangeFairlightAudioMixerInputSourceProperties` | 0x38
 //! `CICP` | `ChangeFairlightAudioMixerInputSourceCompressorProperties` | 0x30
 //! `CILP` | `ChangeFairlightAudioMixerInputSourceLimiterProperties` | 0x2c
 //! `CILP` | `ChangeFairlightAudioMixerInputSourceLimiterProperties` | 0x2c
@@ -56,7 +55,13 @@ use crate::structs::{
     SupportedEqualiserShapes,
 };
 use binrw::{binrw, BinRead, BinWrite};
-use modular_bitfield::{bitfield, specifiers::B12, Specifier};
+use modular_bitfield::{
+    bitfield,
+    specifiers::{B12, B7},
+    Specifier,
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `_FAC`: Fairlight audio mixer capabilities (`CapabilitiesFairlightAudioMixer`)
 ///
@@ -68,6 +73,7 @@ use modular_bitfield::{bitfield, specifiers::B12, Specifier};
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CapabilitiesFairlightAudioMixer {
     pub channels: u8,
 
@@ -80,6 +86,7 @@ pub struct CapabilitiesFairlightAudioMixer {
 #[bitfield(bits = 16)]
 #[repr(u16)]
 #[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[br(map = From::<u16>::from)]
 #[bw(map = |&x| Into::<u16>::into(x))]
 pub struct HeadphoneOutputCapabilities {
@@ -101,6 +108,7 @@ pub struct HeadphoneOutputCapabilities {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CapabilitiesFairlightAudioMixerHeadphoneOut {
     #[brw(pad_after = 2)]
     pub caps: HeadphoneOutputCapabilities,
@@ -117,6 +125,7 @@ pub struct CapabilitiesFairlightAudioMixerHeadphoneOut {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FairlightEqualiserBandRangeCapabilities {
     #[brw(pad_size_to = 4)]
     #[br(temp)]
@@ -145,6 +154,7 @@ pub struct FairlightEqualiserBandRangeCapabilities {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FairlightAudioMixerMasterOutEqualiserBandProperties {
     /// Zero-indexed band ID.
     pub band_id: u8,
@@ -180,10 +190,12 @@ pub struct FairlightAudioMixerMasterOutEqualiserBandProperties {
 
 #[binrw]
 #[brw(repr = u8)]
-#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum FairlightAudioInputSourceState {
     /// Audio source is muted.
+    #[default]
     Off = 0x01,
     /// Audio source is enabled.
     On = 0x02,
@@ -195,12 +207,15 @@ pub enum FairlightAudioInputSourceState {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FairlightAudioMixerInputSourceProperties {
     /// Source ID
     #[brw(pad_size_to = 8)]
     pub source_id: u16,
 
-    unknown_10: u64,
+    /// Channel ID within the source, needed to address it with
+    /// [SetFairlightAudioMixerInputSourceProperties].
+    pub channel_id: i64,
     #[brw(pad_size_to = 4)]
     unknown_18: u16,
     unknown_1c: u32,
@@ -227,10 +242,131 @@ pub struct FairlightAudioMixerInputSourceProperties {
     pub state: FairlightAudioInputSourceState,
 }
 
+#[bitfield(bits = 16)]
+#[repr(u16)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u16>::from)]
+#[bw(map = |&x| Into::<u16>::into(x))]
+struct SetFairlightAudioMixerInputSourceMask {
+    pub frames_delay: bool,
+    pub gain: bool,
+    pub stereo_simulation: bool,
+    pub equaliser_enabled: bool,
+    pub equaliser_gain: bool,
+    pub make_up_gain: bool,
+    pub balance: bool,
+    pub fader_gain: bool,
+    pub state: bool,
+    #[skip]
+    __: B7,
+}
+
+/// `CFSP`: set Fairlight audio mixer input source properties
+/// (`ChangeFairlightAudioMixerInputSourceProperties`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// ## Packet format
+///
+/// * `u16`: setting mask
+/// * `u16`: source ID
+/// * 4 bytes padding
+/// * `i64`: channel ID
+/// * `u8`: frames delay
+/// * 3 bytes padding
+/// * `i32`: gain, in 0.01dB
+/// * `i16`: stereo simulation
+/// * `bool`: equaliser enabled
+/// * 1 byte padding
+/// * `i32`: equaliser gain, in 0.01dB
+/// * `i32`: dynamics make-up gain, in 0.01dB
+/// * `i16`: balance
+/// * 2 bytes padding
+/// * `i32`: fader gain, in 0.01dB
+/// * `u8`: [source state][FairlightAudioInputSourceState]
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetFairlightAudioMixerInputSourceProperties {
+    #[br(temp)]
+    #[bw(calc(
+        SetFairlightAudioMixerInputSourceMask::new()
+            .with_frames_delay(self.frames_delay.is_some())
+            .with_gain(self.gain.is_some())
+            .with_stereo_simulation(self.stereo_simulation.is_some())
+            .with_equaliser_enabled(self.equaliser_enabled.is_some())
+            .with_equaliser_gain(self.equaliser_gain.is_some())
+            .with_make_up_gain(self.make_up_gain.is_some())
+            .with_balance(self.balance.is_some())
+            .with_fader_gain(self.fader_gain.is_some())
+            .with_state(self.state.is_some())
+    ))]
+    mask: SetFairlightAudioMixerInputSourceMask,
+
+    #[brw(pad_after = 4)]
+    pub source_id: u16,
+    pub channel_id: i64,
+
+    #[brw(pad_after = 3)]
+    #[br(map(|v: u8| mask.frames_delay().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub frames_delay: Option<u8>,
+
+    #[br(map(|v: i32| mask.gain().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub gain: Option<i32>,
+
+    #[br(map(|v: i16| mask.stereo_simulation().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub stereo_simulation: Option<i16>,
+
+    #[brw(pad_after = 1)]
+    #[br(map(|v: u8| mask.equaliser_enabled().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub equaliser_enabled: Option<bool>,
+
+    #[br(map(|v: i32| mask.equaliser_gain().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub equaliser_gain: Option<i32>,
+
+    #[br(map(|v: i32| mask.make_up_gain().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub make_up_gain: Option<i32>,
+
+    #[brw(pad_after = 2)]
+    #[br(map(|v: i16| mask.balance().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub balance: Option<i16>,
+
+    #[br(map(|v: i32| mask.fader_gain().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub fader_gain: Option<i32>,
+
+    #[brw(pad_after = 3)]
+    #[br(map(|v: FairlightAudioInputSourceState| mask.state().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub state: Option<FairlightAudioInputSourceState>,
+}
+
+impl SetFairlightAudioMixerInputSourceProperties {
+    /// Creates a new [SetFairlightAudioMixerInputSourceProperties] which
+    /// doesn't change anything.
+    pub fn new(source_id: u16, channel_id: i64) -> Self {
+        Self {
+            source_id,
+            channel_id,
+            ..Default::default()
+        }
+    }
+}
+
 /// `FMTl`: Fairlight audio mixer tally (`FairlightAudioMixerTally`)
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FairlightAudioMixerTally {
     #[brw(pad_after = 6)]
     #[br(temp)]
@@ -245,6 +381,7 @@ pub struct FairlightAudioMixerTally {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FairlightAudioMixerTallyEntry {
     unknown_0: u64,
 
@@ -259,7 +396,7 @@ pub struct FairlightAudioMixerTallyEntry {
 mod test {
     use super::*;
     use crate::{atom::Atom, Result};
-    use binrw::BinRead;
+    use binrw::{BinRead, BinWrite};
     use std::io::Cursor;
 
     #[test]
@@ -330,6 +467,24 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn cfsp() -> Result {
+        let cmd = hex::decode("00380000434653500100000100000000ffffffffffff01000000000000000000000000000000000000000000000000000000000004000000")?;
+        let cfsp = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let expected = Atom::new(SetFairlightAudioMixerInputSourceProperties {
+            state: Some(FairlightAudioInputSourceState::AudioFollowsVideo),
+            ..SetFairlightAudioMixerInputSourceProperties::new(1, -0xff00)
+        });
+        assert_eq!(expected, cfsp);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        expected.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        Ok(())
+    }
+
     #[test]
     fn fec() -> Result {
         // ATEM Mini
@@ -374,7 +529,7 @@ mod test {
 
         let expected = Atom::new(FairlightAudioMixerInputSourceProperties {
             source_id: 0x1,
-            unknown_10: 0xffffffffffff0100,
+            channel_id: -0xff00,
             unknown_18: 0x100,
             unknown_1c: 0,
             unknown_20: 0,
@@ -395,7 +550,7 @@ mod test {
 
         let expected = Atom::new(FairlightAudioMixerInputSourceProperties {
             source_id: 0x2,
-            unknown_10: 0xffffffffffff0100,
+            channel_id: -0xff00,
             unknown_18: 0x100,
             unknown_1c: 0,
             unknown_20: 0,
@@ -416,7 +571,7 @@ mod test {
 
         let expected = Atom::new(FairlightAudioMixerInputSourceProperties {
             source_id: 0x1,
-            unknown_10: 0xffffffffffff0100,
+            channel_id: -0xff00,
             unknown_18: 0x100,
             unknown_1c: 0,
             unknown_20: 0,
@@ -437,7 +592,7 @@ mod test {
 
         let expected = Atom::new(FairlightAudioMixerInputSourceProperties {
             source_id: 0x1,
-            unknown_10: 0xffffffffffff0100,
+            channel_id: -0xff00,
             unknown_18: 0x100,
             unknown_1c: 0,
             unknown_20: 0,
@@ -459,7 +614,7 @@ mod test {
 
         let expected = Atom::new(FairlightAudioMixerInputSourceProperties {
             source_id: 0x1,
-            unknown_10: 0xffffffffffff0100,
+            channel_id: -0xff00,
             unknown_18: 0x100,
             unknown_1c: 0,
             unknown_20: 0,
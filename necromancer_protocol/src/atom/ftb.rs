@@ -6,6 +6,8 @@
 //! ------ | --------- | ------
 //! `FEna` | `FtbEnabled` | 0xc
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `FtbA`: fade to black (auto/transition) (`DoFtbAuto`)
 ///
@@ -16,6 +18,7 @@ use binrw::binrw;
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FadeToBlackAuto {
     #[brw(pad_size_to = 4)]
     pub me: u8,
@@ -31,6 +34,7 @@ pub struct FadeToBlackAuto {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CutToBlack {
     pub me: u8,
     #[brw(pad_after = 2)]
@@ -50,6 +54,7 @@ pub struct CutToBlack {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetFadeToBlackParams {
     #[br(temp, map = |v: u8| v != 0)]
     #[bw(calc(rate.is_some()), map = Into::<u8>::into)]
@@ -71,6 +76,7 @@ pub struct SetFadeToBlackParams {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FadeToBlackParams {
     pub me: u8,
     #[brw(pad_after = 2)]
@@ -88,6 +94,7 @@ pub struct FadeToBlackParams {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FadeToBlackStatus {
     pub me: u8,
     #[br(map = |v: u8| v != 0)]
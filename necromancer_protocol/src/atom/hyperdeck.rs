@@ -1,17 +1,324 @@
-//! # HyperDeck; 0/11 atoms
+//! # HyperDeck; 3/11 atoms
 //!
-//! ## Unimplemented atoms (11)
+//! ## Unimplemented atoms (8)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
-//! `CXCP` | `HyperDeckSetClipPlayStatus` | 0x1c
 //! `CXMS` | `ChangeHyperDeckSetup` | 0x18
 //! `CXSS` | `HyperDeckSetSource` | 0x10
 //! `RXCC` | `HyperDeckClipCount` | 0xc
 //! `RXCE` | `HyperDeckError` | 0xc
-//! `RXCP` | `HyperDeckClipPlayStatus` | 0x1c
 //! `RXCS` | `HyperDeckClipStatus` | 0x5c
 //! `RXHI` | `HyperDeckModel` | 0x4c
 //! `RXHS` | `HyperDeckStorageMediaInfo` | 0x8c
 //! `RXMS` | `HyperDeckSetup` | 0x1c
-//! `RXSS` | `HyperDeckCurrentSource` | 0x28
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{bitfield, specifiers::B4};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Connection state of a HyperDeck.
+#[binrw]
+#[brw(repr = u8)]
+#[derive(Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum HyperDeckConnectionState {
+    /// No HyperDeck is configured, or the switcher can't reach it.
+    #[default]
+    NotConnected = 0x00,
+    /// The switcher is connecting to the HyperDeck.
+    Connecting = 0x01,
+    /// The HyperDeck is connected.
+    Connected = 0x02,
+    /// The HyperDeck is running incompatible firmware.
+    Incompatible = 0x03,
+}
+
+/// Transport mode of a HyperDeck.
+#[binrw]
+#[brw(repr = u8)]
+#[derive(Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum HyperDeckTransportState {
+    /// Stopped.
+    #[default]
+    Idle = 0x00,
+    /// Playing a clip.
+    Play = 0x01,
+    /// Recording a clip.
+    Record = 0x02,
+    /// Shuttling (playing at a speed other than 100%).
+    Shuttle = 0x04,
+}
+
+/// `RXSS`: HyperDeck status (`HyperDeckCurrentSource`)
+///
+/// ## Packet format
+///
+/// * `u16`: HyperDeck ID
+/// * `u8`: [connection state][HyperDeckConnectionState]
+/// * `bool`: remote control enabled
+/// * `u8`: number of storage media slots
+/// * 4 bytes: storage media status, one per slot
+/// * `i8`: active storage media slot, or `-1` if there is none
+/// * `u16`: current clip ID
+/// * `u16`: frame rate
+/// * `u16`: time scale
+/// * `bool`: interlaced
+/// * `bool`: drop-frame timecode
+/// * 14 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HyperDeckStatus {
+    pub hyperdeck_id: u16,
+    pub connection_state: HyperDeckConnectionState,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub remote_enabled: bool,
+    pub storage_media_count: u8,
+    pub storage_media_status: [u8; 4],
+    pub active_storage_media: i8,
+    pub current_clip_id: u16,
+    pub frame_rate: u16,
+    pub time_scale: u16,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub interlaced: bool,
+    #[brw(pad_after = 14)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub drop_frame_timecode: bool,
+}
+
+/// `RXCP`: HyperDeck clip play status (`HyperDeckClipPlayStatus`)
+///
+/// ## Packet format
+///
+/// * `u16`: HyperDeck ID
+/// * `u8`: [transport state][HyperDeckTransportState]
+/// * `bool`: play a single clip
+/// * `bool`: loop playback
+/// * 1 byte padding
+/// * `i16`: playback speed, in percent
+/// * 12 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct HyperDeckClipPlayStatus {
+    pub hyperdeck_id: u16,
+    pub transport_state: HyperDeckTransportState,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub single_clip: bool,
+    #[brw(pad_after = 1)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub loop_playback: bool,
+    #[brw(pad_after = 12)]
+    pub speed: i16,
+}
+
+#[bitfield(bits = 8)]
+#[repr(u8)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u8>::from)]
+#[bw(map = |&x| Into::<u8>::into(x))]
+struct SetHyperDeckClipPlayStatusMask {
+    pub transport_state: bool,
+    pub single_clip: bool,
+    pub loop_playback: bool,
+    pub speed: bool,
+    #[skip]
+    __: B4,
+}
+
+/// `CXCP`: set HyperDeck clip play status (`HyperDeckSetClipPlayStatus`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// ## Packet format
+///
+/// * `u8`: setting mask
+/// * 1 byte padding
+/// * `u16`: HyperDeck ID
+/// * `u8`: [transport state][HyperDeckTransportState]
+/// * `bool`: play a single clip
+/// * `bool`: loop playback
+/// * 1 byte padding
+/// * `i16`: playback speed, in percent
+/// * 10 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetHyperDeckClipPlayStatus {
+    #[br(temp)]
+    #[bw(calc(
+        SetHyperDeckClipPlayStatusMask::new()
+            .with_transport_state(self.transport_state.is_some())
+            .with_single_clip(self.single_clip.is_some())
+            .with_loop_playback(self.loop_playback.is_some())
+            .with_speed(self.speed.is_some())
+    ))]
+    #[brw(pad_after = 1)]
+    mask: SetHyperDeckClipPlayStatusMask,
+
+    pub hyperdeck_id: u16,
+
+    #[br(map(|v: HyperDeckTransportState| mask.transport_state().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub transport_state: Option<HyperDeckTransportState>,
+
+    #[br(map(|v: u8| mask.single_clip().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub single_clip: Option<bool>,
+
+    #[brw(pad_after = 1)]
+    #[br(map(|v: u8| mask.loop_playback().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub loop_playback: Option<bool>,
+
+    #[brw(pad_after = 10)]
+    #[br(map(|v: i16| mask.speed().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub speed: Option<i16>,
+}
+
+impl SetHyperDeckClipPlayStatus {
+    /// Creates a new [SetHyperDeckClipPlayStatus] which doesn't change
+    /// anything.
+    pub fn new(hyperdeck_id: u16) -> Self {
+        Self {
+            hyperdeck_id,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new [SetHyperDeckClipPlayStatus] which only changes the
+    /// transport state.
+    pub fn transport(hyperdeck_id: u16, transport_state: HyperDeckTransportState) -> Self {
+        Self {
+            transport_state: Some(transport_state),
+            ..Self::new(hyperdeck_id)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn hyperdeck_status() -> Result<()> {
+        let expected = HyperDeckStatus {
+            hyperdeck_id: 1,
+            connection_state: HyperDeckConnectionState::Connected,
+            remote_enabled: true,
+            storage_media_count: 2,
+            storage_media_status: [1, 1, 0, 0],
+            active_storage_media: 0,
+            current_clip_id: 3,
+            frame_rate: 30000,
+            time_scale: 1001,
+            interlaced: false,
+            drop_frame_timecode: true,
+        };
+
+        let cmd = hex::decode(concat!(
+            "0028000052585353",
+            "0001020102010100000000037530",
+            "03e90001",
+            "0000000000000000000000000000",
+        ))?;
+        let rxss = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::HyperDeckStatus(rxss) = rxss.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, rxss);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // No active storage media
+        let mut cmd = cmd;
+        cmd[17] = 0xff;
+        let rxss = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::HyperDeckStatus(rxss) = rxss.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(-1, rxss.active_storage_media);
+        Ok(())
+    }
+
+    #[test]
+    fn hyperdeck_clip_play_status() -> Result<()> {
+        let expected = HyperDeckClipPlayStatus {
+            hyperdeck_id: 0,
+            transport_state: HyperDeckTransportState::Play,
+            single_clip: false,
+            loop_playback: true,
+            speed: 100,
+        };
+
+        let cmd = hex::decode(concat!(
+            "001c000052584350",
+            "0000010001000064000000000000000000000000",
+        ))?;
+        let rxcp = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::HyperDeckClipPlayStatus(rxcp) = rxcp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, rxcp);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_hyperdeck_clip_play_status() -> Result<()> {
+        let expected = SetHyperDeckClipPlayStatus::transport(2, HyperDeckTransportState::Record);
+
+        let cmd = hex::decode(concat!(
+            "001c000043584350",
+            "0100000202000000000000000000000000000000",
+        ))?;
+        let cxcp = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::SetHyperDeckClipPlayStatus(cxcp) = cxcp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, cxcp);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Masked fields should be ignored
+        let mut cmd = cmd;
+        cmd[14] = 0x01;
+        let cxcp = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetHyperDeckClipPlayStatus(cxcp) = cxcp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, cxcp);
+        Ok(())
+    }
+}
@@ -1,5 +1,7 @@
 //! # Initialisation; 1/1 atoms
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `InCm`: Initialisation complete (`InitialStateComplete`)
 ///
@@ -7,6 +9,7 @@ use binrw::binrw;
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InitialisationComplete {
     pub unknown1: u8,
     #[brw(pad_after = 2)]
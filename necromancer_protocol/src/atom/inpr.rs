@@ -1,25 +1,29 @@
-//! # Input properties; 1/2 atoms
-//!
-//! ## Unimplemented atoms (1)
-//!
-//! FourCC | Atom name | Length
-//! ------ | --------- | ------
-//! `CInL` | `ChangeInputProperties` | 0x28
+//! # Input properties; 2/2 atoms
 use crate::{
-    atom::{colour::video_source_to_generator_id, str_from_utf8_null},
-    structs::{ExternalPortType, PortType, VideoSource},
+    atom::{
+        colour::video_source_to_generator_id, lossy_utf8_array, str_from_utf8_null,
+        string_from_utf8_null,
+    },
+    structs::{ExternalPortType, PortType, SourceAvailability, VideoSource},
     Result,
 };
-use binrw::binrw;
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{bitfield, specifiers::B5};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
 /// `InPr`: Input properties (`InputProperties`)
 #[binrw]
 #[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
 #[derive(PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct InputProperties {
     pub video_source: VideoSource,
+    #[br(map = |v: [u8; 20]| if lossy_utf8 { lossy_utf8_array(v) } else { v })]
     pub long_name: [u8; 20],
+    #[br(map = |v: [u8; 4]| if lossy_utf8 { lossy_utf8_array(v) } else { v })]
     pub short_name: [u8; 4],
     #[br(map = |v: u8| v != 0)]
     #[bw(map = |v: &bool| Into::<u8>::into(*v))]
@@ -34,6 +38,7 @@ pub struct InputProperties {
     #[brw(pad_after = 1)]
     pub port_type: PortType,
     // 0x2a
+    /// High byte: [SourceAvailability], low byte: ME availability.
     pub availability3: u16,
 }
 
@@ -68,16 +73,38 @@ impl Debug for InputProperties {
 }
 
 impl InputProperties {
+    /// Gets the [long name][Self::long_name] of the input.
+    ///
+    /// If this was parsed with
+    /// [lossy UTF-8 decoding][crate::atom::Atom#lossy-utf-8-decoding], invalid
+    /// UTF-8 sequences have already been replaced (truncating the name if need
+    /// be), so this won't fail.
     #[inline]
     pub fn get_long_name(&self) -> Result<&str> {
         str_from_utf8_null(&self.long_name)
     }
 
+    /// Gets the [short name][Self::short_name] of the input.
+    ///
+    /// This is decoded like [`get_long_name()`][Self::get_long_name].
     #[inline]
     pub fn get_short_name(&self) -> Result<&str> {
         str_from_utf8_null(&self.short_name)
     }
 
+    /// Gets the non-ME buses which this video source can be routed to.
+    #[inline]
+    pub fn source_availability(&self) -> SourceAvailability {
+        SourceAvailability::from((self.availability3 >> 8) as u8)
+    }
+
+    /// Returns `true` if this video source can be used as a program or
+    /// preview source on `me`.
+    #[inline]
+    pub fn available_on_me(&self, me: u8) -> bool {
+        me < 8 && (self.availability3 & (1 << me)) != 0
+    }
+
     /// Gets the colour generator ID for this video source.
     ///
     /// Returns [`None`] if this is not a colour generator.
@@ -89,11 +116,100 @@ impl InputProperties {
     }
 }
 
+#[bitfield(bits = 8)]
+#[repr(u8)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u8>::from)]
+#[bw(map = |&x| Into::<u8>::into(x))]
+struct SetInputPropertiesMask {
+    pub long_name: bool,
+    pub short_name: bool,
+    pub external_port_type: bool,
+    #[skip]
+    __: B5,
+}
+
+/// `CInL`: set input properties (`ChangeInputProperties`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// ## Packet format
+///
+/// * `u8`: setting mask
+/// * 1 byte padding
+/// * `u16`: video source
+/// * `char[20]`: long name, maybe null terminated
+/// * `char[4]`: short name, maybe null terminated
+/// * `u16`: [external port type][ExternalPortType]
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetInputProperties {
+    #[br(temp)]
+    #[bw(calc(
+        SetInputPropertiesMask::new()
+            .with_long_name(self.long_name.is_some())
+            .with_short_name(self.short_name.is_some())
+            .with_external_port_type(self.external_port_type.is_some())
+    ))]
+    #[brw(pad_after = 1)]
+    mask: SetInputPropertiesMask,
+
+    pub video_source: VideoSource,
+
+    /// Long name of the input, up to 20 bytes.
+    #[br(try_map(|v: [u8; 20]| mask
+        .long_name()
+        .then(|| string_from_utf8_null(&v, lossy_utf8))
+        .transpose()))]
+    #[bw(
+        assert(long_name.as_ref().is_none_or(|v| v.len() <= 20)),
+        pad_size_to = 20,
+        map(|v: &Option<String>| v.as_deref().unwrap_or_default().as_bytes().to_vec())
+    )]
+    pub long_name: Option<String>,
+
+    /// Short name of the input, up to 4 bytes.
+    #[br(try_map(|v: [u8; 4]| mask
+        .short_name()
+        .then(|| string_from_utf8_null(&v, lossy_utf8))
+        .transpose()))]
+    #[bw(
+        assert(short_name.as_ref().is_none_or(|v| v.len() <= 4)),
+        pad_size_to = 4,
+        map(|v: &Option<String>| v.as_deref().unwrap_or_default().as_bytes().to_vec())
+    )]
+    pub short_name: Option<String>,
+
+    #[brw(pad_after = 2)]
+    #[br(map(|v: ExternalPortType| mask.external_port_type().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub external_port_type: Option<ExternalPortType>,
+}
+
+impl SetInputProperties {
+    /// Maximum length of [`long_name`][Self::long_name], in bytes.
+    pub const MAX_LONG_NAME_LENGTH: usize = 20;
+
+    /// Maximum length of [`short_name`][Self::short_name], in bytes.
+    pub const MAX_SHORT_NAME_LENGTH: usize = 4;
+
+    /// Creates a new [SetInputProperties] which doesn't change anything.
+    pub fn new(video_source: VideoSource) -> Self {
+        Self {
+            video_source,
+            ..Default::default()
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::atom::{Atom, Payload};
-    use binrw::BinRead;
     use std::io::Cursor;
 
     #[test]
@@ -124,6 +240,30 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn input_properties_lossy() -> Result<()> {
+        // Synthetic example, not captured from hardware
+        // Names "Caméra 1" and "CAMÉ" encoded as Latin-1, which isn't valid
+        // UTF-8.
+        let cmd = hex::decode("002c0000496e5072000143616de97261203100000000000000000000000043414dc901000002000200001101")?;
+        let inpr = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::InputProperties(input_properties) = inpr.payload else {
+            panic!("wrong command type");
+        };
+        assert!(input_properties.get_long_name().is_err());
+        assert!(input_properties.get_short_name().is_err());
+
+        let inpr = Atom::read_args(&mut Cursor::new(&cmd), binrw::args! { lossy_utf8: true })?;
+        let Payload::InputProperties(input_properties) = inpr.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(VideoSource::Input1, input_properties.video_source);
+        assert_eq!("Cam\u{FFFD}ra 1", input_properties.get_long_name()?);
+        // The replacement character doesn't fit in 4 bytes
+        assert_eq!("CAM", input_properties.get_short_name()?);
+        Ok(())
+    }
+
     #[test]
     fn colour_bars() -> Result<()> {
         let pl = hex::decode(
@@ -170,6 +310,14 @@ mod test {
         );
         assert_eq!(PortType::External, input_properties.port_type);
         assert_eq!(None, input_properties.colour_generator_id());
+        assert_eq!(
+            SourceAvailability::new()
+                .with_auxiliary(true)
+                .with_key_source(true),
+            input_properties.source_availability()
+        );
+        assert!(input_properties.available_on_me(0));
+        assert!(!input_properties.available_on_me(1));
         Ok(())
     }
 
@@ -220,4 +368,35 @@ mod test {
         assert_eq!(Some(0), inpr.colour_generator_id());
         Ok(())
     }
+
+    #[test]
+    fn set_input_properties() -> Result<()> {
+        let cmd = hex::decode(
+            "0028000043496e4c030000014c6170746f702048444d490000000000000000004c41500000000000",
+        )?;
+        let cinl = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetInputProperties(cinl) = cinl.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = SetInputProperties {
+            long_name: Some("Laptop HDMI".to_string()),
+            short_name: Some("LAP".to_string()),
+            ..SetInputProperties::new(VideoSource::Input1)
+        };
+        assert_eq!(expected, cinl);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Names which are too long
+        let o = Atom::new(SetInputProperties {
+            short_name: Some("LAPTOP".to_string()),
+            ..SetInputProperties::new(VideoSource::Input1)
+        });
+        assert!(o.write(&mut Cursor::new(Vec::new())).is_err());
+        Ok(())
+    }
 }
@@ -1,13 +1,12 @@
-//! # Key; 0/32 atoms
+//! # Key; 2/32 atoms
 //!
-//! ## Unimplemented atoms (32)
+//! ## Unimplemented atoms (30)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
 //! `CACC` | `ChangeKeyAdvancedChromaCursor` | 0x1c
 //! `CACK` | `ChangeKeyAdvancedChromaParameters` | 0x24
 //! `CKCk` | `ChangeKeyChromaParameters` | 0x18
-//! `CKDV` | `ChangeKeyDVEAndFlyParameters` | 0x48
 //! `CKeC` | `ChangeKeyCut` | 0xc
 //! `CKeF` | `ChangeKeyFill` | 0xc
 //! `CKFP` | `ChangeKeyFlyKeyFrameParameters` | 0x40
@@ -22,7 +21,6 @@
 //! `KBfT` | `KeyBasicParametersKeyTypeFillInput` | 0x10
 //! `KeBP` | `KeyBasicParameters` | 0x1c
 //! `KeCk` | `KeyChromaParameters` | 0x14
-//! `KeDV` | `KeyDVEAndFlyParameters` | 0x44
 //! `KeFS` | `KeyFlyState` | 0x10
 //! `KeLm` | `KeyLumaParameters` | 0x14
 //! `KeOn` | `KeyOnAir` | 0xc
@@ -36,3 +34,504 @@
 //! `SKDV` | `SetKeyDVEAndFlyVelocity` | 0x1c
 //! `SKPV` | `SetKeyPatternVelocity` | 0x18
 //! `CKLm` | `ChangeKeyLumaParameters` | 0x14
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{bitfield, specifiers::B6};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// `KeDV`: upstream keyer DVE and fly parameters (`KeyDVEAndFlyParameters`)
+///
+/// Sent by the switcher to indicate the DVE settings of an upstream keyer.
+///
+/// ## Packet format
+///
+/// * `u8`: ME
+/// * `u8`: keyer
+/// * 2 bytes padding
+/// * `u32`: horizontal size
+/// * `u32`: vertical size
+/// * `i32`: horizontal position
+/// * `i32`: vertical position
+/// * `i32`: rotation
+/// * `bool`: border enabled
+/// * `bool`: border shadow enabled
+/// * `u8`: border bevel style
+/// * 1 byte padding
+/// * `u16`: border outer width
+/// * `u16`: border inner width
+/// * `u8`: border outer softness
+/// * `u8`: border inner softness
+/// * `u8`: border bevel softness
+/// * `u8`: border bevel position
+/// * `u8`: border opacity
+/// * 1 byte padding
+/// * `u16`: border hue
+/// * `u16`: border saturation
+/// * `u16`: border luma
+/// * `u16`: light source direction
+/// * `u8`: light source altitude
+/// * `bool`: mask enabled
+/// * `u16`: mask top
+/// * `u16`: mask bottom
+/// * `u16`: mask left
+/// * `u16`: mask right
+/// * `u8`: rate
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct KeyDVEProperties {
+    /// The ME which the keyer is on.
+    pub me: u8,
+    /// The upstream keyer on the ME.
+    #[brw(pad_after = 2)]
+    pub keyer: u8,
+
+    /// Horizontal size, in thousandths (`1000` = 100%).
+    pub size_x: u32,
+
+    /// Vertical size, in thousandths (`1000` = 100%).
+    pub size_y: u32,
+
+    /// Horizontal position of the centre of the key, in thousandths of the
+    /// picture height (`0` = centre).
+    pub position_x: i32,
+
+    /// Vertical position of the centre of the key, in thousandths of the
+    /// picture height (`0` = centre).
+    pub position_y: i32,
+
+    /// Rotation, in tenths of a degree.
+    pub rotation: i32,
+
+    /// Border is shown.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub border_enabled: bool,
+
+    /// Border drop shadow is shown.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub border_shadow_enabled: bool,
+
+    /// Border bevel style: `0` = none, `1` = in/out, `2` = in, `3` = out.
+    #[brw(pad_after = 1)]
+    pub border_bevel: u8,
+
+    /// Border outer width, in hundredths.
+    pub border_outer_width: u16,
+
+    /// Border inner width, in hundredths.
+    pub border_inner_width: u16,
+
+    /// Border outer softness, in percent.
+    pub border_outer_softness: u8,
+
+    /// Border inner softness, in percent.
+    pub border_inner_softness: u8,
+
+    /// Border bevel softness, in percent.
+    pub border_bevel_softness: u8,
+
+    /// Border bevel position, in percent.
+    pub border_bevel_position: u8,
+
+    /// Border opacity, in percent.
+    #[brw(pad_after = 1)]
+    pub border_opacity: u8,
+
+    /// Border hue, in tenths of a degree (`0..=3600`).
+    pub border_hue: u16,
+
+    /// Border saturation, in tenths of a percent (`0..=1000`).
+    pub border_saturation: u16,
+
+    /// Border luminance, in tenths of a percent (`0..=1000`).
+    pub border_luma: u16,
+
+    /// Shadow light source direction, in tenths of a degree.
+    pub light_source_direction: u16,
+
+    /// Shadow light source altitude.
+    pub light_source_altitude: u8,
+
+    /// Mask is enabled.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub mask_enabled: bool,
+
+    /// Mask top edge, in thousandths.
+    pub mask_top: u16,
+
+    /// Mask bottom edge, in thousandths.
+    pub mask_bottom: u16,
+
+    /// Mask left edge, in thousandths.
+    pub mask_left: u16,
+
+    /// Mask right edge, in thousandths.
+    pub mask_right: u16,
+
+    /// Fly key transition rate, in frames.
+    #[brw(pad_after = 3)]
+    pub rate: u8,
+}
+
+#[bitfield(bits = 32)]
+#[repr(u32)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u32>::from)]
+#[bw(map = |&x| Into::<u32>::into(x))]
+struct SetKeyDVEMask {
+    pub size_x: bool,
+    pub size_y: bool,
+    pub position_x: bool,
+    pub position_y: bool,
+    pub rotation: bool,
+    pub border_enabled: bool,
+    pub border_shadow_enabled: bool,
+    pub border_bevel: bool,
+    pub border_outer_width: bool,
+    pub border_inner_width: bool,
+    pub border_outer_softness: bool,
+    pub border_inner_softness: bool,
+    pub border_bevel_softness: bool,
+    pub border_bevel_position: bool,
+    pub border_opacity: bool,
+    pub border_hue: bool,
+    pub border_saturation: bool,
+    pub border_luma: bool,
+    pub light_source_direction: bool,
+    pub light_source_altitude: bool,
+    pub mask_enabled: bool,
+    pub mask_top: bool,
+    pub mask_bottom: bool,
+    pub mask_left: bool,
+    pub mask_right: bool,
+    pub rate: bool,
+    #[skip]
+    __: B6,
+}
+
+/// `CKDV`: set upstream keyer DVE and fly parameters
+/// (`ChangeKeyDVEAndFlyParameters`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// See [KeyDVEProperties] for units.
+///
+/// ## Packet format
+///
+/// * `u32`: setting mask
+/// * `u8`: ME
+/// * `u8`: keyer
+/// * 2 bytes padding
+/// * `u32`: horizontal size
+/// * `u32`: vertical size
+/// * `i32`: horizontal position
+/// * `i32`: vertical position
+/// * `i32`: rotation
+/// * `bool`: border enabled
+/// * `bool`: border shadow enabled
+/// * `u8`: border bevel style
+/// * 1 byte padding
+/// * `u16`: border outer width
+/// * `u16`: border inner width
+/// * `u8`: border outer softness
+/// * `u8`: border inner softness
+/// * `u8`: border bevel softness
+/// * `u8`: border bevel position
+/// * `u8`: border opacity
+/// * 1 byte padding
+/// * `u16`: border hue
+/// * `u16`: border saturation
+/// * `u16`: border luma
+/// * `u16`: light source direction
+/// * `u8`: light source altitude
+/// * `bool`: mask enabled
+/// * `u16`: mask top
+/// * `u16`: mask bottom
+/// * `u16`: mask left
+/// * `u16`: mask right
+/// * `u8`: rate
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetKeyDVEProperties {
+    #[br(temp)]
+    #[bw(calc(
+        SetKeyDVEMask::new()
+            .with_size_x(self.size_x.is_some())
+            .with_size_y(self.size_y.is_some())
+            .with_position_x(self.position_x.is_some())
+            .with_position_y(self.position_y.is_some())
+            .with_rotation(self.rotation.is_some())
+            .with_border_enabled(self.border_enabled.is_some())
+            .with_border_shadow_enabled(self.border_shadow_enabled.is_some())
+            .with_border_bevel(self.border_bevel.is_some())
+            .with_border_outer_width(self.border_outer_width.is_some())
+            .with_border_inner_width(self.border_inner_width.is_some())
+            .with_border_outer_softness(self.border_outer_softness.is_some())
+            .with_border_inner_softness(self.border_inner_softness.is_some())
+            .with_border_bevel_softness(self.border_bevel_softness.is_some())
+            .with_border_bevel_position(self.border_bevel_position.is_some())
+            .with_border_opacity(self.border_opacity.is_some())
+            .with_border_hue(self.border_hue.is_some())
+            .with_border_saturation(self.border_saturation.is_some())
+            .with_border_luma(self.border_luma.is_some())
+            .with_light_source_direction(self.light_source_direction.is_some())
+            .with_light_source_altitude(self.light_source_altitude.is_some())
+            .with_mask_enabled(self.mask_enabled.is_some())
+            .with_mask_top(self.mask_top.is_some())
+            .with_mask_bottom(self.mask_bottom.is_some())
+            .with_mask_left(self.mask_left.is_some())
+            .with_mask_right(self.mask_right.is_some())
+            .with_rate(self.rate.is_some())
+    ))]
+    mask: SetKeyDVEMask,
+
+    /// The ME which the keyer is on.
+    pub me: u8,
+    /// The upstream keyer on the ME.
+    #[brw(pad_after = 2)]
+    pub keyer: u8,
+
+    /// Horizontal size, in thousandths (`1000` = 100%).
+    #[br(map(|v: u32| mask.size_x().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub size_x: Option<u32>,
+
+    /// Vertical size, in thousandths (`1000` = 100%).
+    #[br(map(|v: u32| mask.size_y().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub size_y: Option<u32>,
+
+    /// Horizontal position of the centre of the key, in thousandths of the
+    /// picture height (`0` = centre).
+    #[br(map(|v: i32| mask.position_x().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub position_x: Option<i32>,
+
+    /// Vertical position of the centre of the key, in thousandths of the
+    /// picture height (`0` = centre).
+    #[br(map(|v: i32| mask.position_y().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub position_y: Option<i32>,
+
+    /// Rotation, in tenths of a degree.
+    #[br(map(|v: i32| mask.rotation().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub rotation: Option<i32>,
+
+    /// Border is shown.
+    #[br(map(|v: u8| mask.border_enabled().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub border_enabled: Option<bool>,
+
+    /// Border drop shadow is shown.
+    #[br(map(|v: u8| mask.border_shadow_enabled().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub border_shadow_enabled: Option<bool>,
+
+    /// Border bevel style: `0` = none, `1` = in/out, `2` = in, `3` = out.
+    #[brw(pad_after = 1)]
+    #[br(map(|v: u8| mask.border_bevel().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_bevel: Option<u8>,
+
+    /// Border outer width, in hundredths.
+    #[br(map(|v: u16| mask.border_outer_width().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_outer_width: Option<u16>,
+
+    /// Border inner width, in hundredths.
+    #[br(map(|v: u16| mask.border_inner_width().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_inner_width: Option<u16>,
+
+    /// Border outer softness, in percent.
+    #[br(map(|v: u8| mask.border_outer_softness().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_outer_softness: Option<u8>,
+
+    /// Border inner softness, in percent.
+    #[br(map(|v: u8| mask.border_inner_softness().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_inner_softness: Option<u8>,
+
+    /// Border bevel softness, in percent.
+    #[br(map(|v: u8| mask.border_bevel_softness().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_bevel_softness: Option<u8>,
+
+    /// Border bevel position, in percent.
+    #[br(map(|v: u8| mask.border_bevel_position().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_bevel_position: Option<u8>,
+
+    /// Border opacity, in percent.
+    #[brw(pad_after = 1)]
+    #[br(map(|v: u8| mask.border_opacity().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_opacity: Option<u8>,
+
+    /// Border hue, in tenths of a degree (`0..=3600`).
+    #[br(map(|v: u16| mask.border_hue().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_hue: Option<u16>,
+
+    /// Border saturation, in tenths of a percent (`0..=1000`).
+    #[br(map(|v: u16| mask.border_saturation().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_saturation: Option<u16>,
+
+    /// Border luminance, in tenths of a percent (`0..=1000`).
+    #[br(map(|v: u16| mask.border_luma().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_luma: Option<u16>,
+
+    /// Shadow light source direction, in tenths of a degree.
+    #[br(map(|v: u16| mask.light_source_direction().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub light_source_direction: Option<u16>,
+
+    /// Shadow light source altitude.
+    #[br(map(|v: u8| mask.light_source_altitude().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub light_source_altitude: Option<u8>,
+
+    /// Mask is enabled.
+    #[br(map(|v: u8| mask.mask_enabled().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub mask_enabled: Option<bool>,
+
+    /// Mask top edge, in thousandths.
+    #[br(map(|v: u16| mask.mask_top().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub mask_top: Option<u16>,
+
+    /// Mask bottom edge, in thousandths.
+    #[br(map(|v: u16| mask.mask_bottom().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub mask_bottom: Option<u16>,
+
+    /// Mask left edge, in thousandths.
+    #[br(map(|v: u16| mask.mask_left().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub mask_left: Option<u16>,
+
+    /// Mask right edge, in thousandths.
+    #[br(map(|v: u16| mask.mask_right().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub mask_right: Option<u16>,
+
+    /// Fly key transition rate, in frames.
+    #[brw(pad_after = 3)]
+    #[br(map(|v: u8| mask.rate().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub rate: Option<u8>,
+}
+
+impl SetKeyDVEProperties {
+    /// Creates a new [SetKeyDVEProperties] which doesn't change anything.
+    pub fn new(me: u8, keyer: u8) -> Self {
+        Self {
+            me,
+            keyer,
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn key_dve_properties() -> Result<()> {
+        let expected = KeyDVEProperties {
+            me: 0,
+            keyer: 1,
+            size_x: 500,
+            size_y: 500,
+            position_x: -8000,
+            position_y: 4500,
+            rotation: 900,
+            border_enabled: true,
+            border_outer_width: 50,
+            border_outer_softness: 20,
+            border_opacity: 100,
+            border_luma: 1000,
+            light_source_direction: 36,
+            light_source_altitude: 25,
+            rate: 25,
+            ..Default::default()
+        };
+
+        let cmd = hex::decode(concat!(
+            "004400004b654456",
+            "00010000000001f4000001f4ffffe0c00000119400000384",
+            "01000000003200001400000064000000000003e8002419000000000000000000",
+            "19000000"
+        ))?;
+        let kedv = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::KeyDVEProperties(kedv) = kedv.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, kedv);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_position_size() -> Result<()> {
+        let expected = SetKeyDVEProperties {
+            size_x: Some(250),
+            size_y: Some(750),
+            position_x: Some(-8000),
+            position_y: Some(4500),
+            ..SetKeyDVEProperties::new(1, 3)
+        };
+
+        let cmd = hex::decode(concat!(
+            "00480000434b4456",
+            "0000000f01030000000000fa000002eeffffe0c00000119400000000",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "00000000"
+        ))?;
+        let ckdv = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::SetKeyDVEProperties(ckdv) = ckdv.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, ckdv);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Masked fields should be ignored
+        let mut cmd = cmd;
+        cmd[0x20] = 0xff;
+        let ckdv = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetKeyDVEProperties(ckdv) = ckdv.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, ckdv);
+        Ok(())
+    }
+}
@@ -1,19 +1,28 @@
-//! # Macros; 1/9 atoms
+//! # Macros; 5/9 atoms
 //!
-//! ## Unimplemented atoms (8)
+//! ## Implemented atoms
+//!
+//! FourCC | Atom name | Length
+//! ------ | --------- | ------
+//! `_MAC` | [`MacroCapabilities`] | 0xc
+//! `MAct` | [`MacroAction`] | 0xc
+//! `MPrp` | [`MacroProperties`] | variable
+//! `MRcS` | [`MacroRecordStatus`] | 0xc
+//! `MSRc` | [`MacroStartRecord`] | variable
+//!
+//! ## Unimplemented atoms (4)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
-//! `MAct` | `MacroAction` | 0xc
-//! `MPrp` | `MacroProperties` | variable
-//! `MRcS` | `MacroRecordStatus` | 0xc
 //! `MRPr` | `MacroRunProperties` | 0xc
 //! `MSlp` | `MacroSleep` | 0xc
-//! `MSRc` | `MacroStartRecord` | variable
 //! `MRCP` | `ChangeMacroRunProperties` | 0xc
 //! `CMPr` | `ChangeMacroProperties` | variable
 
+use super::string_from_utf8_null;
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `_MAC`: Macro capabilities (`CapabilitiesMacros`)
 ///
@@ -24,16 +33,167 @@ use binrw::binrw;
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MacroCapabilities {
     #[brw(pad_size_to = 4)]
     pub count: u8,
 }
 
+/// Macro index used by [MacroAction] for actions which don't apply to a
+/// specific macro.
+pub const NO_MACRO: u16 = 0xffff;
+
+/// `MPrp`: Macro properties (`MacroProperties`)
+///
+/// ## Packet format
+///
+/// Packets are padded to 4 byte boundaries.
+///
+/// * `u16`: macro index
+/// * `bool`: is used
+/// * `bool`: has unsupported operations
+/// * `u16`: name length
+/// * `u16`: description length
+/// * name
+/// * description
+/// * 0 - 3 bytes of padding
+#[binrw]
+#[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MacroProperties {
+    pub index: u16,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub is_used: bool,
+    /// The macro contains operations which this switcher doesn't support.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub has_unsupported_ops: bool,
+
+    #[br(temp)]
+    #[bw(try_calc(u16::try_from(name.len())))]
+    name_length: u16,
+
+    #[br(temp)]
+    #[bw(try_calc(u16::try_from(description.len())))]
+    description_length: u16,
+
+    #[br(count = name_length, try_map = |v: Vec<u8>| string_from_utf8_null(&v, lossy_utf8))]
+    #[bw(map = |v: &String| { v.as_bytes().to_vec() })]
+    pub name: String,
+
+    #[br(count = description_length, try_map = |v: Vec<u8>| string_from_utf8_null(&v, lossy_utf8))]
+    #[bw(map = |v: &String| { v.as_bytes().to_vec() })]
+    #[brw(align_after = 4)]
+    pub description: String,
+}
+
+/// `MRcS`: Macro record status (`MacroRecordStatus`)
+///
+/// ## Packet format
+///
+/// * `bool`: is recording
+/// * 1 byte padding
+/// * `u16`: macro index being recorded, or [NO_MACRO]
+#[binrw]
+#[brw(big)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MacroRecordStatus {
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    #[brw(pad_after = 1)]
+    pub is_recording: bool,
+    pub index: u16,
+}
+
+/// Action for [MacroAction].
+#[binrw]
+#[brw(repr = u8)]
+#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum MacroActionType {
+    /// Run the macro.
+    Run = 0x00,
+    /// Stop the currently-running macro.
+    Stop = 0x01,
+    /// Stop recording a macro.
+    StopRecording = 0x02,
+    /// Insert a "wait for user" pause in the macro being recorded.
+    InsertUserWait = 0x03,
+    /// Continue a macro which is waiting for the user.
+    Continue = 0x04,
+    /// Delete the macro.
+    Delete = 0x05,
+}
+
+/// `MAct`: Macro action (`MacroAction`)
+///
+/// ## Packet format
+///
+/// * `u16`: macro index, or [NO_MACRO] for actions which don't apply to a
+///   specific macro
+/// * `u8`: [action][MacroActionType]
+/// * 1 byte padding
+#[binrw]
+#[brw(big)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MacroAction {
+    pub index: u16,
+    #[brw(pad_after = 1)]
+    pub action: MacroActionType,
+}
+
+/// `MSRc`: Macro start record (`MacroStartRecord`)
+///
+/// ## Packet format
+///
+/// Packets are padded to 4 byte boundaries.
+///
+/// * `u16`: macro index
+/// * `u16`: name length
+/// * `u16`: description length
+/// * name
+/// * description
+/// * 0 - 3 bytes of padding
+#[binrw]
+#[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MacroStartRecord {
+    pub index: u16,
+
+    #[br(temp)]
+    #[bw(try_calc(u16::try_from(name.len())))]
+    name_length: u16,
+
+    #[br(temp)]
+    #[bw(try_calc(u16::try_from(description.len())))]
+    description_length: u16,
+
+    #[br(count = name_length, try_map = |v: Vec<u8>| string_from_utf8_null(&v, lossy_utf8))]
+    #[bw(map = |v: &String| { v.as_bytes().to_vec() })]
+    pub name: String,
+
+    #[br(count = description_length, try_map = |v: Vec<u8>| string_from_utf8_null(&v, lossy_utf8))]
+    #[bw(map = |v: &String| { v.as_bytes().to_vec() })]
+    #[brw(align_after = 4)]
+    pub description: String,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
-    use crate::{atom::Atom, Result};
-    use binrw::BinRead;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
     use std::io::Cursor;
 
     #[test]
@@ -47,4 +207,117 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn macro_properties() -> Result {
+        let cmd = hex::decode("001c00004d50727000020100000700054f70656e696e6748656c6c6f")?;
+        let atom = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::MacroProperties(mprp) = &atom.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = MacroProperties {
+            index: 2,
+            is_used: true,
+            has_unsupported_ops: false,
+            name: "Opening".to_string(),
+            description: "Hello".to_string(),
+        };
+        assert_eq!(&expected, mprp);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        Atom::new(expected).write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Unused slot
+        let cmd = hex::decode("001000004d5072700003000000000000")?;
+        let atom = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::MacroProperties(mprp) = &atom.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(3, mprp.index);
+        assert!(!mprp.is_used);
+        assert!(mprp.name.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn macro_record_status() -> Result {
+        let cmd = hex::decode("000c00004d52635301000004")?;
+        let atom = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::MacroRecordStatus(mrcs) = &atom.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = MacroRecordStatus {
+            is_recording: true,
+            index: 4,
+        };
+        assert_eq!(&expected, mrcs);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        Atom::new(expected).write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn macro_action() -> Result {
+        for (hex, expected) in [
+            (
+                "000c00004d41637400010000",
+                MacroAction {
+                    index: 1,
+                    action: MacroActionType::Run,
+                },
+            ),
+            (
+                "000c00004d416374ffff0100",
+                MacroAction {
+                    index: NO_MACRO,
+                    action: MacroActionType::Stop,
+                },
+            ),
+            (
+                "000c00004d41637400050500",
+                MacroAction {
+                    index: 5,
+                    action: MacroActionType::Delete,
+                },
+            ),
+        ] {
+            let cmd = hex::decode(hex)?;
+            let atom = Atom::read(&mut Cursor::new(&cmd))?;
+            let Payload::MacroAction(mact) = &atom.payload else {
+                panic!("wrong command type");
+            };
+            assert_eq!(&expected, mact);
+
+            let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+            Atom::new(expected).write(&mut out)?;
+            assert_eq!(cmd, out.into_inner());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn macro_start_record() -> Result {
+        let cmd = hex::decode("001400004d535263000100030002546573486900")?;
+        let atom = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::MacroStartRecord(msrc) = &atom.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = MacroStartRecord {
+            index: 1,
+            name: "Tes".to_string(),
+            description: "Hi".to_string(),
+        };
+        assert_eq!(&expected, msrc);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        Atom::new(expected).write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
 }
@@ -1,26 +1,25 @@
-//! # Media player; 5/19 atoms
+//! # Media player; 8/19 atoms
 //!
-//! ## Unimplemented atoms (14)
+//! ## Unimplemented atoms (11)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
-//! `CapA` | `StillCaptureAvailability` | 0xc
 //! `CMPA` | `ClearMediaPlayerAudio` | 0xc
 //! `CMPC` | `ClearMediaPlayerClip` | 0xc
 //! `CMPS` | `ChangeMediaPlayerSetup` | 0x10
 //! `CSTL` | `ClearMediaPlayerStill` | 0xc
-//! `MPAS` | `MediaPlayerAudioEntry` | 0x5c
 //! `MPCS` | `MediaPlayerClipStatus` | 0x4c
 //! `MPfM` | `MediaPlayerImageFrameMultiEntry` | 0x12 + (0x1 * entries)
 //! `MPSp` | `MediaPlayerSetup` | 0x14
 //! `RCPS` | `MediaPlayerClipPlayStatus` | 0x10
 //! `SCPS` | `SetMediaPlayerClipPlayStatus` | 0x10
-//! `SMPA` | `SetMediaPlayerAudio` | 0x4c
 //! `SMPC` | `SetMediaPlayerClip` | 0x4c
 //! `SMPS` | `SetMediaPlayerStill` | 0x4c
 
-use super::str_from_utf8_null;
+use super::string_from_utf8_null;
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
 pub const CAPTURE_STILL: CaptureStill = CaptureStill {};
@@ -32,10 +31,32 @@ pub const CAPTURE_STILL: CaptureStill = CaptureStill {};
 /// No payload.
 #[binrw]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CaptureStill {}
 
+/// `CapA`: still capture availability (`StillCaptureAvailability`)
+///
+/// Sent by the switcher during initialisation, and after a
+/// [storage lock][super::MediaPoolLock] request.
+///
+/// ## Packet format
+///
+/// * `bool`: still capture is available
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StillCaptureAvailability {
+    #[brw(pad_size_to = 4)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub available: bool,
+}
+
 #[binrw]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum MediaPlayerSourceID {
     #[brw(magic = 1u8)]
     Still(#[brw(pad_after = 1)] u8),
@@ -54,6 +75,7 @@ pub enum MediaPlayerSourceID {
 #[binrw]
 #[brw(big)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MediaPlayerSource {
     /// Media player ID
     pub id: u8,
@@ -76,6 +98,7 @@ pub struct MediaPlayerSource {
 #[binrw]
 #[brw(big)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetMediaPlayerSource {
     // We only read the "enable" bit from this, but write all bits.
     #[br(temp)]
@@ -123,6 +146,7 @@ impl SetMediaPlayerSource {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MediaPlayerCapabilities {
     pub still_count: u8,
     pub clip_count: u8,
@@ -149,7 +173,9 @@ pub struct MediaPlayerCapabilities {
 /// * 0 - 3 bytes of padding
 #[binrw]
 #[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
 #[derive(Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MediaPlayerFrameDescription {
     #[brw(pad_after = 1)]
     pub store_id: u8,
@@ -164,7 +190,7 @@ pub struct MediaPlayerFrameDescription {
     #[bw(try_calc(u16::try_from(name.len())))]
     name_length: u16,
 
-    #[br(count = name_length, try_map = |v: Vec<u8>| str_from_utf8_null(&v).map(str::to_string))]
+    #[br(count = name_length, try_map = |v: Vec<u8>| string_from_utf8_null(&v, lossy_utf8))]
     #[bw(map = |v: &String| { v.as_bytes().to_vec() })]
     #[brw(align_after = 4)]
     pub name: String,
@@ -185,6 +211,70 @@ impl Debug for MediaPlayerFrameDescription {
     }
 }
 
+/// `MPAS`: media player clip audio description (`MediaPlayerAudioEntry`)
+///
+/// ## Packet format
+///
+/// * `u16`: clip index
+/// * `bool`: clip audio is in use
+/// * `char[16]`: MD5 hash of clip audio
+/// * `char[64]`: audio name, maybe null terminated
+/// * 1 byte padding
+#[binrw]
+#[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
+#[derive(Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MediaPlayerAudioEntry {
+    pub index: u16,
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub is_used: bool,
+    pub md5: [u8; 16],
+
+    #[brw(pad_after = 1)]
+    #[br(try_map = |v: [u8; 64]| string_from_utf8_null(&v, lossy_utf8))]
+    #[bw(assert(name.len() <= 64), pad_size_to = 64, map = |v: &String| { v.as_bytes().to_vec() })]
+    pub name: String,
+}
+
+impl Debug for MediaPlayerAudioEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut b = f.debug_struct("MediaPlayerAudioEntry");
+
+        b.field("index", &self.index)
+            .field("is_used", &self.is_used);
+        if self.is_used {
+            b.field("md5", &hex::encode(self.md5))
+                .field("name", &self.name);
+        }
+        b.finish()
+    }
+}
+
+/// `SMPA`: set media player clip audio (`SetMediaPlayerAudio`)
+///
+/// ## Packet format
+///
+/// * `u8`: setting mask; always `0x01` (name)
+/// * `u8`: clip index
+/// * `char[64]`: audio name, maybe null terminated
+/// * 2 bytes padding
+#[binrw]
+#[brw(big, magic = 1u8)]
+#[br(import { lossy_utf8: bool = false })]
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetMediaPlayerAudio {
+    pub index: u8,
+
+    /// Name of the clip audio, up to 64 bytes.
+    #[brw(pad_after = 2)]
+    #[br(try_map = |v: [u8; 64]| string_from_utf8_null(&v, lossy_utf8))]
+    #[bw(assert(name.len() <= 64), pad_size_to = 64, map = |v: &String| { v.as_bytes().to_vec() })]
+    pub name: String,
+}
+
 #[cfg(test)]
 mod test {
     use binrw::{BinRead, BinWrite};
@@ -192,7 +282,7 @@ mod test {
 
     use super::*;
     use crate::{
-        atom::{Atom, Payload},
+        atom::{Atom, AtomPayload, Payload},
         Result,
     };
 
@@ -212,6 +302,24 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn still_capture_availability() -> Result<()> {
+        let expected = StillCaptureAvailability { available: true };
+        // modified to remove uninitialised memory
+        let cmd: Vec<u8> = hex::decode("000c00004361704101000000")?;
+        let capa = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::StillCaptureAvailability(capa) = capa.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, capa);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
     #[test]
     fn media_player_source_changed() -> Result<()> {
         let expected = MediaPlayerSource {
@@ -274,6 +382,26 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn media_player_frame_description_lossy() -> Result<()> {
+        // File name "Café.rle" encoded as Latin-1, which isn't valid UTF-8.
+        let cmd: Vec<u8> = hex::decode(
+            "002800004d5066650000000501b1a6194d4f52b449fd519870a63cb3c2000008436166e92e726c65",
+        )?;
+        assert!(MediaPlayerFrameDescription::from_bytes(&cmd[8..]).is_err());
+        let mpfe = Atom::read(&mut Cursor::new(&cmd))?;
+        assert!(matches!(mpfe.payload, Payload::Unknown(..)));
+
+        let mpfe = Atom::read_args(&mut Cursor::new(&cmd), binrw::args! { lossy_utf8: true })?;
+        let Payload::MediaPlayerFrameDescription(mpfe) = mpfe.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(5, mpfe.index);
+        assert!(mpfe.is_valid);
+        assert_eq!("Caf\u{FFFD}.rle", mpfe.name);
+        Ok(())
+    }
+
     #[test]
     fn media_player_frame_description() -> Result<()> {
         let _ = tracing_subscriber::fmt().try_init();
@@ -449,4 +577,49 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn media_player_audio_entry() -> Result<()> {
+        let expected = MediaPlayerAudioEntry {
+            index: 1,
+            is_used: true,
+            md5: [
+                0x0f, 0x1e, 0x2d, 0x3c, 0x4b, 0x5a, 0x69, 0x78, 0x87, 0x96, 0xa5, 0xb4, 0xc3, 0xd2,
+                0xe1, 0xf0,
+            ],
+            name: "Intro".to_string(),
+        };
+        let cmd: Vec<u8> = hex::decode("005c00004d5041530001010f1e2d3c4b5a69788796a5b4c3d2e1f0496e74726f000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")?;
+        let mpas = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::MediaPlayerAudioEntry(mpas) = mpas.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, mpas);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_media_player_audio() -> Result<()> {
+        let expected = SetMediaPlayerAudio {
+            index: 1,
+            name: "Intro".to_string(),
+        };
+        let cmd: Vec<u8> = hex::decode("004c0000534d50410101496e74726f00000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000")?;
+        let smpa = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetMediaPlayerAudio(smpa) = smpa.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, smpa);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
 }
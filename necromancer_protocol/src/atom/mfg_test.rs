@@ -7,12 +7,15 @@
 //!
 //! </div>
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// **Warning:** public ATEM software doesn't call these APIs, so functionality
 /// is unknown. This could damage your device.
 #[binrw]
 #[brw(repr = u8)]
 #[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum TestOperation {
     #[default]
@@ -49,6 +52,7 @@ pub enum TestOperation {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MfgTest {
     #[brw(pad_after = 3)]
     operation: TestOperation,
@@ -68,6 +72,7 @@ pub struct MfgTest {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MfgTestResult {
     operation: TestOperation,
     #[brw(pad_after = 2)]
@@ -1,18 +1,27 @@
 //! # Mix effect block (preview / program output control); 5/5 atoms
 use crate::structs::VideoSource;
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `_MeC`: mix effect block capabilities (`CapabilitiesMEBlock`)
 ///
 /// ## Packet format
 ///
 /// * `u8`: ME
-/// * `u8`: number of keyers on ME
+/// * `u8`: number of upstream keyers on ME
+/// * 2 bytes padding
+///
+/// This doesn't describe whether the keyers support DVE or flying keys; that
+/// is reported switcher-wide by [`DVECapabilities`][super::DVECapabilities].
+/// The padding bytes are `0` on the ATEM Mini, which does support DVE.
 #[binrw]
 #[brw(big)]
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MixEffectBlockCapabilities {
     pub me: u8,
+    /// Number of upstream keyers on the ME.
     #[brw(pad_after = 2)]
     pub keyers: u8,
 }
@@ -31,6 +40,7 @@ pub struct MixEffectBlockCapabilities {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct PreviewInput {
     #[brw(pad_after = 1)]
     pub me: u8,
@@ -53,6 +63,7 @@ pub struct PreviewInput {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetPreviewInput {
     #[brw(pad_after = 1)]
     pub me: u8,
@@ -71,6 +82,7 @@ pub struct SetPreviewInput {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ProgramInput {
     #[brw(pad_after = 1)]
     pub me: u8,
@@ -89,6 +101,7 @@ pub struct ProgramInput {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetProgramInput {
     #[brw(pad_after = 1)]
     pub me: u8,
@@ -105,6 +118,7 @@ mod test {
 
     #[test]
     fn mix_effect_caps() -> Result<()> {
+        // ATEM Mini: 1 ME, 1 upstream keyer
         let expected = MixEffectBlockCapabilities { me: 0, keyers: 1 };
         let cmd = hex::decode("000c00005f4d654300010000")?;
         let mec = Atom::read(&mut Cursor::new(&cmd))?;
@@ -21,10 +21,14 @@
 //! In BMDSwitcherAPI 9.8.3
 //!
 //! * total atoms: 344
-//! * implemented atoms: 69 (20%)
+//! * implemented atoms: 116 (33%)
 //! * identified atoms: 344 (100%)
 //!
-//! ## Uncategorised unimplemented atoms (8)
+//! To find which atoms a particular switcher actually sends, connect with
+//! `ConnectionOptions::record_observed_atoms` set, and check
+//! `AtemController::observed_atoms()`.
+//!
+//! ## Uncategorised unimplemented atoms (3)
 //!
 //! <div class="warning">
 //!
@@ -34,13 +38,8 @@
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
-//! `C3sl` | `ChangeSDI3GOutputLevel` | 0xc
-//! `ClrM` | `ColorimetryMode` | 0xc
-//! `Powr` | `PowerStatus` | 0xc
 //! `RInL` | `ResetInputLabels` | 0xc
 //! `SPtM` | `SerialPortFunction` | 0xc
-//! `V3sl` | `CurrentSDI3GOutputLevel` | 0xc
-//! `Warn` | `WarningMessage` | 0x34
 //! `Whol` | `IdentityInformation` | 0xb8
 
 mod audio;
@@ -62,9 +61,11 @@ mod mfg_test;
 mod mix_effect;
 mod multiview;
 mod network;
+mod power;
 mod recording;
 mod remote_source;
 mod rtmp;
+mod sdi;
 mod settings;
 mod storage;
 mod super_source;
@@ -76,60 +77,114 @@ mod transitions;
 mod ver;
 mod video_mode;
 mod visca;
+mod warning;
 
 use crate::{packet::AtemPacket, util::OffsetCounter, Result};
-use binrw::{binrw, helpers::until_eof, io::TakeSeekExt};
-use std::{fmt::Debug, io::SeekFrom};
+use binrw::{binrw, helpers::until_eof, io::TakeSeekExt, BinRead, BinWrite};
+use std::{
+    fmt::Debug,
+    io::{Cursor, SeekFrom},
+};
 
 pub use self::{
-    camera::{CameraCommand, CameraControl},
-    colour::{ColourGeneratorParams, SetColourGeneratorParams},
+    audio::{
+        audio_gain_from_db, audio_gain_to_db, AudioMixerInputProperties, AudioMixerInputState,
+        AudioMixerMasterOutProperties, SetAudioMixerInputProperties, AUDIO_GAIN_UNITY,
+    },
+    aux::{AuxSource, SetAuxSource},
+    camera::{
+        AudioParam, CameraCommand, CameraControl, CameraParameterID, CameraParameterValue,
+        ColourCorrectionParam, ConfigParam, DisplayParam, LensParam, MediaParam, OutputParam,
+        PtzControlParam, ReferenceParam, TallyParam, VideoParam,
+    },
+    colour::{
+        generator_id_to_video_source, video_source_to_generator_id, ColourGeneratorParams,
+        SetColourGeneratorParams,
+    },
+    dsk::{
+        DownstreamKeyerAuto, DownstreamKeyerProperties, DownstreamKeyerStatus,
+        SetDownstreamKeyerOnAir,
+    },
     fairlight::{
         CapabilitiesFairlightAudioMixer, CapabilitiesFairlightAudioMixerHeadphoneOut,
-        FairlightAudioMixerInputSourceProperties,
+        FairlightAudioInputSourceState, FairlightAudioMixerInputSourceProperties,
         FairlightAudioMixerMasterOutEqualiserBandProperties, FairlightAudioMixerTally,
         FairlightEqualiserBandRangeCapabilities, HeadphoneOutputCapabilities,
+        SetFairlightAudioMixerInputSourceProperties,
     },
     ftb::{
         CutToBlack, FadeToBlackAuto, FadeToBlackParams, FadeToBlackStatus, SetFadeToBlackParams,
     },
+    hyperdeck::{
+        HyperDeckClipPlayStatus, HyperDeckConnectionState, HyperDeckStatus,
+        HyperDeckTransportState, SetHyperDeckClipPlayStatus,
+    },
     initialisation::InitialisationComplete,
-    inpr::InputProperties,
-    macros::MacroCapabilities,
+    inpr::{InputProperties, SetInputProperties},
+    key::{KeyDVEProperties, SetKeyDVEProperties},
+    macros::{
+        MacroAction, MacroActionType, MacroCapabilities, MacroProperties, MacroRecordStatus,
+        MacroStartRecord, NO_MACRO,
+    },
     media_player::{
-        CaptureStill, MediaPlayerCapabilities, MediaPlayerFrameDescription, MediaPlayerSource,
-        MediaPlayerSourceID, SetMediaPlayerSource, CAPTURE_STILL,
+        CaptureStill, MediaPlayerAudioEntry, MediaPlayerCapabilities, MediaPlayerFrameDescription,
+        MediaPlayerSource, MediaPlayerSourceID, SetMediaPlayerAudio, SetMediaPlayerSource,
+        StillCaptureAvailability, CAPTURE_STILL,
     },
     mfg_test::{MfgTest, MfgTestResult},
     mix_effect::{
         MixEffectBlockCapabilities, PreviewInput, ProgramInput, SetPreviewInput, SetProgramInput,
     },
+    multiview::{
+        MultiViewCapabilities, MultiViewSafeArea, MultiViewVuMeterEnabled,
+        SetMultiViewVuMeterEnabled,
+    },
+    power::{PowerStatus, PowerSupplies},
     recording::{
-        RecordToMedia, RecordToMediaDurationRequest, RecordToMediaRecordingTimecode,
+        RecordStatus, RecordToMedia, RecordToMediaDurationRequest, RecordToMediaRecordingTimecode,
         RecordToMediaStatus, RecordToMediaSwitchDisk, RECORD_TO_MEDIA_DURATION_REQUEST,
         RECORD_TO_MEDIA_SWITCH_DISK,
     },
     remote_source::{RemoteSourceForceInternetProbe, REMOTE_SOURCE_FORCE_INTERNET_PROBE},
-    rtmp::{RtmpDurationRequest, RTMP_DURATION_REQUEST},
+    rtmp::{
+        RtmpDurationRequest, StreamRtmp, StreamRtmpStatistics, StreamRtmpStatus, StreamingStatus,
+        RTMP_DURATION_REQUEST,
+    },
+    sdi::{Sdi3gLevel, Sdi3gOutputLevel, SetSdi3gOutputLevel},
     settings::{
         ClearSettings, RestoreSettings, SaveSettings, CLEAR_STARTUP_SETTINGS,
         RESTORE_STARTUP_SETTINGS, SAVE_STARTUP_SETTINGS,
     },
     storage::{
-        ClearMediaPool, FileTransferChunkParams, FileTransferError, FileType, FinishFileDownload,
-        LockObtained, MediaPoolLock, MediaPoolLockStatus, SetupFileDownload, SetupFileUpload,
-        TransferAck, TransferChunk, TransferCompleted, CLEAR_MEDIA_POOL,
+        CancelFileDownload, ClearMediaPool, FileTransferChunkParams, FileTransferError, FileType,
+        FinishFileDownload, LockObtained, MediaPoolLock, MediaPoolLockStatus, SetupFileDownload,
+        SetupFileUpload, TransferAck, TransferChunk, TransferCompleted, UnknownCCST,
+        CLEAR_MEDIA_POOL,
+    },
+    super_source::{
+        SetSuperSourceBoxProperties, SuperSourceBoxParams, SuperSourceBoxProperties,
+        SUPER_SOURCE_BOXES,
     },
+    talkback::{AudioTalkbackMixerProperties, SetAudioTalkbackMixerProperties},
     tally::TalliedSources,
     time::{
         SetTimeOfDay, SetTimecodeConfig, Time, TimeMode, TimecodeConfig, TimecodeRequest,
         TIMECODE_REQUEST,
     },
     topology::Topology,
-    transitions::{Auto, Cut, DVECapabilities, TransitionPosition},
+    transitions::{
+        Auto, Cut, DVECapabilities, DVETransitionParams, SetTransitionDVEProperties,
+        SetTransitionPreview, SetTransitionSettings, SetTransitionWipeProperties,
+        TransitionDVEProperties, TransitionPosition, TransitionPreview, TransitionSettings,
+        TransitionWipeProperties, WipeTransitionParams,
+    },
     ver::{ProductName, Version},
-    video_mode::{CoreVideoMode, SetVideoMode, SupportedVideoModes},
+    video_mode::{
+        Colorimetry, ColorimetryMode, CoreVideoMode, SetVideoMode, SupportedVideoMode,
+        SupportedVideoModes,
+    },
     visca::{Visca422AutoAllocateAddresses, VISCA_422_AUTO_ALLOCATE_ADDRESSES},
+    warning::WarningMessage,
 };
 
 /// Structure for BEP atoms.
@@ -145,9 +200,22 @@ pub use self::{
 /// * `(length - 8)` bytes: payload
 ///
 /// The atom type identifier is parsed as `magic` in [`Payload`][].
+///
+/// ## Lossy UTF-8 decoding
+///
+/// By default, a name (input label, media pool file name, macro name, etc.)
+/// which isn't valid UTF-8 fails to parse with
+/// [`Error::Utf8`][crate::Error::Utf8], and the [`Atom`][] containing it is
+/// parsed as [`Payload::Unknown`][] instead.
+///
+/// When read with the `lossy_utf8` argument set, invalid UTF-8 sequences are
+/// replaced with [`U+FFFD`][std::char::REPLACEMENT_CHARACTER], so that a
+/// single bad name (eg: one set with a Latin-1 encoding) doesn't break
+/// initialisation.
 #[binrw]
 #[derive(Clone, PartialEq, Eq)]
 #[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
 #[bw(stream = r, map_stream = OffsetCounter::new)]
 pub struct Atom {
     // Length for the read path
@@ -158,7 +226,7 @@ pub struct Atom {
 
     /// Atom payload.
     // On read, length includes 4 extra bytes (length field and padding).
-    #[br(map_stream = |reader| { reader.take_seek(u64::from(length) - 4) }, pad_size_to = length - 4)]
+    #[br(args { lossy_utf8 }, map_stream = |reader| { reader.take_seek(u64::from(length) - 4) }, pad_size_to = length - 4)]
     // On write, we haven't written the `length` field yet, and we'll come back to it.
     #[bw(pad_before = 4)]
     pub payload: Payload,
@@ -180,23 +248,47 @@ impl std::fmt::Debug for Atom {
     }
 }
 
+/// Conversion between an [`Atom`][]'s payload type and its raw payload bytes,
+/// without the [`Atom`][] headers (length and type identifier).
+///
+/// This is implemented for every [`Payload`][] variant, and is useful for
+/// storing individual commands outside of an [`Atom`][] or
+/// [`AtemPacket`][].
+pub trait AtomPayload: Sized {
+    /// Atom type identifier (FourCC) for this payload.
+    const MAGIC: [u8; 4];
+
+    /// Serialises this payload to bytes.
+    fn to_bytes(&self) -> Result<Vec<u8>>;
+
+    /// Parses a payload from bytes.
+    ///
+    /// Like [`Atom`][], any trailing bytes after the payload are ignored.
+    fn from_bytes(payload: &[u8]) -> Result<Self>;
+}
+
 macro_rules! atom_payloads {
     (
-        $($magic:expr => $variant:ident,)*
+        $(#[$meta:meta])*
+        $($magic:literal => $variant:ident $(($args:ident))?,)*
     ) => {
         /// [`Atom`][] payload variants.
         ///
         /// When read or written with `binrw` traits, the first 4 bytes contain the atom's type
         /// identifier (`magic`), and all following bytes are the payload variant's parameters
         /// (which may be empty, if the [`Atom`'s][Atom] length is 8).
+        ///
+        /// Variants marked with `(lossy_utf8)` contain names, and are passed the
+        /// [`Atom`'s `lossy_utf8` argument][Atom#lossy-utf-8-decoding].
         #[binrw]
         #[brw(big)]
+        $(#[$meta])*
         #[derive(Clone, PartialEq, Eq)]
         pub enum Payload {
             $(
                 #[doc = concat!("`", stringify!($magic), "`: [`", stringify!($variant), "`][]")]
                 #[brw(magic = $magic)]
-                $variant($variant),
+                $variant($(#[br(args { $args })])? $variant),
             )*
 
             /// Unknown payload type.
@@ -211,8 +303,32 @@ macro_rules! atom_payloads {
                     Payload::$variant(p)
                 }
             }
+
+            impl AtomPayload for $variant {
+                const MAGIC: [u8; 4] = *$magic;
+
+                fn to_bytes(&self) -> Result<Vec<u8>> {
+                    let mut o = Cursor::new(Vec::new());
+                    self.write_be(&mut o)?;
+                    Ok(o.into_inner())
+                }
+
+                fn from_bytes(payload: &[u8]) -> Result<Self> {
+                    Ok(Self::read_be(&mut Cursor::new(payload))?)
+                }
+            }
         )*
 
+        impl Payload {
+            /// Gets the atom type identifier (FourCC) of this payload.
+            pub fn magic(&self) -> [u8; 4] {
+                match self {
+                    $(Self::$variant(_) => *$magic,)*
+                    Self::Unknown(cmd, _) => *cmd,
+                }
+            }
+        }
+
         impl Debug for Payload {
             fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
                 match self {
@@ -234,30 +350,56 @@ macro_rules! atom_payloads {
 }
 
 atom_payloads!(
+    #[br(import { lossy_utf8: bool = false })]
     b"_DVE" => DVECapabilities,
     b"_FAC" => CapabilitiesFairlightAudioMixer,
     b"_FEC" => FairlightEqualiserBandRangeCapabilities,
     b"_FMH" => CapabilitiesFairlightAudioMixerHeadphoneOut,
     b"_MAC" => MacroCapabilities,
     b"_MeC" => MixEffectBlockCapabilities,
+    b"_MvC" => MultiViewCapabilities,
     b"_mpl" => MediaPlayerCapabilities,
-    b"_pin" => ProductName,
+    b"_pin" => ProductName(lossy_utf8),
     b"_top" => Topology,
     b"_ver" => Version,
     b"_VMC" => SupportedVideoModes,
     b"AMBP" => FairlightAudioMixerMasterOutEqualiserBandProperties,
+    b"AMIP" => AudioMixerInputProperties,
+    b"AMMO" => AudioMixerMasterOutProperties,
+    b"ATMP" => AudioTalkbackMixerProperties,
+    b"AuxS" => AuxSource,
+    b"C3sl" => SetSdi3gOutputLevel,
+    b"CAMI" => SetAudioMixerInputProperties,
+    b"CapA" => StillCaptureAvailability,
     b"Capt" => CaptureStill,
+    b"CATM" => SetAudioTalkbackMixerProperties,
+    b"CAuS" => SetAuxSource,
     b"CCdP" => CameraControl,
     b"CClV" => SetColourGeneratorParams,
     b"CCmd" => CameraCommand,
+    b"CCST" => UnknownCCST,
+    b"CDsL" => SetDownstreamKeyerOnAir,
+    b"CFSP" => SetFairlightAudioMixerInputSourceProperties,
+    b"CInL" => SetInputProperties(lossy_utf8),
+    b"CKDV" => SetKeyDVEProperties,
     b"CLMP" => ClearMediaPool,
+    b"ClrM" => Colorimetry,
     b"ColV" => ColourGeneratorParams,
     b"CPgI" => SetProgramInput,
     b"CPvI" => SetPreviewInput,
+    b"CSBP" => SetSuperSourceBoxProperties,
     b"CTCC" => SetTimecodeConfig,
+    b"CTDv" => SetTransitionDVEProperties,
+    b"CTPr" => SetTransitionPreview,
+    b"CTTp" => SetTransitionSettings,
+    b"CTWp" => SetTransitionWipeProperties,
     b"CVdM" => SetVideoMode,
+    b"CXCP" => SetHyperDeckClipPlayStatus,
     b"DAut" => Auto,
     b"DCut" => Cut,
+    b"DDsA" => DownstreamKeyerAuto,
+    b"DskP" => DownstreamKeyerProperties,
+    b"DskS" => DownstreamKeyerStatus,
     b"FASP" => FairlightAudioMixerInputSourceProperties,
     b"FCut" => CutToBlack,
     b"FMTl" => FairlightAudioMixerTally,
@@ -265,24 +407,32 @@ atom_payloads!(
     b"FtbC" => SetFadeToBlackParams,
     b"FtbP" => FadeToBlackParams,
     b"FtbS" => FadeToBlackStatus,
+    b"FTAD" => CancelFileDownload,
     b"FTCD" => FileTransferChunkParams,
     b"FTDa" => TransferChunk,
     b"FTDC" => TransferCompleted,
     b"FTDE" => FileTransferError,
-    b"FTFD" => FinishFileDownload,
+    b"FTFD" => FinishFileDownload(lossy_utf8),
     b"FTSD" => SetupFileDownload,
     b"FTSU" => SetupFileUpload,
     b"FTUA" => TransferAck,
     b"InCm" => InitialisationComplete,
-    b"InPr" => InputProperties,
+    b"InPr" => InputProperties(lossy_utf8),
+    b"KeDV" => KeyDVEProperties,
     b"LKOB" => LockObtained,
     b"LKST" => MediaPoolLockStatus,
     b"LOCK" => MediaPoolLock,
+    b"MAct" => MacroAction,
     b"MfgR" => MfgTestResult,
     b"MfgT" => MfgTest,
+    b"MPAS" => MediaPlayerAudioEntry(lossy_utf8),
     b"MPCE" => MediaPlayerSource,
-    b"MPfe" => MediaPlayerFrameDescription,
+    b"MPfe" => MediaPlayerFrameDescription(lossy_utf8),
+    b"MPrp" => MacroProperties(lossy_utf8),
     b"MPSS" => SetMediaPlayerSource,
+    b"MRcS" => MacroRecordStatus,
+    b"MSRc" => MacroStartRecord(lossy_utf8),
+    b"Powr" => PowerStatus,
     b"PrgI" => ProgramInput,
     b"PrvI" => PreviewInput,
     b"PZSA" => Visca422AutoAllocateAddresses,
@@ -292,28 +442,44 @@ atom_payloads!(
     b"RSip" => RemoteSourceForceInternetProbe,
     b"RTMR" => RecordToMediaRecordingTimecode,
     b"RTMS" => RecordToMediaStatus,
+    b"RXCP" => HyperDeckClipPlayStatus,
+    b"RXSS" => HyperDeckStatus,
+    b"SaMw" => MultiViewSafeArea,
+    b"SMPA" => SetMediaPlayerAudio(lossy_utf8),
     b"SRcl" => ClearSettings,
     b"SRDR" => RtmpDurationRequest,
     b"SRrs" => RestoreSettings,
+    b"SRSS" => StreamRtmpStatistics,
     b"SRsv" => SaveSettings,
+    b"SSBP" => SuperSourceBoxProperties,
     b"SToD" => SetTimeOfDay,
+    b"StrR" => StreamRtmp,
+    b"StRS" => StreamRtmpStatus,
     b"TCCc" => TimecodeConfig,
+    b"TDvP" => TransitionDVEProperties,
     b"Time" => Time,
     b"TiRq" => TimecodeRequest,
     b"TlSr" => TalliedSources,
+    b"TrPr" => TransitionPreview,
     b"TrPs" => TransitionPosition,
+    b"TrSS" => TransitionSettings,
+    b"TWpP" => TransitionWipeProperties,
+    b"V3sl" => Sdi3gOutputLevel,
     b"VidM" => CoreVideoMode,
+    b"VuMC" => MultiViewVuMeterEnabled,
+    b"VuMS" => SetMultiViewVuMeterEnabled,
+    b"Warn" => WarningMessage(lossy_utf8),
 );
 
 impl Atom {
     /// Minimum size of an [Atom], including all headers (length + padding + magic).
-    const HEADERS_LENGTH: u16 = 8;
+    pub(crate) const HEADERS_LENGTH: u16 = 8;
 
     /// Maximum size of an [Atom], including all headers (length + padding + magic).
     const MAX_ATOM_LENGTH: u16 = AtemPacket::MAX_PAYLOAD_LENGTH;
 
     /// Maximum command payload size (minus [Atom] headers).
-    const MAX_PAYLOAD_LENGTH: u16 = Self::MAX_ATOM_LENGTH - Self::HEADERS_LENGTH;
+    pub const MAX_PAYLOAD_LENGTH: u16 = Self::MAX_ATOM_LENGTH - Self::HEADERS_LENGTH;
 
     pub fn new(payload: impl Into<Payload>) -> Self {
         Self {
@@ -336,3 +502,38 @@ fn str_from_utf8_null(p: &[u8]) -> Result<&str> {
     let p = p.split(|c| *c == 0).next().unwrap_or(p);
     Ok(std::str::from_utf8(p)?)
 }
+
+/// Parses a byte slice as an _optionally_-null-terminated, UTF-8-encoded
+/// string like [`str_from_utf8_null`], returning an owned [String].
+///
+/// If `lossy_utf8` is set, invalid UTF-8 sequences are replaced rather than
+/// returning an error.
+fn string_from_utf8_null(p: &[u8], lossy_utf8: bool) -> Result<String> {
+    if lossy_utf8 {
+        let p = p.split(|c| *c == 0).next().unwrap_or(p);
+        Ok(String::from_utf8_lossy(p).into_owned())
+    } else {
+        str_from_utf8_null(p).map(str::to_string)
+    }
+}
+
+/// Replaces invalid UTF-8 sequences in a fixed-length, _optionally_-null
+/// terminated string, so that it can be read with [`str_from_utf8_null`].
+///
+/// Replacement characters are longer than the bytes they replace, so the
+/// string may be truncated to fit.
+fn lossy_utf8_array<const N: usize>(p: [u8; N]) -> [u8; N] {
+    if str_from_utf8_null(&p).is_ok() {
+        return p;
+    }
+
+    let mut o = [0; N];
+    let mut l = 0;
+    for c in String::from_utf8_lossy(p.split(|c| *c == 0).next().unwrap_or(&p)).chars() {
+        if l + c.len_utf8() > N {
+            break;
+        }
+        l += c.encode_utf8(&mut o[l..]).len();
+    }
+    o
+}
@@ -1,10 +1,9 @@
-//! # Multiview; 0/16 atoms
+//! # Multiview; 4/16 atoms
 //!
-//! ## Unimplemented atoms (16)
+//! ## Unimplemented atoms (12)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
-//! `_MvC` | `CapabilitiesMultiView` | 0x14
 //! `CMvI` | `ChangeMultiViewInput` | 0xc
 //! `CMVM` | `ChangeMultiViewVideoMode` | 0xc
 //! `CMvO` | `ChangeMultiViewOverlayVisibility` | 0x10
@@ -15,8 +14,202 @@
 //! `MvOv` | `MultiViewOverlayVisibility` | 0xc
 //! `MvPr` | `MultiViewProperties` | 0xc
 //! `MvVM` | `MultiViewVideoMode` | 0xc
-//! `SaMw` | `MultiViewSafeArea` | 0xc
 //! `StMw` | `MultiViewSafeAreaType` | 0xc
-//! `VuMC` | `MultiViewVuMeterEnabledChanged` | 0xc
 //! `VuMo` | `MultiViewVuMeterOpacity` | 0xc
-//! `VuMS` | `SetMultiViewVuMeterEnabled` | 0xc
+use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// `_MvC`: MultiView capabilities (`CapabilitiesMultiView`)
+///
+/// ## Packet format
+///
+/// * `u8`: number of MultiViews
+/// * `u8`: number of windows on each MultiView
+/// * 10 bytes unknown
+///
+/// The unknown bytes probably describe which MultiView features the switcher
+/// supports, but their layout hasn't been verified.
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiViewCapabilities {
+    pub multiviews: u8,
+    /// Number of windows on each MultiView.
+    #[brw(pad_after = 10)]
+    pub windows: u8,
+}
+
+/// `SaMw`: multiview window safe area overlay (`MultiViewSafeArea`)
+///
+/// This is sent by the switcher to indicate the current state of the safe area
+/// overlay on a multiview window, and by a client to change it.
+///
+/// ## Packet format
+///
+/// * `u8`: multiview ID
+/// * `u8`: window ID
+/// * `bool`: safe area overlay enabled
+/// * 1 byte padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiViewSafeArea {
+    pub multiview: u8,
+    pub window: u8,
+    #[brw(pad_after = 1)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub enabled: bool,
+}
+
+/// `VuMC`: multiview window VU meter overlay changed
+/// (`MultiViewVuMeterEnabledChanged`)
+///
+/// ## Packet format
+///
+/// * `u8`: multiview ID
+/// * `u8`: window ID
+/// * `bool`: VU meter overlay enabled
+/// * 1 byte padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct MultiViewVuMeterEnabled {
+    pub multiview: u8,
+    pub window: u8,
+    #[brw(pad_after = 1)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub enabled: bool,
+}
+
+/// `VuMS`: set multiview window VU meter overlay
+/// (`SetMultiViewVuMeterEnabled`)
+///
+/// ## Packet format
+///
+/// * `u8`: multiview ID
+/// * `u8`: window ID
+/// * `bool`: VU meter overlay enabled
+/// * 1 byte padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetMultiViewVuMeterEnabled {
+    pub multiview: u8,
+    pub window: u8,
+    #[brw(pad_after = 1)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub enabled: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    #[test]
+    fn capabilities() -> Result {
+        // Synthetic example, not captured from hardware
+        let expected = MultiViewCapabilities {
+            multiviews: 1,
+            windows: 10,
+        };
+        let cmd = hex::decode("001400005f4d7643010a00000000000000000000")?;
+        let mvc = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::MultiViewCapabilities(mvc) = mvc.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, mvc);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn safe_area() -> Result {
+        for (cmd, expected) in [
+            (
+                "000c000053614d7700030100",
+                MultiViewSafeArea {
+                    multiview: 0,
+                    window: 3,
+                    enabled: true,
+                },
+            ),
+            (
+                "000c000053614d7701090000",
+                MultiViewSafeArea {
+                    multiview: 1,
+                    window: 9,
+                    enabled: false,
+                },
+            ),
+        ] {
+            let cmd = hex::decode(cmd)?;
+            let samw = Atom::read(&mut Cursor::new(&cmd))?;
+            let Payload::MultiViewSafeArea(samw) = samw.payload else {
+                panic!("wrong command type");
+            };
+            assert_eq!(expected, samw);
+
+            let o = Atom::new(expected);
+            let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+            o.write(&mut out)?;
+            assert_eq!(cmd, out.into_inner());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn vu_meter() -> Result {
+        let expected = MultiViewVuMeterEnabled {
+            multiview: 0,
+            window: 5,
+            enabled: true,
+        };
+        let cmd = hex::decode("000c000056754d4300050100")?;
+        let vumc = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::MultiViewVuMeterEnabled(vumc) = vumc.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, vumc);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        let expected = SetMultiViewVuMeterEnabled {
+            multiview: 0,
+            window: 5,
+            enabled: false,
+        };
+        let cmd = hex::decode("000c000056754d5300050000")?;
+        let vums = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetMultiViewVuMeterEnabled(vums) = vums.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, vums);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
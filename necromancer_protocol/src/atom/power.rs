@@ -0,0 +1,76 @@
+//! # Power; 1/1 atoms
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{bitfield, specifiers::B6, Specifier};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Power supply status bitmask, as reported by [PowerStatus].
+#[bitfield(bits = 8)]
+#[repr(u8)]
+#[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[br(map = From::<u8>::from)]
+#[bw(map = |&x| Into::<u8>::into(x))]
+pub struct PowerSupplies {
+    /// Power supply 1 is present and supplying power.
+    pub supply1: bool,
+    /// Power supply 2 is present and supplying power.
+    pub supply2: bool,
+    #[skip]
+    __: B6,
+}
+
+impl PowerSupplies {
+    /// Gets the state of each power supply, in order.
+    pub fn to_array(&self) -> [bool; 2] {
+        [self.supply1(), self.supply2()]
+    }
+}
+
+/// `Powr`: power supply status (`PowerStatus`)
+///
+/// ## Packet format
+///
+/// * `u8`: [power supply bitmask][PowerSupplies]
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PowerStatus {
+    #[brw(pad_after = 3)]
+    pub supplies: PowerSupplies,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn power_status() -> Result<()> {
+        // Two power supplies, only the second one is supplying power
+        let expected = PowerStatus {
+            supplies: PowerSupplies::new().with_supply2(true),
+        };
+
+        let cmd = hex::decode("000c0000506f777202000000")?;
+        let powr = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::PowerStatus(powr) = powr.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, powr);
+        assert_eq!([false, true], powr.supplies.to_array());
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
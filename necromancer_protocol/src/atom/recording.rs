@@ -20,12 +20,15 @@
 use crate::atom::Time;
 use binrw::{binrw, BinRead, BinWrite};
 use modular_bitfield::{bitfield, prelude::B7};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::ops::{Deref, DerefMut};
 
 /// `RcTM`: Record to media (`RecordToMedia`)
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RecordToMedia {
     #[brw(pad_size_to = 4)]
     #[br(map = |v: u8| v != 0)]
@@ -37,6 +40,7 @@ pub struct RecordToMedia {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RecordToMediaStatus {
     #[brw(pad_size_to = 4)]
     pub status: RecordStatus,
@@ -48,6 +52,7 @@ pub struct RecordToMediaStatus {
 #[bitfield(bits = 16)]
 #[repr(u16)]
 #[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[br(map = From::<u16>::from)]
 #[bw(map = |&x| Into::<u16>::into(x))]
 pub struct RecordStatus {
@@ -71,6 +76,7 @@ pub struct RecordStatus {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RecordToMediaDurationRequest {}
 
 pub const RECORD_TO_MEDIA_DURATION_REQUEST: RecordToMediaDurationRequest =
@@ -80,6 +86,7 @@ pub const RECORD_TO_MEDIA_DURATION_REQUEST: RecordToMediaDurationRequest =
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RecordToMediaRecordingTimecode(pub Time);
 
 impl From<Time> for RecordToMediaRecordingTimecode {
@@ -112,6 +119,7 @@ impl DerefMut for RecordToMediaRecordingTimecode {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RecordToMediaSwitchDisk {}
 
 pub const RECORD_TO_MEDIA_SWITCH_DISK: RecordToMediaSwitchDisk = RecordToMediaSwitchDisk {};
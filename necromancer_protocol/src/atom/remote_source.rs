@@ -17,6 +17,8 @@
 //! `RXML` | `RemoteSourceExternalXML` | 0x40c
 
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `RSip`: Remote source force internet probe (`RemoteSourceForceInternetProbe`)
 ///
@@ -25,6 +27,7 @@ use binrw::binrw;
 /// No payload.
 #[binrw]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RemoteSourceForceInternetProbe {}
 
 /// Command to request RTMP streaming duration.
@@ -1,6 +1,6 @@
-//! # RTMP streaming; 1/16 atoms
+//! # RTMP streaming; 4/16 atoms
 //!
-//! ## Unimplemented atoms (15)
+//! ## Unimplemented atoms (12)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
@@ -12,15 +12,14 @@
 //! `SLow` | `StreamRtmpLowLatency` | 0xc
 //! `SRES` | `StreamRtmpSrtExtensions` | 0x20c
 //! `SRSD` | `StreamRtmpStreamingDuration` | 0x10
-//! `SRSS` | `StreamRtmpStreamingStatistics` | 0x10
 //! `SRST` | `StreamRtmpStreamingTimecode` | 0x10
 //! `SRSU` | `StreamRtmpSetup` | 0x450
 //! `SSDC` | `StreamDownConvertMode` | 0xc
 //! `STAB` | `StreamRtmpAudioBitrates` | 0x10
-//! `StrR` | `StreamRTMP` | 0xc
-//! `StRS` | `StreamRtmpStatus` | 0xc
 
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `SRDR`: RTMP stream duration request (`StreamRtmpDurationRequest`)
 ///
@@ -29,7 +28,140 @@ use binrw::binrw;
 /// No payload.
 #[binrw]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RtmpDurationRequest {}
 
 /// Command to request RTMP streaming duration.
 pub const RTMP_DURATION_REQUEST: RtmpDurationRequest = RtmpDurationRequest {};
+
+/// `StrR`: start or stop RTMP streaming (`StreamRTMP`)
+///
+/// ## Packet format
+///
+/// * `bool`: start streaming
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StreamRtmp {
+    #[brw(pad_after = 3)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub start: bool,
+}
+
+/// RTMP streaming state.
+#[binrw]
+#[brw(big, repr = u16)]
+#[derive(Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u16)]
+pub enum StreamingStatus {
+    #[default]
+    Idle = 0x01,
+    Connecting = 0x02,
+    Streaming = 0x04,
+    Stopping = 0x20,
+}
+
+/// `StRS`: RTMP streaming status (`StreamRtmpStatus`)
+///
+/// ## Packet format
+///
+/// * `u16`: [streaming status][StreamingStatus]
+/// * `u16`: error code (`0` = no error)
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StreamRtmpStatus {
+    pub status: StreamingStatus,
+    pub error: u16,
+}
+
+/// `SRSS`: RTMP streaming statistics (`StreamRtmpStreamingStatistics`)
+///
+/// ## Packet format
+///
+/// * `u32`: encoding bitrate, in bits per second
+/// * `u16`: cache used
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct StreamRtmpStatistics {
+    /// Encoding bitrate, in bits per second.
+    pub encoding_bitrate: u32,
+    #[brw(pad_after = 2)]
+    pub cache_used: u16,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    #[test]
+    fn stream_rtmp() -> Result<()> {
+        let cmd = hex::decode("000c00005374725201000000")?;
+        let strr = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::StreamRtmp(strr) = strr.payload else {
+            panic!("wrong command type");
+        };
+        let expected = StreamRtmp { start: true };
+        assert_eq!(expected, strr);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn stream_rtmp_status() -> Result<()> {
+        let cmd = hex::decode("000c00005374525300040000")?;
+        let strs = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::StreamRtmpStatus(strs) = strs.payload else {
+            panic!("wrong command type");
+        };
+        let expected = StreamRtmpStatus {
+            status: StreamingStatus::Streaming,
+            error: 0,
+        };
+        assert_eq!(expected, strs);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn stream_rtmp_statistics() -> Result<()> {
+        let cmd = hex::decode("0010000053525353004c4b4000010000")?;
+        let srss = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::StreamRtmpStatistics(srss) = srss.payload else {
+            panic!("wrong command type");
+        };
+        let expected = StreamRtmpStatistics {
+            encoding_bitrate: 5_000_000,
+            cache_used: 1,
+        };
+        assert_eq!(expected, srss);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
@@ -1,5 +1,12 @@
 //! # Start-up settings; 3/3 atoms
+//!
+//! The switcher acknowledges these commands at the packet level, but doesn't
+//! send any event when it has _finished_ writing (or clearing) its start-up
+//! configuration, and there is no atom which reports whether the current
+//! settings have been saved.
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Command to save the current settings to the start-up configuration.
 pub const SAVE_STARTUP_SETTINGS: SaveSettings = SaveSettings { slot: 0 };
@@ -21,6 +28,7 @@ pub const CLEAR_STARTUP_SETTINGS: ClearSettings = ClearSettings { slot: 0 };
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SaveSettings {
     #[brw(pad_after = 3)]
     pub slot: u8,
@@ -38,6 +46,7 @@ pub struct SaveSettings {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct RestoreSettings {
     #[brw(pad_after = 3)]
     pub slot: u8,
@@ -50,6 +59,7 @@ pub struct RestoreSettings {
 /// * `u8`: Slot? Always 0 (start-up config)
 /// * 3 bytes padding
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[binrw]
 #[brw(big)]
 pub struct ClearSettings {
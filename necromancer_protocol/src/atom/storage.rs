@@ -1,4 +1,4 @@
-//! # Media pool and file transfers; 12/14 atoms
+//! # Media pool and file transfers; 13/14 atoms
 //!
 //! There are four file types:
 //!
@@ -12,8 +12,8 @@
 //! 1. Client obtains a priority lock (`PLCK`)
 //! 1. Switcher responds with:
 //!    * `LKST`: [lock status][MediaPoolLockStatus]
-//!    * `CapA`: still capture availability
-//!    * `CCST`: ??
+//!    * `CapA`: [still capture availability][super::StillCaptureAvailability]
+//!    * `CCST`: [unidentified][UnknownCCST]
 //! 1. Client [starts a file download][SetupFileDownload] (`FTSD`)
 //! 1. Switcher [indicates how to chunk the data][FileTransferChunkParams] (`FTCD`), and how many
 //!    data chunks to send before waiting for an acknowledgement.
@@ -32,23 +32,25 @@
 //! 1. Client [periodically sends acknowledgement atoms][TransferAck] (`FTUA`)
 //! 1. Switcher [indicates the transfer was completed][TransferCompleted] (`FTDC`)
 //!
-//! ## Unimplemented atoms (2)
+//! ## Unimplemented atoms (1)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
-//! `FTAD` | `FileTransferCancelDownload` | 0xc
 //! `PLCK` | `MediaPoolPriorityLock` | 0x10
 
 // colour format may be defined in BMDSwitcherPixelFormat
 
-use super::{str_from_utf8_null, Atom};
-use binrw::binrw;
+use super::{string_from_utf8_null, Atom};
+use binrw::{binrw, helpers::until_eof};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
 
 /// File type for [SetupFileDownload] and [SetupFileUpload]
 #[binrw]
 #[brw(big, repr = u8)]
 #[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum FileType {
     StillFrame = 0x00,
@@ -57,6 +59,14 @@ pub enum FileType {
     Macro = 0x03,
 }
 
+impl FileType {
+    /// Returns `true` if files of this type are transferred
+    /// [RLE-compressed][crate::rle], for [`SetupFileDownload::is_rle`].
+    pub const fn uses_rle(&self) -> bool {
+        matches!(self, Self::StillFrame | Self::MultiViewLabel)
+    }
+}
+
 /// `FTSU`: File Transfer Setup Upload (`FileTransferSetupUpload`)
 ///
 /// Used by the client to setup a data download from the switcher.
@@ -71,6 +81,7 @@ pub enum FileType {
 #[binrw]
 #[brw(big)]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetupFileUpload {
     pub id: u16,
     pub store_id: u16,
@@ -103,6 +114,7 @@ pub struct SetupFileUpload {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FileTransferChunkParams {
     /// Transfer session ID
     #[brw(pad_after = 2)]
@@ -130,7 +142,7 @@ pub struct FileTransferChunkParams {
 /// ## Packet format
 ///
 /// * `u16`: transfer ID
-/// * `u16`: store ID, (0 = still images)
+/// * `u16`: store ID, (0 = still images, 0xffff = multi-view labels)
 /// * `u32`: storage index / slot
 /// * `u32`: uncompressed payload size
 /// * `u8`: storage type
@@ -141,6 +153,7 @@ pub struct FileTransferChunkParams {
 #[binrw]
 #[brw(big)]
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetupFileDownload {
     /// Transfer ID
     pub id: u16,
@@ -172,17 +185,19 @@ pub struct SetupFileDownload {
 /// * 2 bytes padding
 #[binrw]
 #[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
 #[derive(Debug, Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FinishFileDownload {
     /// Transfer ID
     pub id: u16,
     /// Name of the file, up to 64 bytes. This normally doesn't contain a file
     /// extension.
-    #[br(try_map = |v: [u8; 64]| str_from_utf8_null(&v).map(str::to_string))]
+    #[br(try_map = |v: [u8; 64]| string_from_utf8_null(&v, lossy_utf8))]
     #[bw(assert(name.len() <= 64), pad_size_to = 64, map = |v: &String| { v.as_bytes().to_vec() })]
     pub name: String,
     /// Description of the file.
-    #[br(try_map = |v: [u8; 128]| str_from_utf8_null(&v).map(str::to_string))]
+    #[br(try_map = |v: [u8; 128]| string_from_utf8_null(&v, lossy_utf8))]
     #[bw(assert(description.len() <= 128), pad_size_to = 128, map = |v: &String| { v.as_bytes().to_vec() })]
     pub description: String,
     /// MD5 hash of the _uncompressed_ frame.
@@ -209,6 +224,7 @@ pub struct FinishFileDownload {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[bw(assert(payload.len() <= usize::from(Self::MAX_PAYLOAD_LENGTH)))]
 pub struct TransferChunk {
     /// Transfer ID
@@ -254,6 +270,7 @@ impl TransferChunk {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TransferAck {
     #[brw(pad_after = 2)]
     pub id: u16,
@@ -268,6 +285,7 @@ pub struct TransferAck {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TransferCompleted {
     pub id: u16,
     unknown: u16,
@@ -283,6 +301,7 @@ pub struct TransferCompleted {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct FileTransferError {
     /// Transfer ID
     pub id: u16,
@@ -291,6 +310,25 @@ pub struct FileTransferError {
     pub code: u8,
 }
 
+/// `FTAD`: cancel file download (`FileTransferCancelDownload`)
+///
+/// Used by the client to abandon a [file download][SetupFileDownload] (client
+/// to switcher) which is still in progress.
+///
+/// ## Packet format
+///
+/// * `u16`: transfer ID
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct CancelFileDownload {
+    /// Transfer ID
+    #[brw(pad_after = 2)]
+    pub id: u16,
+}
+
 /// `LOCK`: obtain media pool lock (`MediaPoolLock`)
 ///
 /// ## Packet format
@@ -301,6 +339,7 @@ pub struct FileTransferError {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MediaPoolLock {
     store_id: u16,
     #[brw(pad_after = 1)]
@@ -338,6 +377,7 @@ impl MediaPoolLock {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct LockObtained {
     #[brw(pad_after = 2)]
     pub store_id: u16,
@@ -359,6 +399,7 @@ impl LockObtained {
 #[binrw]
 #[brw(big)]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct MediaPoolLockStatus {
     pub store_id: u16,
     #[brw(pad_after = 1)]
@@ -374,18 +415,58 @@ pub struct MediaPoolLockStatus {
 /// No payload.
 #[binrw]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct ClearMediaPool {}
 
 /// Command to clear the media pool.
 pub const CLEAR_MEDIA_POOL: ClearMediaPool = ClearMediaPool {};
 
+/// `CCST`: unidentified atom, sent by the switcher after a
+/// [storage lock][MediaPoolLock] request.
+///
+/// This atom doesn't appear in `BMDSwitcherAPI`, so its name and purpose are
+/// unknown. It is parsed so that it isn't reported as an unsupported event
+/// on every file transfer.
+///
+/// ## Packet format
+///
+/// Unknown; the payload is retained as-is.
+#[binrw]
+#[brw(big)]
+#[derive(Default, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct UnknownCCST {
+    #[br(parse_with = until_eof)]
+    pub payload: Vec<u8>,
+}
+
+impl Debug for UnknownCCST {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UnknownCCST")
+            .field("payload", &hex::encode(&self.payload))
+            .finish()
+    }
+}
+
 #[cfg(test)]
 mod test {
     use binrw::{BinRead, BinWrite};
     use std::io::Cursor;
 
     use super::*;
-    use crate::{atom::Payload, Result};
+    use crate::{
+        atom::Payload,
+        packet::{AtemPacket, AtemPacketFlags},
+        Result,
+    };
+
+    #[test]
+    fn file_type_uses_rle() {
+        assert!(FileType::StillFrame.uses_rle());
+        assert!(FileType::MultiViewLabel.uses_rle());
+        assert!(!FileType::Audio.uses_rle());
+        assert!(!FileType::Macro.uses_rle());
+    }
 
     #[test]
     fn complete_chunk() -> Result<()> {
@@ -458,6 +539,23 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn cancel_file_download() -> Result<()> {
+        let expected = CancelFileDownload { id: 10420 };
+        let cmd: Vec<u8> = hex::decode("000c00004654414428b40000")?;
+        let cancel = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::CancelFileDownload(cancel) = cancel.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, cancel);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
     #[test]
     fn transfer_completed() -> Result<()> {
         let _ = tracing_subscriber::fmt().try_init();
@@ -671,4 +769,66 @@ mod test {
         assert_eq!(cmd, out.into_inner());
         Ok(())
     }
+
+    #[test]
+    fn ccst() -> Result<()> {
+        let expected = UnknownCCST {
+            payload: vec![0, 1, 0, 0],
+        };
+        let cmd: Vec<u8> = hex::decode("000c00004343535400010000")?;
+        let ccst = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::UnknownCCST(ccst) = ccst.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, ccst);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    /// The switcher's response to a storage lock request should not contain
+    /// any unknown atoms.
+    #[test]
+    fn lock_sequence() -> Result<()> {
+        let expected = AtemPacket::new_atoms(
+            AtemPacketFlags::new().with_ack(true),
+            0x8001,
+            0,
+            0,
+            0x10,
+            vec![
+                Atom::new(MediaPoolLockStatus {
+                    store_id: 0,
+                    locked: true,
+                }),
+                Atom::new(crate::atom::StillCaptureAvailability { available: true }),
+                Atom::new(UnknownCCST {
+                    payload: vec![0, 1, 0, 0],
+                }),
+            ],
+        );
+        // modified to remove uninitialised memory
+        let cmd: Vec<u8> = hex::decode(concat!(
+            "08308001000000000000",
+            "0010",
+            "000c00004c4b535400000100",
+            "000c00004361704101000000",
+            "000c00004343535400010000",
+        ))?;
+        let pkt = AtemPacket::read(&mut Cursor::new(&cmd))?;
+        let atoms = pkt.atoms().expect("wrong payload type");
+        assert_eq!(3, atoms.len());
+        assert!(!atoms
+            .iter()
+            .any(|a| matches!(a.payload, Payload::Unknown(..))));
+        assert_eq!(expected, pkt);
+
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        expected.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
 }
@@ -1,17 +1,399 @@
-//! # SuperSource; 0/11 atoms
+//! # SuperSource; 2/11 atoms
 //!
-//! ## Unimplemented atoms (11)
+//! ## Unimplemented atoms (9)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
 //! `_SSC` | `CapabilitiesSuperSource` | 0xc
 //! `CSBd` | `ChangeSuperSourceBorderParameters` | 0x20
-//! `CSBP` | `ChangeSuperSourceBoxParameters` | 0x20
 //! `CSSB` | `ChangeSuperSourceBoxBorderParameters` | 0x20
 //! `CSSc` | `ChangeSuperSourceParameters` | 0x18
 //! `SSBd` | `SuperSourceBorderParameters` | 0x20
-//! `SSBP` | `SuperSourceBoxParameters` | 0x20
 //! `SSBV` | `SetSuperSourceBoxVelocity` | 0x18
 //! `SSCs` | `SuperSourceCascade` | 0xc
 //! `SSrc` | `SuperSourceParameters` | 0x18
 //! `SSSB` | `SuperSourceBoxBorderParameters` | 0x20
+use crate::structs::VideoSource;
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{bitfield, specifiers::B6};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use std::ops::RangeInclusive;
+
+/// Number of boxes on each SuperSource.
+///
+/// Switchers report this in `_SSC`, which isn't implemented yet; every
+/// switcher with a SuperSource has 4 boxes.
+pub const SUPER_SOURCE_BOXES: u8 = 4;
+
+/// Position, size and crop of a SuperSource box.
+///
+/// ## Format
+///
+/// * `bool`: enabled
+/// * 1 byte padding
+/// * `u16`: video source
+/// * `i16`: horizontal position
+/// * `i16`: vertical position
+/// * `u16`: size
+/// * `bool`: cropped
+/// * 1 byte padding
+/// * `u16`: crop top
+/// * `u16`: crop bottom
+/// * `u16`: crop left
+/// * `u16`: crop right
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SuperSourceBoxParams {
+    /// The box is shown.
+    #[brw(pad_after = 1)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub enabled: bool,
+
+    /// Video source shown in the box.
+    pub video_source: VideoSource,
+
+    /// Horizontal position of the centre of the box, in hundredths
+    /// ([`X_RANGE`][Self::X_RANGE], `0` = centre).
+    pub x: i16,
+
+    /// Vertical position of the centre of the box, in hundredths
+    /// ([`Y_RANGE`][Self::Y_RANGE], `0` = centre).
+    pub y: i16,
+
+    /// Size, in thousandths ([`SIZE_RANGE`][Self::SIZE_RANGE], `1000` =
+    /// 100%).
+    pub size: u16,
+
+    /// The box is cropped.
+    #[brw(pad_after = 1)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub cropped: bool,
+
+    /// Crop from the top edge, in thousandths
+    /// (up to [`MAX_CROP_VERTICAL`][Self::MAX_CROP_VERTICAL]).
+    pub crop_top: u16,
+
+    /// Crop from the bottom edge, in thousandths
+    /// (up to [`MAX_CROP_VERTICAL`][Self::MAX_CROP_VERTICAL]).
+    pub crop_bottom: u16,
+
+    /// Crop from the left edge, in thousandths
+    /// (up to [`MAX_CROP_HORIZONTAL`][Self::MAX_CROP_HORIZONTAL]).
+    pub crop_left: u16,
+
+    /// Crop from the right edge, in thousandths
+    /// (up to [`MAX_CROP_HORIZONTAL`][Self::MAX_CROP_HORIZONTAL]).
+    pub crop_right: u16,
+}
+
+impl SuperSourceBoxParams {
+    /// Valid range of [`x`][Self::x].
+    pub const X_RANGE: RangeInclusive<i16> = -4800..=4800;
+
+    /// Valid range of [`y`][Self::y].
+    pub const Y_RANGE: RangeInclusive<i16> = -2700..=2700;
+
+    /// Valid range of [`size`][Self::size]: from 7% to 100%.
+    pub const SIZE_RANGE: RangeInclusive<u16> = 70..=1000;
+
+    /// Maximum value of [`crop_top`][Self::crop_top] and
+    /// [`crop_bottom`][Self::crop_bottom].
+    pub const MAX_CROP_VERTICAL: u16 = 18000;
+
+    /// Maximum value of [`crop_left`][Self::crop_left] and
+    /// [`crop_right`][Self::crop_right].
+    pub const MAX_CROP_HORIZONTAL: u16 = 32000;
+
+    /// Returns `true` if every parameter is within its valid range.
+    pub fn is_in_range(&self) -> bool {
+        Self::X_RANGE.contains(&self.x)
+            && Self::Y_RANGE.contains(&self.y)
+            && Self::SIZE_RANGE.contains(&self.size)
+            && self.crop_top <= Self::MAX_CROP_VERTICAL
+            && self.crop_bottom <= Self::MAX_CROP_VERTICAL
+            && self.crop_left <= Self::MAX_CROP_HORIZONTAL
+            && self.crop_right <= Self::MAX_CROP_HORIZONTAL
+    }
+}
+
+/// `SSBP`: SuperSource box parameters (`SuperSourceBoxParameters`)
+///
+/// ## Packet format
+///
+/// * `u8`: SuperSource
+/// * `u8`: box
+/// * 20 bytes: [SuperSourceBoxParams]
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SuperSourceBoxProperties {
+    pub super_source: u8,
+    pub box_id: u8,
+    #[brw(pad_after = 2)]
+    pub params: SuperSourceBoxParams,
+}
+
+#[bitfield(bits = 16)]
+#[repr(u16)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u16>::from)]
+#[bw(map = |&x| Into::<u16>::into(x))]
+struct SetSuperSourceBoxMask {
+    pub enabled: bool,
+    pub video_source: bool,
+    pub x: bool,
+    pub y: bool,
+    pub size: bool,
+    pub cropped: bool,
+    pub crop_top: bool,
+    pub crop_bottom: bool,
+    pub crop_left: bool,
+    pub crop_right: bool,
+    #[skip]
+    __: B6,
+}
+
+/// `CSBP`: set SuperSource box parameters
+/// (`ChangeSuperSourceBoxParameters`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// See [SuperSourceBoxParams] for units.
+///
+/// ## Packet format
+///
+/// * `u16`: setting mask
+/// * `u8`: SuperSource
+/// * `u8`: box
+/// * `bool`: enabled
+/// * 1 byte padding
+/// * `u16`: video source
+/// * `i16`: horizontal position
+/// * `i16`: vertical position
+/// * `u16`: size
+/// * `bool`: cropped
+/// * 1 byte padding
+/// * `u16`: crop top
+/// * `u16`: crop bottom
+/// * `u16`: crop left
+/// * `u16`: crop right
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetSuperSourceBoxProperties {
+    #[br(temp)]
+    #[bw(calc(
+        SetSuperSourceBoxMask::new()
+            .with_enabled(self.enabled.is_some())
+            .with_video_source(self.video_source.is_some())
+            .with_x(self.x.is_some())
+            .with_y(self.y.is_some())
+            .with_size(self.size.is_some())
+            .with_cropped(self.cropped.is_some())
+            .with_crop_top(self.crop_top.is_some())
+            .with_crop_bottom(self.crop_bottom.is_some())
+            .with_crop_left(self.crop_left.is_some())
+            .with_crop_right(self.crop_right.is_some())
+    ))]
+    mask: SetSuperSourceBoxMask,
+
+    pub super_source: u8,
+    pub box_id: u8,
+
+    #[brw(pad_after = 1)]
+    #[br(map(|v: u8| mask.enabled().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub enabled: Option<bool>,
+
+    #[br(map(|v: VideoSource| mask.video_source().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub video_source: Option<VideoSource>,
+
+    #[br(map(|v: i16| mask.x().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub x: Option<i16>,
+
+    #[br(map(|v: i16| mask.y().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub y: Option<i16>,
+
+    #[br(map(|v: u16| mask.size().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub size: Option<u16>,
+
+    #[brw(pad_after = 1)]
+    #[br(map(|v: u8| mask.cropped().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub cropped: Option<bool>,
+
+    #[br(map(|v: u16| mask.crop_top().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub crop_top: Option<u16>,
+
+    #[br(map(|v: u16| mask.crop_bottom().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub crop_bottom: Option<u16>,
+
+    #[br(map(|v: u16| mask.crop_left().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub crop_left: Option<u16>,
+
+    #[br(map(|v: u16| mask.crop_right().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub crop_right: Option<u16>,
+}
+
+impl SetSuperSourceBoxProperties {
+    /// Creates a new [SetSuperSourceBoxProperties] which doesn't change
+    /// anything.
+    pub fn new(super_source: u8, box_id: u8) -> Self {
+        Self {
+            super_source,
+            box_id,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new [SetSuperSourceBoxProperties] which sets every parameter
+    /// of the box to `params`.
+    pub fn from_params(super_source: u8, box_id: u8, params: &SuperSourceBoxParams) -> Self {
+        Self {
+            super_source,
+            box_id,
+            enabled: Some(params.enabled),
+            video_source: Some(params.video_source),
+            x: Some(params.x),
+            y: Some(params.y),
+            size: Some(params.size),
+            cropped: Some(params.cropped),
+            crop_top: Some(params.crop_top),
+            crop_bottom: Some(params.crop_bottom),
+            crop_left: Some(params.crop_left),
+            crop_right: Some(params.crop_right),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use std::io::Cursor;
+
+    #[test]
+    fn super_source_box_properties() -> Result<()> {
+        let expected = SuperSourceBoxProperties {
+            super_source: 0,
+            box_id: 1,
+            params: SuperSourceBoxParams {
+                enabled: true,
+                video_source: VideoSource::Input2,
+                x: -4800,
+                y: 2700,
+                size: 500,
+                cropped: true,
+                crop_top: 1000,
+                crop_bottom: 0,
+                crop_left: 2000,
+                crop_right: 0,
+            },
+        };
+
+        let cmd = hex::decode(concat!(
+            "0020000053534250",
+            "000101000002ed400a8c01f4010003e8000007d000000000",
+        ))?;
+        let ssbp = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::SuperSourceBoxProperties(ssbp) = ssbp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, ssbp);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Negative vertical position, positive horizontal position
+        let mut cmd = cmd;
+        cmd[14..18].copy_from_slice(&[0x0c, 0x80, 0xf5, 0x74]);
+        let ssbp = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SuperSourceBoxProperties(ssbp) = ssbp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(3200, ssbp.params.x);
+        assert_eq!(-2700, ssbp.params.y);
+        Ok(())
+    }
+
+    #[test]
+    fn set_super_source_box_properties() -> Result<()> {
+        let expected = SetSuperSourceBoxProperties {
+            video_source: Some(VideoSource::Input3),
+            x: Some(-1600),
+            y: Some(-900),
+            ..SetSuperSourceBoxProperties::new(0, 2)
+        };
+
+        let cmd = hex::decode(concat!(
+            "0020000043534250",
+            "000e000200000003f9c0fc7c000000000000000000000000",
+        ))?;
+        let csbp = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::SetSuperSourceBoxProperties(csbp) = csbp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, csbp);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Masked fields should be ignored
+        let mut cmd = cmd;
+        cmd[20] = 0x03;
+        let csbp = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetSuperSourceBoxProperties(csbp) = csbp.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, csbp);
+        Ok(())
+    }
+
+    #[test]
+    fn set_super_source_box_from_params() -> Result<()> {
+        let params = SuperSourceBoxParams {
+            enabled: true,
+            video_source: VideoSource::Input1,
+            x: 1200,
+            y: -675,
+            size: 250,
+            ..Default::default()
+        };
+        let csbp = SetSuperSourceBoxProperties::from_params(0, 3, &params);
+
+        let cmd = hex::decode(concat!(
+            "0020000043534250",
+            "03ff00030100000104b0fd5d00fa00000000000000000000",
+        ))?;
+        let o = Atom::new(csbp);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
@@ -1,11 +1,132 @@
-//! # Talkback; 0/5 atoms
+//! # Talkback; 2/5 atoms
 //!
-//! ## Unimplemented atoms (5)
+//! ## Unimplemented atoms (3)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
-//! `ATMP` | `AudioTalkbackMixerProperties` | 0xc
-//! `CATM` | `ChangeAudioTalkbackMixerProperties` | 0xc
 //! `CTIP` | `ChangeAudioTalkbackInputProperties` | 0x10
 //! `FMMT` | `FairlightAudioMixerMicTalkbackGain` | 0xc
 //! `TMIP` | `AudioTalkbackInputProperties` | 0x10
+use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// `ATMP`: talkback mixer channel properties (`AudioTalkbackMixerProperties`)
+///
+/// Sent by the switcher during initialisation for each talkback channel it
+/// supports, and whenever the channel's properties change.
+///
+/// ## Packet format
+///
+/// * `u8`: talkback channel (0 = engineering, 1 = production)
+/// * `bool`: mute talkback to SDI outputs
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct AudioTalkbackMixerProperties {
+    pub channel: u8,
+    #[brw(pad_after = 2)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub mute_sdi: bool,
+}
+
+/// `CATM`: set talkback mixer channel properties
+/// (`ChangeAudioTalkbackMixerProperties`)
+///
+/// ## Packet format
+///
+/// * `u8`: set mask; always `0x01` (mute SDI)
+/// * `u8`: talkback channel (0 = engineering, 1 = production)
+/// * `bool`: mute talkback to SDI outputs
+/// * 1 byte padding
+#[binrw]
+#[brw(big, magic = 1u8)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetAudioTalkbackMixerProperties {
+    pub channel: u8,
+    #[brw(pad_after = 1)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub mute_sdi: bool,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    #[test]
+    fn talkback_mixer_properties() -> Result<()> {
+        for (expected, cmd) in [
+            (
+                AudioTalkbackMixerProperties {
+                    channel: 0,
+                    mute_sdi: true,
+                },
+                "000c000041544d5000010000",
+            ),
+            (
+                AudioTalkbackMixerProperties {
+                    channel: 1,
+                    mute_sdi: false,
+                },
+                "000c000041544d5001000000",
+            ),
+        ] {
+            let cmd = hex::decode(cmd)?;
+            let atmp = Atom::read(&mut Cursor::new(&cmd))?;
+            let Payload::AudioTalkbackMixerProperties(atmp) = atmp.payload else {
+                panic!("wrong command type");
+            };
+            assert_eq!(expected, atmp);
+
+            let o = Atom::new(expected);
+            let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+            o.write(&mut out)?;
+            assert_eq!(cmd, out.into_inner());
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn set_talkback_mixer_properties() -> Result<()> {
+        for (expected, cmd) in [
+            (
+                SetAudioTalkbackMixerProperties {
+                    channel: 1,
+                    mute_sdi: true,
+                },
+                "000c00004341544d01010100",
+            ),
+            (
+                SetAudioTalkbackMixerProperties {
+                    channel: 0,
+                    mute_sdi: false,
+                },
+                "000c00004341544d01000000",
+            ),
+        ] {
+            let cmd = hex::decode(cmd)?;
+            let catm = Atom::read(&mut Cursor::new(&cmd))?;
+            let Payload::SetAudioTalkbackMixerProperties(catm) = catm.payload else {
+                panic!("wrong command type");
+            };
+            assert_eq!(expected, catm);
+
+            let o = Atom::new(expected);
+            let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+            o.write(&mut out)?;
+            assert_eq!(cmd, out.into_inner());
+        }
+        Ok(())
+    }
+}
@@ -11,6 +11,8 @@
 
 use crate::structs::{TallyFlags, VideoSource};
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::{collections::HashMap, ops::Deref};
 
 /// `TlSr`: tally status by video source (`TalliedSources`)
@@ -30,6 +32,7 @@ use std::{collections::HashMap, ops::Deref};
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TalliedSources {
     #[br(temp)]
     #[bw(try_calc(u16::try_from(v.len())))]
@@ -42,6 +45,7 @@ pub struct TalliedSources {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TallyBySourceEntry(VideoSource, TallyFlags);
 
 impl TallyBySourceEntry {
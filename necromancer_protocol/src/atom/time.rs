@@ -16,7 +16,9 @@ use binrw::binrw;
 use chrono::{DateTime, FixedOffset, Offset, TimeZone};
 use std::{fmt::Display, time::Duration};
 
-use crate::{error::Error, Result};
+use crate::{error::Error, structs::VideoMode, Result};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `Time`: Timecode (clock) command/event (`Timecode`)
 ///
@@ -35,6 +37,7 @@ use crate::{error::Error, Result};
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Time {
     pub hour: u8,
     pub minute: u8,
@@ -57,6 +60,24 @@ impl Time {
         Ok(())
     }
 
+    /// Returns this timecode if it is valid for `video_mode`.
+    ///
+    /// This checks that `frame` is less than the
+    /// [timecode frame rate][VideoMode::timecode_framerate] of `video_mode`,
+    /// in addition to the range checks on `minute` and `second`.
+    ///
+    /// The frame count is not checked for [`VideoMode::Unknown`].
+    pub fn checked(&self, video_mode: VideoMode) -> Result<&Self> {
+        self.check()?;
+        let framerate = video_mode.timecode_framerate();
+        if framerate != 0 && self.frame >= framerate {
+            error!("frame >= framerate {framerate} ({video_mode}) for {self:?}");
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        Ok(self)
+    }
+
     /// Convert a [`Duration`][] to the nearest timecode, based on an integer frame rate.
     ///
     /// This does not support drop-frames nor non-integer framerates.
@@ -94,6 +115,119 @@ impl Time {
         })
     }
 
+    /// Number of frame labels skipped at the start of each minute (except
+    /// every tenth minute) for a drop-frame timecode, or 0 for non-drop-frame
+    /// timecodes.
+    ///
+    /// Drop-frame is only defined for 30 and 60 frame timecodes (29.97 and
+    /// 59.94 frames per second).
+    fn dropped_frames(framerate: u8, drop_frame: bool) -> Result<u32> {
+        if framerate == 0 {
+            return Err(Error::ParameterOutOfRange);
+        }
+        if !drop_frame {
+            return Ok(0);
+        }
+        if !framerate.is_multiple_of(30) {
+            return Err(Error::DropFrame);
+        }
+
+        Ok(u32::from(framerate / 15))
+    }
+
+    /// Number of frames in a 24 hour day.
+    fn frames_per_day(framerate: u8, drop_frame: bool) -> Result<u32> {
+        let drop = Self::dropped_frames(framerate, drop_frame)?;
+        let frames_per_10_minutes = 600 * u32::from(framerate) - 9 * drop;
+        Ok(frames_per_10_minutes * 6 * 24)
+    }
+
+    /// Converts this timecode into a count of frames since midnight.
+    ///
+    /// `framerate` is the _nominal_ (integer) frame rate of the timecode, ie:
+    /// `30` for 29.97 drop-frame.
+    fn to_frames(&self, framerate: u8) -> Result<u32> {
+        let drop = Self::dropped_frames(framerate, self.drop_frame)?;
+        self.check()?;
+        if self.frame >= framerate || self.hour >= 24 || self.minute >= 60 || self.second >= 60 {
+            error!("timecode out of range for framerate {framerate}: {self:?}");
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        let total_minutes = u32::from(self.hour) * 60 + u32::from(self.minute);
+        if self.second == 0 && u32::from(self.frame) < drop && !self.minute.is_multiple_of(10) {
+            error!("timecode {self} is a dropped frame");
+            return Err(Error::ParameterOutOfRange);
+        }
+
+        let secs = total_minutes * 60 + u32::from(self.second);
+        Ok(secs * u32::from(framerate) + u32::from(self.frame)
+            - drop * (total_minutes - total_minutes / 10))
+    }
+
+    /// Converts a count of frames since midnight into a timecode.
+    fn from_frames(frames: u32, framerate: u8, drop_frame: bool) -> Result<Self> {
+        let drop = Self::dropped_frames(framerate, drop_frame)?;
+        let fps = u32::from(framerate);
+        let mut frames = frames % Self::frames_per_day(framerate, drop_frame)?;
+
+        if drop > 0 {
+            // Add back the frame labels which were skipped.
+            let frames_per_minute = 60 * fps - drop;
+            let frames_per_10_minutes = 600 * fps - 9 * drop;
+            let tens = frames / frames_per_10_minutes;
+            let rem = frames % frames_per_10_minutes;
+
+            frames += 9 * drop * tens;
+            if rem > drop {
+                frames += drop * ((rem - drop) / frames_per_minute);
+            }
+        }
+
+        let secs = frames / fps;
+        Ok(Time {
+            hour: (secs / 3600) as u8,
+            minute: ((secs % 3600) / 60) as u8,
+            second: (secs % 60) as u8,
+            frame: (frames % fps) as u8,
+            drop_frame,
+        })
+    }
+
+    /// Adds (or with a negative value, subtracts) a number of frames to this
+    /// timecode, returning a new [`Time`].
+    ///
+    /// `framerate` is the _nominal_ (integer) frame rate of the timecode, ie:
+    /// `30` for 29.97 fps. If [`drop_frame`][Self::drop_frame] is set,
+    /// `framerate` must be 30 or 60, and dropped frame labels are skipped.
+    ///
+    /// The result rolls over at 24 hours.
+    pub fn add_frames(&self, frames: i64, framerate: u8) -> Result<Self> {
+        let start = self.to_frames(framerate)?;
+        let per_day = Self::frames_per_day(framerate, self.drop_frame)?;
+        let frames = (i64::from(start) + frames).rem_euclid(i64::from(per_day));
+
+        Self::from_frames(frames as u32, framerate, self.drop_frame)
+    }
+
+    /// Adds a [`Duration`][] to this timecode, returning a new [`Time`].
+    ///
+    /// The duration is truncated to a whole number of frames. For drop-frame
+    /// timecodes, this uses the actual frame rate (`framerate * 1000 / 1001`).
+    ///
+    /// See [`Time::add_frames()`] for details of the other parameters.
+    pub fn add_duration(&self, duration: &Duration, framerate: u8) -> Result<Self> {
+        let mut frames = duration.as_nanos() * u128::from(framerate);
+        frames /= if self.drop_frame {
+            1_001_000_000
+        } else {
+            1_000_000_000
+        };
+        let frames = i64::try_from(frames).map_err(|_| Error::ParameterOutOfRange)?;
+
+        self.add_frames(frames, framerate)
+    }
+
     /// Get the field separator character, per SMPTE 258M.
     #[inline]
     pub const fn separator(&self) -> char {
@@ -122,11 +256,13 @@ impl Display for Time {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TimecodeConfig(#[brw(pad_after = 3)] pub TimeMode);
 
 #[binrw]
 #[brw(repr = u8)]
 #[derive(Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum TimeMode {
     FreeRun = 0,
@@ -138,6 +274,7 @@ pub enum TimeMode {
 /// See also: [`TimecodeConfig`][]
 #[binrw]
 #[derive(Clone, Debug, PartialEq, Eq, FromPrimitive, ToPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetTimecodeConfig(#[brw(pad_after = 3)] pub TimeMode);
 
 pub const TIMECODE_REQUEST: TimecodeRequest = TimecodeRequest {};
@@ -147,6 +284,7 @@ pub const TIMECODE_REQUEST: TimecodeRequest = TimecodeRequest {};
 /// This causes the switcher to send a [Time] command to all connected clients.
 #[binrw]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TimecodeRequest {}
 
 /// `SToD`: Set time of day (`SetTimeOfDay`)
@@ -162,6 +300,7 @@ pub struct TimecodeRequest {}
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetTimeOfDay {
     /// Current time, in [seconds since UNIX epoch][0] (ignoring leap seconds).
     ///
@@ -255,6 +394,18 @@ mod test {
             Err(Error::ParameterOutOfRange)
         ));
 
+        // Frame 25 is valid at 50 frames per second, but not 25
+        assert_eq!(&time, time.checked(VideoMode::Fhd1080p50)?);
+        assert!(matches!(
+            time.checked(VideoMode::Fhd1080p25),
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(matches!(
+            time.checked(VideoMode::Fhd1080i50),
+            Err(Error::ParameterOutOfRange)
+        ));
+        assert!(time.checked(VideoMode::Unknown).is_ok());
+
         // Duration -> Time
         assert_eq!(
             time,
@@ -300,6 +451,99 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn add_frames() -> Result<()> {
+        let tc = |hour, minute, second, frame, drop_frame| Time {
+            hour,
+            minute,
+            second,
+            frame,
+            drop_frame,
+        };
+
+        // 25 fps, across a second boundary
+        assert_eq!(
+            tc(0, 0, 1, 0, false),
+            tc(0, 0, 0, 24, false).add_frames(1, 25)?
+        );
+        assert_eq!(
+            tc(0, 0, 0, 24, false),
+            tc(0, 0, 1, 0, false).add_frames(-1, 25)?
+        );
+        assert_eq!(
+            tc(0, 0, 1, 20, false),
+            tc(0, 0, 0, 20, false).add_duration(&Duration::from_secs(1), 25)?
+        );
+
+        // Hour rollover
+        assert_eq!(
+            tc(0, 0, 0, 0, false),
+            tc(23, 59, 59, 24, false).add_frames(1, 25)?
+        );
+        assert_eq!(
+            tc(23, 59, 59, 24, false),
+            tc(0, 0, 0, 0, false).add_frames(-1, 25)?
+        );
+
+        // 29.97 drop-frame, across a second boundary
+        assert_eq!(
+            tc(0, 0, 2, 0, true),
+            tc(0, 0, 1, 29, true).add_frames(1, 30)?
+        );
+
+        // Frames 0 and 1 are dropped at the start of each minute...
+        assert_eq!(
+            tc(0, 1, 0, 2, true),
+            tc(0, 0, 59, 29, true).add_frames(1, 30)?
+        );
+        assert_eq!(
+            tc(0, 0, 59, 29, true),
+            tc(0, 1, 0, 2, true).add_frames(-1, 30)?
+        );
+
+        // ...except every tenth minute.
+        assert_eq!(
+            tc(0, 10, 0, 0, true),
+            tc(0, 9, 59, 29, true).add_frames(1, 30)?
+        );
+        assert_eq!(
+            tc(1, 0, 0, 0, true),
+            tc(0, 59, 59, 29, true).add_frames(1, 30)?
+        );
+
+        // One hour of 29.97 fps is 107892 frames
+        assert_eq!(
+            tc(1, 0, 0, 0, true),
+            tc(0, 0, 0, 0, true).add_frames(107892, 30)?
+        );
+
+        // 60 real seconds is 1798.2 frames at 29.97 fps
+        assert_eq!(
+            tc(0, 0, 59, 28, true),
+            tc(0, 0, 0, 0, true).add_duration(&Duration::from_secs(60), 30)?
+        );
+
+        // Dropped frame labels are invalid
+        assert!(matches!(
+            tc(0, 1, 0, 0, true).add_frames(1, 30),
+            Err(Error::ParameterOutOfRange)
+        ));
+
+        // Drop-frame is only for 29.97 and 59.94 fps
+        assert!(matches!(
+            tc(0, 0, 0, 0, true).add_frames(1, 25),
+            Err(Error::DropFrame)
+        ));
+
+        // Frame out of range for framerate
+        assert!(matches!(
+            tc(0, 0, 0, 25, false).add_frames(1, 25),
+            Err(Error::ParameterOutOfRange)
+        ));
+
+        Ok(())
+    }
+
     #[test]
     fn tccc() -> Result<()> {
         // modified to remove uninitialised memory
@@ -1,9 +1,12 @@
 //! # Topology; 1/1 atoms
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `_top`: Topology of the switcher (`CapabilitiesTopLevel`)
 #[binrw]
 #[derive(Default, Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[brw(big)]
 pub struct Topology {
     /// Number of mix effect blocks (MEs)
@@ -24,7 +27,8 @@ pub struct Topology {
     // Skaarhoj says "Has SD Output"; can't see where this is read in the SDK,
     // and the data length is different.
     unknown9: u8,
-    unknown10: u8,
+    /// Number of stinger transition engines
+    pub stingers: u8,
     unknown11: u8,
     #[br(map = |v: u8| v != 0)]
     #[bw(map = |v: &bool| Into::<u8>::into(*v))]
@@ -1,29 +1,29 @@
-//! # Transitions and digital video effects; 4/21 atoms
+//! # Transitions and digital video effects; 12/21 atoms
 //!
-//! ## Unimplemented atoms (17)
+//! ## Unimplemented atoms (9)
 //!
 //! FourCC | Atom name | Length
 //! ------ | --------- | ------
 //! `CTDp` | `ChangeTransitionDipProperties` | 0x10
-//! `CTDv` | `ChangeTransitionDVEProperties` | 0x1c
 //! `CTMx` | `ChangeTransitionMixProperties` | 0xc
-//! `CTPr` | `ChangeTransitionPreviewTrans` | 0xc
 //! `CTPs` | `ChangeTransitionPosition` | 0xc
 //! `CTSt` | `ChangeTransitionStingerProperties` | 0x1c
-//! `CTTp` | `ChangeTransitionNext` | 0xc
-//! `CTWp` | `ChangeTransitionWipeProperties` | 0x1c
 //! `DAu2` | `DoTransitionAuto_2` | 0xc
 //! `STWV` | `SetTransitionWipeVelocity` | 0x18
 //! `TDpP` | `TransitionDipProperties` | 0xc
-//! `TDvP` | `TransitionDVEProperties` | 0xc
 //! `TMxP` | `TransitionMixProperties` | 0xc
-//! `TrPr` | `TransitionPreviewTrans` | 0xc
-//! `TrSS` | `TransitionSelectionState` | 0x10
 //! `TStP` | `TransitionStingerProperties` | 0x1c
-//! `TWpP` | `TransitionWipeProperties` | 0x1c
 
-use crate::structs::DVETransitionStyle;
-use binrw::binrw;
+use crate::structs::{
+    DVETransitionStyle, TransitionSelection, TransitionStyle, VideoSource, WipePattern,
+};
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{
+    bitfield,
+    specifiers::{B4, B6},
+};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `_DVE`: Digital video effects capabilities (`CapabilitiesDVE`)
 ///
@@ -36,6 +36,7 @@ use binrw::binrw;
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct DVECapabilities {
     #[br(map = |v: u8| v != 0)]
     #[bw(map = |v: &bool| Into::<u8>::into(*v))]
@@ -64,6 +65,7 @@ pub struct DVECapabilities {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Cut {
     #[brw(pad_size_to = 4)]
     pub me: u8,
@@ -80,6 +82,7 @@ pub struct Cut {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Auto {
     #[brw(pad_size_to = 4)]
     pub me: u8,
@@ -98,6 +101,7 @@ pub struct Auto {
 #[binrw]
 #[brw(big)]
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct TransitionPosition {
     pub me: u8,
     #[br(map = |v: u8| v != 0)]
@@ -110,6 +114,590 @@ pub struct TransitionPosition {
     pub position: u16,
 }
 
+/// `TrSS`: transition settings (`TransitionSelectionState`)
+///
+/// ## Packet format
+///
+/// * `u8`: ME
+/// * `u8`: [transition style][TransitionStyle]
+/// * `u8`: [transition selection][TransitionSelection]
+/// * `u8`: next [transition style][TransitionStyle]
+/// * `u8`: next [transition selection][TransitionSelection]
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TransitionSettings {
+    pub me: u8,
+    /// Style of the next transition.
+    pub style: TransitionStyle,
+    /// Layers affected by the next transition.
+    pub selection: TransitionSelection,
+    /// Style of the transition after the next one, if it has been changed
+    /// during a transition.
+    pub next_style: TransitionStyle,
+    /// Layers affected by the transition after the next one, if they have been
+    /// changed during a transition.
+    #[brw(pad_after = 3)]
+    pub next_selection: TransitionSelection,
+}
+
+/// `TrPr`: transition preview (`TransitionPreviewTrans`)
+///
+/// When enabled, the next transition is shown on the preview output, and can
+/// be controlled with the fader ("PREV TRANS").
+///
+/// ## Packet format
+///
+/// * `u8`: ME
+/// * `bool`: transition preview enabled
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TransitionPreview {
+    pub me: u8,
+    #[brw(pad_after = 2)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub enabled: bool,
+}
+
+/// `CTPr`: set transition preview (`ChangeTransitionPreviewTrans`)
+///
+/// ## Packet format
+///
+/// * `u8`: ME
+/// * `bool`: transition preview enabled
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetTransitionPreview {
+    pub me: u8,
+    #[brw(pad_after = 2)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub enabled: bool,
+}
+
+#[bitfield(bits = 8)]
+#[repr(u8)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u8>::from)]
+#[bw(map = |&x| Into::<u8>::into(x))]
+struct SetTransitionSettingsMask {
+    pub style: bool,
+    pub selection: bool,
+    #[skip]
+    __: B6,
+}
+
+/// `CTTp`: set transition settings (`ChangeTransitionNext`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// ## Packet format
+///
+/// * `u8`: setting mask
+/// * `u8`: ME
+/// * `u8`: [transition style][TransitionStyle]
+/// * `u8`: [transition selection][TransitionSelection]
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetTransitionSettings {
+    #[br(temp)]
+    #[bw(calc(
+        SetTransitionSettingsMask::new()
+            .with_style(self.style.is_some())
+            .with_selection(self.selection.is_some())
+    ))]
+    mask: SetTransitionSettingsMask,
+
+    pub me: u8,
+
+    /// Style of the next transition.
+    #[br(map(|v: TransitionStyle| mask.style().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub style: Option<TransitionStyle>,
+
+    /// Layers affected by the next transition.
+    #[br(map(|v: TransitionSelection| mask.selection().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub selection: Option<TransitionSelection>,
+}
+
+/// Parameters of a wipe transition.
+///
+/// ## Format
+///
+/// * `u8`: rate, in frames
+/// * `u8`: [wipe pattern][WipePattern]
+/// * 1 byte padding
+/// * `u16`: border width
+/// * `u16`: border fill source
+/// * `u16`: symmetry
+/// * `u16`: border softness
+/// * `u16`: horizontal position
+/// * `u16`: vertical position
+/// * `bool`: reverse direction
+/// * `bool`: flip flop
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WipeTransitionParams {
+    /// Duration of the transition, in frames.
+    pub rate: u8,
+
+    #[brw(pad_after = 1)]
+    pub pattern: WipePattern,
+
+    /// Border width, in hundredths of a percent (`0..=10000`).
+    pub border_width: u16,
+
+    /// Video source used to fill the border.
+    pub border_input: VideoSource,
+
+    /// Symmetry, in hundredths of a percent (`0..=10000`).
+    pub symmetry: u16,
+
+    /// Border softness, in hundredths of a percent (`0..=10000`).
+    pub border_softness: u16,
+
+    /// Horizontal position of the pattern's centre, in ten-thousandths
+    /// (`0..=10000`, `5000` = centre).
+    pub x: u16,
+
+    /// Vertical position of the pattern's centre, in ten-thousandths
+    /// (`0..=10000`, `5000` = centre).
+    pub y: u16,
+
+    /// The wipe runs in the reverse direction.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub reverse: bool,
+
+    /// The direction of the wipe alternates with each transition.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub flip_flop: bool,
+}
+
+impl WipeTransitionParams {
+    /// Maximum value of the percentage and position fields.
+    pub const MAX: u16 = 10000;
+
+    /// Returns `true` if every field is within the documented range.
+    pub fn is_valid(&self) -> bool {
+        self.border_width <= Self::MAX
+            && self.symmetry <= Self::MAX
+            && self.border_softness <= Self::MAX
+            && self.x <= Self::MAX
+            && self.y <= Self::MAX
+    }
+}
+
+/// `TWpP`: wipe transition parameters (`TransitionWipeProperties`)
+///
+/// ## Packet format
+///
+/// * `u8`: ME
+/// * 17 bytes: [WipeTransitionParams]
+/// * 2 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TransitionWipeProperties {
+    pub me: u8,
+    #[brw(pad_after = 2)]
+    pub params: WipeTransitionParams,
+}
+
+#[bitfield(bits = 16)]
+#[repr(u16)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u16>::from)]
+#[bw(map = |&x| Into::<u16>::into(x))]
+struct SetTransitionWipeMask {
+    pub rate: bool,
+    pub pattern: bool,
+    pub border_width: bool,
+    pub border_input: bool,
+    pub symmetry: bool,
+    pub border_softness: bool,
+    pub x: bool,
+    pub y: bool,
+    pub reverse: bool,
+    pub flip_flop: bool,
+    #[skip]
+    __: B6,
+}
+
+/// `CTWp`: set wipe transition parameters (`ChangeTransitionWipeProperties`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// See [WipeTransitionParams] for units.
+///
+/// ## Packet format
+///
+/// * `u16`: setting mask
+/// * `u8`: ME
+/// * `u8`: rate
+/// * `u8`: [wipe pattern][WipePattern]
+/// * 1 byte padding
+/// * `u16`: border width
+/// * `u16`: border fill source
+/// * `u16`: symmetry
+/// * `u16`: border softness
+/// * `u16`: horizontal position
+/// * `u16`: vertical position
+/// * `bool`: reverse direction
+/// * `bool`: flip flop
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetTransitionWipeProperties {
+    #[br(temp)]
+    #[bw(calc(
+        SetTransitionWipeMask::new()
+            .with_rate(self.rate.is_some())
+            .with_pattern(self.pattern.is_some())
+            .with_border_width(self.border_width.is_some())
+            .with_border_input(self.border_input.is_some())
+            .with_symmetry(self.symmetry.is_some())
+            .with_border_softness(self.border_softness.is_some())
+            .with_x(self.x.is_some())
+            .with_y(self.y.is_some())
+            .with_reverse(self.reverse.is_some())
+            .with_flip_flop(self.flip_flop.is_some())
+    ))]
+    mask: SetTransitionWipeMask,
+
+    pub me: u8,
+
+    #[br(map(|v: u8| mask.rate().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub rate: Option<u8>,
+
+    #[brw(pad_after = 1)]
+    #[br(map(|v: WipePattern| mask.pattern().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub pattern: Option<WipePattern>,
+
+    #[br(map(|v: u16| mask.border_width().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_width: Option<u16>,
+
+    #[br(map(|v: VideoSource| mask.border_input().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_input: Option<VideoSource>,
+
+    #[br(map(|v: u16| mask.symmetry().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub symmetry: Option<u16>,
+
+    #[br(map(|v: u16| mask.border_softness().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub border_softness: Option<u16>,
+
+    #[br(map(|v: u16| mask.x().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub x: Option<u16>,
+
+    #[br(map(|v: u16| mask.y().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub y: Option<u16>,
+
+    #[br(map(|v: u8| mask.reverse().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub reverse: Option<bool>,
+
+    #[br(map(|v: u8| mask.flip_flop().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub flip_flop: Option<bool>,
+}
+
+impl SetTransitionWipeProperties {
+    /// Creates a new [SetTransitionWipeProperties] which doesn't change
+    /// anything.
+    pub fn new(me: u8) -> Self {
+        Self {
+            me,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new [SetTransitionWipeProperties] which sets every parameter
+    /// of the wipe transition to `params`.
+    pub fn from_params(me: u8, params: &WipeTransitionParams) -> Self {
+        Self {
+            me,
+            rate: Some(params.rate),
+            pattern: Some(params.pattern),
+            border_width: Some(params.border_width),
+            border_input: Some(params.border_input),
+            symmetry: Some(params.symmetry),
+            border_softness: Some(params.border_softness),
+            x: Some(params.x),
+            y: Some(params.y),
+            reverse: Some(params.reverse),
+            flip_flop: Some(params.flip_flop),
+        }
+    }
+}
+
+/// Parameters of a digital video effect (DVE) transition.
+///
+/// ## Format
+///
+/// * `u8`: rate, in frames
+/// * `u8`: logo rate, in frames
+/// * `u8`: [DVE transition style][DVETransitionStyle]
+/// * `u16`: fill source
+/// * `u16`: key source
+/// * `bool`: key enabled
+/// * `bool`: key is pre-multiplied
+/// * `u16`: key clip
+/// * `u16`: key gain
+/// * `bool`: invert key
+/// * `bool`: reverse direction
+/// * `bool`: flip flop
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct DVETransitionParams {
+    /// Duration of the transition, in frames.
+    pub rate: u8,
+
+    /// Duration of the [graphic logo wipe][DVETransitionStyle::GraphicLogoWipe],
+    /// in frames.
+    pub logo_rate: u8,
+
+    pub style: DVETransitionStyle,
+
+    /// Fill source for graphic transitions.
+    pub fill_source: VideoSource,
+
+    /// Key source for graphic transitions.
+    pub key_source: VideoSource,
+
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub key_enabled: bool,
+
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub pre_multiplied: bool,
+
+    /// Key clip, in tenths of a percent (`0..=1000`).
+    pub clip: u16,
+
+    /// Key gain, in tenths of a percent (`0..=1000`).
+    pub gain: u16,
+
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub invert_key: bool,
+
+    /// The transition runs in the reverse direction.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub reverse: bool,
+
+    /// The direction of the transition alternates with each transition.
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub flip_flop: bool,
+}
+
+impl DVETransitionParams {
+    /// Maximum value of [`clip`][Self::clip] and [`gain`][Self::gain].
+    pub const MAX_CLIP_GAIN: u16 = 1000;
+
+    /// Returns `true` if every field is within the documented range.
+    pub fn is_valid(&self) -> bool {
+        self.clip <= Self::MAX_CLIP_GAIN && self.gain <= Self::MAX_CLIP_GAIN
+    }
+}
+
+/// `TDvP`: DVE transition parameters (`TransitionDVEProperties`)
+///
+/// ## Packet format
+///
+/// * `u8`: ME
+/// * 16 bytes: [DVETransitionParams]
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TransitionDVEProperties {
+    pub me: u8,
+    #[brw(pad_after = 3)]
+    pub params: DVETransitionParams,
+}
+
+#[bitfield(bits = 16)]
+#[repr(u16)]
+#[derive(Specifier, BinRead, BinWrite, Default, Clone, Copy, PartialEq, Eq)]
+#[br(map = From::<u16>::from)]
+#[bw(map = |&x| Into::<u16>::into(x))]
+struct SetTransitionDVEMask {
+    pub rate: bool,
+    pub logo_rate: bool,
+    pub style: bool,
+    pub fill_source: bool,
+    pub key_source: bool,
+    pub key_enabled: bool,
+    pub pre_multiplied: bool,
+    pub clip: bool,
+    pub gain: bool,
+    pub invert_key: bool,
+    pub reverse: bool,
+    pub flip_flop: bool,
+    #[skip]
+    __: B4,
+}
+
+/// `CTDv`: set DVE transition parameters (`ChangeTransitionDVEProperties`)
+///
+/// Fields which are `None` are left unchanged.
+///
+/// See [DVETransitionParams] for units.
+///
+/// ## Packet format
+///
+/// * `u16`: setting mask
+/// * `u8`: ME
+/// * `u8`: rate
+/// * `u8`: logo rate
+/// * `u8`: [DVE transition style][DVETransitionStyle]
+/// * `u16`: fill source
+/// * `u16`: key source
+/// * `bool`: key enabled
+/// * `bool`: key is pre-multiplied
+/// * `u16`: key clip
+/// * `u16`: key gain
+/// * `bool`: invert key
+/// * `bool`: reverse direction
+/// * `bool`: flip flop
+/// * 1 byte padding
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SetTransitionDVEProperties {
+    #[br(temp)]
+    #[bw(calc(
+        SetTransitionDVEMask::new()
+            .with_rate(self.rate.is_some())
+            .with_logo_rate(self.logo_rate.is_some())
+            .with_style(self.style.is_some())
+            .with_fill_source(self.fill_source.is_some())
+            .with_key_source(self.key_source.is_some())
+            .with_key_enabled(self.key_enabled.is_some())
+            .with_pre_multiplied(self.pre_multiplied.is_some())
+            .with_clip(self.clip.is_some())
+            .with_gain(self.gain.is_some())
+            .with_invert_key(self.invert_key.is_some())
+            .with_reverse(self.reverse.is_some())
+            .with_flip_flop(self.flip_flop.is_some())
+    ))]
+    mask: SetTransitionDVEMask,
+
+    pub me: u8,
+
+    #[br(map(|v: u8| mask.rate().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub rate: Option<u8>,
+
+    #[br(map(|v: u8| mask.logo_rate().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub logo_rate: Option<u8>,
+
+    #[br(map(|v: DVETransitionStyle| mask.style().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub style: Option<DVETransitionStyle>,
+
+    #[br(map(|v: VideoSource| mask.fill_source().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub fill_source: Option<VideoSource>,
+
+    #[br(map(|v: VideoSource| mask.key_source().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub key_source: Option<VideoSource>,
+
+    #[br(map(|v: u8| mask.key_enabled().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub key_enabled: Option<bool>,
+
+    #[br(map(|v: u8| mask.pre_multiplied().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub pre_multiplied: Option<bool>,
+
+    #[br(map(|v: u16| mask.clip().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub clip: Option<u16>,
+
+    #[br(map(|v: u16| mask.gain().then_some(v)))]
+    #[bw(map(|v| v.unwrap_or_default()))]
+    pub gain: Option<u16>,
+
+    #[br(map(|v: u8| mask.invert_key().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub invert_key: Option<bool>,
+
+    #[br(map(|v: u8| mask.reverse().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub reverse: Option<bool>,
+
+    #[brw(pad_after = 1)]
+    #[br(map(|v: u8| mask.flip_flop().then_some(v != 0)))]
+    #[bw(map(|v: &Option<bool>| Into::<u8>::into(v.unwrap_or_default())))]
+    pub flip_flop: Option<bool>,
+}
+
+impl SetTransitionDVEProperties {
+    /// Creates a new [SetTransitionDVEProperties] which doesn't change
+    /// anything.
+    pub fn new(me: u8) -> Self {
+        Self {
+            me,
+            ..Default::default()
+        }
+    }
+
+    /// Creates a new [SetTransitionDVEProperties] which sets every parameter
+    /// of the DVE transition to `params`.
+    pub fn from_params(me: u8, params: &DVETransitionParams) -> Self {
+        Self {
+            me,
+            rate: Some(params.rate),
+            logo_rate: Some(params.logo_rate),
+            style: Some(params.style),
+            fill_source: Some(params.fill_source),
+            key_source: Some(params.key_source),
+            key_enabled: Some(params.key_enabled),
+            pre_multiplied: Some(params.pre_multiplied),
+            clip: Some(params.clip),
+            gain: Some(params.gain),
+            invert_key: Some(params.invert_key),
+            reverse: Some(params.reverse),
+            flip_flop: Some(params.flip_flop),
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -182,4 +770,245 @@ mod test {
         assert_eq!(cmd, out.into_inner());
         Ok(())
     }
+
+    #[test]
+    fn transition_settings() -> Result {
+        let cmd = hex::decode("00100000547253530002030003000000")?;
+        let trss = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::TransitionSettings(trss) = trss.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = TransitionSettings {
+            me: 0,
+            style: TransitionStyle::Wipe,
+            selection: TransitionSelection::new()
+                .with_background(true)
+                .with_key1(true),
+            next_style: TransitionStyle::Mix,
+            next_selection: TransitionSelection::new()
+                .with_background(true)
+                .with_key1(true),
+        };
+        assert_eq!(expected, trss);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn transition_preview() -> Result {
+        let cmd = hex::decode("000c00005472507201010000")?;
+        let trpr = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::TransitionPreview(trpr) = trpr.payload else {
+            panic!("wrong command type");
+        };
+        let expected = TransitionPreview {
+            me: 1,
+            enabled: true,
+        };
+        assert_eq!(expected, trpr);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_transition_preview() -> Result {
+        let cmd = hex::decode("000c00004354507200000000")?;
+        let ctpr = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetTransitionPreview(ctpr) = ctpr.payload else {
+            panic!("wrong command type");
+        };
+        let expected = SetTransitionPreview {
+            me: 0,
+            enabled: false,
+        };
+        assert_eq!(expected, ctpr);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_transition_style() -> Result {
+        let cmd = hex::decode("000c00004354547001000300")?;
+        let cttp = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetTransitionSettings(cttp) = cttp.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = SetTransitionSettings {
+            me: 0,
+            style: Some(TransitionStyle::DVE),
+            selection: None,
+        };
+        assert_eq!(expected, cttp);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn transition_wipe_properties() -> Result {
+        let cmd = hex::decode("001c0000545770500019070000fa0003138827101f4009c401000000")?;
+        let twpp = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::TransitionWipeProperties(twpp) = twpp.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = TransitionWipeProperties {
+            me: 0,
+            params: WipeTransitionParams {
+                rate: 25,
+                pattern: WipePattern::CircleIris,
+                // 2.50%
+                border_width: 250,
+                border_input: VideoSource::Input3,
+                // 50.00%
+                symmetry: 5000,
+                // 100.00%
+                border_softness: WipeTransitionParams::MAX,
+                // 0.8000
+                x: 8000,
+                // 0.2500
+                y: 2500,
+                reverse: true,
+                flip_flop: false,
+            },
+        };
+        assert_eq!(expected, twpp);
+        assert!(twpp.params.is_valid());
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_transition_wipe_properties() -> Result {
+        let cmd = hex::decode("001c00004354577000c4010000000001ffff00000000138809c40000")?;
+        let ctwp = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetTransitionWipeProperties(ctwp) = ctwp.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = SetTransitionWipeProperties {
+            border_width: Some(1),
+            x: Some(5000),
+            y: Some(2500),
+            ..SetTransitionWipeProperties::new(1)
+        };
+        assert_eq!(expected, ctwp);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        // Setting every parameter sets every bit of the mask.
+        let params = WipeTransitionParams {
+            border_width: 10000,
+            symmetry: 1,
+            ..Default::default()
+        };
+        let o = Atom::new(SetTransitionWipeProperties::from_params(0, &params));
+        let mut out = Cursor::new(Vec::new());
+        o.write(&mut out)?;
+        let out = out.into_inner();
+        assert_eq!(&[0x03, 0xff], &out[8..10]);
+        assert_eq!(&[0x27, 0x10], &out[14..16]);
+
+        let Payload::SetTransitionWipeProperties(ctwp) =
+            Atom::read(&mut Cursor::new(&out))?.payload
+        else {
+            panic!("wrong command type");
+        };
+        assert_eq!(SetTransitionWipeProperties::from_params(0, &params), ctwp);
+        Ok(())
+    }
+
+    #[test]
+    fn transition_dve_properties() -> Result {
+        let cmd = hex::decode("001c000054447650001e3c1b0bc20bc3010001f402bc010001000000")?;
+        let tdvp = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::TransitionDVEProperties(tdvp) = tdvp.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = TransitionDVEProperties {
+            me: 0,
+            params: DVETransitionParams {
+                rate: 30,
+                logo_rate: 60,
+                style: DVETransitionStyle::PushLeft,
+                fill_source: VideoSource::MediaPlayer1,
+                key_source: VideoSource::MediaPlayer1Key,
+                key_enabled: true,
+                pre_multiplied: false,
+                // 50.0%
+                clip: 500,
+                // 70.0%
+                gain: 700,
+                invert_key: true,
+                reverse: false,
+                flip_flop: true,
+            },
+        };
+        assert_eq!(expected, tdvp);
+        assert!(tdvp.params.is_valid());
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn set_transition_dve_properties() -> Result {
+        let cmd = hex::decode("001c00004354447601840000001bffffffff000001f402bc00000000")?;
+        let ctdv = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::SetTransitionDVEProperties(ctdv) = ctdv.payload else {
+            panic!("wrong command type");
+        };
+
+        let expected = SetTransitionDVEProperties {
+            style: Some(DVETransitionStyle::PushLeft),
+            clip: Some(500),
+            gain: Some(700),
+            ..SetTransitionDVEProperties::new(0)
+        };
+        assert_eq!(expected, ctdv);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+
+        let params = DVETransitionParams {
+            gain: DVETransitionParams::MAX_CLIP_GAIN,
+            ..Default::default()
+        };
+        assert!(params.is_valid());
+        let o = Atom::new(SetTransitionDVEProperties::from_params(0, &params));
+        let mut out = Cursor::new(Vec::new());
+        o.write(&mut out)?;
+        assert_eq!(&[0x0f, 0xff], &out.into_inner()[8..10]);
+        Ok(())
+    }
 }
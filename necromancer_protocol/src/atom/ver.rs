@@ -6,8 +6,10 @@
 //! ------ | --------- | ------
 //! `SwVr` | `SoftwareVersion` | 0x28
 
-use crate::{atom::str_from_utf8_null, error::Error, Result};
+use crate::{atom::string_from_utf8_null, error::Error, Result};
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 
 /// `_ver`: protocol version (`CapabilitiesVersion`)
@@ -22,6 +24,7 @@ use std::fmt::Display;
 /// * `u16`: [minor version](Self::minor)
 #[binrw]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[brw(big)]
 pub struct Version {
     pub major: u16,
@@ -38,7 +41,10 @@ impl Version {
     pub fn check_firmware_version(&self) -> Result<()> {
         // FIXME: cut/fade to black on 2.31
         if self.major != 2 || self.minor < 30 || self.minor > 31 {
-            return Err(Error::UnsupportedFirmwareVersion(*self));
+            return Err(Error::UnsupportedFirmware {
+                major: self.major,
+                minor: self.minor,
+            });
         }
 
         Ok(())
@@ -54,9 +60,11 @@ impl Version {
 /// * 3 bytes padding (null)
 #[binrw]
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
 pub struct ProductName {
-    #[br(try_map = |v: [u8; Self::MAX_NAME_LENGTH]| str_from_utf8_null(&v).map(str::to_string))]
+    #[br(try_map = |v: [u8; Self::MAX_NAME_LENGTH]| string_from_utf8_null(&v, lossy_utf8))]
     #[bw(assert(name.len() <= Self::MAX_NAME_LENGTH), pad_size_to = Self::MAX_NAME_LENGTH, map = |v: &String| { v.as_bytes().to_vec() })]
     name: String,
     #[brw(pad_after = 3)]
@@ -139,6 +147,33 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn unsupported_firmware() -> Result<()> {
+        // Firmware 2.28
+        let cmd = hex::decode("000c00005f7665720002001c")?;
+        let ver = Atom::read(&mut Cursor::new(&cmd))?;
+        let Payload::Version(version) = ver.payload else {
+            panic!("wrong command type");
+        };
+
+        let err = version.check_firmware_version().unwrap_err();
+        assert!(matches!(
+            err,
+            Error::UnsupportedFirmware {
+                major: 2,
+                minor: 28
+            }
+        ));
+        assert_eq!("unsupported firmware version: 2.28", err.to_string());
+
+        Version {
+            major: 2,
+            minor: 30,
+        }
+        .check_firmware_version()?;
+        Ok(())
+    }
+
     #[test]
     fn pin() -> Result<()> {
         let expected = ProductName::new("ATEM Mini".to_string(), 0xd)?;
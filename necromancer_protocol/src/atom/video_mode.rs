@@ -1,4 +1,4 @@
-//! # Video mode; 3/6 atoms
+//! # Video mode; 4/7 atoms
 //!
 //! ## Unimplemented atoms (3)
 //!
@@ -9,6 +9,9 @@
 //! `VMC2` | `CapabilitiesVideoModeExtended` | 0xc + (entries * 0x10)
 use crate::structs::VideoMode;
 use binrw::binrw;
+use num_traits::{FromPrimitive, ToPrimitive};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::ops::Deref;
 
 /// `VidM`: current video mode (`CoreVideoMode`)
@@ -20,6 +23,7 @@ use std::ops::Deref;
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct CoreVideoMode(#[brw(pad_size_to = 4)] pub VideoMode);
 
 impl From<VideoMode> for CoreVideoMode {
@@ -46,6 +50,7 @@ impl Deref for CoreVideoMode {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SetVideoMode(#[brw(pad_size_to = 4)] pub VideoMode);
 
 impl From<VideoMode> for SetVideoMode {
@@ -68,6 +73,62 @@ impl Deref for SetVideoMode {
     }
 }
 
+/// Colorimetry (colour space) used for video processing.
+///
+/// These values have not been verified against a switcher.
+#[binrw]
+#[brw(big, repr = u8)]
+#[derive(Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum ColorimetryMode {
+    /// ITU-R BT.601 (standard definition)
+    Rec601 = 0x0,
+    /// ITU-R BT.709 (high definition)
+    #[default]
+    Rec709 = 0x1,
+    /// ITU-R BT.2020 (Ultra HD)
+    Rec2020 = 0x2,
+}
+
+/// `ClrM`: colorimetry mode (`ColorimetryMode`)
+///
+/// This is expected to only be reported by switchers which support Ultra HD
+/// video modes.
+///
+/// It has not been verified that the client sends the same atom to change the
+/// colorimetry mode.
+///
+/// ## Packet format
+///
+/// * `u8`: [colorimetry mode][ColorimetryMode]
+/// * 3 bytes padding
+#[binrw]
+#[brw(big)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Colorimetry(#[brw(pad_size_to = 4)] pub ColorimetryMode);
+
+impl From<ColorimetryMode> for Colorimetry {
+    fn from(value: ColorimetryMode) -> Self {
+        Colorimetry(value)
+    }
+}
+
+impl From<Colorimetry> for ColorimetryMode {
+    fn from(value: Colorimetry) -> Self {
+        value.0
+    }
+}
+
+impl Deref for Colorimetry {
+    type Target = ColorimetryMode;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 /// `_VMC`: supported video modes (`CapabilitiesVideoMode`)
 ///
 /// ## Packet format
@@ -76,10 +137,14 @@ impl Deref for SetVideoMode {
 /// * 2 bytes padding
 /// * (repeated)
 ///   * `u8`: video mode
-///   * 12 bytes padding
+///   * `u32`: bitmask of supported MultiView video modes
+///   * `u32`: bitmask of supported down-converted video modes
+///   * `u8`: requires reconfiguration
+///   * 3 bytes padding
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct SupportedVideoModes {
     #[brw(pad_after = 2)]
     #[br(temp)]
@@ -90,20 +155,78 @@ pub struct SupportedVideoModes {
     pub modes: Vec<SupportedVideoMode>,
 }
 
+/// A video mode supported by the switcher, and the capabilities of the
+/// switcher when operating in that mode.
 #[binrw]
 #[brw(big)]
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct SupportedVideoMode(#[brw(pad_size_to = 13)] pub VideoMode);
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct SupportedVideoMode {
+    pub mode: VideoMode,
+
+    /// Video modes which the MultiView outputs support in this mode.
+    #[br(map = video_modes_from_mask)]
+    #[bw(map = |v: &Vec<VideoMode>| video_modes_to_mask(v))]
+    pub multiview_modes: Vec<VideoMode>,
+
+    /// Video modes which outputs can be down-converted to in this mode.
+    #[br(map = video_modes_from_mask)]
+    #[bw(map = |v: &Vec<VideoMode>| video_modes_to_mask(v))]
+    pub down_convert_modes: Vec<VideoMode>,
+
+    /// If `true`, switching to this mode requires the switcher to reconfigure
+    /// itself.
+    #[brw(pad_after = 3)]
+    #[br(map = |v: u8| v != 0)]
+    #[bw(map = |v: &bool| Into::<u8>::into(*v))]
+    pub requires_reconfiguration: bool,
+}
+
+impl SupportedVideoMode {
+    /// Returns `true` if the MultiView outputs support `mode` when the
+    /// switcher is in this video mode.
+    pub fn supports_multiview_mode(&self, mode: VideoMode) -> bool {
+        self.multiview_modes.contains(&mode)
+    }
+
+    /// Returns `true` if outputs can be down-converted to `mode` when the
+    /// switcher is in this video mode.
+    pub fn supports_down_convert_mode(&self, mode: VideoMode) -> bool {
+        self.down_convert_modes.contains(&mode)
+    }
+}
+
+/// Converts a bitmask of [VideoMode] values (where bit `n` is [VideoMode]
+/// `n`) into a list, skipping unknown values.
+fn video_modes_from_mask(mask: u32) -> Vec<VideoMode> {
+    (0..u32::BITS)
+        .filter(|b| mask & (1 << b) != 0)
+        .filter_map(VideoMode::from_u32)
+        .collect()
+}
+
+/// Converts a list of [VideoMode] values into a bitmask, skipping values which
+/// don't fit.
+fn video_modes_to_mask(modes: &[VideoMode]) -> u32 {
+    modes
+        .iter()
+        .filter_map(|m| m.to_u32())
+        .filter(|b| *b < u32::BITS)
+        .fold(0, |a, b| a | (1 << b))
+}
 
 impl From<VideoMode> for SupportedVideoMode {
-    fn from(value: VideoMode) -> Self {
-        SupportedVideoMode(value)
+    fn from(mode: VideoMode) -> Self {
+        Self {
+            mode,
+            ..Default::default()
+        }
     }
 }
 
 impl From<SupportedVideoMode> for VideoMode {
     fn from(value: SupportedVideoMode) -> Self {
-        value.0
+        value.mode
     }
 }
 
@@ -111,7 +234,7 @@ impl Deref for SupportedVideoMode {
     type Target = VideoMode;
 
     fn deref(&self) -> &Self::Target {
-        &self.0
+        &self.mode
     }
 }
 
@@ -200,4 +323,63 @@ mod test {
         assert_eq!(cmd, out.into_inner());
         Ok(())
     }
+
+    #[test]
+    fn supported_video_mode_flags() -> Result<()> {
+        let expected = SupportedVideoModes::from(vec![
+            SupportedVideoMode {
+                mode: VideoMode::Fhd1080p25,
+                multiview_modes: vec![VideoMode::Fhd1080p25],
+                down_convert_modes: vec![VideoMode::Pal625i50, VideoMode::PalAnamorphic625i50],
+                requires_reconfiguration: false,
+            },
+            SupportedVideoMode {
+                mode: VideoMode::Uhd4Kp25,
+                multiview_modes: vec![VideoMode::Fhd1080p25, VideoMode::Uhd4Kp25],
+                down_convert_modes: vec![VideoMode::Fhd1080p25],
+                requires_reconfiguration: true,
+            },
+        ]);
+        // Synthetic example, not captured from hardware
+        let cmd = hex::decode(concat!(
+            "00260000",
+            "5f564d43",
+            "00020000",
+            "0a000004000000000a00000000",
+            "10000104000000040001000000",
+        ))?;
+        let vmc = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::SupportedVideoModes(vmc) = vmc.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, vmc);
+        assert!(vmc[1].supports_multiview_mode(VideoMode::Uhd4Kp25));
+        assert!(!vmc[0].supports_multiview_mode(VideoMode::Uhd4Kp25));
+        assert!(vmc[0].supports_down_convert_mode(VideoMode::Pal625i50));
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+
+    #[test]
+    fn colorimetry() -> Result<()> {
+        // Synthetic example, not captured from hardware
+        let cmd = hex::decode("000c0000436c724d02000000")?;
+        let clrm = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::Colorimetry(clrm) = clrm.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(ColorimetryMode::Rec2020, *clrm);
+
+        let o = Atom::new(Colorimetry(ColorimetryMode::Rec2020));
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
 }
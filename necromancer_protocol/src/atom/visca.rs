@@ -22,6 +22,8 @@
 //! `vspg` | `ViscaIPAddressPingResponse` | 0x4c
 
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// `PZSA`: Auto-allocate addresses to Visca-compatible PTZ cameras connected over RS-422
 /// (`PtzRs422ViscaAutoAllocateAddresses`)
@@ -31,6 +33,7 @@ use binrw::binrw;
 /// No payload.
 #[binrw]
 #[derive(Default, Debug, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Visca422AutoAllocateAddresses {}
 
 /// Command to automatically allocate addresses to Visca-compatible PTZ cameras connected over
@@ -0,0 +1,56 @@
+//! # Warnings; 1/1 atoms
+use super::string_from_utf8_null;
+use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// `Warn`: switcher warning message (`WarningMessage`)
+///
+/// Sent by the switcher when it has a warning for the operator (eg: fan
+/// failure, overheating). An empty message clears the warning.
+///
+/// ## Packet format
+///
+/// * `char[44]`: message, maybe null terminated
+#[binrw]
+#[brw(big)]
+#[br(import { lossy_utf8: bool = false })]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct WarningMessage {
+    #[br(try_map = |v: [u8; 44]| string_from_utf8_null(&v, lossy_utf8))]
+    #[bw(assert(message.len() <= 44), pad_size_to = 44, map = |v: &String| { v.as_bytes().to_vec() })]
+    pub message: String,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        atom::{Atom, Payload},
+        Result,
+    };
+    use binrw::{BinRead, BinWrite};
+    use std::io::Cursor;
+
+    #[test]
+    fn warning_message() -> Result<()> {
+        let expected = WarningMessage {
+            message: "Fan failure".to_string(),
+        };
+
+        let cmd = hex::decode("003400005761726e46616e206661696c757265000000000000000000000000000000000000000000000000000000000000000000")?;
+        let warn = Atom::read(&mut Cursor::new(&cmd))?;
+
+        let Payload::WarningMessage(warn) = warn.payload else {
+            panic!("wrong command type");
+        };
+        assert_eq!(expected, warn);
+
+        let o = Atom::new(expected);
+        let mut out = Cursor::new(Vec::with_capacity(cmd.len()));
+        o.write(&mut out)?;
+        assert_eq!(cmd, out.into_inner());
+        Ok(())
+    }
+}
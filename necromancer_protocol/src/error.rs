@@ -1,5 +1,4 @@
 //! # Error types
-use crate::atom::Version;
 use std::str::Utf8Error;
 use thiserror::Error;
 
@@ -28,9 +27,12 @@ pub enum Error {
     #[error("drop frame timecodes are not supported")]
     DropFrame,
 
+    #[error("unknown video source: {0:?}")]
+    UnknownVideoSource(String),
+
     #[error("data parse error: {0}")]
     BinRwError(#[from] binrw::Error),
 
-    #[error("unsupported firmware version: {0:?}")]
-    UnsupportedFirmwareVersion(Version),
+    #[error("unsupported firmware version: {major}.{minor}")]
+    UnsupportedFirmware { major: u16, minor: u16 },
 }
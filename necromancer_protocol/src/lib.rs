@@ -17,7 +17,7 @@ mod util;
 pub use crate::{
     atom::Atom,
     error::Error,
-    packet::{AtemControl, AtemPacket, AtemPacketFlags},
+    packet::{AtemControl, AtemPacket, AtemPacketBuilder, AtemPacketFlags},
     util::IntReader,
 };
 
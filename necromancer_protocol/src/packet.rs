@@ -2,9 +2,9 @@
 #![allow(rustdoc::private_intra_doc_links)]
 
 use crate::{atom::*, util::OffsetCounter, Error, Result};
-use binrw::{binrw, helpers::until_eof, io::TakeSeekExt, BinRead, BinWrite};
+use binrw::{binrw, helpers::until_eof, io::TakeSeekExt, BinRead, BinResult, BinWrite};
 use modular_bitfield::{bitfield, specifiers::B11, Specifier};
-use std::io::SeekFrom;
+use std::io::{Cursor, Read, Seek, SeekFrom};
 
 /// Packet flags, part of [`AtemPacketFlagsLength`][].
 ///
@@ -123,6 +123,7 @@ impl AtemControl {
 #[binrw]
 #[derive(Debug, Clone, PartialEq, Eq)]
 #[brw(big, stream = s)]
+#[br(import { lenient: bool = false, lossy_utf8: bool = false })]
 #[bw(map_stream = OffsetCounter::new)]
 pub struct AtemPacket {
     // Read path for AtemPacketFlagsLength
@@ -156,7 +157,8 @@ pub struct AtemPacket {
     /// * `0xb1` when the controller sends an `AtemInitPayload`
     /// * `0xd4` when the controller requests initial switcher status
     /// * `0xd2` when the controller acknowledges any switcher messages
-    /// * `0x00` or `0x01` when the controller sends commands to the switcher
+    /// * `0x00` or `0x01` when the controller sends commands to the switcher;
+    ///   switchers don't appear to care which
     /// * `0x1e` when the switcher acknowledges any controller commands
     /// * `0x00` when the switcher sends any `ACK | RESPONSE` message
     pub client_packet_id: u16,
@@ -176,7 +178,7 @@ pub struct AtemPacket {
     // #[br(if(!flags_length.control(), Vec::new()))]
     // #[br(map_stream = |reader| reader.take_seek(u64::from(flags_length.length()) - 12))]
     // pub commands: Vec<Atom>,
-    #[br(args(flags_length), map_stream = |reader| { reader.take_seek(u64::from(flags_length.length() - Self::HEADERS_LENGTH)) })]
+    #[br(args(flags_length, lenient, lossy_utf8), map_stream = |reader| { reader.take_seek(u64::from(flags_length.length() - Self::HEADERS_LENGTH)) })]
     #[bw(args(flags))]
     payload: AtemPacketPayload,
 
@@ -202,13 +204,13 @@ pub struct AtemPacket {
 #[binrw]
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 // #[br(import(control: bool, length: B11))]
-#[br(import(flags_length: AtemPacketFlagsLength))]
+#[br(import(flags_length: AtemPacketFlagsLength, lenient: bool, lossy_utf8: bool))]
 #[bw(import(flags: &AtemPacketFlags))]
 enum AtemPacketPayload {
     /// The packet payload is 0 or more atoms.
     #[br(pre_assert(!flags_length.flags().control() && flags_length.length() > AtemPacket::HEADERS_LENGTH))]
     #[bw(assert(!flags.control()))]
-    Atom(#[br(parse_with = until_eof)] Vec<Atom>),
+    Atom(#[br(parse_with = parse_atoms, args(lenient, lossy_utf8))] Vec<Atom>),
 
     /// The packet payload contains control commands.
     #[br(pre_assert(flags_length.flags().control() && flags_length.length() == AtemPacket::HEADERS_LENGTH + AtemControl::LENGTH))]
@@ -223,6 +225,67 @@ enum AtemPacketPayload {
     None,
 }
 
+/// Reads [Atom]s until EOF.
+///
+/// If `lenient` is set, atoms which can't be parsed (such as those with an
+/// invalid length) don't fail the whole packet. Instead, each atom's length
+/// header is used to find the next atom, and invalid atoms are recorded as
+/// [`Payload::Unknown`][] (truncated to the end of the packet, if need be).
+///
+/// Parsing stops early if an atom's length header is too short to advance
+/// past it.
+///
+/// `lossy_utf8` is passed to each [Atom], to control
+/// [how names are decoded][Atom#lossy-utf-8-decoding].
+#[binrw::parser(reader, endian)]
+fn parse_atoms(lenient: bool, lossy_utf8: bool) -> BinResult<Vec<Atom>> {
+    if !lenient {
+        return until_eof::<_, Atom, _, _>(reader, endian, binrw::args! { lossy_utf8 });
+    }
+
+    let mut atoms = Vec::new();
+    loop {
+        let start = reader.stream_position()?;
+        let length = match u16::read_options(reader, endian, ()) {
+            Ok(length) => length,
+            Err(e) if e.is_eof() => break,
+            Err(e) => return Err(e),
+        };
+        reader.seek(SeekFrom::Start(start))?;
+
+        let e = match Atom::read_options(reader, endian, binrw::args! { lossy_utf8 }) {
+            Ok(atom) => {
+                atoms.push(atom);
+                continue;
+            }
+            Err(e) => e,
+        };
+
+        if length < Atom::HEADERS_LENGTH {
+            // We can't advance past this atom, so give up on the rest.
+            warn!("skipping rest of packet after invalid atom at {start:#x} ({length} bytes): {e}");
+            break;
+        }
+
+        reader.seek(SeekFrom::Start(start + 4))?;
+        let mut magic = [0; 4];
+        if let Err(e) = reader.read_exact(&mut magic) {
+            warn!("skipping truncated atom at {start:#x}: {e}");
+            break;
+        }
+        let mut payload = Vec::new();
+        reader
+            .by_ref()
+            .take(u64::from(length - Atom::HEADERS_LENGTH))
+            .read_to_end(&mut payload)?;
+        warn!("recording invalid atom at {start:#x} ({length} bytes) as unknown: {e}");
+        atoms.push(Atom::new(Payload::Unknown(magic, payload)));
+        reader.seek(SeekFrom::Start(start + u64::from(length)))?;
+    }
+
+    Ok(atoms)
+}
+
 impl AtemPacket {
     const HEADERS_LENGTH: u16 = 12;
     /// Maximum packet size, including headers.
@@ -296,6 +359,97 @@ impl AtemPacket {
         o
     }
 
+    /// Creates an [AtemPacketBuilder], for constructing packets without
+    /// specifying every field.
+    ///
+    /// ```rust
+    /// use necromancer_protocol::{atom::{Atom, Cut}, AtemPacket};
+    ///
+    /// let pkt = AtemPacket::builder()
+    ///     .session(0x8001)
+    ///     .sender_packet_id(1)
+    ///     .atoms(vec![Atom::new(Cut { me: 0 })])
+    ///     .build();
+    /// assert!(pkt.flags.ack());
+    /// assert!(pkt.has_atoms());
+    /// ```
+    pub fn builder() -> AtemPacketBuilder {
+        AtemPacketBuilder::default()
+    }
+
+    /// Reads an [AtemPacket], recording any [Atom]s which can't be parsed as
+    /// [`Payload::Unknown`][].
+    ///
+    /// [`AtemPacket::read()`][BinRead::read] fails on the first invalid atom,
+    /// which loses every other atom in the packet. This logs a warning and
+    /// records the invalid atom instead, using its length header to find the
+    /// next atom.
+    ///
+    /// If `lossy_utf8` is set, names in atoms are
+    /// [decoded lossily][Atom#lossy-utf-8-decoding].
+    pub fn read_lenient<R: Read + Seek>(reader: &mut R, lossy_utf8: bool) -> Result<Self> {
+        Ok(Self::read_args(
+            reader,
+            binrw::args! { lenient: true, lossy_utf8 },
+        )?)
+    }
+
+    /// Greedily packs `atoms` into as few [AtemPacket]s as possible, without
+    /// exceeding [`AtemPacket::MAX_PACKET_LENGTH`].
+    ///
+    /// Atoms are kept in order, and are never split across packets.
+    ///
+    /// Some atoms (such as [`CameraControl`]) contain fields aligned to 8
+    /// bytes within the packet, so an atom's size depends on where it lands.
+    /// Each atom is measured by writing it at the offset it would occupy in
+    /// the packet.
+    ///
+    /// Packets are created with [`AtemPacketFlags::ack`] set, and all IDs set
+    /// to 0; the caller needs to fill in the [session ID] and
+    /// [sender packet ID] before sending.
+    ///
+    /// Returns [`Error::InvalidLength`] if an atom cannot fit in a packet on
+    /// its own.
+    ///
+    /// [session ID]: Self::session_id
+    /// [sender packet ID]: Self::sender_packet_id
+    pub fn pack_atoms(atoms: impl IntoIterator<Item = Atom>) -> Result<Vec<Self>> {
+        let max_length = u64::from(Self::MAX_PACKET_LENGTH);
+        let mut packets = Vec::new();
+        let mut current: Vec<Atom> = Vec::new();
+        let mut out = Cursor::new(Vec::new());
+        out.set_position(Self::HEADERS_LENGTH.into());
+
+        for atom in atoms {
+            atom.write(&mut out)?;
+
+            if out.position() > max_length {
+                if current.is_empty() {
+                    return Err(Error::InvalidLength);
+                }
+
+                // Start a new packet, and measure the atom again from there.
+                packets.push(std::mem::take(&mut current));
+                out.set_position(Self::HEADERS_LENGTH.into());
+                atom.write(&mut out)?;
+                if out.position() > max_length {
+                    return Err(Error::InvalidLength);
+                }
+            }
+
+            current.push(atom);
+        }
+
+        if !current.is_empty() {
+            packets.push(current);
+        }
+
+        Ok(packets
+            .into_iter()
+            .map(|atoms| Self::new_atoms(AtemPacketFlags::new().with_ack(true), 0, 0, 0, 0, atoms))
+            .collect())
+    }
+
     /// If this packet [requires acknowledgement][AtemPacketFlags::ack], make a
     /// [AtemPacketFlags::response] packet to this packet.
     ///
@@ -393,11 +547,101 @@ impl AtemPacket {
     }
 }
 
+/// Builder for [AtemPacket], created with [`AtemPacket::builder()`].
+///
+/// By default, this builds a packet with [`AtemPacketFlags::ack`] set, all IDs
+/// set to 0, and no payload. The [control flag][AtemPacketFlags::control] is
+/// always set to match the payload.
+#[derive(Debug, Clone)]
+pub struct AtemPacketBuilder {
+    flags: AtemPacketFlags,
+    session_id: u16,
+    acked_packet_id: u16,
+    client_packet_id: u16,
+    sender_packet_id: u16,
+    payload: AtemPacketPayload,
+}
+
+impl Default for AtemPacketBuilder {
+    fn default() -> Self {
+        Self {
+            flags: AtemPacketFlags::new().with_ack(true),
+            session_id: 0,
+            acked_packet_id: 0,
+            client_packet_id: 0,
+            sender_packet_id: 0,
+            payload: AtemPacketPayload::None,
+        }
+    }
+}
+
+impl AtemPacketBuilder {
+    /// Sets the packet's [flags][AtemPacket::flags].
+    pub fn flags(mut self, flags: AtemPacketFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Sets the packet's [session ID][AtemPacket::session_id].
+    pub fn session(mut self, session_id: u16) -> Self {
+        self.session_id = session_id;
+        self
+    }
+
+    /// Sets the packet's [acked packet ID][AtemPacket::acked_packet_id].
+    pub fn acked_packet_id(mut self, acked_packet_id: u16) -> Self {
+        self.acked_packet_id = acked_packet_id;
+        self
+    }
+
+    /// Sets the packet's [client packet ID][AtemPacket::client_packet_id].
+    pub fn client_packet_id(mut self, client_packet_id: u16) -> Self {
+        self.client_packet_id = client_packet_id;
+        self
+    }
+
+    /// Sets the packet's [sender packet ID][AtemPacket::sender_packet_id].
+    pub fn sender_packet_id(mut self, sender_packet_id: u16) -> Self {
+        self.sender_packet_id = sender_packet_id;
+        self
+    }
+
+    /// Sets the packet's payload to `atoms`.
+    pub fn atoms(mut self, atoms: Vec<Atom>) -> Self {
+        self.payload = AtemPacketPayload::Atom(atoms);
+        self
+    }
+
+    /// Sets the packet's payload to a control command.
+    pub fn control(mut self, control: AtemControl) -> Self {
+        self.payload = AtemPacketPayload::Control(control);
+        self
+    }
+
+    /// Builds the [AtemPacket].
+    pub fn build(self) -> AtemPacket {
+        let mut o = AtemPacket {
+            flags: self.flags,
+            session_id: self.session_id,
+            acked_packet_id: self.acked_packet_id,
+            unknown: 0,
+            client_packet_id: self.client_packet_id,
+            sender_packet_id: self.sender_packet_id,
+            payload: self.payload,
+        };
+
+        o.flags
+            .set_control(matches!(o.payload, AtemPacketPayload::Control(_)));
+        o
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::io::Cursor;
 
     use super::*;
+    use binrw::Endian;
 
     #[test]
     fn test_flags() -> Result<()> {
@@ -456,6 +700,59 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn builder() -> Result<()> {
+        let atoms = vec![Atom::new(Cut { me: 0 })];
+        let expected = AtemPacket::new_atoms(
+            AtemPacketFlags::new().with_ack(true),
+            0x8001,
+            0,
+            0,
+            5,
+            atoms.clone(),
+        );
+        let pkt = AtemPacket::builder()
+            .session(0x8001)
+            .sender_packet_id(5)
+            .atoms(atoms)
+            .build();
+        assert_eq!(expected, pkt);
+
+        let expected = AtemPacket::new_control(
+            AtemPacketFlags::new(),
+            0x2970,
+            0,
+            0xb1,
+            0,
+            AtemControl::Connect,
+        );
+        let pkt = AtemPacket::builder()
+            .flags(AtemPacketFlags::new())
+            .session(0x2970)
+            .client_packet_id(0xb1)
+            .control(AtemControl::Connect)
+            .build();
+        assert_eq!(expected, pkt);
+
+        // Control flag follows the payload, not the flags
+        let expected = AtemPacket::new(AtemPacketFlags::new().with_response(true), 0x8001, 7, 0, 0);
+        let pkt = AtemPacket::builder()
+            .flags(
+                AtemPacketFlags::new()
+                    .with_response(true)
+                    .with_control(true),
+            )
+            .session(0x8001)
+            .acked_packet_id(7)
+            .build();
+        assert_eq!(expected, pkt);
+
+        let mut out = Cursor::new(Vec::new());
+        pkt.write(&mut out)?;
+        assert_eq!(hex::decode("800c80010007000000000000")?, out.into_inner());
+        Ok(())
+    }
+
     #[test]
     fn rfip() {
         // single RFIP
@@ -493,4 +790,57 @@ mod test {
         pkt.write(&mut out).unwrap();
         assert_eq!(out.into_inner(), cmd);
     }
+
+    #[test]
+    fn lenient_atoms() -> Result<()> {
+        let mut cut = Cursor::new(Vec::new());
+        Atom::new(Cut { me: 1 }).write(&mut cut)?;
+        let cut = cut.into_inner();
+
+        // Oversized atom, followed by a valid one
+        let mut b = vec![0; 0x800];
+        b[..8].copy_from_slice(b"\x08\x00\x00\x00DCut");
+        b.extend_from_slice(&cut);
+
+        assert!(parse_atoms(&mut Cursor::new(&b), Endian::Big, (false, false)).is_err());
+        let atoms = parse_atoms(&mut Cursor::new(&b), Endian::Big, (true, false))?;
+        assert_eq!(
+            vec![
+                Atom::new(Payload::Unknown(*b"DCut", vec![0; 0x7f8])),
+                Atom::new(Cut { me: 1 }),
+            ],
+            atoms
+        );
+
+        // Truncated atom with a zero length shouldn't loop forever
+        let mut b = cut.clone();
+        b.extend_from_slice(&[0; 8]);
+        let atoms = parse_atoms(&mut Cursor::new(&b), Endian::Big, (true, false))?;
+        assert_eq!(vec![Atom::new(Cut { me: 1 })], atoms);
+        Ok(())
+    }
+
+    #[test]
+    fn read_lenient() -> Result<()> {
+        // Valid Cut atom, followed by an atom which claims to be larger than
+        // the packet
+        let cmd = hex::decode(concat!(
+            "08248001000000000000003f",
+            "000c0000444375740100ffff",
+            "080000004443757401000000",
+        ))
+        .unwrap();
+        assert!(AtemPacket::read(&mut Cursor::new(&cmd)).is_err());
+
+        let pkt = AtemPacket::read_lenient(&mut Cursor::new(&cmd), false)?;
+        assert_eq!(0x8001, pkt.session_id);
+        assert_eq!(
+            Some(&vec![
+                Atom::new(Cut { me: 1 }),
+                Atom::new(Payload::Unknown(*b"DCut", vec![1, 0, 0, 0])),
+            ]),
+            pkt.atoms()
+        );
+        Ok(())
+    }
 }
@@ -13,11 +13,15 @@
 //! Otherwise, the entry is passed as-is.
 //!
 //! The frame pixel format (`ay10`) [is described `ay10` module docs][crate::ay10].
+//!
+//! [`rle_compress_rgba()`] and [`rle_decompress_to_rgba()`] convert directly
+//! between 8-bit RGBA pixels and compressed frames.
 
-use crate::{Error, Result};
+use crate::{structs::VideoMode, Error, Result};
+use std::collections::VecDeque;
 
 /// Maximum number of repeats (in blocks).
-const MAX_REPEATS: u64 = 7860 * 4680;
+pub const MAX_REPEATS: u64 = 7860 * 4680;
 
 /// Marker for the start of an RLE sequence.
 pub const RLE_MARKER: u64 = 0xfefefefefefefefe;
@@ -229,6 +233,103 @@ where
     }
 }
 
+/// BT.709 luma coefficients
+const KR: f32 = 0.2126;
+const KB: f32 = 0.0722;
+const KG: f32 = 1. - KR - KB;
+
+/// Converts an 8-bit RGBA pixel to 10-bit video-range `(y, cb, cr, a)`.
+///
+/// `cb` and `cr` are left unrounded, so that they can be averaged for chroma
+/// subsampling.
+fn rgba_to_ycbcra([r, g, b, a]: [u8; 4]) -> (u64, f32, f32, u64) {
+    let (r, g, b) = (
+        f32::from(r) / 255.,
+        f32::from(g) / 255.,
+        f32::from(b) / 255.,
+    );
+    let y = KR * r + KG * g + KB * b;
+    let cb = 512. + 896. * (b - y) / (2. * (1. - KB));
+    let cr = 512. + 896. * (r - y) / (2. * (1. - KR));
+
+    (
+        (64. + 876. * y).round() as u64,
+        cb,
+        cr,
+        (64. + 876. * f32::from(a) / 255.).round() as u64,
+    )
+}
+
+/// Converts a 10-bit video-range `(y, cb, cr, a)` pixel to 8-bit RGBA.
+fn ycbcra_to_rgba(y: u64, cb: u64, cr: u64, a: u64) -> [u8; 4] {
+    let to_u8 = |v: f32| (v * 255.).round().clamp(0., 255.) as u8;
+    let y = (y as f32 - 64.) / 876.;
+    let pb = (cb as f32 - 512.) / 896.;
+    let pr = (cr as f32 - 512.) / 896.;
+
+    let r = y + 2. * (1. - KR) * pr;
+    let b = y + 2. * (1. - KB) * pb;
+    let g = (y - KR * r - KB * b) / KG;
+    [to_u8(r), to_u8(g), to_u8(b), to_u8((a as f32 - 64.) / 876.)]
+}
+
+/// Checks that a frame of `pixels` pixels is the right size for `video_mode`.
+fn check_frame_size(pixels: usize, video_mode: VideoMode) -> Result {
+    let expected = video_mode.pixels();
+    if expected == 0 || usize::try_from(expected).ok() != Some(pixels) {
+        error!("frame has {pixels} pixels, but {video_mode:?} expects {expected} pixels");
+        return Err(Error::InvalidLength);
+    }
+    Ok(())
+}
+
+/// Converts a frame of 8-bit RGBA pixels into a compressed frame, suitable for
+/// uploading to the switcher.
+///
+/// Pixels are converted to 10-bit YCbCr (BT.709, video range) with 4:2:2
+/// chroma subsampling, and the frame is then run-length encoded.
+///
+/// ## Errors
+///
+/// * [`Error::InvalidLength`] when `pixels` doesn't contain exactly
+///   [`video_mode.pixels()`][VideoMode::pixels] pixels
+pub fn rle_compress_rgba(pixels: &[[u8; 4]], video_mode: VideoMode) -> Result<VecDeque<u64>> {
+    check_frame_size(pixels.len(), video_mode)?;
+
+    let words = pixels.chunks_exact(2).map(|p| {
+        let (y0, cb0, cr0, a0) = rgba_to_ycbcra(p[0]);
+        let (y1, cb1, cr1, a1) = rgba_to_ycbcra(p[1]);
+        let cb = ((cb0 + cb1) / 2.).round() as u64;
+        let cr = ((cr0 + cr1) / 2.).round() as u64;
+        a0 << 52 | cb << 42 | y0 << 32 | a1 << 20 | cr << 10 | y1
+    });
+
+    Ok(RleCompressor::new(words).collect())
+}
+
+/// Converts a compressed frame into 8-bit RGBA pixels.
+///
+/// This is the inverse of [`rle_compress_rgba()`]. The conversion is lossy.
+///
+/// ## Errors
+///
+/// * [`Error::InvalidLength`] when the decompressed frame doesn't contain
+///   exactly [`video_mode.pixels()`][VideoMode::pixels] pixels
+pub fn rle_decompress_to_rgba(
+    i: impl Iterator<Item = u64>,
+    video_mode: VideoMode,
+) -> Result<Vec<[u8; 4]>> {
+    let mut o = Vec::with_capacity(video_mode.pixels() as usize);
+    for w in RleDecompressor::new(i) {
+        let cb = (w >> 42) & 0x3ff;
+        let cr = (w >> 10) & 0x3ff;
+        o.push(ycbcra_to_rgba((w >> 32) & 0x3ff, cb, cr, (w >> 52) & 0x3ff));
+        o.push(ycbcra_to_rgba(w & 0x3ff, cb, cr, (w >> 20) & 0x3ff));
+    }
+    check_frame_size(o.len(), video_mode)?;
+    Ok(o)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -344,4 +445,60 @@ mod test {
 
         Ok(())
     }
+
+    #[test]
+    fn rgba_black() -> Result {
+        let black = vec![[0, 0, 0, 255]; 1920 * 1080];
+        let out = rle_compress_rgba(&black, VideoMode::Fhd1080p25)?;
+        assert_eq!(
+            VecDeque::from([RLE_MARKER, 1920 * 1080 / 2, 0x3ac800403ac80040]),
+            out
+        );
+
+        let rgba = rle_decompress_to_rgba(out.into_iter(), VideoMode::Fhd1080p25)?;
+        assert_eq!(black, rgba);
+        Ok(())
+    }
+
+    #[test]
+    fn rgba_round_trip() -> Result {
+        // Pairs of pixels share chroma, so keep them the same colour.
+        let mode = VideoMode::Ntsc525i59_94;
+        let pixels: Vec<[u8; 4]> = (0..mode.pixels())
+            .map(|i| {
+                let i = (i / 2) as u8;
+                [i, i.wrapping_mul(3), 255 - i, i.wrapping_mul(7)]
+            })
+            .collect();
+
+        let compressed = rle_compress_rgba(&pixels, mode)?;
+        let rgba = rle_decompress_to_rgba(compressed.into_iter(), mode)?;
+        assert_eq!(pixels.len(), rgba.len());
+        for (expected, actual) in pixels.iter().zip(rgba.iter()) {
+            for (e, a) in expected.iter().zip(actual.iter()) {
+                assert!(e.abs_diff(*a) <= 2, "{expected:?} != {actual:?}");
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rgba_wrong_size() -> Result {
+        let pixels = vec![[0; 4]; 1280 * 720];
+        assert!(matches!(
+            rle_compress_rgba(&pixels, VideoMode::Fhd1080p25),
+            Err(Error::InvalidLength)
+        ));
+        assert!(matches!(
+            rle_compress_rgba(&[], VideoMode::Unknown),
+            Err(Error::InvalidLength)
+        ));
+
+        let compressed = rle_compress_rgba(&pixels, VideoMode::Hd720p50)?;
+        assert!(matches!(
+            rle_decompress_to_rgba(compressed.into_iter(), VideoMode::Fhd1080p25),
+            Err(Error::InvalidLength)
+        ));
+        Ok(())
+    }
 }
@@ -3,6 +3,8 @@ use modular_bitfield::{
     bitfield,
     specifiers::{B2, B4},
 };
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 use std::ops::RangeInclusive;
 
 /// Equaliser frequency band range (`BMDSwitcherFairlightAudioEqualizerBandFrequencyRange`).
@@ -12,6 +14,7 @@ use std::ops::RangeInclusive;
 #[binrw]
 #[brw(big, repr = u8)]
 #[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum EqualiserRange {
     /// Low frequency range
@@ -42,6 +45,7 @@ impl From<EqualiserRange> for SupportedEqualiserRanges {
 #[bitfield(bits = 8)]
 #[repr(u8)]
 #[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[br(map = From::<u8>::from)]
 #[bw(map = |&x| Into::<u8>::into(x))]
 pub struct SupportedEqualiserRanges {
@@ -72,6 +76,7 @@ impl SupportedEqualiserRanges {
 #[binrw]
 #[brw(big, repr = u8)]
 #[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum EqualiserShape {
     /// Low shelf filter. >─
@@ -103,6 +108,7 @@ impl From<EqualiserShape> for SupportedEqualiserShapes {
 #[bitfield(bits = 8)]
 #[repr(u8)]
 #[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[br(map = From::<u8>::from)]
 #[bw(map = |&x| Into::<u8>::into(x))]
 pub struct SupportedEqualiserShapes {
@@ -147,6 +153,7 @@ impl SupportedEqualiserShapes {
 #[binrw]
 #[brw(big)]
 #[derive(Clone, Debug, PartialEq, Eq, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct EqualiserRangeLimit {
     /// Equaliser range the limits apply to
     #[brw(pad_size_to = 4)]
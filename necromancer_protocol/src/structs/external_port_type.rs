@@ -1,5 +1,7 @@
 use binrw::{BinRead, BinWrite};
 use modular_bitfield::{bitfield, specifiers::B4};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// The external port type of the video switcher.
 ///
@@ -19,6 +21,7 @@ use modular_bitfield::{bitfield, specifiers::B4};
 #[bitfield(bits = 16)]
 #[repr(u16)]
 #[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[br(map = From::<u16>::from)]
 #[bw(map = |&x| Into::<u16>::into(x))]
 pub struct ExternalPortType {
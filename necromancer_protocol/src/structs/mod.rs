@@ -4,6 +4,7 @@
 mod equaliser;
 mod external_port_type;
 mod port_type;
+mod source_availability;
 mod tally;
 mod transition_style;
 mod video_mode;
@@ -16,8 +17,9 @@ pub use self::{
     },
     external_port_type::ExternalPortType,
     port_type::PortType,
+    source_availability::SourceAvailability,
     tally::TallyFlags,
-    transition_style::DVETransitionStyle,
+    transition_style::{DVETransitionStyle, TransitionSelection, TransitionStyle, WipePattern},
     video_mode::VideoMode,
     video_source::VideoSource,
 };
@@ -1,9 +1,12 @@
 use binrw::binrw;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Switcher port types.
 #[binrw]
 #[brw(big, repr = u8)]
 #[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum PortType {
     /// External port (see [ExternalPortType][super::ExternalPortType] for specifics).
@@ -0,0 +1,22 @@
+use modular_bitfield::{bitfield, specifiers::B3};
+
+/// Buses (other than MEs) which a video source may be routed to.
+///
+/// This is reported in [`InputProperties`][crate::atom::InputProperties].
+#[bitfield(bits = 8)]
+#[repr(u8)]
+#[derive(Specifier, Debug, Default, PartialEq, Eq, Clone, Copy)]
+pub struct SourceAvailability {
+    /// Auxiliary outputs.
+    pub auxiliary: bool,
+    /// MultiView windows.
+    pub multiview: bool,
+    /// SuperSource art fill / key.
+    pub super_source_art: bool,
+    /// SuperSource boxes.
+    pub super_source_box: bool,
+    /// Upstream and downstream key fill / key sources.
+    pub key_source: bool,
+    #[skip]
+    __: B3,
+}
@@ -1,10 +1,13 @@
 use binrw::{BinRead, BinWrite};
 use modular_bitfield::{bitfield, specifiers::B6};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
 /// Source tally status.
 #[bitfield(bits = 8)]
 #[repr(u8)]
 #[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[br(map = From::<u8>::from)]
 #[bw(map = |&x| Into::<u8>::into(x))]
 pub struct TallyFlags {
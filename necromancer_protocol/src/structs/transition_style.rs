@@ -1,10 +1,56 @@
-use binrw::binrw;
+use binrw::{binrw, BinRead, BinWrite};
+use modular_bitfield::{bitfield, specifiers::B3};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
 
+/// Transition style.
 #[binrw]
 #[brw(big, repr = u8)]
-#[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+#[derive(
+    Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash,
+)]
+#[repr(u8)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub enum TransitionStyle {
+    #[default]
+    Mix = 0x0,
+    Dip = 0x1,
+    Wipe = 0x2,
+    DVE = 0x3,
+    Stinger = 0x4,
+}
+
+/// Layers which will be affected by the next transition.
+#[bitfield(bits = 8)]
+#[repr(u8)]
+#[derive(Specifier, BinRead, BinWrite, Debug, Default, PartialEq, Eq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[br(map = From::<u8>::from)]
+#[bw(map = |&x| Into::<u8>::into(x))]
+pub struct TransitionSelection {
+    /// The background (program / preview) will transition.
+    pub background: bool,
+    /// Upstream keyer 1 will transition.
+    pub key1: bool,
+    /// Upstream keyer 2 will transition.
+    pub key2: bool,
+    /// Upstream keyer 3 will transition.
+    pub key3: bool,
+    /// Upstream keyer 4 will transition.
+    pub key4: bool,
+    #[skip]
+    __: B3,
+}
+
+#[binrw]
+#[brw(big, repr = u8)]
+#[derive(
+    Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u8)]
 pub enum DVETransitionStyle {
+    #[default]
     SwooshTopLeft = 0x0,
     SwooshTop = 0x1,
     SwooshTopRight = 0x2,
@@ -45,3 +91,33 @@ pub enum DVETransitionStyle {
     GraphicCCVSpin = 0x21,
     GraphicLogoWipe = 0x22,
 }
+
+/// Pattern of a wipe transition.
+#[binrw]
+#[brw(big, repr = u8)]
+#[derive(
+    Debug, Default, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(u8)]
+pub enum WipePattern {
+    #[default]
+    LeftToRightBar = 0x0,
+    TopToBottomBar = 0x1,
+    HorizontalBarnDoor = 0x2,
+    VerticalBarnDoor = 0x3,
+    CornersInFourBox = 0x4,
+    RectangleIris = 0x5,
+    DiamondIris = 0x6,
+    CircleIris = 0x7,
+    TopLeftBox = 0x8,
+    TopRightBox = 0x9,
+    BottomRightBox = 0xa,
+    BottomLeftBox = 0xb,
+    TopCentreBox = 0xc,
+    RightCentreBox = 0xd,
+    BottomCentreBox = 0xe,
+    LeftCentreBox = 0xf,
+    TopLeftDiagonal = 0x10,
+    TopRightDiagonal = 0x11,
+}
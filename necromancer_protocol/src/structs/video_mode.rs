@@ -1,5 +1,8 @@
+use crate::error::Error;
 use binrw::binrw;
-use std::fmt::Display;
+#[cfg(feature = "serde")]
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt::Display, str::FromStr, time::Duration};
 
 /// Input/output video mode
 ///
@@ -17,6 +20,9 @@ use std::fmt::Display;
 ///
 /// * `uint32_t`: SDK FourCC
 /// * `uint32_t`: Wire format
+///
+/// With the `serde` feature, this is (de)serialised by its [Display] name (eg:
+/// `"1080p50"`), rather than its wire format.
 #[binrw]
 #[brw(repr = u8)]
 #[derive(Debug, FromPrimitive, ToPrimitive, PartialEq, Eq, Clone, Copy, Default)]
@@ -102,6 +108,33 @@ pub enum VideoMode {
 }
 
 impl VideoMode {
+    /// Finds the video mode with the given properties.
+    ///
+    /// This is the inverse of [`width()`][Self::width],
+    /// [`lines()`][Self::lines], [`rate_per_100sec()`][Self::rate_per_100sec],
+    /// [`is_interlaced()`][Self::is_interlaced] and
+    /// [`is_anamorphic()`][Self::is_anamorphic], so `rate_per_100sec` is in
+    /// _fields_ for interlaced modes (ie: `5994` for 1080i59.94).
+    ///
+    /// Returns `None` if no mode matches.
+    pub fn find(
+        width: u32,
+        lines: u32,
+        rate_per_100sec: u16,
+        interlaced: bool,
+        anamorphic: bool,
+    ) -> Option<Self> {
+        (0..Self::Unknown as u8)
+            .filter_map(num_traits::FromPrimitive::from_u8)
+            .find(|m: &Self| {
+                m.width() == width
+                    && m.lines() == lines
+                    && m.rate_per_100sec() == rate_per_100sec
+                    && m.is_interlaced() == interlaced
+                    && m.is_anamorphic() == anamorphic
+            })
+    }
+
     /// Total number of lines in the image.
     pub const fn lines(&self) -> u32 {
         match self {
@@ -233,6 +266,32 @@ impl VideoMode {
         }
     }
 
+    /// Returns the duration of a single frame (or pair of fields, for
+    /// interlaced modes).
+    ///
+    /// Returns `None` for [`VideoMode::Unknown`].
+    pub fn frame_duration(&self) -> Option<Duration> {
+        let rate = u32::from(self.rate_per_100sec());
+        if rate == 0 {
+            return None;
+        }
+        let secs = if self.is_interlaced() { 200 } else { 100 };
+        Some(Duration::from_secs(secs) / rate)
+    }
+
+    /// Returns the mode's _nominal_ (integer) timecode frame rate, ie: `30`
+    /// for 29.97 frames per second, or `25` for 50 fields per second.
+    ///
+    /// Returns `0` for [`VideoMode::Unknown`].
+    pub const fn timecode_framerate(&self) -> u8 {
+        let fps = self.rate_per_100sec().div_ceil(100) as u8;
+        if self.is_interlaced() {
+            fps / 2
+        } else {
+            fps
+        }
+    }
+
     /// Returns `true` if the video mode is [anamorphic format][0]
     /// (stretched to 16:9).
     ///
@@ -243,6 +302,57 @@ impl VideoMode {
             Self::PalAnamorphic625i50 | Self::NtscAnamorphic525i59_94
         )
     }
+
+    /// Returns the display aspect ratio of the video mode, as
+    /// `(width, height)`.
+    ///
+    /// Standard definition modes are 4:3, unless they are
+    /// [anamorphic][Self::is_anamorphic]. All other modes are 16:9.
+    ///
+    /// Returns `(0, 0)` for [`VideoMode::Unknown`].
+    pub const fn display_aspect_ratio(&self) -> (u32, u32) {
+        match self {
+            Self::Ntsc525i59_94 | Self::Pal625i50 => (4, 3),
+            Self::Unknown => (0, 0),
+            _ => (16, 9),
+        }
+    }
+
+    /// Returns the pixel aspect ratio of the video mode, as
+    /// `(width, height)` in lowest terms.
+    ///
+    /// This is the shape of a single pixel when an image of
+    /// [`width()`][Self::width] &times; the active picture height is shown at
+    /// the [display aspect ratio][Self::display_aspect_ratio]. Images need to
+    /// be scaled horizontally by the inverse of this ratio before upload.
+    ///
+    /// The active picture height is 486 lines for 525-line (NTSC) modes, and
+    /// 576 lines for 625-line (PAL) modes, rather than the
+    /// [total number of lines][Self::lines].
+    ///
+    /// High definition modes always have square pixels (`(1, 1)`).
+    ///
+    /// Returns `(0, 0)` for [`VideoMode::Unknown`].
+    pub const fn pixel_aspect_ratio(&self) -> (u32, u32) {
+        let (dar_w, dar_h) = self.display_aspect_ratio();
+        let active_lines = match self {
+            Self::Ntsc525i59_94 | Self::NtscAnamorphic525i59_94 => 486,
+            Self::Pal625i50 | Self::PalAnamorphic625i50 => 576,
+            _ => self.lines(),
+        };
+        let w = dar_w * active_lines;
+        let h = dar_h * self.width();
+        if w == 0 || h == 0 {
+            return (0, 0);
+        }
+
+        // Euclid's algorithm
+        let (mut a, mut b) = (w, h);
+        while b != 0 {
+            (a, b) = (b, a % b);
+        }
+        (w / a, h / a)
+    }
 }
 
 impl Display for VideoMode {
@@ -270,6 +380,38 @@ impl Display for VideoMode {
     }
 }
 
+impl FromStr for VideoMode {
+    type Err = Error;
+
+    /// Parses a video mode from its [Display] name, eg: `1080p50` or
+    /// `625i50 (Anamorphic)`.
+    ///
+    /// This is case-insensitive.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        (0..=u8::MAX)
+            .filter_map(num_traits::FromPrimitive::from_u8)
+            .find(|m: &Self| m.to_string().eq_ignore_ascii_case(s))
+            .ok_or(Error::ParameterOutOfRange)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for VideoMode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> Deserialize<'de> for VideoMode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse()
+            .map_err(|_| de::Error::custom(format!("unknown video mode: {s:?}")))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use num_traits::FromPrimitive;
@@ -298,4 +440,160 @@ mod test {
             assert!(!m.is_interlaced(), "mode {m:?} must be progressive");
         }
     }
+
+    #[test]
+    fn from_str() -> Result<(), Error> {
+        assert_eq!(VideoMode::Fhd1080p50, "1080p50".parse()?);
+        assert_eq!(VideoMode::Fhd1080i59_94, "1080I59.94".parse()?);
+        assert_eq!(
+            VideoMode::PalAnamorphic625i50,
+            " 625i50 (anamorphic) ".parse()?
+        );
+        assert_eq!(VideoMode::Pal625i50, "625i50".parse()?);
+
+        for s in ["", "1080p", "1080p59", "1080i50.00", "garbage"] {
+            assert!(
+                matches!(s.parse::<VideoMode>(), Err(Error::ParameterOutOfRange)),
+                "{s:?}"
+            );
+        }
+
+        // Every mode round-trips through its display name
+        for m in (0..=u8::MAX).filter_map(VideoMode::from_u8) {
+            assert_eq!(m, m.to_string().parse()?, "mode {m:?}");
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde() {
+        use serde_test::{assert_de_tokens, assert_de_tokens_error, assert_tokens, Token};
+
+        assert_tokens(&VideoMode::Fhd1080p50, &[Token::Str("1080p50")]);
+        assert_tokens(
+            &VideoMode::NtscAnamorphic525i59_94,
+            &[Token::Str("525i59.94 (Anamorphic)")],
+        );
+        assert_de_tokens(&VideoMode::Uhd4Kp23_98, &[Token::Str("2160P23.98")]);
+        assert_de_tokens_error::<VideoMode>(
+            &[Token::Str("1080p51")],
+            "unknown video mode: \"1080p51\"",
+        );
+    }
+
+    #[test]
+    fn find() {
+        assert_eq!(
+            Some(VideoMode::Fhd1080p59_94),
+            VideoMode::find(1920, 1080, 59_94, false, false)
+        );
+        assert_eq!(
+            Some(VideoMode::Fhd1080p60),
+            VideoMode::find(1920, 1080, 60_00, false, false)
+        );
+        assert_eq!(
+            Some(VideoMode::Fhd1080i59_94),
+            VideoMode::find(1920, 1080, 59_94, true, false)
+        );
+        assert_eq!(
+            Some(VideoMode::Hd720p60),
+            VideoMode::find(1280, 720, 60_00, false, false)
+        );
+        assert_eq!(
+            Some(VideoMode::Uhd4Kp23_98),
+            VideoMode::find(3840, 2160, 23_98, false, false)
+        );
+        assert_eq!(
+            Some(VideoMode::NtscAnamorphic525i59_94),
+            VideoMode::find(720, 525, 59_94, true, true)
+        );
+        assert_eq!(
+            Some(VideoMode::Ntsc525i59_94),
+            VideoMode::find(720, 525, 59_94, true, false)
+        );
+
+        // No such modes
+        assert_eq!(None, VideoMode::find(1920, 1080, 59_00, false, false));
+        assert_eq!(None, VideoMode::find(720, 525, 60_00, true, false));
+        assert_eq!(None, VideoMode::find(1280, 720, 59_94, true, false));
+        assert_eq!(None, VideoMode::find(1920, 1080, 50_00, false, true));
+        assert_eq!(None, VideoMode::find(0, 0, 0, false, false));
+
+        // Round trip every mode
+        for m in (0..=u8::MAX).filter_map(VideoMode::from_u8) {
+            if m == VideoMode::Unknown {
+                continue;
+            }
+            assert_eq!(
+                Some(m),
+                VideoMode::find(
+                    m.width(),
+                    m.lines(),
+                    m.rate_per_100sec(),
+                    m.is_interlaced(),
+                    m.is_anamorphic()
+                ),
+                "mode {m:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn frame_duration() {
+        assert_eq!(
+            Some(Duration::from_millis(20)),
+            VideoMode::Fhd1080p50.frame_duration()
+        );
+        assert_eq!(
+            Some(Duration::from_millis(40)),
+            VideoMode::Fhd1080i50.frame_duration()
+        );
+        assert_eq!(
+            Some(Duration::from_nanos(33_366_700)),
+            VideoMode::Ntsc525i59_94.frame_duration()
+        );
+        assert_eq!(None, VideoMode::Unknown.frame_duration());
+    }
+
+    #[test]
+    fn timecode_framerate() {
+        for (m, fps) in [
+            (VideoMode::Pal625i50, 25),
+            (VideoMode::Ntsc525i59_94, 30),
+            (VideoMode::Fhd1080p23_98, 24),
+            (VideoMode::Fhd1080p25, 25),
+            (VideoMode::Hd720p59_94, 60),
+            (VideoMode::Uhd4Kp30, 30),
+            (VideoMode::Unknown, 0),
+        ] {
+            assert_eq!(fps, m.timecode_framerate(), "mode {m:?}");
+        }
+    }
+
+    #[test]
+    fn aspect_ratio() {
+        let m = VideoMode::NtscAnamorphic525i59_94;
+        assert_eq!((16, 9), m.display_aspect_ratio());
+        assert_eq!((6, 5), m.pixel_aspect_ratio());
+
+        let m = VideoMode::Ntsc525i59_94;
+        assert_eq!((4, 3), m.display_aspect_ratio());
+        assert_eq!((9, 10), m.pixel_aspect_ratio());
+
+        let m = VideoMode::PalAnamorphic625i50;
+        assert_eq!((16, 9), m.display_aspect_ratio());
+        assert_eq!((64, 45), m.pixel_aspect_ratio());
+
+        let m = VideoMode::Pal625i50;
+        assert_eq!((4, 3), m.display_aspect_ratio());
+        assert_eq!((16, 15), m.pixel_aspect_ratio());
+
+        let m = VideoMode::Fhd1080p25;
+        assert_eq!((16, 9), m.display_aspect_ratio());
+        assert_eq!((1, 1), m.pixel_aspect_ratio());
+
+        assert_eq!((0, 0), VideoMode::Unknown.display_aspect_ratio());
+        assert_eq!((0, 0), VideoMode::Unknown.pixel_aspect_ratio());
+    }
 }
@@ -1,8 +1,10 @@
+use crate::error::Error;
 use binrw::binrw;
 #[cfg(feature = "clap")]
 use clap::ValueEnum;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 /// Video input source.
 ///
@@ -108,3 +110,230 @@ pub enum VideoSource {
     #[default]
     Unknown = 0xffff,
 }
+
+impl VideoSource {
+    /// Iterates over all defined [VideoSource]s, in numeric order.
+    ///
+    /// This does not include [VideoSource::Unknown], and not all sources are
+    /// available on all switchers.
+    pub fn iter() -> impl Iterator<Item = Self> {
+        (0..u16::MAX).filter_map(num_traits::FromPrimitive::from_u16)
+    }
+
+    /// Gets the [VideoSource] for a 1-indexed external input number, eg: `3`
+    /// is [VideoSource::Input3].
+    ///
+    /// Returns `None` if there is no such input.
+    pub fn from_input_number(n: u8) -> Option<Self> {
+        if n == 0 || n > 40 {
+            return None;
+        }
+        num_traits::FromPrimitive::from_u8(n)
+    }
+
+    /// Gets the 0-indexed media player ID for a media player fill or key
+    /// source.
+    ///
+    /// Returns `None` if this is not a media player source.
+    pub const fn media_player_id(&self) -> Option<u8> {
+        match self {
+            Self::MediaPlayer1 | Self::MediaPlayer1Key => Some(0),
+            Self::MediaPlayer2 | Self::MediaPlayer2Key => Some(1),
+            Self::MediaPlayer3 | Self::MediaPlayer3Key => Some(2),
+            Self::MediaPlayer4 | Self::MediaPlayer4Key => Some(3),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if this is a media player's key output, or `false` for
+    /// all other sources (including a media player's fill output).
+    pub const fn is_media_player_key(&self) -> bool {
+        matches!(
+            self,
+            Self::MediaPlayer1Key
+                | Self::MediaPlayer2Key
+                | Self::MediaPlayer3Key
+                | Self::MediaPlayer4Key
+        )
+    }
+
+    /// Gets the [VideoSource] for a 0-indexed media player's fill or key
+    /// output.
+    ///
+    /// Returns `None` if there is no such media player source.
+    pub const fn from_media_player_id(id: u8, key: bool) -> Option<Self> {
+        Some(match (id, key) {
+            (0, false) => Self::MediaPlayer1,
+            (0, true) => Self::MediaPlayer1Key,
+            (1, false) => Self::MediaPlayer2,
+            (1, true) => Self::MediaPlayer2Key,
+            (2, false) => Self::MediaPlayer3,
+            (2, true) => Self::MediaPlayer3Key,
+            (3, false) => Self::MediaPlayer4,
+            (3, true) => Self::MediaPlayer4Key,
+            _ => return None,
+        })
+    }
+}
+
+impl FromStr for VideoSource {
+    type Err = Error;
+
+    /// Parses a video source from its variant name (eg: `ME1Prog`), or a
+    /// friendlier form (eg: `me1 program`, `color 2`, `mp1 key`, `aux3`).
+    ///
+    /// This is case-insensitive, and ignores spaces, `-` and `_`.
+    /// [VideoSource::Unknown] can't be parsed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalised: String = s
+            .chars()
+            .filter(|c| !matches!(c, ' ' | '-' | '_'))
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        // Split into <prefix><number><suffix>, eg: "me" 1 "prog"
+        let digits = normalised
+            .find(|c: char| c.is_ascii_digit())
+            .unwrap_or(normalised.len());
+        let (prefix, rest) = normalised.split_at(digits);
+        let suffix_start = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (n, suffix) = rest.split_at(suffix_start);
+        let n: Option<u16> = if n.is_empty() {
+            None
+        } else {
+            n.parse().ok().filter(|n| *n < 100)
+        };
+
+        let src = match (prefix, n, suffix) {
+            ("black", None, "") => Some(Self::Black),
+            ("colourbars" | "colorbars" | "bars", None, "") => Some(Self::ColourBars),
+            ("supersource" | "ssrc", None, "") => Some(Self::SuperSource),
+            ("input", Some(n), "") => u8::try_from(n).ok().and_then(Self::from_input_number),
+            ("input", Some(1), "direct") => Some(Self::Input1Direct),
+            ("colour" | "color", Some(n @ 1..=8), "") => {
+                num_traits::FromPrimitive::from_u16(2000 + n)
+            }
+            ("mediaplayer" | "mp", Some(n @ 1..), suffix @ ("" | "key")) => {
+                Self::from_media_player_id((n - 1) as u8, suffix == "key")
+            }
+            ("key", Some(n @ 1..=9), "mask") => num_traits::FromPrimitive::from_u16(4000 + n * 10),
+            ("dsk", Some(n @ 1..=9), "mask") => num_traits::FromPrimitive::from_u16(5000 + n * 10),
+            ("cleanfeed", Some(n @ 1..=9), "") => num_traits::FromPrimitive::from_u16(7000 + n),
+            ("aux" | "auxilary" | "auxiliary", Some(n @ 1..=9), "") => {
+                num_traits::FromPrimitive::from_u16(8000 + n)
+            }
+            ("me", Some(n @ 1..=9), "prog" | "program" | "pgm") => {
+                num_traits::FromPrimitive::from_u16(10000 + n * 10)
+            }
+            ("me", Some(n @ 1..=9), "prev" | "preview" | "pvw") => {
+                num_traits::FromPrimitive::from_u16(10001 + n * 10)
+            }
+            _ => None,
+        };
+
+        src.ok_or_else(|| Error::UnknownVideoSource(s.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use num_traits::FromPrimitive;
+
+    #[test]
+    fn media_player() {
+        let src = VideoSource::from_u16(3010).unwrap();
+        assert_eq!(VideoSource::MediaPlayer1, src);
+        assert_eq!(Some(0), src.media_player_id());
+        assert!(!src.is_media_player_key());
+        assert_eq!(Some(src), VideoSource::from_media_player_id(0, false));
+
+        let src = VideoSource::from_u16(3021).unwrap();
+        assert_eq!(VideoSource::MediaPlayer2Key, src);
+        assert_eq!(Some(1), src.media_player_id());
+        assert!(src.is_media_player_key());
+        assert_eq!(Some(src), VideoSource::from_media_player_id(1, true));
+
+        assert_eq!(None, VideoSource::Input1.media_player_id());
+        assert!(!VideoSource::Input1.is_media_player_key());
+        assert_eq!(None, VideoSource::from_media_player_id(4, false));
+    }
+
+    #[test]
+    fn from_str() -> Result<(), Error> {
+        for (expected, s) in [
+            (VideoSource::Black, "black"),
+            (VideoSource::Input3, "input3"),
+            (VideoSource::Input3, "Input3"),
+            (VideoSource::Input12, "INPUT 12"),
+            (VideoSource::Input1Direct, "Input1Direct"),
+            (VideoSource::ColourBars, "ColourBars"),
+            (VideoSource::ColourBars, "color-bars"),
+            (VideoSource::Colour1, "colour1"),
+            (VideoSource::Colour2, "Color 2"),
+            (VideoSource::MediaPlayer1, "MediaPlayer1"),
+            (VideoSource::MediaPlayer2Key, "mp2_key"),
+            (VideoSource::Key1Mask, "Key1Mask"),
+            (VideoSource::DSK2Mask, "DSK2Mask"),
+            (VideoSource::SuperSource, "supersource"),
+            (VideoSource::CleanFeed1, "clean feed 1"),
+            (VideoSource::Auxilary1, "Auxilary1"),
+            (VideoSource::Auxilary3, "aux3"),
+            (VideoSource::ME1Prog, "me1prog"),
+            (VideoSource::ME1Prog, "ME1Prog"),
+            (VideoSource::ME2Prev, "me2 preview"),
+        ] {
+            assert_eq!(expected, s.parse()?, "{s:?}");
+        }
+
+        for s in [
+            "",
+            "garbage",
+            "input",
+            "input0",
+            "input41",
+            "input99999999999",
+            "colour9",
+            "mp0",
+            "mp5key",
+            "me3prog",
+            "me1",
+            "unknown",
+            "input1 garbage",
+        ] {
+            let Err(Error::UnknownVideoSource(e)) = s.parse::<VideoSource>() else {
+                panic!("{s:?} should not parse");
+            };
+            assert_eq!(s, e);
+        }
+        assert_eq!(
+            "unknown video source: \"garbage\"",
+            "garbage".parse::<VideoSource>().unwrap_err().to_string()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn iter() {
+        let sources: Vec<_> = VideoSource::iter().collect();
+        assert_eq!(78, sources.len());
+        assert_eq!(Some(&VideoSource::Black), sources.first());
+        assert_eq!(Some(&VideoSource::Input1Direct), sources.last());
+        assert!(!sources.contains(&VideoSource::Unknown));
+    }
+
+    #[test]
+    fn input_number() {
+        assert_eq!(Some(VideoSource::Input1), VideoSource::from_input_number(1));
+        assert_eq!(Some(VideoSource::Input3), VideoSource::from_input_number(3));
+        assert_eq!(
+            Some(VideoSource::Input40),
+            VideoSource::from_input_number(40)
+        );
+        assert_eq!(None, VideoSource::from_input_number(0));
+        assert_eq!(None, VideoSource::from_input_number(41));
+        assert_eq!(None, VideoSource::from_input_number(99));
+    }
+}